@@ -1,44 +1,184 @@
-use crate::ast::{FuncCallNode, FuncDefNode, Node};
+use crate::ast::{DeclareNode, FuncDefNode};
+use crate::ir::{Function, Global, Instr, Local, Location, Program};
+use crate::backend::Target;
+use crate::operator::Op;
+use crate::token::{Number, Position};
+use crate::variable::{Scopes, VariableKind};
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::fs;
-use std::io::Write;
 use std::rc::Rc;
 
 pub struct Assembler {
-    text: String,
+    target: Target,
+    program: Program,
+    /// Instructions of the function being assembled
+    code: Vec<Instr>,
+    /// Variables of the function being assembled
+    locals: Vec<Local>,
     funcs: Rc<RefCell<HashMap<String, FuncDefNode>>>,
+    globals: Scopes<(VariableKind, Location)>,
+    return_kind: VariableKind,
     id: usize,
+    loop_labels: Vec<(String, String)>,
+    /// Whether signed overflow aborts the program instead of wrapping around
+    overflow_checks: bool,
 }
 
 impl Assembler {
-    pub fn new(base_file: String, funcs: &Rc<RefCell<HashMap<String, FuncDefNode>>>) -> Self {
-        let mut text = fs::read_to_string(base_file).unwrap();
-        text.push('\n');
-
+    pub fn new(target: Target, funcs: &Rc<RefCell<HashMap<String, FuncDefNode>>>) -> Self {
         Self {
-            text,
+            target,
+            program: Program::default(),
+            code: vec![],
+            locals: vec![],
             funcs: funcs.clone(),
+            globals: Scopes::new(),
+            return_kind: VariableKind::None,
             id: 0,
+            loop_labels: vec![],
+            overflow_checks: false,
         }
     }
 
-    pub fn assemble(&mut self) {
-        let fc = FuncCallNode::new("main".to_string(), vec![], &self.funcs);
-        fc.assemble(self, &mut HashMap::new(), &mut 0);
-        self.push_line("pop ebp\nmov eax, 1\nmov ebx, 0\nint 0x80");
+    /// Assembles the whole program, returning the assembly of the target
+    pub fn assemble(&mut self, globals: &[DeclareNode]) -> String {
+        for global in globals.iter() {
+            global.assemble_global(self);
+        }
+
+        let funcs = self.funcs.clone();
+        let funcs = funcs.borrow();
+        let mut names: Vec<&String> = funcs.keys().collect();
+        names.sort();
+        for name in names {
+            funcs[name].assemble(self);
+        }
+
+        self.target.emit(&self.program)
+    }
+
+    /// Makes the target emit debug info mapping the code back to the file `source`
+    pub fn debug(&mut self, source: String) {
+        self.program.source = Some(source);
+    }
+
+    /// Makes signed overflow abort the program instead of wrapping around
+    pub fn check_overflow(&mut self) {
+        self.overflow_checks = true;
+    }
 
-        let mut out = fs::File::create("out.asm").unwrap();
-        out.write_all(self.text.as_bytes()).unwrap();
+    /// Whether signed overflow aborts the program
+    pub fn checks_overflow(&self) -> bool {
+        self.overflow_checks
     }
 
-    pub fn push_line<'a>(&mut self, s: &'a str) {
-        self.text.push_str(s.as_ref());
-        self.text.push('\n');
+    /// Position arithmetic at `position` of the source reports when it overflows, `None` without
+    /// overflow checks
+    pub fn overflow_check(&self, position: Position) -> Option<Position> {
+        self.overflow_checks.then_some(position)
+    }
+
+    /// Pushes a binary operator, aborting the program on division by zero and, with overflow
+    /// checks, on overflow
+    pub fn push_binop(&mut self, op: Op, position: Position) {
+        if matches!(op, Op::Div | Op::Mod) {
+            self.push(Instr::CheckDivisor(position));
+        }
+        let checked = match op {
+            Op::Mul | Op::Div | Op::Mod | Op::Add | Op::Sub => self.overflow_check(position),
+            _ => None,
+        };
+        self.push(Instr::BinOp(op, checked));
+    }
+
+    pub fn push(&mut self, instr: Instr) {
+        self.code.push(instr);
+    }
+
+    /// Records a variable of the function being assembled for the debug info
+    pub fn declare_local(
+        &mut self,
+        name: &str,
+        kind: &VariableKind,
+        location: &Location,
+        argument: bool,
+    ) {
+        self.locals.push(Local {
+            name: name.to_string(),
+            kind: kind.clone(),
+            location: location.clone(),
+            argument,
+        });
+    }
+
+    /// Ends the function whose instructions were pushed since the previous one, defined on `line`
+    pub fn push_function(&mut self, name: String, line: usize, args: usize, frame: usize) {
+        let code = std::mem::take(&mut self.code);
+        let locals = std::mem::take(&mut self.locals);
+        self.program.functions.push(Function {
+            name,
+            line,
+            args,
+            frame,
+            code,
+            locals,
+        });
+    }
+
+    pub fn declare_global(&mut self, name: String, kind: VariableKind, value: Option<Number>) {
+        let label = format!("global_{}", name);
+        self.program.globals.push(Global {
+            name: name.clone(),
+            label: label.clone(),
+            value,
+            kind: kind.clone(),
+        });
+        self.globals
+            .declare(name, (kind, Location::Global(label, 0)));
+    }
+
+    /// Scope holding only the globals, each function body is assembled on top of it
+    pub fn globals(&self) -> Scopes<(VariableKind, Location)> {
+        self.globals.clone()
+    }
+
+    /// Return kind of the function being assembled
+    pub fn return_kind(&self) -> &VariableKind {
+        &self.return_kind
+    }
+
+    pub fn set_return_kind(&mut self, kind: VariableKind) {
+        self.return_kind = kind;
     }
 
     pub fn next_id(&mut self) -> usize {
         self.id += 1;
         self.id
     }
+
+    /// Enters a loop whose `continue` and `break` jump to the given labels
+    pub fn push_loop(&mut self, continue_label: String, break_label: String) {
+        self.loop_labels.push((continue_label, break_label));
+    }
+
+    /// Enters a switch, `break` leaves it while `continue` still targets the enclosing loop
+    pub fn push_switch(&mut self, break_label: String) {
+        let continue_label = self
+            .loop_labels
+            .last()
+            .map(|(continue_label, _)| continue_label.clone())
+            .unwrap_or_default();
+        self.loop_labels.push((continue_label, break_label));
+    }
+
+    pub fn pop_loop(&mut self) {
+        self.loop_labels.pop();
+    }
+
+    /// Labels `(continue, break)` of the innermost loop being assembled
+    pub fn loop_labels(&self) -> &(String, String) {
+        self.loop_labels
+            .last()
+            .expect("break/continue outside of a loop")
+    }
 }
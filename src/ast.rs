@@ -1,39 +1,127 @@
 use crate::assembler::Assembler;
+use crate::checker::{expect_numeric, is_assignable, Checker};
+use crate::error::RuntimeError;
+use crate::ir::{Instr, Location};
 use crate::operator::{CondOp, Op};
-use crate::token::Number;
+use crate::token::{Number, Position};
 use crate::variable::*;
 
-use std::any::Any;
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::convert::TryInto;
+use std::convert::TryFrom;
 use std::fmt::Debug;
 use std::rc::Rc;
 
-pub trait Node: Debug + Any {
-    fn eval(&self, vars: &mut HashMap<String, Variable>) -> VariableData;
+use color_eyre::eyre::{bail, Result};
+
+/// Control-flow signal produced by executing a statement
+#[derive(Debug, Clone)]
+pub enum Flow {
+    Normal,
+    Return(VariableData),
+    Break,
+    Continue,
+}
+
+/// Node behind a `dyn Node`, for the backends lowering each kind of node their own way
+#[derive(Clone, Copy)]
+pub enum NodeRef<'a> {
+    Binary(&'a BinaryNode),
+    Unary(&'a UnaryNode),
+    Number(&'a NumberNode),
+    NumberLiteral(&'a NumberLiteralNode),
+    SimpleVariable(&'a SimpleVariableNode),
+    Bool(&'a BoolNode),
+    BoolLiteral(&'a BoolLiteralNode),
+    String(&'a StringNode),
+    StringLiteral(&'a StringLiteralNode),
+    Declare(&'a DeclareNode),
+    Assign(&'a AssignNode),
+    IncDec(&'a IncDecNode),
+    Variable(&'a VariableNode),
+    ArrayLiteral(&'a ArrayLiteralNode),
+    Index(&'a IndexNode),
+    IndexAssign(&'a IndexAssignNode),
+    Len(&'a LenNode),
+    StructLiteral(&'a StructLiteralNode),
+    Field(&'a FieldNode),
+    FieldAssign(&'a FieldAssignNode),
+    EnumVariant(&'a EnumVariantNode),
+    Cond(&'a CondNode),
+    Ternary(&'a TernaryNode),
+    If(&'a IfNode),
+    While(&'a WhileNode),
+    For(&'a ForNode),
+    DoWhile(&'a DoWhileNode),
+    Switch(&'a SwitchNode),
+    LoopJump(&'a LoopJumpNode),
+    Block(&'a BlockNode),
+    FuncCall(&'a FuncCallNode),
+    Return(&'a ReturnNode),
+}
+
+pub trait Node: Debug {
+    fn eval(&self, vars: &mut Environment) -> Result<VariableData>;
+
+    /// Executes the node as a statement, reporting how control leaves it
+    fn exec(&self, vars: &mut Environment) -> Result<Flow> {
+        self.eval(vars)?;
+        Ok(Flow::Normal)
+    }
+
+    /// Concrete node, which the backends match on
+    fn node_ref(&self) -> NodeRef<'_>;
+
+    /// Statically checks the node, returning the kind of value it evaluates to
+    fn check(&self, checker: &mut Checker) -> Result<VariableKind>;
+
+    /// Value of the node if it can be computed at compile time
+    fn const_eval(&self) -> Option<Number> {
+        None
+    }
+
+    /// Whether evaluating the node can neither fail nor modify any state
+    fn is_pure(&self) -> bool {
+        false
+    }
+
+    /// Pushes the instructions of the node, `frame_offset` being the words of the frame already
+    /// taken by locals
     fn assemble(
         &self,
         assembler: &mut Assembler,
-        vars: &mut HashMap<String, (VariableKind, usize)>,
-        ebp_offset: &mut usize,
+        vars: &mut Scopes<(VariableKind, Location)>,
+        frame_offset: &mut usize,
     );
+}
 
-    fn as_any(&self) -> &dyn Any;
+/// Applies an arithmetic operator in the interpreter, failing with the fault of the operator at
+/// `position`
+fn execute(op: Op, lhs: Number, rhs: Number, checked: bool, position: Position) -> Result<Number> {
+    op.execute(lhs, rhs, checked)
+        .map_err(|fault| RuntimeError::fault(fault, position).into())
 }
 
 // Binary Node
 #[derive(Debug)]
 pub struct BinaryNode {
-    op: Op,
-    left_child: Box<dyn Node>,
-    right_child: Box<dyn Node>,
+    pub op: Op,
+    /// Where the operator is, reported when it faults
+    pub position: Position,
+    pub left_child: Box<dyn Node>,
+    pub right_child: Box<dyn Node>,
 }
 
 impl BinaryNode {
-    pub fn new(op: Op, left_child: Box<dyn Node>, right_child: Box<dyn Node>) -> Self {
+    pub fn new(
+        op: Op,
+        position: Position,
+        left_child: Box<dyn Node>,
+        right_child: Box<dyn Node>,
+    ) -> Self {
         Self {
             op,
+            position,
             left_child,
             right_child,
         }
@@ -41,12 +129,13 @@ impl BinaryNode {
 }
 
 impl Node for BinaryNode {
-    fn as_any(&self) -> &dyn Any {
-        self
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Binary(self)
     }
-    fn eval(&self, vars: &mut HashMap<String, Variable>) -> VariableData {
-        let n1 = self.left_child.eval(vars);
-        let n2 = self.right_child.eval(vars);
+
+    fn eval(&self, vars: &mut Environment) -> Result<VariableData> {
+        let n1 = self.left_child.eval(vars)?;
+        let n2 = self.right_child.eval(vars)?;
 
         // println!(
         //     "n1 {:?} n2 {:?}",
@@ -54,36 +143,43 @@ impl Node for BinaryNode {
         //     self.right_child.eval(vars)
         // );
 
-        let n2 = match n2 {
-            VariableData::Number(n2) => n2,
-            VariableData::Bool(n2) => n2 as Number,
-            VariableData::String(_) => panic!("n2 string"),
-            VariableData::None => panic!("n2 None"),
-        };
+        let (lhs, rhs) = (n1.to_number(), n2.to_number());
+        let checked = vars.limits().overflow_checks;
+        Ok(execute(self.op, lhs, rhs, checked, self.position)?.into())
+    }
 
-        let n1 = match n1 {
-            VariableData::Number(n1) => n1,
-            VariableData::Bool(n1) => n1 as Number,
+    fn check(&self, checker: &mut Checker) -> Result<VariableKind> {
+        let context = format!("operator {}", self.op);
+        expect_numeric(&self.left_child.check(checker)?, &context)?;
+        expect_numeric(&self.right_child.check(checker)?, &context)?;
+        Ok(VariableKind::Number)
+    }
 
-            VariableData::String(_) => panic!("n1 string"),
-            VariableData::None => panic!("n1 None"),
-        };
+    fn const_eval(&self) -> Option<Number> {
+        let lhs = self.left_child.const_eval()?;
+        let rhs = self.right_child.const_eval()?;
+        // Faults are left for the program to report when it runs
+        self.op.execute(lhs, rhs, true).ok()
+    }
 
-        self.op.execute(n1, n2).into()
+    fn is_pure(&self) -> bool {
+        !matches!(self.op, Op::Div | Op::Mod)
+            && self.left_child.is_pure()
+            && self.right_child.is_pure()
     }
 
     fn assemble(
         &self,
         assembler: &mut Assembler,
-        vars: &mut HashMap<String, (VariableKind, usize)>,
-        ebp_offset: &mut usize,
+        vars: &mut Scopes<(VariableKind, Location)>,
+        frame_offset: &mut usize,
     ) {
-        self.left_child.assemble(assembler, vars, ebp_offset);
-        assembler.push_line("push ebx");
-        self.right_child.assemble(assembler, vars, ebp_offset);
-        assembler.push_line("pop eax");
+        self.left_child.assemble(assembler, vars, frame_offset);
+        assembler.push(Instr::Push);
+        self.right_child.assemble(assembler, vars, frame_offset);
+        assembler.push(Instr::PopOperand);
 
-        assembler.push_line(self.op.assemble());
+        assembler.push_binop(self.op, self.position);
     }
 }
 
@@ -92,34 +188,43 @@ pub enum UnaryNodeKind {
     Pos,
     Neg,
     Not,
+    BitNot,
 }
 
 // Unary Node
 #[derive(Debug)]
 pub struct UnaryNode {
-    child: Box<dyn Node>,
-    kind: UnaryNodeKind,
+    pub child: Box<dyn Node>,
+    pub kind: UnaryNodeKind,
+    /// Where the operator is, reported when a negation overflows
+    pub position: Position,
 }
 
 impl UnaryNode {
-    pub fn new(kind: UnaryNodeKind, child: Box<dyn Node>) -> Self {
-        Self { child, kind }
+    pub fn new(kind: UnaryNodeKind, position: Position, child: Box<dyn Node>) -> Self {
+        Self {
+            child,
+            kind,
+            position,
+        }
     }
 }
 
 impl Node for UnaryNode {
-    fn as_any(&self) -> &dyn Any {
-        self
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Unary(self)
     }
-    fn eval(&self, vars: &mut HashMap<String, Variable>) -> VariableData {
-        let eval = self.child.eval(vars);
 
-        match eval {
+    fn eval(&self, vars: &mut Environment) -> Result<VariableData> {
+        let eval = self.child.eval(vars)?;
+        let checked = vars.limits().overflow_checks;
+
+        Ok(match eval {
             VariableData::Number(n) => {
                 let n = match &self.kind {
                     UnaryNodeKind::Pos => n,
-                    UnaryNodeKind::Neg => -n,
-                    UnaryNodeKind::Not => !n,
+                    UnaryNodeKind::Neg => execute(Op::Sub, 0, n, checked, self.position)?,
+                    UnaryNodeKind::Not | UnaryNodeKind::BitNot => !n,
                 };
                 VariableData::Number(n)
             }
@@ -128,22 +233,49 @@ impl Node for UnaryNode {
                 UnaryNodeKind::Pos => VariableData::Bool(b),
                 UnaryNodeKind::Neg => VariableData::Number(-(b as Number)),
                 UnaryNodeKind::Not => VariableData::Bool(!b),
+                UnaryNodeKind::BitNot => VariableData::Number(!(b as Number)),
             },
             _ => unreachable!(),
-        }
+        })
+    }
+
+    fn check(&self, checker: &mut Checker) -> Result<VariableKind> {
+        let kind = self.child.check(checker)?;
+        expect_numeric(&kind, "unary operator")?;
+        Ok(match (&self.kind, kind) {
+            (UnaryNodeKind::Pos | UnaryNodeKind::Not, VariableKind::Bool) => VariableKind::Bool,
+            _ => VariableKind::Number,
+        })
+    }
+
+    fn const_eval(&self) -> Option<Number> {
+        let n = self.child.const_eval()?;
+        Some(match self.kind {
+            UnaryNodeKind::Pos => n,
+            UnaryNodeKind::Neg => Op::Sub.execute(0, n, true).ok()?,
+            UnaryNodeKind::Not | UnaryNodeKind::BitNot => !n,
+        })
+    }
+
+    fn is_pure(&self) -> bool {
+        self.child.is_pure()
     }
 
     fn assemble(
         &self,
         assembler: &mut Assembler,
-        vars: &mut HashMap<String, (VariableKind, usize)>,
-        ebp_offset: &mut usize,
+        vars: &mut Scopes<(VariableKind, Location)>,
+        frame_offset: &mut usize,
     ) {
-        self.child.assemble(assembler, vars, ebp_offset);
+        self.child.assemble(assembler, vars, frame_offset);
         match self.kind {
             UnaryNodeKind::Pos => {}
-            UnaryNodeKind::Neg => assembler.push_line("xor eax, eax\nsub eax, ebx\nmov eax, ebx"),
-            UnaryNodeKind::Not => assembler.push_line("not ebx"),
+            UnaryNodeKind::Neg => {
+                let checked = assembler.overflow_check(self.position);
+                assembler.push(Instr::Negate(checked))
+            }
+            UnaryNodeKind::Not => assembler.push(Instr::Not),
+            UnaryNodeKind::BitNot => assembler.push(Instr::BitNot),
         }
     }
 }
@@ -151,7 +283,7 @@ impl Node for UnaryNode {
 // Number Node
 #[derive(Debug)]
 pub struct NumberNode {
-    child: Box<dyn Node>,
+    pub child: Box<dyn Node>,
 }
 
 impl NumberNode {
@@ -161,30 +293,47 @@ impl NumberNode {
 }
 
 impl Node for NumberNode {
-    fn as_any(&self) -> &dyn Any {
-        self
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Number(self)
     }
-    fn eval(&self, vars: &mut HashMap<String, Variable>) -> VariableData {
-        let v = self.child.eval(vars);
+
+    fn eval(&self, vars: &mut Environment) -> Result<VariableData> {
+        let v = self.child.eval(vars)?;
         match v {
-            VariableData::Number(_) => v,
+            VariableData::Number(_) => Ok(v),
             _ => panic!("NumberNode"),
         }
     }
 
+    fn check(&self, checker: &mut Checker) -> Result<VariableKind> {
+        let kind = self.child.check(checker)?;
+        if !matches!(kind, VariableKind::Number | VariableKind::Enum(_)) {
+            bail!("Expected int, found {:?}", kind);
+        }
+        Ok(VariableKind::Number)
+    }
+
+    fn const_eval(&self) -> Option<Number> {
+        self.child.const_eval()
+    }
+
+    fn is_pure(&self) -> bool {
+        self.child.is_pure()
+    }
+
     fn assemble(
         &self,
         assembler: &mut Assembler,
-        vars: &mut HashMap<String, (VariableKind, usize)>,
-        ebp_offset: &mut usize,
+        vars: &mut Scopes<(VariableKind, Location)>,
+        frame_offset: &mut usize,
     ) {
-        self.child.assemble(assembler, vars, ebp_offset);
+        self.child.assemble(assembler, vars, frame_offset);
     }
 }
 
 #[derive(Debug)]
 pub struct NumberLiteralNode {
-    value: Number,
+    pub value: Number,
 }
 
 impl NumberLiteralNode {
@@ -194,26 +343,39 @@ impl NumberLiteralNode {
 }
 
 impl Node for NumberLiteralNode {
-    fn as_any(&self) -> &dyn Any {
-        self
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::NumberLiteral(self)
+    }
+
+    fn eval(&self, _vars: &mut Environment) -> Result<VariableData> {
+        Ok(VariableData::Number(self.value))
+    }
+
+    fn check(&self, _checker: &mut Checker) -> Result<VariableKind> {
+        Ok(VariableKind::Number)
+    }
+
+    fn const_eval(&self) -> Option<Number> {
+        Some(self.value)
     }
-    fn eval(&self, _vars: &mut HashMap<String, Variable>) -> VariableData {
-        VariableData::Number(self.value)
+
+    fn is_pure(&self) -> bool {
+        true
     }
 
     fn assemble(
         &self,
         assembler: &mut Assembler,
-        _vars: &mut HashMap<String, (VariableKind, usize)>,
-        _ebp_offset: &mut usize,
+        _vars: &mut Scopes<(VariableKind, Location)>,
+        _frame_offset: &mut usize,
     ) {
-        assembler.push_line(format!("mov ebx, {}", self.value).as_str())
+        assembler.push(Instr::Imm(self.value))
     }
 }
 
 #[derive(Debug)]
 pub struct SimpleVariableNode {
-    value: VariableData,
+    pub value: VariableData,
 }
 
 impl SimpleVariableNode {
@@ -223,33 +385,39 @@ impl SimpleVariableNode {
 }
 
 impl Node for SimpleVariableNode {
-    fn as_any(&self) -> &dyn Any {
-        self
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::SimpleVariable(self)
+    }
+
+    fn eval(&self, _vars: &mut Environment) -> Result<VariableData> {
+        Ok(self.value.clone())
     }
-    fn eval(&self, _vars: &mut HashMap<String, Variable>) -> VariableData {
-        self.value.clone()
+
+    fn check(&self, _checker: &mut Checker) -> Result<VariableKind> {
+        Ok(match self.value {
+            VariableData::String(_) => VariableKind::String,
+            VariableData::Number(_) => VariableKind::Number,
+            VariableData::Bool(_) => VariableKind::Bool,
+            VariableData::Array(ref v) => VariableKind::Array(v.len()),
+            VariableData::Struct(_) => unreachable!("struct values only appear as arguments"),
+            VariableData::None => VariableKind::None,
+        })
     }
 
     fn assemble(
         &self,
         assembler: &mut Assembler,
-        _vars: &mut HashMap<String, (VariableKind, usize)>,
-        _ebp_offset: &mut usize,
+        _vars: &mut Scopes<(VariableKind, Location)>,
+        _frame_offset: &mut usize,
     ) {
-        let n = match &self.value {
-            VariableData::String(_) => panic!("Cannot convert String to Number"),
-            VariableData::Number(n) => *n,
-            VariableData::Bool(b) => *b as Number,
-            VariableData::None => panic!("Cannot convert None to Number"),
-        };
-        assembler.push_line(format!("mov ebx, {}", n).as_str());
+        assembler.push(Instr::Imm(self.value.to_number()));
     }
 }
 
 // Bool Node
 #[derive(Debug)]
 pub struct BoolNode {
-    child: Box<dyn Node>,
+    pub child: Box<dyn Node>,
 }
 
 impl BoolNode {
@@ -259,29 +427,46 @@ impl BoolNode {
 }
 
 impl Node for BoolNode {
-    fn as_any(&self) -> &dyn Any {
-        self
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Bool(self)
     }
-    fn eval(&self, vars: &mut HashMap<String, Variable>) -> VariableData {
-        let v = self.child.eval(vars);
+
+    fn eval(&self, vars: &mut Environment) -> Result<VariableData> {
+        let v = self.child.eval(vars)?;
         match v {
-            VariableData::Bool(_) => v,
+            VariableData::Bool(_) => Ok(v),
             _ => panic!("BoolNode"),
         }
     }
+    fn check(&self, checker: &mut Checker) -> Result<VariableKind> {
+        let kind = self.child.check(checker)?;
+        if kind != VariableKind::Bool {
+            bail!("Expected bool, found {:?}", kind);
+        }
+        Ok(kind)
+    }
+
+    fn const_eval(&self) -> Option<Number> {
+        self.child.const_eval()
+    }
+
+    fn is_pure(&self) -> bool {
+        self.child.is_pure()
+    }
+
     fn assemble(
         &self,
         assembler: &mut Assembler,
-        vars: &mut HashMap<String, (VariableKind, usize)>,
-        ebp_offset: &mut usize,
+        vars: &mut Scopes<(VariableKind, Location)>,
+        frame_offset: &mut usize,
     ) {
-        self.child.assemble(assembler, vars, ebp_offset);
+        self.child.assemble(assembler, vars, frame_offset);
     }
 }
 
 #[derive(Debug)]
 pub struct BoolLiteralNode {
-    value: bool,
+    pub value: bool,
 }
 
 impl BoolLiteralNode {
@@ -291,28 +476,40 @@ impl BoolLiteralNode {
 }
 
 impl Node for BoolLiteralNode {
-    fn as_any(&self) -> &dyn Any {
-        self
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::BoolLiteral(self)
+    }
+
+    fn eval(&self, _vars: &mut Environment) -> Result<VariableData> {
+        Ok(VariableData::Bool(self.value))
     }
-    fn eval(&self, _vars: &mut HashMap<String, Variable>) -> VariableData {
-        VariableData::Bool(self.value)
+
+    fn check(&self, _checker: &mut Checker) -> Result<VariableKind> {
+        Ok(VariableKind::Bool)
+    }
+
+    fn const_eval(&self) -> Option<Number> {
+        Some(self.value as Number)
+    }
+
+    fn is_pure(&self) -> bool {
+        true
     }
 
     fn assemble(
         &self,
         assembler: &mut Assembler,
-        _vars: &mut HashMap<String, (VariableKind, usize)>,
-        _ebp_offset: &mut usize,
+        _vars: &mut Scopes<(VariableKind, Location)>,
+        _frame_offset: &mut usize,
     ) {
-        let n: Number = self.value.try_into().unwrap();
-        assembler.push_line(format!("mov ebx, {}", n).as_str());
+        assembler.push(Instr::Imm(self.value.into()));
     }
 }
 
 // String Node
 #[derive(Debug)]
 pub struct StringNode {
-    child: Box<dyn Node>,
+    pub child: Box<dyn Node>,
 }
 
 impl StringNode {
@@ -322,30 +519,40 @@ impl StringNode {
 }
 
 impl Node for StringNode {
-    fn as_any(&self) -> &dyn Any {
-        self
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::String(self)
     }
-    fn eval(&self, vars: &mut HashMap<String, Variable>) -> VariableData {
-        let v = self.child.eval(vars);
+
+    fn eval(&self, vars: &mut Environment) -> Result<VariableData> {
+        let v = self.child.eval(vars)?;
         match v {
-            VariableData::String(_) => v,
+            VariableData::String(_) => Ok(v),
             _ => panic!("StringNode"),
         }
     }
 
+    fn check(&self, checker: &mut Checker) -> Result<VariableKind> {
+        let kind = self.child.check(checker)?;
+        if kind != VariableKind::String {
+            bail!("Expected string, found {:?}", kind);
+        }
+        Ok(kind)
+    }
+
     fn assemble(
         &self,
-        _assembler: &mut Assembler,
-        _vars: &mut HashMap<String, (VariableKind, usize)>,
-        _ebp_offset: &mut usize,
+        assembler: &mut Assembler,
+        vars: &mut Scopes<(VariableKind, Location)>,
+        frame_offset: &mut usize,
     ) {
-        panic!();
+        // Only the arguments of the program are strings there, literals are not supported
+        self.child.assemble(assembler, vars, frame_offset);
     }
 }
 
 #[derive(Debug)]
 pub struct StringLiteralNode {
-    value: String,
+    pub value: String,
 }
 
 impl StringLiteralNode {
@@ -355,28 +562,39 @@ impl StringLiteralNode {
 }
 
 impl Node for StringLiteralNode {
-    fn as_any(&self) -> &dyn Any {
-        self
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::StringLiteral(self)
+    }
+
+    fn eval(&self, _vars: &mut Environment) -> Result<VariableData> {
+        Ok(VariableData::String(self.value.clone()))
+    }
+    fn check(&self, checker: &mut Checker) -> Result<VariableKind> {
+        checker.expect_string_literals()?;
+        Ok(VariableKind::String)
     }
-    fn eval(&self, _vars: &mut HashMap<String, Variable>) -> VariableData {
-        VariableData::String(self.value.clone())
+
+    fn is_pure(&self) -> bool {
+        true
     }
+
     fn assemble(
         &self,
         _assembler: &mut Assembler,
-        _vars: &mut HashMap<String, (VariableKind, usize)>,
-        _ebp_offset: &mut usize,
+        _vars: &mut Scopes<(VariableKind, Location)>,
+        _frame_offset: &mut usize,
     ) {
-        panic!();
+        unreachable!("string literals are rejected by the checker on the assembly targets");
     }
 }
 
 // Declare Node
 #[derive(Debug)]
 pub struct DeclareNode {
-    name: String,
-    expression: Option<Box<dyn Node>>,
-    kind: VariableKind,
+    pub name: String,
+    pub expression: Option<Box<dyn Node>>,
+    pub kind: VariableKind,
+    pub constant: bool,
 }
 impl DeclareNode {
     pub fn new(name: String, expression: Option<Box<dyn Node>>, kind: VariableKind) -> Self {
@@ -384,130 +602,317 @@ impl DeclareNode {
             name,
             expression,
             kind,
+            constant: false,
+        }
+    }
+
+    /// Declaration of a constant, whose uses the parser already replaced by its value
+    pub fn new_const(name: String, expression: Box<dyn Node>, kind: VariableKind) -> Self {
+        Self {
+            name,
+            expression: Some(expression),
+            kind,
+            constant: true,
+        }
+    }
+
+    /// Reserves the storage of a global in `.data`, or `.bss` when it has no initializer
+    pub fn assemble_global(&self, assembler: &mut Assembler) {
+        if self.constant {
+            return;
+        }
+        let value = self.expression.as_ref().and_then(|e| e.const_eval());
+        assembler.declare_global(self.name.clone(), self.kind.clone(), value);
+    }
+}
+
+impl DeclareNode {
+    /// Lays the elements out upwards from the start of the slot, zeroing those not initialized
+    fn assemble_array(
+        &self,
+        assembler: &mut Assembler,
+        vars: &mut Scopes<(VariableKind, Location)>,
+        frame_offset: &mut usize,
+        len: usize,
+    ) {
+        if let Some(e) = &self.expression {
+            e.assemble(assembler, vars, frame_offset);
+        }
+
+        *frame_offset += len;
+        let location = Location::Local(*frame_offset);
+
+        assembler.push(Instr::Zero(location.clone(), len));
+
+        if self.expression.is_some() {
+            // The literal left its elements on the stack and their count in the accumulator
+            assembler.push(Instr::PopArray(location.clone()));
         }
+
+        assembler.declare_local(&self.name, &self.kind, &location, false);
+        vars.declare(self.name.clone(), (self.kind.clone(), location));
     }
 }
 
 impl Node for DeclareNode {
-    fn as_any(&self) -> &dyn Any {
-        self
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Declare(self)
     }
-    fn eval(&self, vars: &mut HashMap<String, Variable>) -> VariableData {
+
+    fn eval(&self, vars: &mut Environment) -> Result<VariableData> {
         let eval = match &self.expression {
-            Some(ex) => Some(ex.eval(vars)),
+            Some(ex) => Some(ex.eval(vars)?),
             None => None,
         };
 
+        let kind = self.kind.clone();
         let v = match eval {
-            Some(VariableData::String(_)) => {
-                assert_eq!(self.kind, VariableKind::String);
-                Variable::new(self.kind, eval)
-            }
-            Some(VariableData::Number(n)) => {
-                if self.kind == VariableKind::Number {
-                    Variable::new(self.kind, eval)
-                } else if self.kind == VariableKind::Bool {
-                    Variable::new(self.kind, Some(VariableData::Bool(n != 0)))
-                } else {
-                    panic!()
-                }
-            }
-
-            Some(VariableData::Bool(b)) => {
-                if self.kind == VariableKind::Number {
-                    Variable::new(self.kind, Some(VariableData::Number(b as Number)))
-                } else if self.kind == VariableKind::Bool {
-                    Variable::new(self.kind, eval)
-                } else {
-                    panic!()
+            Some(VariableData::Array(mut v)) => {
+                // Elements missing from the literal are zeroed
+                match self.kind {
+                    VariableKind::Array(len) => v.resize(len, 0),
+                    _ => panic!(),
                 }
+                Variable::new(kind, Some(VariableData::Array(v)))
             }
-            Some(VariableData::None) => {
-                assert_eq!(self.kind, VariableKind::None);
-                Variable::new(self.kind, eval)
-            }
-            None => Variable::new(self.kind, eval),
+            Some(data) => Variable::new(kind.clone(), Some(Variable::match_data_kind(data, &kind))),
+            None => Variable::new(kind.clone(), kind.zeroed()),
         };
 
-        assert!(vars.get(&self.name).is_none());
         let sn: &str = self.name.as_ref();
         assert_ne!(sn, "println");
         assert_ne!(sn, "readln");
-        vars.insert(self.name.clone(), v);
+        assert!(vars.declare(self.name.clone(), v)?);
         // println!("vars {:#?}", vars);
 
-        VariableData::None
+        Ok(VariableData::None)
+    }
+
+    fn check(&self, checker: &mut Checker) -> Result<VariableKind> {
+        if let Some(e) = &self.expression {
+            let kind = e.check(checker)?;
+            if !is_assignable(&self.kind, &kind) {
+                bail!(
+                    "Cannot initialize {:?} variable {} with {:?}",
+                    self.kind,
+                    self.name,
+                    kind
+                );
+            }
+        }
+        checker.declare(&self.name, self.kind.clone(), self.constant)?;
+        Ok(VariableKind::None)
     }
 
     fn assemble(
         &self,
         assembler: &mut Assembler,
-        vars: &mut HashMap<String, (VariableKind, usize)>,
-        ebp_offset: &mut usize,
+        vars: &mut Scopes<(VariableKind, Location)>,
+        frame_offset: &mut usize,
     ) {
-        assembler.push_line("push DWORD 0");
+        if self.constant {
+            return;
+        }
 
-        if let Some(e) = &self.expression {
-            e.assemble(assembler, vars, ebp_offset);
+        if let VariableKind::Array(len) = self.kind {
+            self.assemble_array(assembler, vars, frame_offset, len);
+            return;
         }
 
-        *ebp_offset += 4;
+        // The slot is reserved by the function prologue, the initializer still sees outer variables
+        if let Some(e) = &self.expression {
+            e.assemble(assembler, vars, frame_offset);
+        }
 
-        vars.insert(self.name.clone(), (self.kind, *ebp_offset));
+        *frame_offset += self.kind.words();
 
+        let location = Location::Local(*frame_offset);
         if self.expression.is_some() {
-            assembler.push_line(format!("mov [ebp - {}], ebx", ebp_offset).as_str());
+            assemble_store(assembler, &location, &self.kind);
+        } else {
+            assemble_zero(assembler, &location, &self.kind);
         }
+        assembler.declare_local(&self.name, &self.kind, &location, false);
+        vars.declare(self.name.clone(), (self.kind.clone(), location));
     }
 }
 
 // Assign Node
 #[derive(Debug)]
 pub struct AssignNode {
-    name: String,
-    expression: Box<dyn Node>,
+    pub name: String,
+    pub op: Option<Op>,
+    /// Where the assignment is, reported when its operator faults
+    pub position: Position,
+    pub expression: Box<dyn Node>,
 }
 impl AssignNode {
-    pub fn new(name: String, expression: Box<dyn Node>) -> Self {
-        Self { name, expression }
+    pub fn new(
+        name: String,
+        op: Option<Op>,
+        position: Position,
+        expression: Box<dyn Node>,
+    ) -> Self {
+        Self {
+            name,
+            op,
+            position,
+            expression,
+        }
     }
 }
 
 impl Node for AssignNode {
-    fn as_any(&self) -> &dyn Any {
-        self
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Assign(self)
     }
-    fn eval(&self, vars: &mut HashMap<String, Variable>) -> VariableData {
-        let eval = self.expression.eval(vars);
+
+    fn eval(&self, vars: &mut Environment) -> Result<VariableData> {
+        let mut eval = self.expression.eval(vars)?;
+        let checked = vars.limits().overflow_checks;
 
         let var = vars.get_mut(&self.name).unwrap();
 
-        let kind = var.kind;
+        let kind = var.kind.clone();
+
+        if let Some(op) = self.op {
+            let current = var.data.as_ref().expect("variable used before assignment");
+            let n = execute(
+                op,
+                current.to_number(),
+                eval.to_number(),
+                checked,
+                self.position,
+            )?;
+            eval = n.into();
+        }
 
-        let v = Variable::match_data_kind(eval, kind);
+        let v = Variable::match_data_kind(eval, &kind);
+        let before = string_bytes(var.data.as_ref());
         var.data = Some(v);
+        let after = string_bytes(var.data.as_ref());
+        vars.resize_strings(before, after)?;
+
+        Ok(VariableData::None)
+    }
+
+    fn check(&self, checker: &mut Checker) -> Result<VariableKind> {
+        let target = checker.lookup_mut(&self.name)?;
+        if let VariableKind::Array(_) = target {
+            bail!("Cannot assign to array {}", self.name);
+        }
+        let kind = self.expression.check(checker)?;
+        if let Some(op) = self.op {
+            let context = format!("operator {}=", op);
+            expect_numeric(&target, &context)?;
+            expect_numeric(&kind, &context)?;
+        } else if !is_assignable(&target, &kind) {
+            bail!(
+                "Cannot assign {:?} to {:?} variable {}",
+                kind,
+                target,
+                self.name
+            );
+        }
+        Ok(VariableKind::None)
+    }
+
+    fn assemble(
+        &self,
+        assembler: &mut Assembler,
+        vars: &mut Scopes<(VariableKind, Location)>,
+        frame_offset: &mut usize,
+    ) {
+        self.expression.assemble(assembler, vars, frame_offset);
+
+        let (kind, location) = vars.get(&self.name).unwrap();
+
+        if let Some(op) = self.op {
+            assembler.push(Instr::LoadOperand(location.clone()));
+            assembler.push_binop(op, self.position);
+        }
+
+        assemble_store(assembler, location, kind);
+    }
+}
+
+// IncDec Node
+#[derive(Debug)]
+pub struct IncDecNode {
+    pub name: String,
+    pub op: Op,
+    /// Where the operator is, reported when it overflows
+    pub position: Position,
+    pub prefix: bool,
+}
+
+impl IncDecNode {
+    pub fn new(name: String, op: Op, position: Position, prefix: bool) -> Self {
+        Self {
+            name,
+            op,
+            position,
+            prefix,
+        }
+    }
+}
+
+impl Node for IncDecNode {
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::IncDec(self)
+    }
 
-        VariableData::None
+    fn eval(&self, vars: &mut Environment) -> Result<VariableData> {
+        let checked = vars.limits().overflow_checks;
+        let var = vars.get_mut(&self.name).unwrap();
+
+        let old = var
+            .data
+            .as_ref()
+            .expect("variable used before assignment")
+            .to_number();
+        let new = execute(self.op, old, 1, checked, self.position)?;
+        var.data = Some(Variable::match_data_kind(new.into(), &var.kind));
+
+        Ok(VariableData::Number(if self.prefix { new } else { old }))
+    }
+
+    fn check(&self, checker: &mut Checker) -> Result<VariableKind> {
+        expect_numeric(&checker.lookup_mut(&self.name)?, "increment/decrement")?;
+        Ok(VariableKind::Number)
     }
 
     fn assemble(
         &self,
         assembler: &mut Assembler,
-        vars: &mut HashMap<String, (VariableKind, usize)>,
-        ebp_offset: &mut usize,
+        vars: &mut Scopes<(VariableKind, Location)>,
+        _frame_offset: &mut usize,
     ) {
-        self.expression.assemble(assembler, vars, ebp_offset);
+        let (_, location) = vars.get(&self.name).unwrap();
 
-        let (_, offset) = vars.get(&self.name).unwrap();
+        let delta = match self.op {
+            Op::Add => 1,
+            Op::Sub => -1,
+            _ => unreachable!(),
+        };
+        let checked = assembler.overflow_check(self.position);
+        let update = Instr::AddTo(location.clone(), delta, checked);
+        let load = Instr::Load(location.clone());
 
-        assembler.push_line(format!("mov [ebp - {}], ebx", offset).as_str());
+        if self.prefix {
+            assembler.push(update);
+            assembler.push(load);
+        } else {
+            assembler.push(load);
+            assembler.push(update);
+        }
     }
 }
 
 // Variable Node
 #[derive(Debug)]
 pub struct VariableNode {
-    name: String,
+    pub name: String,
 }
 impl VariableNode {
     pub fn new(name: String) -> Self {
@@ -516,249 +921,1417 @@ impl VariableNode {
 }
 
 impl Node for VariableNode {
-    fn as_any(&self) -> &dyn Any {
-        self
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Variable(self)
     }
-    fn eval(&self, vars: &mut HashMap<String, Variable>) -> VariableData {
+
+    fn eval(&self, vars: &mut Environment) -> Result<VariableData> {
         let val = vars.get(&self.name);
         // if val.is_none() {
         //     println!("none {:?}", vars);
         // }
 
         let val = val.expect("variable used before assignment");
-        val.data.clone().unwrap()
+        Ok(val.data.clone().unwrap())
+    }
+
+    fn check(&self, checker: &mut Checker) -> Result<VariableKind> {
+        checker.lookup(&self.name)
+    }
+
+    fn is_pure(&self) -> bool {
+        true
     }
 
     fn assemble(
         &self,
         assembler: &mut Assembler,
-        vars: &mut HashMap<String, (VariableKind, usize)>,
-        _ebp_offset: &mut usize,
+        vars: &mut Scopes<(VariableKind, Location)>,
+        _frame_offset: &mut usize,
     ) {
-        let (_, offset) = vars.get(&self.name).unwrap();
-
-        assembler.push_line(format!("mov ebx, [ebp - {}]", offset).as_str());
+        let (kind, location) = vars.get(&self.name).unwrap();
+        assemble_load(assembler, location, kind);
     }
 }
 
-// Cond Node
+// ArrayLiteral Node
 #[derive(Debug)]
-pub struct CondNode {
-    cond: CondOp,
-    left_child: Box<dyn Node>,
-    right_child: Box<dyn Node>,
+pub struct ArrayLiteralNode {
+    pub elements: Vec<Box<dyn Node>>,
 }
 
-impl CondNode {
-    pub fn new(cond: CondOp, left_child: Box<dyn Node>, right_child: Box<dyn Node>) -> Self {
-        CondNode {
-            cond,
-            left_child,
-            right_child,
-        }
+impl ArrayLiteralNode {
+    pub fn new(elements: Vec<Box<dyn Node>>) -> Self {
+        Self { elements }
+    }
+
+    pub fn len(&self) -> usize {
+        self.elements.len()
     }
 }
 
-impl Node for CondNode {
-    fn as_any(&self) -> &dyn Any {
-        self
+impl Node for ArrayLiteralNode {
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::ArrayLiteral(self)
     }
-    fn eval(&self, vars: &mut HashMap<String, Variable>) -> VariableData {
-        let b = match self.cond {
-            CondOp::LT => self.left_child.eval(vars) < self.right_child.eval(vars),
-            CondOp::LEQ => self.left_child.eval(vars) <= self.right_child.eval(vars),
-            CondOp::GT => self.left_child.eval(vars) > self.right_child.eval(vars),
-            CondOp::GEQ => self.left_child.eval(vars) >= self.right_child.eval(vars),
-            CondOp::EQ => self.left_child.eval(vars) == self.right_child.eval(vars),
-            CondOp::NEQ => self.left_child.eval(vars) != self.right_child.eval(vars),
-            CondOp::And => {
-                self.left_child.eval(vars) != VariableData::Number(0)
-                    && self.right_child.eval(vars) != VariableData::Number(0)
-            }
-            CondOp::Or => {
-                self.left_child.eval(vars) != VariableData::Number(0)
-                    || self.right_child.eval(vars) != VariableData::Number(0)
-            }
-        };
-        VariableData::Bool(b)
+
+    fn eval(&self, vars: &mut Environment) -> Result<VariableData> {
+        let elements = self
+            .elements
+            .iter()
+            .map(|e| Ok(e.eval(vars)?.to_number()))
+            .collect::<Result<_>>()?;
+        Ok(VariableData::Array(elements))
+    }
+
+    fn check(&self, checker: &mut Checker) -> Result<VariableKind> {
+        for element in self.elements.iter() {
+            expect_numeric(&element.check(checker)?, "array literal")?;
+        }
+        Ok(VariableKind::Array(self.elements.len()))
     }
+
+    /// Leaves the elements on the stack, the first one on top, and their count in the accumulator
     fn assemble(
         &self,
         assembler: &mut Assembler,
-        vars: &mut HashMap<String, (VariableKind, usize)>,
-        ebp_offset: &mut usize,
+        vars: &mut Scopes<(VariableKind, Location)>,
+        frame_offset: &mut usize,
     ) {
-        self.left_child.assemble(assembler, vars, ebp_offset);
-        assembler.push_line("push ebx");
-        self.right_child.assemble(assembler, vars, ebp_offset);
-        assembler.push_line("pop eax");
-        assembler.push_line("cmp eax, ebx");
+        for element in self.elements.iter().rev() {
+            element.assemble(assembler, vars, frame_offset);
+            assembler.push(Instr::Push);
+        }
+        assembler.push(Instr::Imm(self.elements.len() as Number));
+    }
+}
 
-        let s = match self.cond {
-            CondOp::LT => "call binop_jl",
-            CondOp::LEQ => "call binop_jle",
-            CondOp::GT => "call binop_jg",
-            CondOp::GEQ => "call binop_jge",
-            CondOp::EQ => "call binop_je",
-            CondOp::NEQ => "call binop_jne",
-            CondOp::And => "and eax, ebx",
-            CondOp::Or => "or ebx, eax",
-        };
-        assembler.push_line(s);
+/// Index into an array of the given length, stopping the program like the compiled one does when
+/// it is out of bounds
+fn checked_index(name: &str, index: Number, len: usize, position: Position) -> Result<usize> {
+    if index < 0 || index as usize >= len {
+        let error = RuntimeError::IndexOutOfBounds(index, name.to_string(), len, position);
+        return Err(error.into());
     }
+    Ok(index as usize)
 }
 
-// If Node
+fn expect_array(kind: VariableKind, name: &str) -> Result<usize> {
+    match kind {
+        VariableKind::Array(len) => Ok(len),
+        _ => bail!("Variable {} is not an array", name),
+    }
+}
+
+// Index Node
 #[derive(Debug)]
-pub struct IfNode {
-    cond: Box<dyn Node>,
-    if_child: Box<dyn Node>,
-    else_child: Option<Box<dyn Node>>,
+pub struct IndexNode {
+    pub name: String,
+    pub index: Box<dyn Node>,
+    /// Where the `[` is, reported when the index is out of bounds
+    pub position: Position,
 }
 
-impl IfNode {
-    pub fn new(
-        cond: Box<dyn Node>,
-        if_child: Box<dyn Node>,
-        else_child: Option<Box<dyn Node>>,
-    ) -> Self {
-        IfNode {
-            cond,
-            if_child,
-            else_child,
+impl IndexNode {
+    pub fn new(name: String, index: Box<dyn Node>, position: Position) -> Self {
+        Self {
+            name,
+            index,
+            position,
         }
     }
 }
 
-impl Node for IfNode {
-    fn as_any(&self) -> &dyn Any {
-        self
+impl Node for IndexNode {
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Index(self)
     }
-    fn eval(&self, vars: &mut HashMap<String, Variable>) -> VariableData {
-        if self.cond.eval(vars) != VariableData::Number(0) {
-            self.if_child.eval(vars)
-        } else if let Some(child) = &self.else_child {
-            child.eval(vars)
-        } else {
-            VariableData::None
+
+    fn eval(&self, vars: &mut Environment) -> Result<VariableData> {
+        let index = self.index.eval(vars)?.to_number();
+        match &vars.get(&self.name).unwrap().data {
+            Some(VariableData::Array(v)) => {
+                let index = checked_index(&self.name, index, v.len(), self.position)?;
+                Ok(VariableData::Number(v[index]))
+            }
+            _ => panic!("Indexing non array {}", self.name),
         }
     }
 
+    fn check(&self, checker: &mut Checker) -> Result<VariableKind> {
+        expect_array(checker.lookup(&self.name)?, &self.name)?;
+        expect_numeric(&self.index.check(checker)?, "array index")?;
+        Ok(VariableKind::Number)
+    }
+
     fn assemble(
         &self,
         assembler: &mut Assembler,
-        vars: &mut HashMap<String, (VariableKind, usize)>,
-        ebp_offset: &mut usize,
+        vars: &mut Scopes<(VariableKind, Location)>,
+        frame_offset: &mut usize,
     ) {
-        self.cond.assemble(assembler, vars, ebp_offset);
-        assembler.push_line("cmp ebx, False");
-
-        let id = assembler.next_id();
-        if self.else_child.is_some() {
-            assembler.push_line(format!("je else_{}", id).as_str());
-        } else {
-            assembler.push_line(format!("je end_if_{}", id).as_str());
-        }
+        self.index.assemble(assembler, vars, frame_offset);
 
-        self.if_child.assemble(assembler, vars, ebp_offset);
-
-        if let Some(e) = &self.else_child {
-            assembler.push_line(format!("jmp end_if_{}", id).as_str());
-            assembler.push_line(format!("else_{}:", id).as_str());
-            e.assemble(assembler, vars, ebp_offset);
-        }
-        assembler.push_line(format!("end_if_{}:", id).as_str());
+        let (kind, location) = vars.get(&self.name).unwrap();
+        let len = match kind {
+            VariableKind::Array(len) => *len,
+            _ => unreachable!(),
+        };
+        assembler.push(Instr::BoundsCheck(len));
+        assembler.push(Instr::LoadElement(location.clone()));
     }
 }
 
-// While Node
+// IndexAssign Node
 #[derive(Debug)]
-pub struct WhileNode {
-    cond: Box<dyn Node>,
-    child: Box<dyn Node>,
+pub struct IndexAssignNode {
+    pub name: String,
+    pub index: Box<dyn Node>,
+    pub op: Option<Op>,
+    /// Where the assignment is, reported when its operator faults or the index is out of bounds
+    pub position: Position,
+    pub expression: Box<dyn Node>,
 }
 
-impl WhileNode {
-    pub fn new(cond: Box<dyn Node>, child: Box<dyn Node>) -> Self {
-        WhileNode { cond, child }
+impl IndexAssignNode {
+    pub fn new(
+        name: String,
+        index: Box<dyn Node>,
+        op: Option<Op>,
+        position: Position,
+        expression: Box<dyn Node>,
+    ) -> Self {
+        Self {
+            name,
+            index,
+            op,
+            position,
+            expression,
+        }
     }
 }
 
-impl Node for WhileNode {
-    fn as_any(&self) -> &dyn Any {
-        self
+impl Node for IndexAssignNode {
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::IndexAssign(self)
     }
-    fn eval(&self, vars: &mut HashMap<String, Variable>) -> VariableData {
-        while self.cond.eval(vars) != VariableData::Number(0) {
-            self.child.eval(vars);
+
+    fn eval(&self, vars: &mut Environment) -> Result<VariableData> {
+        let eval = self.expression.eval(vars)?.to_number();
+        let index = self.index.eval(vars)?.to_number();
+        let checked = vars.limits().overflow_checks;
+
+        match &mut vars.get_mut(&self.name).unwrap().data {
+            Some(VariableData::Array(v)) => {
+                let index = checked_index(&self.name, index, v.len(), self.position)?;
+                let element = &mut v[index];
+                *element = match self.op {
+                    Some(op) => execute(op, *element, eval, checked, self.position)?,
+                    None => eval,
+                };
+            }
+            _ => panic!("Indexing non array {}", self.name),
         }
-        VariableData::None
+
+        Ok(VariableData::None)
+    }
+
+    fn check(&self, checker: &mut Checker) -> Result<VariableKind> {
+        expect_array(checker.lookup(&self.name)?, &self.name)?;
+        expect_numeric(&self.index.check(checker)?, "array index")?;
+        expect_numeric(&self.expression.check(checker)?, "array element assignment")?;
+        Ok(VariableKind::None)
+    }
+
+    fn assemble(
+        &self,
+        assembler: &mut Assembler,
+        vars: &mut Scopes<(VariableKind, Location)>,
+        frame_offset: &mut usize,
+    ) {
+        self.expression.assemble(assembler, vars, frame_offset);
+        assembler.push(Instr::Push);
+        self.index.assemble(assembler, vars, frame_offset);
+
+        let (kind, location) = vars.get(&self.name).unwrap();
+        let len = match kind {
+            VariableKind::Array(len) => *len,
+            _ => unreachable!(),
+        };
+        assembler.push(Instr::BoundsCheck(len));
+
+        assembler.push(Instr::SetIndex);
+        assembler.push(Instr::Pop);
+        if let Some(op) = self.op {
+            assembler.push(Instr::LoadOperandElement(location.clone()));
+            assembler.push_binop(op, self.position);
+        }
+        assembler.push(Instr::StoreElement(location.clone()));
+    }
+}
+
+// Len Node
+#[derive(Debug)]
+pub struct LenNode {
+    pub name: String,
+}
+
+impl LenNode {
+    pub fn new(name: String) -> Self {
+        Self { name }
+    }
+}
+
+impl Node for LenNode {
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Len(self)
+    }
+
+    fn eval(&self, vars: &mut Environment) -> Result<VariableData> {
+        match vars.get(&self.name).unwrap().kind {
+            VariableKind::Array(len) => Ok(VariableData::Number(len as Number)),
+            _ => panic!("len of non array {}", self.name),
+        }
+    }
+
+    fn check(&self, checker: &mut Checker) -> Result<VariableKind> {
+        expect_array(checker.lookup(&self.name)?, &self.name)?;
+        Ok(VariableKind::Number)
+    }
+
+    fn is_pure(&self) -> bool {
+        true
+    }
+
+    fn assemble(
+        &self,
+        assembler: &mut Assembler,
+        vars: &mut Scopes<(VariableKind, Location)>,
+        _frame_offset: &mut usize,
+    ) {
+        let (kind, _) = vars.get(&self.name).unwrap();
+        if let VariableKind::Array(len) = kind {
+            assembler.push(Instr::Imm(*len as Number));
+        }
+    }
+}
+
+/// Loads a value into the accumulator, structs being handled through their address
+fn assemble_load(assembler: &mut Assembler, location: &Location, kind: &VariableKind) {
+    match kind {
+        VariableKind::Struct(_) => assembler.push(Instr::LoadAddress(location.clone())),
+        _ => assembler.push(Instr::Load(location.clone())),
+    }
+}
+
+/// Stores the accumulator, copying the struct it points to for struct kinds
+fn assemble_store(assembler: &mut Assembler, location: &Location, kind: &VariableKind) {
+    match kind {
+        VariableKind::Struct(t) => assembler.push(Instr::Copy(location.clone(), t.words())),
+        _ => assembler.push(Instr::Store(location.clone())),
+    }
+}
+
+/// Gives a variable declared without an initializer its starting value
+fn assemble_zero(assembler: &mut Assembler, location: &Location, kind: &VariableKind) {
+    assembler.push(Instr::Zero(location.clone(), kind.words()));
+    for (offset, value) in kind.initial_words() {
+        assembler.push(Instr::Imm(value));
+        assembler.push(Instr::Store(location.field(offset)));
+    }
+}
+
+/// Kind of the field at the end of `path` inside a variable of kind `kind`, along with the index
+/// of every field on the way and the offset in words of the last one
+pub fn resolve_field(
+    kind: &VariableKind,
+    path: &[String],
+) -> Result<(Vec<usize>, usize, VariableKind)> {
+    let mut indexes = vec![];
+    let mut offset = 0;
+    let mut kind = kind.clone();
+    for field in path.iter() {
+        let t = match &kind {
+            VariableKind::Struct(t) => t.clone(),
+            _ => bail!("Cannot access field {} of {:?}", field, kind),
+        };
+        match t.field(field) {
+            Some((idx, field_offset, field_kind)) => {
+                indexes.push(idx);
+                offset += field_offset;
+                kind = field_kind.clone();
+            }
+            None => bail!("Struct {} has no field {}", t.name, field),
+        }
+    }
+    Ok((indexes, offset, kind))
+}
+
+// StructLiteral Node
+#[derive(Debug)]
+pub struct StructLiteralNode {
+    pub kind: Rc<StructType>,
+    pub fields: Vec<Box<dyn Node>>,
+}
+
+impl StructLiteralNode {
+    pub fn new(kind: Rc<StructType>, fields: Vec<Box<dyn Node>>) -> Self {
+        Self { kind, fields }
+    }
+}
+
+impl Node for StructLiteralNode {
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::StructLiteral(self)
+    }
+
+    fn eval(&self, vars: &mut Environment) -> Result<VariableData> {
+        let fields = self
+            .fields
+            .iter()
+            .zip(self.kind.fields.iter())
+            .map(|(field, (_, kind))| Ok(Variable::match_data_kind(field.eval(vars)?, kind)))
+            .collect::<Result<_>>()?;
+        Ok(VariableData::Struct(fields))
+    }
+
+    fn check(&self, checker: &mut Checker) -> Result<VariableKind> {
+        for (field, (name, kind)) in self.fields.iter().zip(self.kind.fields.iter()) {
+            let value = field.check(checker)?;
+            if !is_assignable(kind, &value) {
+                bail!(
+                    "Cannot initialize field {} of {} with {:?}",
+                    name,
+                    self.kind.name,
+                    value
+                );
+            }
+        }
+        Ok(VariableKind::Struct(self.kind.clone()))
+    }
+
+    /// Builds the struct in a temporary of the current frame and leaves its address in the accumulator
+    fn assemble(
+        &self,
+        assembler: &mut Assembler,
+        vars: &mut Scopes<(VariableKind, Location)>,
+        frame_offset: &mut usize,
+    ) {
+        *frame_offset += self.kind.words();
+        let temporary = Location::Local(*frame_offset);
+
+        for (field, (name, kind)) in self.fields.iter().zip(self.kind.fields.iter()) {
+            field.assemble(assembler, vars, frame_offset);
+            let (_, offset, _) = self.kind.field(name).unwrap();
+            assemble_store(assembler, &temporary.field(offset), kind);
+        }
+        assembler.push(Instr::LoadAddress(temporary));
+    }
+}
+
+// Field Node
+#[derive(Debug)]
+pub struct FieldNode {
+    pub name: String,
+    pub path: Vec<String>,
+}
+
+impl FieldNode {
+    pub fn new(name: String, path: Vec<String>) -> Self {
+        Self { name, path }
+    }
+}
+
+impl Node for FieldNode {
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Field(self)
+    }
+
+    fn eval(&self, vars: &mut Environment) -> Result<VariableData> {
+        let var = vars.get(&self.name).unwrap();
+        let (indexes, _, _) = resolve_field(&var.kind, &self.path).unwrap();
+
+        let mut data = var.data.as_ref().unwrap();
+        for idx in indexes {
+            data = match data {
+                VariableData::Struct(fields) => &fields[idx],
+                _ => unreachable!(),
+            };
+        }
+        Ok(data.clone())
+    }
+
+    fn check(&self, checker: &mut Checker) -> Result<VariableKind> {
+        let (_, _, kind) = resolve_field(&checker.lookup(&self.name)?, &self.path)?;
+        Ok(kind)
+    }
+
+    fn is_pure(&self) -> bool {
+        true
+    }
+
+    fn assemble(
+        &self,
+        assembler: &mut Assembler,
+        vars: &mut Scopes<(VariableKind, Location)>,
+        _frame_offset: &mut usize,
+    ) {
+        let (kind, location) = vars.get(&self.name).unwrap();
+        let (_, offset, kind) = resolve_field(kind, &self.path).unwrap();
+        assemble_load(assembler, &location.field(offset), &kind);
+    }
+}
+
+// FieldAssign Node
+#[derive(Debug)]
+pub struct FieldAssignNode {
+    pub name: String,
+    pub path: Vec<String>,
+    pub op: Option<Op>,
+    /// Where the assignment is, reported when its operator faults
+    pub position: Position,
+    pub expression: Box<dyn Node>,
+}
+
+impl FieldAssignNode {
+    pub fn new(
+        name: String,
+        path: Vec<String>,
+        op: Option<Op>,
+        position: Position,
+        expression: Box<dyn Node>,
+    ) -> Self {
+        Self {
+            name,
+            path,
+            op,
+            position,
+            expression,
+        }
+    }
+}
+
+impl Node for FieldAssignNode {
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::FieldAssign(self)
+    }
+
+    fn eval(&self, vars: &mut Environment) -> Result<VariableData> {
+        let eval = self.expression.eval(vars)?;
+        let checked = vars.limits().overflow_checks;
+
+        let var = vars.get_mut(&self.name).unwrap();
+        let (indexes, _, kind) = resolve_field(&var.kind, &self.path).unwrap();
+
+        let before = string_bytes(var.data.as_ref());
+        let mut data = var.data.as_mut().unwrap();
+        for idx in indexes {
+            data = match data {
+                VariableData::Struct(fields) => &mut fields[idx],
+                _ => unreachable!(),
+            };
+        }
+        *data = match self.op {
+            Some(op) => {
+                let n = execute(
+                    op,
+                    data.to_number(),
+                    eval.to_number(),
+                    checked,
+                    self.position,
+                )?;
+                Variable::match_data_kind(n.into(), &kind)
+            }
+            None => Variable::match_data_kind(eval, &kind),
+        };
+        let after = string_bytes(var.data.as_ref());
+        vars.resize_strings(before, after)?;
+
+        Ok(VariableData::None)
+    }
+
+    fn check(&self, checker: &mut Checker) -> Result<VariableKind> {
+        let (_, _, target) = resolve_field(&checker.lookup_mut(&self.name)?, &self.path)?;
+        let kind = self.expression.check(checker)?;
+        if let Some(op) = self.op {
+            let context = format!("operator {}=", op);
+            expect_numeric(&target, &context)?;
+            expect_numeric(&kind, &context)?;
+        } else if !is_assignable(&target, &kind) {
+            bail!(
+                "Cannot assign {:?} to {:?} field {}.{}",
+                kind,
+                target,
+                self.name,
+                self.path.join(".")
+            );
+        }
+        Ok(VariableKind::None)
+    }
+
+    fn assemble(
+        &self,
+        assembler: &mut Assembler,
+        vars: &mut Scopes<(VariableKind, Location)>,
+        frame_offset: &mut usize,
+    ) {
+        self.expression.assemble(assembler, vars, frame_offset);
+
+        let (kind, location) = vars.get(&self.name).unwrap();
+        let (_, offset, kind) = resolve_field(kind, &self.path).unwrap();
+        let location = location.field(offset);
+
+        if let Some(op) = self.op {
+            assembler.push(Instr::LoadOperand(location.clone()));
+            assembler.push_binop(op, self.position);
+        }
+        assemble_store(assembler, &location, &kind);
+    }
+}
+
+/// `Enum::Variant`, lowered to its int value
+#[derive(Debug)]
+pub struct EnumVariantNode {
+    pub kind: Rc<EnumType>,
+    pub value: Number,
+}
+
+impl EnumVariantNode {
+    pub fn new(kind: Rc<EnumType>, value: Number) -> Self {
+        Self { kind, value }
+    }
+}
+
+impl Node for EnumVariantNode {
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::EnumVariant(self)
+    }
+
+    fn eval(&self, _vars: &mut Environment) -> Result<VariableData> {
+        Ok(VariableData::Number(self.value))
+    }
+
+    fn check(&self, _checker: &mut Checker) -> Result<VariableKind> {
+        Ok(VariableKind::Enum(self.kind.clone()))
+    }
+
+    fn const_eval(&self) -> Option<Number> {
+        Some(self.value)
+    }
+
+    fn is_pure(&self) -> bool {
+        true
+    }
+
+    fn assemble(
+        &self,
+        assembler: &mut Assembler,
+        _vars: &mut Scopes<(VariableKind, Location)>,
+        _frame_offset: &mut usize,
+    ) {
+        assembler.push(Instr::Imm(self.value))
+    }
+}
+
+// Cond Node
+#[derive(Debug)]
+pub struct CondNode {
+    pub cond: CondOp,
+    pub left_child: Box<dyn Node>,
+    pub right_child: Box<dyn Node>,
+}
+
+impl CondNode {
+    pub fn new(cond: CondOp, left_child: Box<dyn Node>, right_child: Box<dyn Node>) -> Self {
+        CondNode {
+            cond,
+            left_child,
+            right_child,
+        }
+    }
+}
+
+impl Node for CondNode {
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Cond(self)
+    }
+
+    fn eval(&self, vars: &mut Environment) -> Result<VariableData> {
+        let b = match self.cond {
+            CondOp::LT => self.left_child.eval(vars)? < self.right_child.eval(vars)?,
+            CondOp::LEQ => self.left_child.eval(vars)? <= self.right_child.eval(vars)?,
+            CondOp::GT => self.left_child.eval(vars)? > self.right_child.eval(vars)?,
+            CondOp::GEQ => self.left_child.eval(vars)? >= self.right_child.eval(vars)?,
+            CondOp::EQ => self.left_child.eval(vars)? == self.right_child.eval(vars)?,
+            CondOp::NEQ => self.left_child.eval(vars)? != self.right_child.eval(vars)?,
+            CondOp::And => {
+                self.left_child.eval(vars)? != VariableData::Number(0)
+                    && self.right_child.eval(vars)? != VariableData::Number(0)
+            }
+            CondOp::Or => {
+                self.left_child.eval(vars)? != VariableData::Number(0)
+                    || self.right_child.eval(vars)? != VariableData::Number(0)
+            }
+        };
+        Ok(VariableData::Bool(b))
+    }
+    fn check(&self, checker: &mut Checker) -> Result<VariableKind> {
+        let lhs = self.left_child.check(checker)?;
+        let rhs = self.right_child.check(checker)?;
+        let strings = lhs == VariableKind::String && rhs == VariableKind::String;
+        if strings {
+            checker.expect_string_comparison()?;
+        }
+        let enums = matches!(lhs, VariableKind::Enum(_)) && lhs == rhs;
+        if !(enums || strings && matches!(self.cond, CondOp::EQ | CondOp::NEQ)) {
+            let context = format!("operator {}", self.cond);
+            expect_numeric(&lhs, &context)?;
+            expect_numeric(&rhs, &context)?;
+        }
+        Ok(VariableKind::Bool)
+    }
+
+    fn const_eval(&self) -> Option<Number> {
+        let lhs = self.left_child.const_eval()?;
+        let rhs = self.right_child.const_eval()?;
+        let b = match self.cond {
+            CondOp::LT => lhs < rhs,
+            CondOp::LEQ => lhs <= rhs,
+            CondOp::GT => lhs > rhs,
+            CondOp::GEQ => lhs >= rhs,
+            CondOp::EQ => lhs == rhs,
+            CondOp::NEQ => lhs != rhs,
+            CondOp::And => lhs != 0 && rhs != 0,
+            CondOp::Or => lhs != 0 || rhs != 0,
+        };
+        Some(b as Number)
+    }
+
+    fn is_pure(&self) -> bool {
+        self.left_child.is_pure() && self.right_child.is_pure()
+    }
+
+    fn assemble(
+        &self,
+        assembler: &mut Assembler,
+        vars: &mut Scopes<(VariableKind, Location)>,
+        frame_offset: &mut usize,
+    ) {
+        self.left_child.assemble(assembler, vars, frame_offset);
+
+        // The right side of && and || only runs when the left one does not decide the result,
+        // which matters unless it can neither fail nor modify any state
+        let pure = self.right_child.is_pure() && !assembler.checks_overflow();
+        let id = match self.cond {
+            CondOp::And | CondOp::Or if !pure => Some(assembler.next_id()),
+            _ => None,
+        };
+        match (self.cond, id) {
+            (CondOp::And, Some(id)) => {
+                // A false left side is already the result, 0
+                assembler.push(Instr::JumpIfFalse(format!("cond_end_{}", id)));
+            }
+            (CondOp::Or, Some(id)) => {
+                assembler.push(Instr::JumpIfFalse(format!("cond_right_{}", id)));
+                assembler.push(Instr::Imm(1));
+                assembler.push(Instr::Jump(format!("cond_end_{}", id)));
+                assembler.push(Instr::Label(format!("cond_right_{}", id)));
+            }
+            _ => {}
+        }
+
+        assembler.push(Instr::Push);
+        self.right_child.assemble(assembler, vars, frame_offset);
+        assembler.push(Instr::PopOperand);
+        assembler.push(Instr::Compare(self.cond));
+        if let Some(id) = id {
+            assembler.push(Instr::Label(format!("cond_end_{}", id)));
+        }
+    }
+}
+
+// Ternary Node
+#[derive(Debug)]
+pub struct TernaryNode {
+    pub cond: Box<dyn Node>,
+    pub if_child: Box<dyn Node>,
+    pub else_child: Box<dyn Node>,
+}
+
+impl TernaryNode {
+    pub fn new(cond: Box<dyn Node>, if_child: Box<dyn Node>, else_child: Box<dyn Node>) -> Self {
+        TernaryNode {
+            cond,
+            if_child,
+            else_child,
+        }
+    }
+}
+
+impl Node for TernaryNode {
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Ternary(self)
+    }
+
+    fn eval(&self, vars: &mut Environment) -> Result<VariableData> {
+        if self.cond.eval(vars)? != VariableData::Number(0) {
+            self.if_child.eval(vars)
+        } else {
+            self.else_child.eval(vars)
+        }
+    }
+
+    fn check(&self, checker: &mut Checker) -> Result<VariableKind> {
+        expect_numeric(&self.cond.check(checker)?, "conditional expression")?;
+        let if_kind = self.if_child.check(checker)?;
+        let else_kind = self.else_child.check(checker)?;
+        if if_kind != else_kind {
+            bail!(
+                "Conditional expression arms differ: {:?} and {:?}",
+                if_kind,
+                else_kind
+            );
+        }
+        Ok(if_kind)
+    }
+
+    fn const_eval(&self) -> Option<Number> {
+        if self.cond.const_eval()? != 0 {
+            self.if_child.const_eval()
+        } else {
+            self.else_child.const_eval()
+        }
+    }
+
+    fn is_pure(&self) -> bool {
+        self.cond.is_pure() && self.if_child.is_pure() && self.else_child.is_pure()
+    }
+
+    fn assemble(
+        &self,
+        assembler: &mut Assembler,
+        vars: &mut Scopes<(VariableKind, Location)>,
+        frame_offset: &mut usize,
+    ) {
+        // Checked arithmetic can fail, so with overflow checks only the arm taken is evaluated
+        let pure = self.if_child.is_pure() && self.else_child.is_pure();
+        if pure && !assembler.checks_overflow() {
+            // Both arms can be evaluated unconditionally, select with cmov instead of branching
+            self.cond.assemble(assembler, vars, frame_offset);
+            assembler.push(Instr::Push);
+            self.if_child.assemble(assembler, vars, frame_offset);
+            assembler.push(Instr::Push);
+            self.else_child.assemble(assembler, vars, frame_offset);
+            assembler.push(Instr::Select);
+            return;
+        }
+
+        let id = assembler.next_id();
+        self.cond.assemble(assembler, vars, frame_offset);
+        assembler.push(Instr::JumpIfFalse(format!("ternary_else_{}", id)));
+        self.if_child.assemble(assembler, vars, frame_offset);
+        assembler.push(Instr::Jump(format!("ternary_end_{}", id)));
+        assembler.push(Instr::Label(format!("ternary_else_{}", id)));
+        self.else_child.assemble(assembler, vars, frame_offset);
+        assembler.push(Instr::Label(format!("ternary_end_{}", id)));
+    }
+}
+
+// If Node
+#[derive(Debug)]
+pub struct IfNode {
+    pub cond: Box<dyn Node>,
+    pub if_child: Box<dyn Node>,
+    pub else_child: Option<Box<dyn Node>>,
+}
+
+impl IfNode {
+    pub fn new(
+        cond: Box<dyn Node>,
+        if_child: Box<dyn Node>,
+        else_child: Option<Box<dyn Node>>,
+    ) -> Self {
+        IfNode {
+            cond,
+            if_child,
+            else_child,
+        }
+    }
+}
+
+impl Node for IfNode {
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::If(self)
+    }
+
+    fn eval(&self, _vars: &mut Environment) -> Result<VariableData> {
+        unreachable!("statement evaluated as an expression")
+    }
+
+    fn exec(&self, vars: &mut Environment) -> Result<Flow> {
+        if self.cond.eval(vars)? != VariableData::Number(0) {
+            self.if_child.exec(vars)
+        } else if let Some(child) = &self.else_child {
+            child.exec(vars)
+        } else {
+            Ok(Flow::Normal)
+        }
+    }
+
+    fn check(&self, checker: &mut Checker) -> Result<VariableKind> {
+        expect_numeric(&self.cond.check(checker)?, "if condition")?;
+        self.if_child.check(checker)?;
+        if let Some(e) = &self.else_child {
+            e.check(checker)?;
+        }
+        Ok(VariableKind::None)
+    }
+
+    fn assemble(
+        &self,
+        assembler: &mut Assembler,
+        vars: &mut Scopes<(VariableKind, Location)>,
+        frame_offset: &mut usize,
+    ) {
+        self.cond.assemble(assembler, vars, frame_offset);
+
+        let id = assembler.next_id();
+        if self.else_child.is_some() {
+            assembler.push(Instr::JumpIfFalse(format!("else_{}", id)));
+        } else {
+            assembler.push(Instr::JumpIfFalse(format!("end_if_{}", id)));
+        }
+
+        self.if_child.assemble(assembler, vars, frame_offset);
+
+        if let Some(e) = &self.else_child {
+            assembler.push(Instr::Jump(format!("end_if_{}", id)));
+            assembler.push(Instr::Label(format!("else_{}", id)));
+            e.assemble(assembler, vars, frame_offset);
+        }
+        assembler.push(Instr::Label(format!("end_if_{}", id)));
+    }
+}
+
+// While Node
+#[derive(Debug)]
+pub struct WhileNode {
+    pub cond: Box<dyn Node>,
+    pub child: Box<dyn Node>,
+}
+
+impl WhileNode {
+    pub fn new(cond: Box<dyn Node>, child: Box<dyn Node>) -> Self {
+        WhileNode { cond, child }
+    }
+}
+
+impl Node for WhileNode {
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::While(self)
+    }
+
+    fn eval(&self, _vars: &mut Environment) -> Result<VariableData> {
+        unreachable!("statement evaluated as an expression")
+    }
+
+    fn exec(&self, vars: &mut Environment) -> Result<Flow> {
+        while self.cond.eval(vars)? != VariableData::Number(0) {
+            vars.step()?;
+            match self.child.exec(vars)? {
+                Flow::Break => break,
+                Flow::Return(v) => return Ok(Flow::Return(v)),
+                Flow::Normal | Flow::Continue => {}
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn check(&self, checker: &mut Checker) -> Result<VariableKind> {
+        expect_numeric(&self.cond.check(checker)?, "while condition")?;
+        self.child.check(checker)?;
+        Ok(VariableKind::None)
+    }
+
+    fn assemble(
+        &self,
+        assembler: &mut Assembler,
+        vars: &mut Scopes<(VariableKind, Location)>,
+        frame_offset: &mut usize,
+    ) {
+        let id = assembler.next_id();
+        assembler.push(Instr::Label(format!("while_{}", id)));
+        self.cond.assemble(assembler, vars, frame_offset);
+        assembler.push(Instr::JumpIfFalse(format!("while_end_{}", id)));
+        assembler.push_loop(format!("while_{}", id), format!("while_end_{}", id));
+        self.child.assemble(assembler, vars, frame_offset);
+        assembler.pop_loop();
+        assembler.push(Instr::Jump(format!("while_{}", id)));
+        assembler.push(Instr::Label(format!("while_end_{}", id)));
+    }
+}
+
+// For Node
+#[derive(Debug)]
+pub struct ForNode {
+    pub init: Option<Box<dyn Node>>,
+    pub cond: Option<Box<dyn Node>>,
+    pub step: Option<Box<dyn Node>>,
+    pub child: Box<dyn Node>,
+}
+
+impl ForNode {
+    pub fn new(
+        init: Option<Box<dyn Node>>,
+        cond: Option<Box<dyn Node>>,
+        step: Option<Box<dyn Node>>,
+        child: Box<dyn Node>,
+    ) -> Self {
+        ForNode {
+            init,
+            cond,
+            step,
+            child,
+        }
+    }
+}
+
+impl Node for ForNode {
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::For(self)
+    }
+
+    fn eval(&self, _vars: &mut Environment) -> Result<VariableData> {
+        unreachable!("statement evaluated as an expression")
+    }
+
+    fn exec(&self, vars: &mut Environment) -> Result<Flow> {
+        // Variables declared in the initializer only live for the loop
+        vars.push_scope();
+        let flow = self.exec_loop(vars)?;
+        vars.pop_scope();
+        Ok(flow)
+    }
+
+    fn check(&self, checker: &mut Checker) -> Result<VariableKind> {
+        checker.push_scope();
+        if let Some(init) = &self.init {
+            init.check(checker)?;
+        }
+        if let Some(cond) = &self.cond {
+            expect_numeric(&cond.check(checker)?, "for condition")?;
+        }
+        if let Some(step) = &self.step {
+            step.check(checker)?;
+        }
+        self.child.check(checker)?;
+        checker.pop_scope();
+        Ok(VariableKind::None)
+    }
+
+    fn assemble(
+        &self,
+        assembler: &mut Assembler,
+        vars: &mut Scopes<(VariableKind, Location)>,
+        frame_offset: &mut usize,
+    ) {
+        let id = assembler.next_id();
+        vars.push_scope();
+        if let Some(init) = &self.init {
+            init.assemble(assembler, vars, frame_offset);
+        }
+        assembler.push(Instr::Label(format!("for_{}", id)));
+        if let Some(cond) = &self.cond {
+            cond.assemble(assembler, vars, frame_offset);
+            assembler.push(Instr::JumpIfFalse(format!("for_end_{}", id)));
+        }
+        assembler.push_loop(format!("for_step_{}", id), format!("for_end_{}", id));
+        self.child.assemble(assembler, vars, frame_offset);
+        assembler.pop_loop();
+        assembler.push(Instr::Label(format!("for_step_{}", id)));
+        if let Some(step) = &self.step {
+            step.assemble(assembler, vars, frame_offset);
+        }
+        assembler.push(Instr::Jump(format!("for_{}", id)));
+        assembler.push(Instr::Label(format!("for_end_{}", id)));
+        vars.pop_scope();
+    }
+}
+
+impl ForNode {
+    fn exec_loop(&self, vars: &mut Environment) -> Result<Flow> {
+        if let Some(init) = &self.init {
+            init.exec(vars)?;
+        }
+        loop {
+            if let Some(cond) = &self.cond {
+                if cond.eval(vars)? == VariableData::Number(0) {
+                    break;
+                }
+            }
+            vars.step()?;
+            match self.child.exec(vars)? {
+                Flow::Break => break,
+                Flow::Return(v) => return Ok(Flow::Return(v)),
+                Flow::Normal | Flow::Continue => {}
+            }
+            if let Some(step) = &self.step {
+                step.exec(vars)?;
+            }
+        }
+        Ok(Flow::Normal)
+    }
+}
+
+// DoWhile Node
+#[derive(Debug)]
+pub struct DoWhileNode {
+    pub cond: Box<dyn Node>,
+    pub child: Box<dyn Node>,
+}
+
+impl DoWhileNode {
+    pub fn new(cond: Box<dyn Node>, child: Box<dyn Node>) -> Self {
+        DoWhileNode { cond, child }
+    }
+}
+
+impl Node for DoWhileNode {
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::DoWhile(self)
+    }
+
+    fn eval(&self, _vars: &mut Environment) -> Result<VariableData> {
+        unreachable!("statement evaluated as an expression")
+    }
+
+    fn exec(&self, vars: &mut Environment) -> Result<Flow> {
+        loop {
+            vars.step()?;
+            match self.child.exec(vars)? {
+                Flow::Break => break,
+                Flow::Return(v) => return Ok(Flow::Return(v)),
+                Flow::Normal | Flow::Continue => {}
+            }
+            if self.cond.eval(vars)? == VariableData::Number(0) {
+                break;
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn check(&self, checker: &mut Checker) -> Result<VariableKind> {
+        self.child.check(checker)?;
+        expect_numeric(&self.cond.check(checker)?, "do-while condition")?;
+        Ok(VariableKind::None)
+    }
+
+    fn assemble(
+        &self,
+        assembler: &mut Assembler,
+        vars: &mut Scopes<(VariableKind, Location)>,
+        frame_offset: &mut usize,
+    ) {
+        let id = assembler.next_id();
+        assembler.push(Instr::Label(format!("do_{}", id)));
+        assembler.push_loop(format!("do_cond_{}", id), format!("do_end_{}", id));
+        self.child.assemble(assembler, vars, frame_offset);
+        assembler.pop_loop();
+        assembler.push(Instr::Label(format!("do_cond_{}", id)));
+        self.cond.assemble(assembler, vars, frame_offset);
+        assembler.push(Instr::JumpIfTrue(format!("do_{}", id)));
+        assembler.push(Instr::Label(format!("do_end_{}", id)));
+    }
+}
+
+// Switch Node
+/// Case label (None for default) and the statements following it
+pub type SwitchCase = (Option<Number>, Vec<Box<dyn Node>>);
+
+#[derive(Debug)]
+pub struct SwitchNode {
+    pub expression: Box<dyn Node>,
+    pub cases: Vec<SwitchCase>,
+}
+
+impl SwitchNode {
+    pub fn new(expression: Box<dyn Node>, cases: Vec<SwitchCase>) -> Self {
+        SwitchNode { expression, cases }
+    }
+}
+
+impl Node for SwitchNode {
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Switch(self)
+    }
+
+    fn eval(&self, _vars: &mut Environment) -> Result<VariableData> {
+        unreachable!("statement evaluated as an expression")
+    }
+
+    fn exec(&self, vars: &mut Environment) -> Result<Flow> {
+        let value = self.expression.eval(vars)?.to_number();
+
+        let start = self
+            .cases
+            .iter()
+            .position(|(label, _)| *label == Some(value))
+            .or_else(|| self.cases.iter().position(|(label, _)| label.is_none()));
+
+        // The cases share a single scope
+        vars.push_scope();
+        let flow = match start {
+            Some(start) => self.exec_from(start, vars)?,
+            None => Flow::Normal,
+        };
+        vars.pop_scope();
+        Ok(flow)
+    }
+
+    fn check(&self, checker: &mut Checker) -> Result<VariableKind> {
+        let kind = self.expression.check(checker)?;
+        match &kind {
+            VariableKind::Enum(t) => self.check_enum_cases(t)?,
+            _ => expect_numeric(&kind, "switch expression")?,
+        }
+
+        checker.push_scope();
+        let mut seen = vec![];
+        for (label, children) in self.cases.iter() {
+            if seen.contains(label) {
+                match label {
+                    Some(n) => bail!("Duplicate case {} in switch", n),
+                    None => bail!("Multiple default labels in switch"),
+                }
+            }
+            seen.push(*label);
+
+            for child in children.iter() {
+                child.check(checker)?;
+            }
+        }
+        checker.pop_scope();
+        Ok(VariableKind::None)
     }
 
     fn assemble(
         &self,
         assembler: &mut Assembler,
-        vars: &mut HashMap<String, (VariableKind, usize)>,
-        ebp_offset: &mut usize,
+        vars: &mut Scopes<(VariableKind, Location)>,
+        frame_offset: &mut usize,
     ) {
         let id = assembler.next_id();
-        assembler.push_line(format!("while_{}:", id).as_str());
-        self.cond.assemble(assembler, vars, ebp_offset);
-        assembler.push_line("cmp ebx, False");
-        assembler.push_line(format!("je while_end_{}", id).as_str());
-        self.child.assemble(assembler, vars, ebp_offset);
-        assembler.push_line(format!("jmp while_{}", id).as_str());
-        assembler.push_line(format!("while_end_{}:", id).as_str());
+        let end_label = format!("switch_end_{}", id);
+        let case_label = |idx: usize| format!("switch_{}_case_{}", id, idx);
+
+        self.expression.assemble(assembler, vars, frame_offset);
+
+        let default_label = match self.cases.iter().position(|(label, _)| label.is_none()) {
+            Some(idx) => case_label(idx),
+            None => end_label.clone(),
+        };
+        let values: Vec<(Number, usize)> = self
+            .cases
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, (label, _))| label.map(|v| (v, idx)))
+            .collect();
+
+        let min = values.iter().map(|(v, _)| *v).min().unwrap_or(0);
+        let max = values.iter().map(|(v, _)| *v).max().unwrap_or(0);
+        let range = max as i128 - min as i128 + 1;
+
+        // Jump table when at least half of the range between the smallest and largest case is used
+        if values.len() >= 3 && range <= 2 * values.len() as i128 {
+            let labels: Vec<String> = (min..=max)
+                .map(|v| match values.iter().find(|(value, _)| *value == v) {
+                    Some((_, idx)) => case_label(*idx),
+                    None => default_label.clone(),
+                })
+                .collect();
+            assembler.push(Instr::JumpTable {
+                name: format!("switch_table_{}", id),
+                min,
+                labels,
+                default: default_label,
+            });
+        } else {
+            for (v, idx) in values.iter() {
+                assembler.push(Instr::JumpIfEqual(*v, case_label(*idx)));
+            }
+            assembler.push(Instr::Jump(default_label));
+        }
+
+        assembler.push_switch(end_label.clone());
+        vars.push_scope();
+        for (idx, (_, children)) in self.cases.iter().enumerate() {
+            assembler.push(Instr::Label(case_label(idx)));
+            for child in children.iter() {
+                child.assemble(assembler, vars, frame_offset);
+            }
+        }
+        vars.pop_scope();
+        assembler.pop_loop();
+        assembler.push(Instr::Label(end_label));
+    }
+}
+
+impl SwitchNode {
+    /// Every case must be a variant of `t` and, without a default, every variant needs a case
+    fn check_enum_cases(&self, t: &EnumType) -> Result<()> {
+        for label in self.cases.iter().filter_map(|(label, _)| *label) {
+            if t.variant(label).is_none() {
+                bail!("Case {} is not a variant of {}", label, t.name);
+            }
+        }
+        if self.cases.iter().any(|(label, _)| label.is_none()) {
+            return Ok(());
+        }
+        let missing: Vec<String> = t
+            .variants
+            .iter()
+            .filter(|(_, value)| !self.cases.iter().any(|(label, _)| *label == Some(*value)))
+            .map(|(name, _)| format!("{}::{}", t.name, name))
+            .collect();
+        if !missing.is_empty() {
+            bail!(
+                "Switch on {} does not handle {}",
+                t.name,
+                missing.join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    /// Runs the cases starting at `start`, falling through until a break
+    fn exec_from(&self, start: usize, vars: &mut Environment) -> Result<Flow> {
+        for (_, children) in self.cases[start..].iter() {
+            for child in children.iter() {
+                match child.exec(vars)? {
+                    Flow::Normal => {}
+                    Flow::Break => return Ok(Flow::Normal),
+                    flow => return Ok(flow),
+                }
+            }
+        }
+        Ok(Flow::Normal)
+    }
+}
+
+// Break/Continue Node
+#[derive(Debug)]
+pub struct LoopJumpNode {
+    pub flow: Flow,
+}
+
+impl LoopJumpNode {
+    pub fn new(flow: Flow) -> Self {
+        Self { flow }
+    }
+}
+
+impl Node for LoopJumpNode {
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::LoopJump(self)
+    }
+
+    fn eval(&self, _vars: &mut Environment) -> Result<VariableData> {
+        unreachable!("statement evaluated as an expression")
+    }
+
+    fn exec(&self, _vars: &mut Environment) -> Result<Flow> {
+        Ok(self.flow.clone())
+    }
+
+    fn check(&self, _checker: &mut Checker) -> Result<VariableKind> {
+        Ok(VariableKind::None)
+    }
+
+    fn assemble(
+        &self,
+        assembler: &mut Assembler,
+        _vars: &mut Scopes<(VariableKind, Location)>,
+        _frame_offset: &mut usize,
+    ) {
+        let (continue_label, break_label) = assembler.loop_labels();
+        let label = match self.flow {
+            Flow::Break => break_label,
+            Flow::Continue => continue_label,
+            Flow::Normal | Flow::Return(_) => unreachable!(),
+        };
+        assembler.push(Instr::Jump(label.clone()));
     }
 }
 
 // Block Node
 #[derive(Debug)]
 pub struct BlockNode {
-    children: Vec<Box<dyn Node>>,
+    pub children: Vec<Box<dyn Node>>,
+    /// Line of the source each child starts on, counted from 1
+    pub lines: Vec<usize>,
 }
 
 impl BlockNode {
-    pub fn new(children: Vec<Box<dyn Node>>) -> Self {
-        BlockNode { children }
+    pub fn new(children: Vec<Box<dyn Node>>, lines: Vec<usize>) -> Self {
+        BlockNode { children, lines }
     }
 }
 
 impl Node for BlockNode {
-    fn as_any(&self) -> &dyn Any {
-        self
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Block(self)
     }
-    fn eval(&self, vars: &mut HashMap<String, Variable>) -> VariableData {
-        for child in self.children.iter() {
-            let any_child = child.as_any();
 
-            let return_node = any_child.downcast_ref::<ReturnNode>().is_some();
-            let v = child.eval(vars);
+    fn eval(&self, _vars: &mut Environment) -> Result<VariableData> {
+        unreachable!("statement evaluated as an expression")
+    }
 
-            if return_node || v != VariableData::None {
-                return v;
+    fn exec(&self, vars: &mut Environment) -> Result<Flow> {
+        vars.push_scope();
+        let mut flow = Flow::Normal;
+        for (child, line) in self.children.iter().zip(self.lines.iter()) {
+            vars.statement(*line)?;
+            flow = child.exec(vars)?;
+            if !matches!(flow, Flow::Normal) {
+                break;
             }
         }
-        VariableData::None
+        vars.pop_scope();
+        Ok(flow)
+    }
+
+    fn check(&self, checker: &mut Checker) -> Result<VariableKind> {
+        checker.push_scope();
+        for child in self.children.iter() {
+            child.check(checker)?;
+        }
+        checker.pop_scope();
+        Ok(VariableKind::None)
     }
+
     fn assemble(
         &self,
         assembler: &mut Assembler,
-        vars: &mut HashMap<String, (VariableKind, usize)>,
-        ebp_offset: &mut usize,
+        vars: &mut Scopes<(VariableKind, Location)>,
+        frame_offset: &mut usize,
     ) {
-        for child in self.children.iter() {
-            child.assemble(assembler, vars, ebp_offset);
+        vars.push_scope();
+        for (child, line) in self.children.iter().zip(self.lines.iter()) {
+            assembler.push(Instr::Line(*line));
+            child.assemble(assembler, vars, frame_offset);
         }
+        vars.pop_scope();
     }
 }
 
 // Func Node
 #[derive(Debug)]
 pub struct FuncCallNode {
-    name: String,
-    params: Rc<RefCell<Vec<Box<dyn Node>>>>,
-    funcs: Rc<RefCell<HashMap<String, FuncDefNode>>>,
+    pub name: String,
+    pub params: Rc<RefCell<Vec<Box<dyn Node>>>>,
+    pub funcs: Rc<RefCell<HashMap<String, FuncDefNode>>>,
+    /// Kinds of the parameters found by `check`, for the targets printing strings apart
+    pub kinds: RefCell<Vec<VariableKind>>,
 }
 
 impl FuncCallNode {
@@ -771,77 +2344,176 @@ impl FuncCallNode {
             name,
             params: Rc::new(RefCell::new(params)),
             funcs: funcs.clone(),
+            kinds: RefCell::new(vec![]),
         }
     }
 }
 
 impl Node for FuncCallNode {
-    fn as_any(&self) -> &dyn Any {
-        self
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::FuncCall(self)
     }
 
-    fn eval(&self, vars: &mut HashMap<String, Variable>) -> VariableData {
+    fn eval(&self, vars: &mut Environment) -> Result<VariableData> {
         match self.name.as_ref() {
             "println" => {
                 let borrow = self.params.borrow();
                 assert_eq!(borrow.len(), 1);
-                let eval = borrow[0].eval(vars);
-                match eval {
-                    VariableData::Number(n) => println!("{}", n),
-                    VariableData::Bool(b) => println!("{}", b as Number),
-                    VariableData::String(s) => println!("{}", s),
+                let eval = borrow[0].eval(vars)?;
+                let line = match eval {
+                    VariableData::Number(n) => n.to_string(),
+                    VariableData::Bool(b) => (b as Number).to_string(),
+                    VariableData::String(s) => s,
+                    VariableData::Array(_) | VariableData::Struct(_) => panic!("Print aggregate"),
                     VariableData::None => panic!("Print None"),
                 };
-                VariableData::None
+                vars.console().write_line(&line)?;
+                Ok(VariableData::None)
             }
             "readln" => {
                 let borrow = self.params.borrow();
                 assert_eq!(borrow.len(), 0);
-                use std::io;
 
-                let mut input = String::new();
-                io::stdin().read_line(&mut input).unwrap();
-                let input: Number = input.trim().parse().unwrap(); // Maybe do ? here
-                VariableData::Number(input)
+                if vars.limits().deterministic {
+                    return Err(RuntimeError::Input.into());
+                }
+                let input = match vars.console().read_line()? {
+                    Some(input) => input,
+                    None => return Err(RuntimeError::EndOfInput.into()),
+                };
+                match input.trim().parse() {
+                    Ok(n) => Ok(VariableData::Number(n)),
+                    Err(_) => Err(RuntimeError::InvalidInput(input.trim().to_string()).into()),
+                }
+            }
+            "exit" => {
+                let borrow = self.params.borrow();
+                assert_eq!(borrow.len(), 1);
+                let status = borrow[0].eval(vars)?.to_number();
+                Err(RuntimeError::Exit(status).into())
+            }
+            "argc" => Ok(VariableData::Number(vars.args().len() as Number)),
+            "arg" => {
+                let borrow = self.params.borrow();
+                assert_eq!(borrow.len(), 1);
+                let index = borrow[0].eval(vars)?.to_number();
+                let args = vars.args();
+                match usize::try_from(index).ok().and_then(|idx| args.get(idx)) {
+                    Some(arg) => Ok(VariableData::String(arg.clone())),
+                    None => Err(RuntimeError::Argument(index, args.len()).into()),
+                }
             }
             _ => {
-                let mut new_vars = HashMap::new();
                 let fborrow = self.funcs.borrow(); // NOTE: borrow
                 let pborrow = self.params.borrow(); // NOTE: borrow
                 if let Some(func) = fborrow.get(&self.name) {
                     assert_eq!(pborrow.len(), func.args.len());
-                    let plen = pborrow.len();
 
-                    for idx in 0..plen {
-                        let param = &pborrow[idx]; //.pop().unwrap();
-                        let (arg_kind, arg_name) = &func.args[idx];
-                        let param = param.eval(vars);
+                    let params = pborrow
+                        .iter()
+                        .map(|p| p.eval(vars))
+                        .collect::<Result<Vec<VariableData>>>()?;
 
+                    // Only globals stay visible to the callee
+                    let base = vars.enter_call()?;
+                    for (param, (arg_kind, arg_name)) in params.into_iter().zip(func.args.iter()) {
                         let d_node = DeclareNode::new(
                             arg_name.clone(),
                             Some(Box::new(SimpleVariableNode::new(param))),
-                            *arg_kind,
+                            arg_kind.clone(),
                         );
 
-                        d_node.eval(&mut new_vars);
+                        d_node.eval(vars)?;
                     }
 
-                    for child in func.code.children.iter() {
-                        let any_child = child.as_any();
-
-                        let return_node = any_child.downcast_ref::<ReturnNode>().is_some();
-                        let func_node = any_child.downcast_ref::<FuncCallNode>().is_some();
+                    vars.call(&self.name);
+                    let flow = func.code.exec(vars)?;
+                    let value = match flow {
+                        Flow::Return(VariableData::None) | Flow::Normal => VariableData::None,
+                        Flow::Return(v) => Variable::match_data_kind(v, &func.kind),
+                        Flow::Break | Flow::Continue => unreachable!(),
+                    };
+                    vars.ret(&func.kind, &value);
+                    vars.leave_call(base);
+                    Ok(value)
+                } else {
+                    panic!("Function not in funcs")
+                }
+            }
+        }
+    }
 
-                        let v = child.eval(&mut new_vars);
+    fn check(&self, checker: &mut Checker) -> Result<VariableKind> {
+        let params = self.params.borrow();
+        let kinds = params
+            .iter()
+            .map(|p| p.check(checker))
+            .collect::<Result<Vec<_>>>()?;
+        self.kinds.replace(kinds.clone());
 
-                        if return_node || (!func_node && v != VariableData::None) {
-                            return Variable::match_data_kind(v, func.kind);
-                        }
+        match self.name.as_ref() {
+            "println" => {
+                if kinds.len() != 1
+                    || matches!(
+                        kinds[0],
+                        VariableKind::None | VariableKind::Array(_) | VariableKind::Struct(_)
+                    )
+                {
+                    bail!("println expects exactly one value");
+                }
+                Ok(VariableKind::None)
+            }
+            "readln" => {
+                if !kinds.is_empty() {
+                    bail!("readln expects no arguments");
+                }
+                Ok(VariableKind::Number)
+            }
+            "exit" => {
+                if kinds.len() != 1 || !is_assignable(&VariableKind::Number, &kinds[0]) {
+                    bail!("exit expects exactly one int");
+                }
+                Ok(VariableKind::None)
+            }
+            "argc" => {
+                if !kinds.is_empty() {
+                    bail!("argc expects no arguments");
+                }
+                Ok(VariableKind::Number)
+            }
+            "arg" => {
+                if kinds.len() != 1 || !is_assignable(&VariableKind::Number, &kinds[0]) {
+                    bail!("arg expects exactly one int");
+                }
+                checker.expect_program_args()?;
+                Ok(VariableKind::String)
+            }
+            _ => {
+                let fborrow = self.funcs.borrow(); // NOTE: borrow
+                let func = match fborrow.get(&self.name) {
+                    Some(func) => func,
+                    None => bail!("Function {} is not defined", self.name),
+                };
+                if kinds.len() != func.args.len() {
+                    bail!(
+                        "Function {} expects {} arguments, got {}",
+                        self.name,
+                        func.args.len(),
+                        kinds.len()
+                    );
+                }
+                for (kind, (arg_kind, arg_name)) in kinds.iter().zip(func.args.iter()) {
+                    if !is_assignable(arg_kind, kind) {
+                        bail!(
+                            "Argument {} of {} expects {:?}, got {:?}",
+                            arg_name,
+                            self.name,
+                            arg_kind,
+                            kind
+                        );
                     }
-                    VariableData::None
-                } else {
-                    panic!("Function not in funcs")
                 }
+                Ok(func.kind.clone())
             }
         }
     }
@@ -849,8 +2521,8 @@ impl Node for FuncCallNode {
     fn assemble(
         &self,
         assembler: &mut Assembler,
-        vars: &mut HashMap<String, (VariableKind, usize)>,
-        ebp_offset: &mut usize,
+        vars: &mut Scopes<(VariableKind, Location)>,
+        frame_offset: &mut usize,
     ) {
         let fborrow = self.funcs.borrow(); // NOTE: borrow
 
@@ -858,14 +2530,44 @@ impl Node for FuncCallNode {
             "println" | "print" => {
                 let borrow = self.params.borrow();
                 assert_eq!(borrow.len(), 1);
-                borrow[0].assemble(assembler, vars, ebp_offset);
-                assembler.push_line("push ebx\ncall print\npop ebx");
+                borrow[0].assemble(assembler, vars, frame_offset);
+                match self.kinds.borrow()[0] {
+                    VariableKind::String => assembler.push(Instr::PrintString),
+                    _ => assembler.push(Instr::Print),
+                }
+            }
+            "readln" => assembler.push(Instr::Read),
+            "exit" => {
+                let borrow = self.params.borrow();
+                borrow[0].assemble(assembler, vars, frame_offset);
+                assembler.push(Instr::Exit);
+            }
+            "argc" => assembler.push(Instr::ArgCount),
+            "arg" => {
+                let borrow = self.params.borrow();
+                borrow[0].assemble(assembler, vars, frame_offset);
+                assembler.push(Instr::ProgramArg);
             }
             _ => {
                 let func = fborrow.get(&self.name).unwrap();
-                for child in func.code.children.iter() {
-                    child.assemble(assembler, vars, ebp_offset);
+                let params = self.params.borrow();
+                assert_eq!(params.len(), func.args.len());
+
+                // Struct arguments are pushed as addresses, the callee copies them
+                for param in params.iter().rev() {
+                    param.assemble(assembler, vars, frame_offset);
+                    assembler.push(Instr::Push);
+                }
+                let mut pushed = params.len();
+
+                // Structs are returned into a temporary of the caller, passed as a hidden first argument
+                if let VariableKind::Struct(t) = &func.kind {
+                    *frame_offset += t.words();
+                    assembler.push(Instr::PushAddress(Location::Local(*frame_offset)));
+                    pushed += 1;
                 }
+
+                assembler.push(Instr::Call(self.name.clone(), pushed));
             }
         }
     }
@@ -874,7 +2576,7 @@ impl Node for FuncCallNode {
 // Return Node
 #[derive(Debug)]
 pub struct ReturnNode {
-    child: Option<Box<dyn Node>>,
+    pub child: Option<Box<dyn Node>>,
 }
 
 impl ReturnNode {
@@ -884,33 +2586,72 @@ impl ReturnNode {
 }
 
 impl Node for ReturnNode {
-    fn as_any(&self) -> &dyn Any {
-        self
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Return(self)
     }
-    fn eval(&self, vars: &mut HashMap<String, Variable>) -> VariableData {
-        if let Some(c) = &self.child {
-            c.eval(vars)
-        } else {
-            VariableData::Number(1) // WARNING FIXME
+
+    fn eval(&self, _vars: &mut Environment) -> Result<VariableData> {
+        unreachable!("statement evaluated as an expression")
+    }
+
+    fn exec(&self, vars: &mut Environment) -> Result<Flow> {
+        match &self.child {
+            Some(c) => Ok(Flow::Return(c.eval(vars)?)),
+            None => Ok(Flow::Return(VariableData::None)),
+        }
+    }
+
+    fn check(&self, checker: &mut Checker) -> Result<VariableKind> {
+        let kind = match &self.child {
+            Some(c) => c.check(checker)?,
+            None => VariableKind::None,
+        };
+        let return_kind = checker.return_kind();
+        if kind == VariableKind::None || *return_kind == VariableKind::None {
+            if kind != *return_kind {
+                bail!(
+                    "Function returning {:?} cannot return {:?}",
+                    return_kind,
+                    kind
+                );
+            }
+        } else if !is_assignable(return_kind, &kind) {
+            bail!(
+                "Function returning {:?} cannot return {:?}",
+                return_kind,
+                kind
+            );
         }
+        Ok(VariableKind::None)
     }
+
     fn assemble(
         &self,
-        _assembler: &mut Assembler,
-        _vars: &mut HashMap<String, (VariableKind, usize)>,
-        _ebp_offset: &mut usize,
+        assembler: &mut Assembler,
+        vars: &mut Scopes<(VariableKind, Location)>,
+        frame_offset: &mut usize,
     ) {
-        unimplemented!()
+        match &self.child {
+            Some(c) => c.assemble(assembler, vars, frame_offset),
+            // Returning nothing returns zero, the exit status of a `main` returning nothing
+            None => assembler.push(Instr::Imm(0)),
+        }
+        if let VariableKind::Struct(t) = assembler.return_kind() {
+            assembler.push(Instr::ReturnStruct(t.words()));
+        }
+        assembler.push(Instr::Return);
     }
 }
 
 // FuncDef Node
 #[derive(Debug)]
 pub struct FuncDefNode {
-    kind: VariableKind,
-    name: String,
-    args: Vec<(VariableKind, String)>,
-    code: BlockNode,
+    pub kind: VariableKind,
+    pub name: String,
+    pub args: Vec<(VariableKind, String)>,
+    pub code: BlockNode,
+    /// Line of the source the function is defined on, counted from 1
+    pub line: usize,
 }
 
 impl FuncDefNode {
@@ -919,12 +2660,67 @@ impl FuncDefNode {
         name: String,
         args: Vec<(VariableKind, String)>,
         code: BlockNode,
+        line: usize,
     ) -> Self {
         Self {
             kind,
             name,
             args,
             code,
+            line,
+        }
+    }
+
+    pub fn assemble(&self, assembler: &mut Assembler) {
+        let mut vars = assembler.globals();
+        vars.push_scope();
+        let mut frame_offset = 0;
+
+        assembler.set_return_kind(self.kind.clone());
+        let hidden = match self.kind {
+            VariableKind::Struct(_) => 1,
+            _ => 0,
+        };
+
+        // Copy arguments into local slots so they are addressed like any other variable
+        for (idx, (kind, name)) in self.args.iter().enumerate() {
+            frame_offset += kind.words();
+            let location = Location::Local(frame_offset);
+            assembler.push(Instr::LoadArg(hidden + idx));
+            assemble_store(assembler, &location, kind);
+            assembler.declare_local(name, kind, &location, true);
+            vars.declare(name.clone(), (kind.clone(), location));
+        }
+
+        self.code.assemble(assembler, &mut vars, &mut frame_offset);
+        // Falling off the end returns zero like in the other targets
+        assembler.push(Instr::Imm(0));
+        assembler.push(Instr::Return);
+
+        // Every local, including those of nested scopes, gets its own slot in a frame sized once
+        assembler.push_function(
+            self.name.clone(),
+            self.line,
+            hidden + self.args.len(),
+            frame_offset,
+        );
+    }
+
+    pub fn check(&self, checker: &mut Checker) -> Result<()> {
+        // What `main` returns is the exit status of the program
+        if self.name == "main"
+            && self.kind != VariableKind::None
+            && !is_assignable(&VariableKind::Number, &self.kind)
+        {
+            bail!("main must return int or nothing, not {:?}", self.kind);
+        }
+        checker.expect_supported(&self.kind)?;
+        checker.enter_function(self.kind.clone());
+        for (kind, name) in self.args.iter() {
+            checker.declare(name, kind.clone(), false)?;
         }
+        self.code.check(checker)?;
+        checker.leave_function();
+        Ok(())
     }
 }
@@ -0,0 +1,919 @@
+use crate::ast::{
+    resolve_field, BlockNode, CondNode, DeclareNode, Flow, ForNode, FuncCallNode, FuncDefNode,
+    Node, NodeRef, SwitchNode, UnaryNodeKind,
+};
+use crate::operator::{CondOp, Op};
+use crate::token::{Number, Position};
+use crate::variable::{Scopes, StructType, VariableData, VariableKind};
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use color_eyre::eyre::Result;
+
+const RUNTIME: &str = include_str!("../base.h");
+/// Identifiers that variables cannot take, the keywords of C and the names used by the runtime
+const RESERVED: [&str; 47] = [
+    "auto", "break", "case", "char", "const", "continue", "default", "do", "double", "else",
+    "enum", "extern", "float", "for", "goto", "if", "inline", "int", "long", "register",
+    "restrict", "return", "short", "signed", "sizeof", "static", "struct", "switch", "typedef",
+    "union", "unsigned", "void", "volatile", "while", "main", "memset", "strcmp", "printf", "puts",
+    "fputs", "fprintf", "fgets", "strtoll", "exit", "stdin", "stderr", "NULL",
+];
+
+/// Emits a standalone C file straight from the AST, the runtime being copied at its start
+pub struct C {
+    funcs: Rc<RefCell<HashMap<String, FuncDefNode>>>,
+    globals: Scopes<(VariableKind, String)>,
+    /// Names of the globals and functions, which no local may shadow since locals are declared
+    /// at the start of their function
+    global_names: HashSet<String>,
+    declarations: String,
+    prototypes: String,
+    functions: String,
+
+    /// Statements of the function being emitted
+    code: Vec<String>,
+    depth: usize,
+    locals: Vec<(String, VariableKind)>,
+    names: HashSet<String>,
+    id: usize,
+    /// Label `continue` jumps to in each loop being emitted, `None` when C's own `continue` does
+    loops: Vec<Option<String>>,
+    /// C name of the array being declared, whose elements its array literal assigns one by one
+    array: Option<String>,
+    /// Kind returned by `main`, whose value is the exit status of the program
+    main_kind: VariableKind,
+}
+
+/// Name of a variable, struct or field in C, those clashing with C being renamed
+fn identifier(name: &str) -> String {
+    if RESERVED.contains(&name) || name.starts_with("logik_") || name.starts_with("func_") {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Type of a scalar or struct in C, ints being 64 bit like in the other targets
+fn c_type(kind: &VariableKind) -> String {
+    match kind {
+        VariableKind::Number | VariableKind::Enum(_) => "long long".to_string(),
+        VariableKind::Bool => "_Bool".to_string(),
+        VariableKind::String => "const char *".to_string(),
+        VariableKind::Struct(t) => format!("struct {}", identifier(&t.name)),
+        VariableKind::None => "void".to_string(),
+        VariableKind::Array(_) => unreachable!("arrays are declared with their length"),
+    }
+}
+
+/// Declaration of `name`, as a variable, parameter or field
+fn declaration(kind: &VariableKind, name: &str) -> String {
+    match kind {
+        VariableKind::Array(len) => format!("long long {}[{}]", name, len),
+        VariableKind::String => format!("const char *{}", name),
+        _ => format!("{} {}", c_type(kind), name),
+    }
+}
+
+/// Literal of an int, `long long` so that shifts and products do not overflow an `int`
+fn number(value: Number) -> String {
+    if value == Number::MIN {
+        format!("({}LL - 1)", Number::MIN + 1)
+    } else if value < 0 {
+        format!("({}LL)", value)
+    } else {
+        format!("{}LL", value)
+    }
+}
+
+/// Literal of a string, escaping what C would not take as is
+fn string(value: &str) -> String {
+    let mut literal = String::from("\"");
+    for byte in value.bytes() {
+        match byte {
+            b'"' => literal.push_str("\\\""),
+            b'\\' => literal.push_str("\\\\"),
+            b'\n' => literal.push_str("\\n"),
+            b'\t' => literal.push_str("\\t"),
+            // Octal escapes take at most 3 digits, so that a digit after them is kept apart
+            0x20..=0x7e => literal.push(byte as char),
+            _ => literal.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// Value of a variable never assigned, uninitialized strings being `""` and enums their first
+/// variant
+fn default_value(kind: &VariableKind) -> String {
+    match kind {
+        VariableKind::String => "\"\"".to_string(),
+        VariableKind::Enum(t) => number(t.first()),
+        VariableKind::Struct(t) => format!("(struct {}){}", identifier(&t.name), initializer(kind)),
+        _ => "0".to_string(),
+    }
+}
+
+/// Initializer list of a struct or array never assigned, zeroed but for its enums
+fn initializer(kind: &VariableKind) -> String {
+    match kind {
+        VariableKind::Struct(t) if !kind.initial_words().is_empty() => {
+            let fields: Vec<String> = t.fields.iter().map(|(_, kind)| initializer(kind)).collect();
+            format!("{{{}}}", fields.join(", "))
+        }
+        VariableKind::Struct(_) | VariableKind::Array(_) => "{0}".to_string(),
+        VariableKind::Enum(t) => number(t.first()),
+        _ => "0".to_string(),
+    }
+}
+
+impl C {
+    pub fn new(funcs: &Rc<RefCell<HashMap<String, FuncDefNode>>>) -> Self {
+        Self {
+            funcs: funcs.clone(),
+            globals: Scopes::new(),
+            global_names: HashSet::new(),
+            declarations: String::new(),
+            prototypes: String::new(),
+            functions: String::new(),
+            code: vec![],
+            depth: 0,
+            locals: vec![],
+            names: HashSet::new(),
+            id: 0,
+            loops: vec![],
+            array: None,
+            main_kind: VariableKind::None,
+        }
+    }
+
+    /// Emits the whole program, whose `main` calls the `main` of logik
+    pub fn program(
+        &mut self,
+        structs: &HashMap<String, Rc<StructType>>,
+        globals: &[DeclareNode],
+    ) -> Result<String> {
+        let funcs = self.funcs.clone();
+        let funcs = funcs.borrow();
+        let mut names: Vec<&String> = funcs.keys().collect();
+        names.sort();
+        for name in names.iter() {
+            self.global_names.insert(format!("func_{}", name));
+        }
+
+        let mut program = String::from(RUNTIME);
+
+        // Structs are defined after the structs of their fields
+        let mut defined = HashSet::new();
+        let mut struct_names: Vec<&String> = structs.keys().collect();
+        struct_names.sort();
+        for name in struct_names {
+            define_struct(&structs[name], &mut defined, &mut program);
+        }
+
+        for global in globals.iter() {
+            self.global(global);
+        }
+        for name in names {
+            self.function(&funcs[name])?;
+        }
+
+        program.push('\n');
+        program.push_str(&self.declarations);
+        program.push('\n');
+        program.push_str(&self.prototypes);
+        program.push_str(&self.functions);
+        program.push_str("\nint main(int argc, char **argv) {\n");
+        program.push_str("    logik_argc = argc;\n    logik_argv = argv;\n");
+        match self.main_kind {
+            VariableKind::None => program.push_str("    func_main();\n    return 0;\n}\n"),
+            _ => program.push_str("    return (int) func_main();\n}\n"),
+        }
+        Ok(program)
+    }
+
+    pub fn line(&mut self, s: &str) {
+        if s.starts_with('}') {
+            self.depth -= 1;
+        }
+        self.code
+            .push(format!("{}{}", "    ".repeat(self.depth + 1), s));
+        if s.ends_with('{') {
+            self.depth += 1;
+        }
+    }
+
+    /// Index of the next statement, to take the ones emitted after it with `take`
+    pub fn mark(&self) -> usize {
+        self.code.len()
+    }
+
+    pub fn take(&mut self, mark: usize) -> Vec<String> {
+        self.code.split_off(mark)
+    }
+
+    /// Puts statements taken with `take` back one block deeper
+    pub fn extend(&mut self, lines: Vec<String>) {
+        self.code
+            .extend(lines.into_iter().map(|line| format!("    {}", line)));
+    }
+
+    /// Starts a function, only the names of the globals being taken
+    pub fn begin_function(&mut self) {
+        self.locals.clear();
+        self.names = self.global_names.clone();
+    }
+
+    /// Unused name based on `name`, shadowed variables getting a name of their own
+    fn unique(names: &mut HashSet<String>, name: &str) -> String {
+        let name = identifier(name);
+        let mut unique = name.clone();
+        let mut n = 0;
+        while names.contains(&unique) {
+            n += 1;
+            unique = format!("{}_{}", name, n);
+        }
+        names.insert(unique.clone());
+        unique
+    }
+
+    pub fn param(&mut self, name: &str) -> String {
+        Self::unique(&mut self.names, name)
+    }
+
+    /// New local of the current function, declared at its start
+    pub fn local(&mut self, name: &str, kind: &VariableKind) -> String {
+        let name = Self::unique(&mut self.names, name);
+        self.locals.push((name.clone(), kind.clone()));
+        name
+    }
+
+    /// Local holding an operand whose evaluation must happen before the ones after it
+    pub fn temporary(&mut self, kind: &VariableKind) -> String {
+        self.local("tmp", kind)
+    }
+
+    /// Ends the function whose statements were emitted since `begin_function`
+    pub fn push_function(
+        &mut self,
+        name: &str,
+        params: &[(VariableKind, String)],
+        kind: &VariableKind,
+    ) {
+        let params: Vec<String> = params
+            .iter()
+            .map(|(kind, name)| declaration(kind, name))
+            .collect();
+        let params = if params.is_empty() {
+            "void".to_string()
+        } else {
+            params.join(", ")
+        };
+        if name == "main" {
+            self.main_kind = kind.clone();
+        }
+        let signature = format!("static {} func_{}({})", c_type(kind), name, params);
+        self.prototypes.push_str(&format!("{};\n", signature));
+
+        let f = &mut self.functions;
+        f.push_str(&format!("\n{} {{\n", signature));
+        for (local, kind) in self.locals.iter() {
+            f.push_str(&format!("    {};\n", declaration(kind, local)));
+        }
+        for line in self.code.drain(..) {
+            f.push_str(&line);
+            f.push('\n');
+        }
+        // Falling off the end returns zero like in the other targets
+        if *kind != VariableKind::None {
+            f.push_str(&format!("    return {};\n", default_value(kind)));
+        }
+        f.push_str("}\n");
+    }
+
+    pub fn declare_global(&mut self, name: String, kind: VariableKind, value: Option<Number>) {
+        let c_name = Self::unique(&mut self.global_names, &name);
+        let value = match (&kind, value) {
+            (_, Some(value)) => number(value),
+            (VariableKind::Array(_) | VariableKind::Struct(_), None) => initializer(&kind),
+            (_, None) => default_value(&kind),
+        };
+        self.declarations.push_str(&format!(
+            "static {} = {};\n",
+            declaration(&kind, &c_name),
+            value
+        ));
+        self.globals.declare(name, (kind, c_name));
+    }
+
+    /// C names of the globals, the scope every function body starts from
+    pub fn globals(&self) -> Scopes<(VariableKind, String)> {
+        self.globals.clone()
+    }
+
+    pub fn next_id(&mut self) -> usize {
+        self.id += 1;
+        self.id
+    }
+
+    /// Enters a loop, `label` being where `continue` jumps when it cannot use C's `continue`
+    pub fn push_loop(&mut self, label: Option<String>) {
+        self.loops.push(label);
+    }
+
+    pub fn pop_loop(&mut self) {
+        self.loops.pop();
+    }
+
+    /// Statement continuing the innermost loop being emitted
+    pub fn continue_statement(&self) -> String {
+        match self.loops.last() {
+            Some(Some(label)) => format!("goto {};", label),
+            _ => "continue;".to_string(),
+        }
+    }
+
+    /// Makes the array literal of the declaration being emitted assign the elements of `name`
+    pub fn set_array(&mut self, name: String) {
+        self.array = Some(name);
+    }
+
+    pub fn take_array(&mut self) -> String {
+        self.array
+            .take()
+            .expect("array literal outside of a declaration")
+    }
+
+    /// Expression giving a variable the value of one never assigned
+    pub fn zero(&self, name: &str, kind: &VariableKind) -> String {
+        match kind {
+            VariableKind::Struct(_) if !kind.initial_words().is_empty() => {
+                format!("{} = {}", name, default_value(kind))
+            }
+            VariableKind::Array(_) | VariableKind::Struct(_) => {
+                format!("memset(&{}, 0, sizeof {})", name, name)
+            }
+            _ => format!("{} = {}", name, default_value(kind)),
+        }
+    }
+}
+
+fn define_struct(t: &Rc<StructType>, defined: &mut HashSet<String>, program: &mut String) {
+    if !defined.insert(t.name.clone()) {
+        return;
+    }
+    for (_, kind) in t.fields.iter() {
+        if let VariableKind::Struct(field) = kind {
+            define_struct(field, defined, program);
+        }
+    }
+
+    program.push_str(&format!("\nstruct {} {{\n", identifier(&t.name)));
+    for (name, kind) in t.fields.iter() {
+        program.push_str(&format!("    {};\n", declaration(kind, &identifier(name))));
+    }
+    // C has no empty structs
+    if t.fields.is_empty() {
+        program.push_str("    char empty;\n");
+    }
+    program.push_str("};\n");
+}
+
+/// Assignments to evaluate first, then the operands along with their kinds
+type Operands = (Vec<String>, Vec<(String, VariableKind)>);
+
+/// `expression` evaluated after the assignments `before`
+fn sequence(before: Vec<String>, expression: String) -> String {
+    if before.is_empty() {
+        expression
+    } else {
+        format!("({}, {})", before.join(", "), expression)
+    }
+}
+
+/// Operator applied through the runtime, which wraps around on overflow like the other targets
+/// and aborts on a division by zero at `position`
+fn binop(op: Op, lhs: &str, rhs: &str, position: Position) -> String {
+    let name = match op {
+        Op::Mul => "mul",
+        Op::Div => return format!("logik_div({}, {}, \"{}\")", lhs, rhs, position),
+        Op::Mod => return format!("logik_mod({}, {}, \"{}\")", lhs, rhs, position),
+        Op::Add => "add",
+        Op::Sub => "sub",
+        Op::Shl => "shl",
+        Op::Shr => "shr",
+        Op::BitAnd | Op::BitOr | Op::BitXor => return format!("({} {} {})", lhs, op, rhs),
+        Op::Not | Op::BitNot => unreachable!(),
+    };
+    format!("logik_{}({}, {})", name, lhs, rhs)
+}
+
+/// Assignment of `value` to `target`, which compound assignments read again
+fn assignment(target: &str, op: Option<Op>, value: String, position: Position) -> String {
+    match op {
+        Some(op) => format!("{} = {}", target, binop(op, target, &value, position)),
+        None => format!("{} = {}", target, value),
+    }
+}
+
+fn element(name: &str, kind: &VariableKind, index: String) -> String {
+    match kind {
+        VariableKind::Array(len) => format!("{}[logik_index({}, {})]", name, index, len),
+        _ => unreachable!(),
+    }
+}
+
+fn field(name: &str, path: &[String]) -> String {
+    let mut field = name.to_string();
+    for name in path.iter() {
+        field.push('.');
+        field.push_str(&identifier(name));
+    }
+    field
+}
+
+// Lowering of the nodes, each returning the expression it evaluates to along with its kind, or
+// `None` once its statements were emitted
+impl C {
+    fn global(&mut self, global: &DeclareNode) {
+        if global.constant {
+            return;
+        }
+        let value = global.expression.as_ref().and_then(|e| e.const_eval());
+        self.declare_global(global.name.clone(), global.kind.clone(), value);
+    }
+
+    fn function(&mut self, func: &FuncDefNode) -> Result<()> {
+        let mut vars = self.globals();
+        vars.push_scope();
+
+        self.begin_function();
+        let mut params = vec![];
+        for (kind, name) in func.args.iter() {
+            let param = self.param(name);
+            vars.declare(name.clone(), (kind.clone(), param.clone()));
+            params.push((kind.clone(), param));
+        }
+
+        self.block_node(&func.code, &mut vars)?;
+        self.push_function(&func.name, &params, &func.kind);
+        Ok(())
+    }
+
+    fn node(
+        &mut self,
+        node: &dyn Node,
+        vars: &mut Scopes<(VariableKind, String)>,
+    ) -> Result<Option<(String, VariableKind)>> {
+        match node.node_ref() {
+            NodeRef::Binary(node) => {
+                let (before, operands) =
+                    self.operands(&[node.left_child.as_ref(), node.right_child.as_ref()], vars)?;
+                let expression = binop(node.op, &operands[0].0, &operands[1].0, node.position);
+                Ok(Some((sequence(before, expression), VariableKind::Number)))
+            }
+            NodeRef::Unary(node) => {
+                let (child, kind) = self.value(node.child.as_ref(), vars)?;
+                let expression = match node.kind {
+                    UnaryNodeKind::Pos => return Ok(Some((child, kind))),
+                    UnaryNodeKind::Neg => format!("logik_neg({})", child),
+                    UnaryNodeKind::Not => format!("(!{})", child),
+                    UnaryNodeKind::BitNot => format!("(~{})", child),
+                };
+                Ok(Some((expression, VariableKind::Number)))
+            }
+            NodeRef::Number(node) => {
+                let (expression, _) = self.value(node.child.as_ref(), vars)?;
+                Ok(Some((expression, VariableKind::Number)))
+            }
+            NodeRef::NumberLiteral(node) => Ok(Some((number(node.value), VariableKind::Number))),
+            NodeRef::SimpleVariable(node) => {
+                let kind = match node.value {
+                    VariableData::Bool(_) => VariableKind::Bool,
+                    _ => VariableKind::Number,
+                };
+                Ok(Some((number(node.value.to_number()), kind)))
+            }
+            NodeRef::Bool(node) => {
+                let (expression, _) = self.value(node.child.as_ref(), vars)?;
+                Ok(Some((expression, VariableKind::Bool)))
+            }
+            NodeRef::BoolLiteral(node) => Ok(Some((
+                (node.value as Number).to_string(),
+                VariableKind::Bool,
+            ))),
+            NodeRef::String(node) => self.node(node.child.as_ref(), vars),
+            NodeRef::StringLiteral(node) => Ok(Some((string(&node.value), VariableKind::String))),
+            NodeRef::Declare(node) => self.declare_node(node, vars),
+            NodeRef::Assign(node) => {
+                let (before, value) =
+                    self.assigned(node.expression.as_ref(), node.op.is_some(), false, vars)?;
+                let (_, name) = vars.get(&node.name).unwrap();
+                let statement = assignment(name, node.op, value, node.position);
+                Ok(Some((sequence(before, statement), VariableKind::None)))
+            }
+            NodeRef::IncDec(node) => {
+                let (kind, name) = vars.get(&node.name).unwrap();
+                let update = format!("{} = {}", name, binop(node.op, name, "1LL", node.position));
+                let expression = if node.prefix {
+                    format!("({})", update)
+                } else {
+                    let old = self.temporary(kind);
+                    format!("({} = {}, {}, {})", old, name, update, old)
+                };
+                Ok(Some((expression, VariableKind::Number)))
+            }
+            NodeRef::Variable(node) => Ok(vars
+                .get(&node.name)
+                .map(|(kind, name)| (name.clone(), kind.clone()))),
+            NodeRef::ArrayLiteral(node) => {
+                // Assigns the elements of the array being declared one after the other
+                let name = self.take_array();
+                let mut elements = vec![];
+                for (idx, element) in node.elements.iter().enumerate() {
+                    let (value, _) = self.value(element.as_ref(), vars)?;
+                    elements.push(format!("{}[{}] = {}", name, idx, value));
+                }
+                Ok(Some((elements.join(", "), VariableKind::None)))
+            }
+            NodeRef::Index(node) => {
+                let (index, _) = self.value(node.index.as_ref(), vars)?;
+                let (kind, name) = vars.get(&node.name).unwrap();
+                Ok(Some((element(name, kind, index), VariableKind::Number)))
+            }
+            NodeRef::IndexAssign(node) => {
+                let reads_after = node.op.is_some() || node.index.const_eval().is_none();
+                let impure_after = !node.index.is_pure() && node.index.const_eval().is_none();
+                let (mut before, value) =
+                    self.assigned(node.expression.as_ref(), reads_after, impure_after, vars)?;
+                let (mut index, _) = self.value(node.index.as_ref(), vars)?;
+                // A compound assignment reads the element again
+                if node.op.is_some() && !node.index.is_pure() {
+                    let temporary = self.temporary(&VariableKind::Number);
+                    before.push(format!("{} = {}", temporary, index));
+                    index = temporary;
+                }
+
+                let (kind, name) = vars.get(&node.name).unwrap();
+                let target = element(name, kind, index);
+                let statement = assignment(&target, node.op, value, node.position);
+                Ok(Some((sequence(before, statement), VariableKind::None)))
+            }
+            NodeRef::Len(node) => match vars.get(&node.name) {
+                Some((VariableKind::Array(len), _)) => {
+                    Ok(Some((number(*len as Number), VariableKind::Number)))
+                }
+                _ => unreachable!(),
+            },
+            NodeRef::StructLiteral(node) => {
+                let fields: Vec<&dyn Node> =
+                    node.fields.iter().map(|field| field.as_ref()).collect();
+                let (before, operands) = self.operands(&fields, vars)?;
+                let values: Vec<String> = operands.into_iter().map(|(value, _)| value).collect();
+
+                let kind = VariableKind::Struct(node.kind.clone());
+                let values = if values.is_empty() {
+                    "0".to_string()
+                } else {
+                    values.join(", ")
+                };
+                let expression = format!("({}){{{}}}", c_type(&kind), values);
+                Ok(Some((sequence(before, expression), kind)))
+            }
+            NodeRef::Field(node) => {
+                let (kind, name) = vars.get(&node.name).unwrap();
+                let (_, _, kind) = resolve_field(kind, &node.path)?;
+                Ok(Some((field(name, &node.path), kind)))
+            }
+            NodeRef::FieldAssign(node) => {
+                let (before, value) =
+                    self.assigned(node.expression.as_ref(), node.op.is_some(), false, vars)?;
+                let (_, name) = vars.get(&node.name).unwrap();
+                let statement = assignment(&field(name, &node.path), node.op, value, node.position);
+                Ok(Some((sequence(before, statement), VariableKind::None)))
+            }
+            NodeRef::EnumVariant(node) => Ok(Some((
+                number(node.value),
+                VariableKind::Enum(node.kind.clone()),
+            ))),
+            NodeRef::Cond(node) => self.cond_node(node, vars),
+            NodeRef::Ternary(node) => {
+                let (cond, _) = self.value(node.cond.as_ref(), vars)?;
+                let (if_value, kind) = self.value(node.if_child.as_ref(), vars)?;
+                let (else_value, _) = self.value(node.else_child.as_ref(), vars)?;
+                let expression = format!("({} ? {} : {})", cond, if_value, else_value);
+                Ok(Some((expression, kind)))
+            }
+            NodeRef::If(node) => {
+                let (cond, _) = self.value(node.cond.as_ref(), vars)?;
+                self.line(&format!("if ({}) {{", cond));
+                self.statement(node.if_child.as_ref(), vars)?;
+                if let Some(e) = &node.else_child {
+                    self.line("} else {");
+                    self.statement(e.as_ref(), vars)?;
+                }
+                self.line("}");
+                Ok(None)
+            }
+            NodeRef::While(node) => {
+                let (cond, _) = self.value(node.cond.as_ref(), vars)?;
+                self.line(&format!("while ({}) {{", cond));
+                self.push_loop(None);
+                self.statement(node.child.as_ref(), vars)?;
+                self.pop_loop();
+                self.line("}");
+                Ok(None)
+            }
+            NodeRef::For(node) => self.for_node(node, vars),
+            NodeRef::DoWhile(node) => {
+                self.line("do {");
+                self.push_loop(None);
+                self.statement(node.child.as_ref(), vars)?;
+                self.pop_loop();
+                let (cond, _) = self.value(node.cond.as_ref(), vars)?;
+                self.line(&format!("}} while ({});", cond));
+                Ok(None)
+            }
+            NodeRef::Switch(node) => self.switch_node(node, vars),
+            NodeRef::LoopJump(node) => {
+                let statement = match node.flow {
+                    Flow::Break => "break;".to_string(),
+                    Flow::Continue => self.continue_statement(),
+                    Flow::Normal | Flow::Return(_) => unreachable!(),
+                };
+                self.line(&statement);
+                Ok(None)
+            }
+            NodeRef::Block(node) => self.block_node(node, vars),
+            NodeRef::FuncCall(node) => self.func_call_node(node, vars),
+            NodeRef::Return(node) => {
+                match &node.child {
+                    Some(child) => {
+                        let (value, _) = self.value(child.as_ref(), vars)?;
+                        self.line(&format!("return {};", value));
+                    }
+                    None => self.line("return;"),
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// Emits a node whose value is used
+    fn value(
+        &mut self,
+        node: &dyn Node,
+        vars: &mut Scopes<(VariableKind, String)>,
+    ) -> Result<(String, VariableKind)> {
+        Ok(self.node(node, vars)?.expect("node without a value"))
+    }
+
+    /// Emits a node used as a statement
+    fn statement(
+        &mut self,
+        node: &dyn Node,
+        vars: &mut Scopes<(VariableKind, String)>,
+    ) -> Result<()> {
+        if let Some((statement, _)) = self.node(node, vars)? {
+            if !statement.is_empty() {
+                self.line(&format!("{};", statement));
+            }
+        }
+        Ok(())
+    }
+
+    /// Emits operands that `eval` evaluates from left to right, an order C leaves unspecified.
+    /// When an operand has side effects, those before it, and those before any operand reading
+    /// variables after it, are saved in temporaries, returning the assignments to evaluate first
+    fn operands(
+        &mut self,
+        nodes: &[&dyn Node],
+        vars: &mut Scopes<(VariableKind, String)>,
+    ) -> Result<Operands> {
+        let constant = |node: &&dyn Node| node.const_eval().is_some();
+        let mut before = vec![];
+        let mut operands = vec![];
+        for (idx, node) in nodes.iter().enumerate() {
+            let (mut operand, kind) = self.value(*node, vars)?;
+            let after: Vec<&&dyn Node> = nodes[idx + 1..].iter().filter(|n| !constant(n)).collect();
+            let impure_after = after.iter().any(|next| !next.is_pure());
+            if !constant(node) && (impure_after || !node.is_pure() && !after.is_empty()) {
+                let temporary = self.temporary(&kind);
+                before.push(format!("{} = {}", temporary, operand));
+                operand = temporary;
+            }
+            operands.push((operand, kind));
+        }
+        Ok((before, operands))
+    }
+
+    /// Emits the value of an assignment, which `eval` computes before reading the variable or the
+    /// index. It is saved in a temporary when C could compute it after them while they depend on
+    /// it
+    fn assigned(
+        &mut self,
+        node: &dyn Node,
+        reads_after: bool,
+        impure_after: bool,
+        vars: &mut Scopes<(VariableKind, String)>,
+    ) -> Result<(Vec<String>, String)> {
+        let (value, kind) = self.value(node, vars)?;
+        let constant = node.const_eval().is_some();
+        if !constant && (impure_after || reads_after && !node.is_pure()) {
+            let temporary = self.temporary(&kind);
+            Ok((vec![format!("{} = {}", temporary, value)], temporary))
+        } else {
+            Ok((vec![], value))
+        }
+    }
+
+    fn declare_node(
+        &mut self,
+        node: &DeclareNode,
+        vars: &mut Scopes<(VariableKind, String)>,
+    ) -> Result<Option<(String, VariableKind)>> {
+        if node.constant {
+            return Ok(None);
+        }
+
+        let name = self.local(&node.name, &node.kind);
+        if let VariableKind::Array(_) = node.kind {
+            self.set_array(name.clone());
+        }
+        // Emitted before the local is declared, so naming the variable reads the one it shadows
+        let expression = match &node.expression {
+            Some(e) => Some(self.value(e.as_ref(), vars)?.0),
+            None => None,
+        };
+        vars.declare(node.name.clone(), (node.kind.clone(), name.clone()));
+
+        let statement = match (&node.kind, expression) {
+            (VariableKind::Array(_), expression) => {
+                let zero = self.zero(&name, &node.kind);
+                match expression {
+                    Some(elements) if !elements.is_empty() => format!("{}, {}", zero, elements),
+                    _ => zero,
+                }
+            }
+            (_, Some(expression)) => format!("{} = {}", name, expression),
+            (_, None) => self.zero(&name, &node.kind),
+        };
+        Ok(Some((statement, VariableKind::None)))
+    }
+
+    fn cond_node(
+        &mut self,
+        node: &CondNode,
+        vars: &mut Scopes<(VariableKind, String)>,
+    ) -> Result<Option<(String, VariableKind)>> {
+        // `&&` and `||` evaluate their right side only when needed, like `eval` does
+        if let CondOp::And | CondOp::Or = node.cond {
+            let (lhs, _) = self.value(node.left_child.as_ref(), vars)?;
+            let (rhs, _) = self.value(node.right_child.as_ref(), vars)?;
+            let expression = format!("({} {} {})", lhs, node.cond, rhs);
+            return Ok(Some((expression, VariableKind::Bool)));
+        }
+
+        let (before, operands) =
+            self.operands(&[node.left_child.as_ref(), node.right_child.as_ref()], vars)?;
+        let ((lhs, kind), (rhs, _)) = (&operands[0], &operands[1]);
+        let expression = match (kind, node.cond) {
+            (VariableKind::String, CondOp::EQ) => format!("logik_string_eq({}, {})", lhs, rhs),
+            (VariableKind::String, _) => format!("(!logik_string_eq({}, {}))", lhs, rhs),
+            _ => format!("({} {} {})", lhs, node.cond, rhs),
+        };
+        Ok(Some((sequence(before, expression), VariableKind::Bool)))
+    }
+
+    fn for_node(
+        &mut self,
+        node: &ForNode,
+        vars: &mut Scopes<(VariableKind, String)>,
+    ) -> Result<Option<(String, VariableKind)>> {
+        vars.push_scope();
+        if let Some(init) = &node.init {
+            self.statement(init.as_ref(), vars)?;
+        }
+        let cond = match &node.cond {
+            Some(cond) => self.value(cond.as_ref(), vars)?.0,
+            None => String::new(),
+        };
+
+        // A step needing statements of its own is emitted after the body, `continue` jumping
+        // to it with a `goto`
+        let mark = self.mark();
+        let step = match &node.step {
+            Some(step) => self.node(step.as_ref(), vars)?,
+            None => None,
+        };
+        let statements = self.take(mark);
+
+        if statements.is_empty() {
+            let step = step.map(|(step, _)| step).unwrap_or_default();
+            self.line(&format!("for (; {}; {}) {{", cond, step));
+            self.push_loop(None);
+            self.statement(node.child.as_ref(), vars)?;
+            self.pop_loop();
+        } else {
+            let label = format!("for_step_{}", self.next_id());
+            self.line(&format!("for (; {};) {{", cond));
+            self.push_loop(Some(label.clone()));
+            self.statement(node.child.as_ref(), vars)?;
+            self.pop_loop();
+            self.line(&format!("{}:;", label));
+            self.extend(statements);
+        }
+        self.line("}");
+        vars.pop_scope();
+        Ok(None)
+    }
+
+    /// Cases fall through like in C, whose `continue` also targets the enclosing loop
+    fn switch_node(
+        &mut self,
+        node: &SwitchNode,
+        vars: &mut Scopes<(VariableKind, String)>,
+    ) -> Result<Option<(String, VariableKind)>> {
+        let (value, _) = self.value(node.expression.as_ref(), vars)?;
+        self.line(&format!("switch ({}) {{", value));
+
+        vars.push_scope();
+        for (label, children) in node.cases.iter() {
+            match label {
+                Some(value) => self.line(&format!("case {}:", number(*value))),
+                None => self.line("default:"),
+            }
+            for child in children.iter() {
+                self.statement(child.as_ref(), vars)?;
+            }
+        }
+        vars.pop_scope();
+
+        // A label must be followed by a statement
+        if let Some((_, children)) = node.cases.last() {
+            if children.is_empty() {
+                self.line("break;");
+            }
+        }
+        self.line("}");
+        Ok(None)
+    }
+
+    fn block_node(
+        &mut self,
+        node: &BlockNode,
+        vars: &mut Scopes<(VariableKind, String)>,
+    ) -> Result<Option<(String, VariableKind)>> {
+        vars.push_scope();
+        for child in node.children.iter() {
+            self.statement(child.as_ref(), vars)?;
+        }
+        vars.pop_scope();
+        Ok(None)
+    }
+
+    fn func_call_node(
+        &mut self,
+        node: &FuncCallNode,
+        vars: &mut Scopes<(VariableKind, String)>,
+    ) -> Result<Option<(String, VariableKind)>> {
+        let fborrow = node.funcs.borrow(); // NOTE: borrow
+        let params = node.params.borrow();
+
+        match node.name.as_ref() {
+            "println" | "print" => {
+                let (value, kind) = self.value(params[0].as_ref(), vars)?;
+                let call = match kind {
+                    VariableKind::String => format!("logik_println_string({})", value),
+                    _ => format!("logik_println_int({})", value),
+                };
+                Ok(Some((call, VariableKind::None)))
+            }
+            "readln" => Ok(Some(("logik_readln()".to_string(), VariableKind::Number))),
+            "exit" => {
+                let (value, _) = self.value(params[0].as_ref(), vars)?;
+                Ok(Some((format!("exit((int) {})", value), VariableKind::None)))
+            }
+            "argc" => Ok(Some(("logik_argc".to_string(), VariableKind::Number))),
+            "arg" => {
+                let (value, _) = self.value(params[0].as_ref(), vars)?;
+                Ok(Some((
+                    format!("logik_arg({})", value),
+                    VariableKind::String,
+                )))
+            }
+            _ => {
+                let func = fborrow.get(&node.name).unwrap();
+                let params: Vec<&dyn Node> = params.iter().map(|param| param.as_ref()).collect();
+                let (before, operands) = self.operands(&params, vars)?;
+                let args: Vec<String> = operands.into_iter().map(|(arg, _)| arg).collect();
+
+                let call = format!("func_{}({})", node.name, args.join(", "));
+                Ok(Some((sequence(before, call), func.kind.clone())))
+            }
+        }
+    }
+}
@@ -0,0 +1,484 @@
+use crate::ir::{Global, Local};
+use crate::variable::VariableKind;
+
+// Tags, attributes, forms and operations of DWARF 4 in use
+const TAG_ARRAY_TYPE: u8 = 0x01;
+const TAG_ENUMERATION_TYPE: u8 = 0x04;
+const TAG_FORMAL_PARAMETER: u8 = 0x05;
+const TAG_MEMBER: u8 = 0x0d;
+const TAG_COMPILE_UNIT: u8 = 0x11;
+const TAG_STRUCTURE_TYPE: u8 = 0x13;
+const TAG_SUBRANGE_TYPE: u8 = 0x21;
+const TAG_BASE_TYPE: u8 = 0x24;
+const TAG_ENUMERATOR: u8 = 0x28;
+const TAG_SUBPROGRAM: u8 = 0x2e;
+const TAG_VARIABLE: u8 = 0x34;
+
+const AT_LOCATION: u8 = 0x02;
+const AT_NAME: u8 = 0x03;
+const AT_BYTE_SIZE: u8 = 0x0b;
+const AT_STMT_LIST: u8 = 0x10;
+const AT_LOW_PC: u8 = 0x11;
+const AT_HIGH_PC: u8 = 0x12;
+const AT_LANGUAGE: u8 = 0x13;
+const AT_CONST_VALUE: u8 = 0x1c;
+const AT_PRODUCER: u8 = 0x25;
+const AT_COUNT: u8 = 0x37;
+const AT_DATA_MEMBER_LOCATION: u8 = 0x38;
+const AT_DECL_FILE: u8 = 0x3a;
+const AT_DECL_LINE: u8 = 0x3b;
+const AT_ENCODING: u8 = 0x3e;
+const AT_EXTERNAL: u8 = 0x3f;
+const AT_FRAME_BASE: u8 = 0x40;
+const AT_TYPE: u8 = 0x49;
+
+const FORM_ADDR: u8 = 0x01;
+const FORM_DATA1: u8 = 0x0b;
+const FORM_STRING: u8 = 0x08;
+const FORM_SDATA: u8 = 0x0d;
+const FORM_UDATA: u8 = 0x0f;
+const FORM_REF4: u8 = 0x13;
+const FORM_SEC_OFFSET: u8 = 0x17;
+const FORM_EXPRLOC: u8 = 0x18;
+const FORM_FLAG_PRESENT: u8 = 0x19;
+
+const OP_ADDR: u8 = 0x03;
+const OP_BREG0: u8 = 0x70;
+const OP_FBREG: u8 = 0x91;
+
+const LANG_C99: u8 = 0x0c;
+const ATE_BOOLEAN: u8 = 0x02;
+const ATE_SIGNED: u8 = 0x05;
+
+const LNS_COPY: u8 = 0x01;
+const LNS_ADVANCE_LINE: u8 = 0x03;
+const LNE_END_SEQUENCE: u8 = 0x01;
+const LNE_SET_ADDRESS: u8 = 0x02;
+
+/// Tag of an entry, whether it has children, and the pairs of attribute and form of its values
+type Abbreviation = (u8, bool, &'static [(u8, u8)]);
+
+/// Abbreviations of the entries of `.debug_info`, numbered from 1 in this order
+const ABBREVIATIONS: [Abbreviation; 12] = [
+    (
+        TAG_COMPILE_UNIT,
+        true,
+        &[
+            (AT_PRODUCER, FORM_STRING),
+            (AT_LANGUAGE, FORM_DATA1),
+            (AT_NAME, FORM_STRING),
+            (AT_LOW_PC, FORM_ADDR),
+            (AT_HIGH_PC, FORM_ADDR),
+            (AT_STMT_LIST, FORM_SEC_OFFSET),
+        ],
+    ),
+    (
+        TAG_BASE_TYPE,
+        false,
+        &[
+            (AT_NAME, FORM_STRING),
+            (AT_ENCODING, FORM_DATA1),
+            (AT_BYTE_SIZE, FORM_DATA1),
+        ],
+    ),
+    (
+        TAG_SUBPROGRAM,
+        true,
+        &[
+            (AT_NAME, FORM_STRING),
+            (AT_DECL_FILE, FORM_DATA1),
+            (AT_DECL_LINE, FORM_UDATA),
+            (AT_EXTERNAL, FORM_FLAG_PRESENT),
+            (AT_LOW_PC, FORM_ADDR),
+            (AT_HIGH_PC, FORM_ADDR),
+            (AT_FRAME_BASE, FORM_EXPRLOC),
+        ],
+    ),
+    (
+        TAG_VARIABLE,
+        false,
+        &[
+            (AT_NAME, FORM_STRING),
+            (AT_TYPE, FORM_REF4),
+            (AT_LOCATION, FORM_EXPRLOC),
+        ],
+    ),
+    (
+        TAG_FORMAL_PARAMETER,
+        false,
+        &[
+            (AT_NAME, FORM_STRING),
+            (AT_TYPE, FORM_REF4),
+            (AT_LOCATION, FORM_EXPRLOC),
+        ],
+    ),
+    (
+        TAG_VARIABLE,
+        false,
+        &[
+            (AT_NAME, FORM_STRING),
+            (AT_TYPE, FORM_REF4),
+            (AT_EXTERNAL, FORM_FLAG_PRESENT),
+            (AT_LOCATION, FORM_EXPRLOC),
+        ],
+    ),
+    (
+        TAG_STRUCTURE_TYPE,
+        true,
+        &[(AT_NAME, FORM_STRING), (AT_BYTE_SIZE, FORM_UDATA)],
+    ),
+    (
+        TAG_MEMBER,
+        false,
+        &[
+            (AT_NAME, FORM_STRING),
+            (AT_TYPE, FORM_REF4),
+            (AT_DATA_MEMBER_LOCATION, FORM_UDATA),
+        ],
+    ),
+    (TAG_ARRAY_TYPE, true, &[(AT_TYPE, FORM_REF4)]),
+    (TAG_SUBRANGE_TYPE, false, &[(AT_COUNT, FORM_UDATA)]),
+    (
+        TAG_ENUMERATION_TYPE,
+        true,
+        &[
+            (AT_NAME, FORM_STRING),
+            (AT_TYPE, FORM_REF4),
+            (AT_BYTE_SIZE, FORM_DATA1),
+        ],
+    ),
+    (
+        TAG_ENUMERATOR,
+        false,
+        &[(AT_NAME, FORM_STRING), (AT_CONST_VALUE, FORM_SDATA)],
+    ),
+];
+
+// Codes of the abbreviations
+const COMPILE_UNIT: u64 = 1;
+const BASE_TYPE: u64 = 2;
+const SUBPROGRAM: u64 = 3;
+const LOCAL: u64 = 4;
+const ARGUMENT: u64 = 5;
+const GLOBAL: u64 = 6;
+const STRUCTURE: u64 = 7;
+const MEMBER: u64 = 8;
+const ARRAY: u64 = 9;
+const SUBRANGE: u64 = 10;
+const ENUMERATION: u64 = 11;
+const ENUMERATOR: u64 = 12;
+
+/// Function whose code was emitted between the labels `start` and `end`
+pub struct Function<'a> {
+    pub name: &'a str,
+    pub line: usize,
+    pub start: String,
+    pub end: String,
+    /// Label of the first instruction of each statement and its line
+    pub rows: Vec<(String, usize)>,
+    /// Variables and their offset from the frame pointer in bytes
+    pub locals: Vec<(&'a Local, i64)>,
+}
+
+/// Debug info of a program assembled by NASM, or by `logik build`, for a target whose words and
+/// addresses take `word` bytes and whose frame pointer is the DWARF register `frame_register`
+pub struct Dwarf<'a> {
+    pub source: &'a str,
+    pub word: usize,
+    pub frame_register: u8,
+    pub globals: &'a [Global],
+    pub functions: Vec<Function<'a>>,
+}
+
+impl Dwarf<'_> {
+    /// Sections `.debug_abbrev`, `.debug_info` and `.debug_line` in NASM syntax
+    pub fn sections(&self) -> String {
+        let mut out = Writer::default();
+        out.section(".debug_abbrev");
+        for (idx, (tag, children, attributes)) in ABBREVIATIONS.iter().enumerate() {
+            out.uleb(idx as u64 + 1);
+            out.uleb(*tag as u64);
+            out.byte(*children as u8);
+            for (attribute, form) in attributes.iter() {
+                out.bytes(&[*attribute, *form]);
+            }
+            out.bytes(&[0, 0]);
+        }
+        out.byte(0);
+
+        out.section(".debug_info");
+        self.info(&mut out);
+        out.section(".debug_line");
+        self.lines(&mut out);
+        out.text
+    }
+
+    fn data(&self) -> &'static str {
+        if self.word == 8 {
+            "dq"
+        } else {
+            "dd"
+        }
+    }
+
+    fn info(&self, out: &mut Writer) {
+        let mut types = Types::default();
+        let data = self.data();
+        out.label("debug_info");
+        out.line("dd debug_info_end - debug_info_version");
+        out.label("debug_info_version");
+        out.line("dw 4");
+        out.line("dd 0");
+        out.byte(self.word as u8);
+
+        let (first, last) = match (self.functions.first(), self.functions.last()) {
+            (Some(first), Some(last)) => (first.start.as_str(), last.end.as_str()),
+            _ => ("0", "0"),
+        };
+        out.uleb(COMPILE_UNIT);
+        out.string("logik");
+        out.byte(LANG_C99);
+        out.string(self.source);
+        out.line(&format!("{} {}", data, first));
+        out.line(&format!("{} {}", data, last));
+        out.line("dd 0");
+
+        for global in self.globals.iter() {
+            out.uleb(GLOBAL);
+            out.string(&global.name);
+            out.reference(&types.label(&global.kind));
+            out.uleb(1 + self.word as u64);
+            out.byte(OP_ADDR);
+            out.line(&format!("{} {}", data, global.label));
+        }
+
+        for function in self.functions.iter() {
+            out.uleb(SUBPROGRAM);
+            out.string(function.name);
+            out.byte(1);
+            out.uleb(function.line as u64);
+            out.line(&format!("{} {}", data, function.start));
+            out.line(&format!("{} {}", data, function.end));
+            out.uleb(2);
+            out.bytes(&[OP_BREG0 + self.frame_register, 0]);
+
+            for (local, offset) in function.locals.iter() {
+                out.uleb(if local.argument { ARGUMENT } else { LOCAL });
+                out.string(&local.name);
+                out.reference(&types.label(&local.kind));
+                let offset = sleb(*offset);
+                out.uleb(1 + offset.len() as u64);
+                out.byte(OP_FBREG);
+                out.bytes(&offset);
+            }
+            out.byte(0);
+        }
+
+        // Describing a type may add the types it refers to
+        let mut idx = 0;
+        while idx < types.kinds.len() {
+            let kind = types.kinds[idx].clone();
+            out.label(&types.name(idx));
+            self.kind(&kind, out, &mut types);
+            idx += 1;
+        }
+
+        out.byte(0);
+        out.label("debug_info_end");
+    }
+
+    fn kind(&self, kind: &VariableKind, out: &mut Writer, types: &mut Types) {
+        let word = self.word as u64;
+        match kind {
+            VariableKind::Bool => {
+                out.uleb(BASE_TYPE);
+                out.string("bool");
+                out.bytes(&[ATE_BOOLEAN, self.word as u8]);
+            }
+            VariableKind::Array(len) => {
+                out.uleb(ARRAY);
+                out.reference(&types.label(&VariableKind::Number));
+                out.uleb(SUBRANGE);
+                out.uleb(*len as u64);
+                out.byte(0);
+            }
+            VariableKind::Struct(t) => {
+                out.uleb(STRUCTURE);
+                out.string(&t.name);
+                out.uleb(t.words() as u64 * word);
+                let mut offset = 0;
+                for (name, kind) in t.fields.iter() {
+                    out.uleb(MEMBER);
+                    out.string(name);
+                    out.reference(&types.label(kind));
+                    out.uleb(offset * word);
+                    offset += kind.words() as u64;
+                }
+                out.byte(0);
+            }
+            VariableKind::Enum(t) => {
+                out.uleb(ENUMERATION);
+                out.string(&t.name);
+                out.reference(&types.label(&VariableKind::Number));
+                out.byte(self.word as u8);
+                for (name, value) in t.variants.iter() {
+                    out.uleb(ENUMERATOR);
+                    out.string(name);
+                    out.bytes(&sleb(*value));
+                }
+                out.byte(0);
+            }
+            // Strings are never assembled for these targets
+            VariableKind::Number | VariableKind::String | VariableKind::None => {
+                out.uleb(BASE_TYPE);
+                out.string("int");
+                out.bytes(&[ATE_SIGNED, self.word as u8]);
+            }
+        }
+    }
+
+    /// Line number program with a sequence for each function, rows starting on the labels of the
+    /// statements
+    fn lines(&self, out: &mut Writer) {
+        let data = self.data();
+        out.line("dd debug_line_end - debug_line_version");
+        out.label("debug_line_version");
+        out.line("dw 4");
+        out.line("dd debug_line_program - debug_line_header");
+        out.label("debug_line_header");
+        // Minimum instruction length, maximum operations per instruction, default is_stmt, line
+        // base, line range and opcode base, followed by the operands of the standard opcodes
+        out.bytes(&[1, 1, 1, -5i8 as u8, 14, 13]);
+        out.bytes(&[0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1]);
+        // No include directories, the source being relative to the working directory
+        out.byte(0);
+        out.string(self.source);
+        out.bytes(&[0, 0, 0, 0]);
+        out.label("debug_line_program");
+
+        let set_address = |out: &mut Writer, label: &str| {
+            out.bytes(&[0, 1 + self.word as u8, LNE_SET_ADDRESS]);
+            out.line(&format!("{} {}", data, label));
+        };
+        for function in self.functions.iter() {
+            let mut line = 1;
+            let rows = std::iter::once((&function.start, function.line))
+                .chain(function.rows.iter().map(|(label, line)| (label, *line)));
+            for (label, row) in rows {
+                set_address(out, label);
+                out.byte(LNS_ADVANCE_LINE);
+                out.bytes(&sleb(row as i64 - line as i64));
+                out.byte(LNS_COPY);
+                line = row;
+            }
+            set_address(out, &function.end);
+            out.bytes(&[0, 1, LNE_END_SEQUENCE]);
+        }
+        out.label("debug_line_end");
+    }
+}
+
+/// Kinds described in `.debug_info`, each one at the label `debug_type_<index>`
+#[derive(Default)]
+struct Types {
+    kinds: Vec<VariableKind>,
+}
+
+impl Types {
+    fn label(&mut self, kind: &VariableKind) -> String {
+        let idx = match self.kinds.iter().position(|k| k == kind) {
+            Some(idx) => idx,
+            None => {
+                self.kinds.push(kind.clone());
+                self.kinds.len() - 1
+            }
+        };
+        self.name(idx)
+    }
+
+    fn name(&self, idx: usize) -> String {
+        format!("debug_type_{}", idx)
+    }
+}
+
+#[derive(Default)]
+struct Writer {
+    text: String,
+}
+
+impl Writer {
+    fn line(&mut self, s: &str) {
+        self.text.push_str(s);
+        self.text.push('\n');
+    }
+
+    fn label(&mut self, label: &str) {
+        self.line(&format!("{}:", label));
+    }
+
+    /// Section holding debug info, which is not loaded in memory
+    fn section(&mut self, name: &str) {
+        self.line("");
+        self.line(&format!(
+            "section {} progbits noalloc noexec nowrite align=1",
+            name
+        ));
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        let bytes: Vec<String> = bytes.iter().map(|b| b.to_string()).collect();
+        self.line(&format!("db {}", bytes.join(", ")));
+    }
+
+    fn byte(&mut self, byte: u8) {
+        self.bytes(&[byte]);
+    }
+
+    fn uleb(&mut self, value: u64) {
+        self.bytes(&uleb(value));
+    }
+
+    /// Null terminated string, quoted only when NASM reads it back unchanged
+    fn string(&mut self, s: &str) {
+        if s.chars()
+            .all(|c| c.is_ascii_graphic() && c != '"' && c != '\'' && c != '`')
+        {
+            self.line(&format!("db \"{}\", 0", s));
+        } else {
+            let mut bytes = s.as_bytes().to_vec();
+            bytes.push(0);
+            self.bytes(&bytes);
+        }
+    }
+
+    /// Offset of a type from the start of the compilation unit
+    fn reference(&mut self, label: &str) {
+        self.line(&format!("dd {} - debug_info", label));
+    }
+}
+
+fn uleb(mut value: u64) -> Vec<u8> {
+    let mut bytes = vec![];
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return bytes;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn sleb(mut value: i64) -> Vec<u8> {
+    let mut bytes = vec![];
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            bytes.push(byte);
+            return bytes;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
@@ -0,0 +1,195 @@
+use super::encoder::{Program, DEBUG_SECTIONS};
+
+use color_eyre::eyre::{eyre, Result};
+
+const PAGE: u64 = 0x1000;
+
+/// Fields of the ELF header and program headers whose layout depends on the class of the file
+struct Class {
+    word: usize,
+    /// Value of `e_ident[EI_CLASS]`
+    class: u8,
+    machine: u16,
+    /// Address the headers and the text are loaded at, the usual one of `ld`
+    base: u64,
+    header: usize,
+    program_header: usize,
+    section_header: usize,
+}
+
+const ELF32: Class = Class {
+    word: 4,
+    class: 1,
+    machine: 3,
+    base: 0x0804_8000,
+    header: 52,
+    program_header: 32,
+    section_header: 40,
+};
+
+const ELF64: Class = Class {
+    word: 8,
+    class: 2,
+    machine: 62,
+    base: 0x40_0000,
+    header: 64,
+    program_header: 56,
+    section_header: 64,
+};
+
+fn align(value: u64, alignment: u64) -> u64 {
+    value.div_ceil(alignment) * alignment
+}
+
+/// Statically linked executable, without libc, of the assembly of an x86 target whose words
+/// take `word` bytes. The text is loaded with the headers in a read only segment, followed by a
+/// writable one holding the data and the bss. Debug info is appended after them, along with the
+/// section headers debuggers need to find it
+pub fn executable(asm: &str, word: usize) -> Result<Vec<u8>> {
+    let class = if word == 8 { &ELF64 } else { &ELF32 };
+    let mut program = Program::parse(asm, word)?;
+    let [text, data, bss] = program.sizes()?;
+
+    let text_offset = (class.header + 2 * class.program_header) as u64;
+    let data_offset = align(text_offset + text as u64, word as u64);
+    // Both segments map the same page offset of the file and of the memory
+    let data_address = align(class.base + data_offset, PAGE) + data_offset % PAGE;
+    let bss_address = align(data_address + data as u64, word as u64);
+    let sections = program.encode([class.base + text_offset, data_address, bss_address])?;
+    let entry = program
+        .address("_start")
+        .ok_or_else(|| eyre!("Missing entry point `_start`"))?;
+
+    // Names of the sections in `.shstrtab`, the first one being empty
+    let debug = sections.debug.iter().any(|section| !section.is_empty());
+    let mut names = vec![0];
+    let mut name = |s: &str| {
+        let offset = names.len() as u32;
+        names.extend_from_slice(s.as_bytes());
+        names.push(0);
+        offset
+    };
+    let mut headers = vec![];
+    let data_end = data_offset + sections.data.len() as u64;
+    let mut offset = data_end;
+    if debug {
+        headers.push((
+            name(".text"),
+            1,
+            6,
+            class.base + text_offset,
+            text_offset,
+            text as u64,
+        ));
+        headers.push((name(".data"), 1, 3, data_address, data_offset, data as u64));
+        headers.push((name(".bss"), 8, 3, bss_address, data_end, bss as u64));
+        for ((section, _), bytes) in DEBUG_SECTIONS.iter().zip(sections.debug.iter()) {
+            headers.push((name(section), 1, 0, 0, offset, bytes.len() as u64));
+            offset += bytes.len() as u64;
+        }
+        let shstrtab = name(".shstrtab");
+        let size = names.len() as u64;
+        headers.push((shstrtab, 3, 0, 0, offset, size));
+        offset = align(offset + size, word as u64);
+    }
+
+    let mut elf = Writer {
+        word: class.word,
+        bytes: vec![],
+    };
+    elf.bytes(&[0x7f, b'E', b'L', b'F', class.class, 1, 1]);
+    elf.bytes(&[0; 9]);
+    elf.u16(2); // ET_EXEC
+    elf.u16(class.machine);
+    elf.u32(1);
+    elf.word(entry);
+    elf.word(class.header as u64);
+    elf.word(if debug { offset } else { 0 });
+    elf.u32(0);
+    elf.u16(class.header as u16);
+    elf.u16(class.program_header as u16);
+    elf.u16(2);
+    elf.u16(class.section_header as u16);
+    // The null section comes before the others, `.shstrtab` being the last one
+    let count = if debug { headers.len() + 1 } else { 0 };
+    elf.u16(count as u16);
+    elf.u16(count.saturating_sub(1) as u16);
+
+    let text_size = text_offset + text as u64;
+    elf.segment(0, class.base, text_size, text_size, 5);
+    // The bss takes the memory after the data, zeroed by the kernel
+    let memory = bss_address + bss as u64 - data_address;
+    elf.segment(data_offset, data_address, data as u64, memory, 6);
+
+    elf.bytes(&sections.text);
+    elf.bytes.resize(data_offset as usize, 0);
+    elf.bytes(&sections.data);
+    if debug {
+        for section in sections.debug.iter() {
+            elf.bytes(section);
+        }
+        elf.bytes(&names);
+        elf.bytes.resize(offset as usize, 0);
+        elf.bytes(&vec![0; class.section_header]);
+        for (name, kind, flags, address, offset, size) in headers {
+            elf.section(name, kind, flags, address, offset, size);
+        }
+    }
+    Ok(elf.bytes)
+}
+
+struct Writer {
+    word: usize,
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    /// Address or offset, whose size is the one of a word
+    fn word(&mut self, value: u64) {
+        let bytes = value.to_le_bytes();
+        self.bytes(&bytes[..self.word]);
+    }
+
+    /// Program header of a loadable segment
+    fn segment(&mut self, offset: u64, address: u64, size: u64, memory: u64, flags: u32) {
+        self.u32(1);
+        if self.word == 8 {
+            self.u32(flags);
+        }
+        self.word(offset);
+        self.word(address);
+        self.word(address);
+        self.word(size);
+        self.word(memory);
+        if self.word == 4 {
+            self.u32(flags);
+        }
+        self.word(PAGE);
+    }
+
+    /// Section header, aligned on bytes
+    fn section(&mut self, name: u32, kind: u32, flags: u64, address: u64, offset: u64, size: u64) {
+        self.u32(name);
+        self.u32(kind);
+        self.word(flags);
+        self.word(address);
+        self.word(offset);
+        self.word(size);
+        self.u32(0);
+        self.u32(0);
+        self.word(1);
+        self.word(0);
+    }
+}
@@ -0,0 +1,885 @@
+use crate::token::Number;
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use color_eyre::eyre::{bail, Result};
+
+/// Registers by name, with their number in the encoding and their size in bytes
+const REGISTERS: [(&str, u8, usize); 28] = [
+    ("rax", 0, 8),
+    ("rcx", 1, 8),
+    ("rdx", 2, 8),
+    ("rbx", 3, 8),
+    ("rsp", 4, 8),
+    ("rbp", 5, 8),
+    ("rsi", 6, 8),
+    ("rdi", 7, 8),
+    ("r8", 8, 8),
+    ("r9", 9, 8),
+    ("r10", 10, 8),
+    ("r11", 11, 8),
+    ("r12", 12, 8),
+    ("r13", 13, 8),
+    ("r14", 14, 8),
+    ("r15", 15, 8),
+    ("eax", 0, 4),
+    ("ecx", 1, 4),
+    ("edx", 2, 4),
+    ("ebx", 3, 4),
+    ("esp", 4, 4),
+    ("ebp", 5, 4),
+    ("esi", 6, 4),
+    ("edi", 7, 4),
+    ("al", 0, 1),
+    ("cl", 1, 1),
+    ("dl", 2, 1),
+    ("bl", 3, 1),
+];
+
+/// Condition codes of `jcc`, `setcc` and `cmovcc`
+const CONDITIONS: [(&str, u8); 16] = [
+    ("e", 0x4),
+    ("z", 0x4),
+    ("ne", 0x5),
+    ("nz", 0x5),
+    ("b", 0x2),
+    ("ae", 0x3),
+    ("be", 0x6),
+    ("a", 0x7),
+    ("l", 0xc),
+    ("ge", 0xd),
+    ("le", 0xe),
+    ("g", 0xf),
+    ("s", 0x8),
+    ("ns", 0x9),
+    ("o", 0x0),
+    ("no", 0x1),
+];
+
+/// Opcode extension of the arithmetic instructions sharing their encodings
+const ARITHMETIC: [(&str, u8); 6] = [
+    ("add", 0),
+    ("or", 1),
+    ("and", 4),
+    ("sub", 5),
+    ("xor", 6),
+    ("cmp", 7),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Section {
+    Text,
+    Data,
+    Bss,
+    DebugAbbrev,
+    DebugInfo,
+    DebugLine,
+}
+
+/// Names of the sections holding debug info, which are not loaded and whose labels are offsets
+/// from their start
+pub const DEBUG_SECTIONS: [(&str, Section); 3] = [
+    (".debug_abbrev", Section::DebugAbbrev),
+    (".debug_info", Section::DebugInfo),
+    (".debug_line", Section::DebugLine),
+];
+
+impl Section {
+    /// Index of a debug section in `DEBUG_SECTIONS`
+    fn debug(self) -> Option<usize> {
+        DEBUG_SECTIONS
+            .iter()
+            .position(|(_, section)| *section == self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Register {
+    number: u8,
+    size: usize,
+}
+
+/// Constant plus or minus symbols, `$` being the address of the current line
+#[derive(Debug, Clone, Default)]
+struct Expr {
+    constant: Number,
+    symbols: Vec<(bool, String)>,
+}
+
+impl Expr {
+    /// Values depending on symbols always take the longest encoding, so that the size of the
+    /// code is known before the addresses
+    fn is_symbolic(&self) -> bool {
+        !self.symbols.is_empty()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Memory {
+    /// Bytes accessed, when given by `byte`, `dword` or `qword`
+    size: Option<usize>,
+    base: Option<Register>,
+    index: Option<(Register, u8)>,
+    disp: Expr,
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Register(Register),
+    Memory(Memory),
+    Immediate(Expr),
+}
+
+#[derive(Debug)]
+enum Statement {
+    Section(Section),
+    Label(String),
+    Equ(String, Expr),
+    /// Values of the given size in bytes, strings being only allowed in `db`
+    Data(usize, Vec<Data>),
+    Reserve(usize, usize),
+    Instruction(String, Vec<Operand>),
+}
+
+#[derive(Debug)]
+enum Data {
+    String(Vec<u8>),
+    Value(Expr),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Symbol {
+    Label(Section, usize),
+    Value(Number),
+}
+
+/// Machine code and data of a program, each section starting at the address it was encoded for
+#[derive(Debug, Default)]
+pub struct Sections {
+    pub text: Vec<u8>,
+    pub data: Vec<u8>,
+    pub bss: usize,
+    /// Contents of the sections of `DEBUG_SECTIONS`
+    pub debug: [Vec<u8>; 3],
+}
+
+/// Assembly in the subset of the NASM syntax emitted by the x86 backends, encoded without an
+/// external assembler
+pub struct Program {
+    word: usize,
+    statements: Vec<Statement>,
+    symbols: HashMap<String, Symbol>,
+    /// Start of the text, data and bss sections
+    addresses: [u64; 3],
+}
+
+impl Program {
+    /// Parses the assembly of a target whose words take `word` bytes, 4 for 32 bit x86 or 8 for
+    /// x86-64
+    pub fn parse(asm: &str, word: usize) -> Result<Self> {
+        let mut statements = vec![];
+        for line in asm.lines() {
+            parse_line(strip_comment(line), &mut statements)?;
+        }
+        Ok(Self {
+            word,
+            statements,
+            symbols: HashMap::new(),
+            addresses: [0; 3],
+        })
+    }
+
+    /// Sizes of the text, data and bss sections
+    pub fn sizes(&mut self) -> Result<[usize; 3]> {
+        let sections = self.pass(false)?;
+        Ok([sections.text.len(), sections.data.len(), sections.bss])
+    }
+
+    /// Encodes the program once the addresses of its sections were chosen from their sizes
+    pub fn encode(&mut self, addresses: [u64; 3]) -> Result<Sections> {
+        if self.symbols.is_empty() {
+            self.sizes()?;
+        }
+        self.addresses = addresses;
+        self.pass(true)
+    }
+
+    /// Address of a label, once the program was encoded
+    pub fn address(&self, label: &str) -> Option<u64> {
+        match self.symbols.get(label) {
+            Some(Symbol::Label(section, offset)) => {
+                Some(start(&self.addresses, *section) + *offset as u64)
+            }
+            _ => None,
+        }
+    }
+
+    /// Walks the whole program, the offsets of the labels being recorded on the first pass and
+    /// undefined symbols only reported on the last one
+    fn pass(&mut self, last: bool) -> Result<Sections> {
+        let statements = std::mem::take(&mut self.statements);
+        let mut encoder = Encoder {
+            word: self.word,
+            addresses: self.addresses,
+            symbols: &mut self.symbols,
+            last,
+            section: Section::Text,
+            sections: Sections::default(),
+        };
+        let result = statements
+            .iter()
+            .try_for_each(|statement| encoder.statement(statement));
+        let sections = encoder.sections;
+        self.statements = statements;
+        result.map(|_| sections)
+    }
+}
+
+/// Address a section was encoded for, debug sections starting at zero
+fn start(addresses: &[u64; 3], section: Section) -> u64 {
+    match section {
+        Section::Text | Section::Data | Section::Bss => addresses[section as usize],
+        _ => 0,
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, ';') => return &line[..i],
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Splits on `separator` outside of quotes
+fn split(s: &str, separator: char) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, _) if c == separator => {
+                parts.push(s[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(s[start..].trim());
+    parts
+}
+
+/// First word of a line and the rest of it
+fn first_word(s: &str) -> (&str, &str) {
+    let s = s.trim();
+    match s.find(char::is_whitespace) {
+        Some(i) => (&s[..i], s[i..].trim()),
+        None => (s, ""),
+    }
+}
+
+fn data_size(directive: &str) -> Option<Statement> {
+    match directive {
+        "db" => Some(Statement::Data(1, vec![])),
+        "dw" => Some(Statement::Data(2, vec![])),
+        "dd" => Some(Statement::Data(4, vec![])),
+        "dq" => Some(Statement::Data(8, vec![])),
+        "resb" => Some(Statement::Reserve(1, 0)),
+        "resd" => Some(Statement::Reserve(4, 0)),
+        "resq" => Some(Statement::Reserve(8, 0)),
+        _ => None,
+    }
+}
+
+fn parse_line(line: &str, statements: &mut Vec<Statement>) -> Result<()> {
+    let (first, mut rest) = first_word(line);
+    if first.is_empty() {
+        return Ok(());
+    }
+    let (second, after) = first_word(rest);
+
+    match first.to_lowercase().as_str() {
+        "section" | "segment" => {
+            // Attributes of the debug sections are implied by their name
+            let (name, _) = first_word(rest);
+            let section = match name {
+                ".text" => Section::Text,
+                ".data" => Section::Data,
+                ".bss" => Section::Bss,
+                _ => match DEBUG_SECTIONS.iter().find(|(n, _)| *n == name) {
+                    Some((_, section)) => *section,
+                    None => bail!("Unknown section `{}`", rest),
+                },
+            };
+            statements.push(Statement::Section(section));
+            return Ok(());
+        }
+        "global" => return Ok(()),
+        _ => {}
+    }
+
+    if let Some(label) = first.strip_suffix(':') {
+        statements.push(Statement::Label(label.to_string()));
+    } else if second.eq_ignore_ascii_case("equ") {
+        statements.push(Statement::Equ(first.to_string(), parse_expr(after)?));
+        return Ok(());
+    } else if data_size(&second.to_lowercase()).is_some() {
+        // NASM allows labels without a colon before data
+        statements.push(Statement::Label(first.to_string()));
+    } else {
+        rest = line;
+    }
+
+    let (directive, operands) = first_word(rest);
+    if directive.is_empty() {
+        return Ok(());
+    }
+    let directive = directive.to_lowercase();
+    let statement = match data_size(&directive) {
+        Some(Statement::Data(size, _)) => {
+            let mut values = vec![];
+            for value in split(operands, ',') {
+                match value.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+                    Some(string) if size == 1 => values.push(Data::String(string.into())),
+                    Some(_) => bail!("Strings are only allowed in db: `{}`", line),
+                    None => values.push(Data::Value(parse_expr(value)?)),
+                }
+            }
+            Statement::Data(size, values)
+        }
+        Some(Statement::Reserve(size, _)) => match parse_expr(operands)? {
+            Expr { constant, symbols } if symbols.is_empty() && constant >= 0 => {
+                Statement::Reserve(size, constant as usize)
+            }
+            _ => bail!("Invalid reservation `{}`", line),
+        },
+        _ if directive == "rep" => {
+            let (instruction, _) = first_word(operands);
+            Statement::Instruction(format!("rep {}", instruction.to_lowercase()), vec![])
+        }
+        _ if operands.is_empty() => Statement::Instruction(directive, vec![]),
+        _ => {
+            let operands = split(operands, ',')
+                .into_iter()
+                .map(parse_operand)
+                .collect::<Result<_>>()?;
+            Statement::Instruction(directive, operands)
+        }
+    };
+    statements.push(statement);
+    Ok(())
+}
+
+fn register(name: &str) -> Option<Register> {
+    let name = name.to_lowercase();
+    REGISTERS
+        .iter()
+        .find(|(n, _, _)| *n == name)
+        .map(|&(_, number, size)| Register { number, size })
+}
+
+fn parse_operand(operand: &str) -> Result<Operand> {
+    let (first, rest) = first_word(operand);
+    let (size, operand) = match first.to_lowercase().as_str() {
+        "byte" => (Some(1), rest),
+        "dword" => (Some(4), rest),
+        "qword" => (Some(8), rest),
+        _ => (None, operand),
+    };
+
+    if let Some(inner) = operand.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+        return parse_memory(size, inner).map(Operand::Memory);
+    }
+    if let Some(register) = register(operand) {
+        return Ok(Operand::Register(register));
+    }
+    parse_expr(operand).map(Operand::Immediate)
+}
+
+/// Terms of a sum with their sign, `true` for the subtracted ones
+fn terms(s: &str) -> Vec<(bool, &str)> {
+    let mut terms = vec![];
+    let mut negative = false;
+    let mut start = 0;
+    let mut quote = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            '\'' => quote = !quote,
+            '+' | '-' if !quote => {
+                if !s[start..i].trim().is_empty() {
+                    terms.push((negative, s[start..i].trim()));
+                    negative = false;
+                }
+                negative ^= c == '-';
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    terms.push((negative, s[start..].trim()));
+    terms
+}
+
+fn parse_number(term: &str) -> Option<Number> {
+    if let Some(hex) = term.strip_prefix("0x").or_else(|| term.strip_prefix("0X")) {
+        return Number::from_str_radix(hex, 16).ok();
+    }
+    if let Some(c) = term.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')) {
+        let mut chars = c.chars();
+        return match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c as Number),
+            _ => None,
+        };
+    }
+    term.parse().ok()
+}
+
+fn add_term(expr: &mut Expr, negative: bool, term: &str) -> Result<()> {
+    match parse_number(term) {
+        Some(n) if negative => expr.constant = expr.constant.wrapping_sub(n),
+        Some(n) => expr.constant = expr.constant.wrapping_add(n),
+        None if term == "$"
+            || term
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.') =>
+        {
+            expr.symbols.push((negative, term.to_string()))
+        }
+        None => bail!("Invalid expression `{}`", term),
+    }
+    Ok(())
+}
+
+fn parse_expr(s: &str) -> Result<Expr> {
+    let mut expr = Expr::default();
+    for (negative, term) in terms(s) {
+        add_term(&mut expr, negative, term)?;
+    }
+    Ok(expr)
+}
+
+fn parse_memory(size: Option<usize>, inner: &str) -> Result<Memory> {
+    let mut memory = Memory {
+        size,
+        base: None,
+        index: None,
+        disp: Expr::default(),
+    };
+    for (negative, term) in terms(inner) {
+        let parts: Vec<&str> = term.split('*').map(str::trim).collect();
+        let index = match parts[..] {
+            [a, b] => match (register(a), register(b)) {
+                (Some(r), None) => Some((r, b)),
+                (None, Some(r)) => Some((r, a)),
+                _ => bail!("Invalid memory operand `[{}]`", inner),
+            },
+            _ => None,
+        };
+        match (index, register(term)) {
+            (Some((register, scale)), _) if !negative && memory.index.is_none() => {
+                let scale = match scale {
+                    "1" => 0,
+                    "2" => 1,
+                    "4" => 2,
+                    "8" => 3,
+                    _ => bail!("Invalid scale in `[{}]`", inner),
+                };
+                memory.index = Some((register, scale));
+            }
+            (None, Some(register)) if !negative && memory.base.is_none() => {
+                memory.base = Some(register)
+            }
+            (None, Some(register)) if !negative && memory.index.is_none() => {
+                memory.index = Some((register, 0))
+            }
+            (None, None) => add_term(&mut memory.disp, negative, term)?,
+            _ => bail!("Invalid memory operand `[{}]`", inner),
+        }
+    }
+    if let Some((index, _)) = memory.index {
+        if index.number == 4 {
+            bail!("The stack pointer cannot be an index: `[{}]`", inner);
+        }
+    }
+    Ok(memory)
+}
+
+fn condition(suffix: &str) -> Option<u8> {
+    CONDITIONS
+        .iter()
+        .find(|(name, _)| *name == suffix)
+        .map(|(_, code)| *code)
+}
+
+struct Encoder<'a> {
+    word: usize,
+    addresses: [u64; 3],
+    symbols: &'a mut HashMap<String, Symbol>,
+    last: bool,
+    section: Section,
+    sections: Sections,
+}
+
+impl Encoder<'_> {
+    fn offset(&self) -> usize {
+        match self.section {
+            Section::Text => self.sections.text.len(),
+            Section::Data => self.sections.data.len(),
+            Section::Bss => self.sections.bss,
+            section => self.sections.debug[section.debug().unwrap()].len(),
+        }
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        match self.section {
+            Section::Text => self.sections.text.extend_from_slice(bytes),
+            Section::Data => self.sections.data.extend_from_slice(bytes),
+            Section::Bss => bail!("Only reservations are allowed in the bss section"),
+            section => self.sections.debug[section.debug().unwrap()].extend_from_slice(bytes),
+        }
+        Ok(())
+    }
+
+    fn byte(&mut self, byte: u8) -> Result<()> {
+        self.bytes(&[byte])
+    }
+
+    fn value(&self, expr: &Expr) -> Result<Number> {
+        let mut value = expr.constant;
+        for (negative, name) in expr.symbols.iter() {
+            let symbol = match (name.as_str(), self.symbols.get(name)) {
+                ("$", _) => {
+                    start(&self.addresses, self.section) as Number + self.offset() as Number
+                }
+                (_, Some(Symbol::Label(section, offset))) => {
+                    start(&self.addresses, *section) as Number + *offset as Number
+                }
+                (_, Some(Symbol::Value(v))) => *v,
+                (_, None) if self.last => bail!("Undefined symbol `{}`", name),
+                (_, None) => 0,
+            };
+            value = if *negative {
+                value.wrapping_sub(symbol)
+            } else {
+                value.wrapping_add(symbol)
+            };
+        }
+        Ok(value)
+    }
+
+    fn imm8(&mut self, value: Number) -> Result<()> {
+        match i8::try_from(value) {
+            Ok(v) => self.byte(v as u8),
+            Err(_) if !self.last => self.byte(0),
+            Err(_) => bail!("Value {} does not fit in a byte", value),
+        }
+    }
+
+    /// 32 bit immediate, sign extended to 64 bits on x86-64 and truncated like NASM on x86
+    fn imm32(&mut self, value: Number) -> Result<()> {
+        let value = match i32::try_from(value) {
+            Ok(v) => v as u32,
+            Err(_) if self.word == 4 => value as u32,
+            Err(_) if !self.last => 0,
+            Err(_) => bail!("Value {} does not fit in 32 bits", value),
+        };
+        self.bytes(&value.to_le_bytes())
+    }
+
+    /// REX prefix, only emitted when a 64 bit operand or one of r8 to r15 needs it
+    fn rex(&mut self, wide: bool, reg: u8, index: u8, base: u8) -> Result<()> {
+        let rex = (wide as u8) << 3 | (reg >> 3) << 2 | (index >> 3) << 1 | base >> 3;
+        if rex == 0 {
+            return Ok(());
+        }
+        if self.word == 4 {
+            bail!("64 bit registers are only available on x86-64");
+        }
+        self.byte(0x40 | rex)
+    }
+
+    /// Instruction taking a ModRM byte, `reg` being either a register or an opcode extension
+    fn rm(&mut self, size: usize, opcode: &[u8], reg: u8, rm: &Operand) -> Result<()> {
+        let (index, base) = match rm {
+            Operand::Register(r) => (0, r.number),
+            Operand::Memory(m) => (
+                m.index.map_or(0, |(index, _)| index.number),
+                m.base.map_or(0, |base| base.number),
+            ),
+            Operand::Immediate(_) => bail!("Expected a register or memory operand"),
+        };
+        self.rex(size == 8, reg, index, base)?;
+        self.bytes(opcode)?;
+
+        let reg = (reg & 7) << 3;
+        let memory = match rm {
+            Operand::Register(r) => return self.byte(0xc0 | reg | r.number & 7),
+            Operand::Memory(m) => m,
+            Operand::Immediate(_) => unreachable!(),
+        };
+        let disp = self.value(&memory.disp)?;
+        match (memory.base, memory.index) {
+            // Absolute address, which needs a SIB byte on x86-64 where this ModRM is RIP relative
+            (None, None) if self.word == 8 => self.bytes(&[reg | 4, 0x25])?,
+            (None, None) => self.byte(reg | 5)?,
+            (None, Some((index, scale))) => {
+                self.bytes(&[reg | 4, scale << 6 | (index.number & 7) << 3 | 5])?
+            }
+            (Some(base), index) => {
+                let short = !memory.disp.is_symbolic() && i8::try_from(disp).is_ok();
+                let mode = if short && disp == 0 && base.number & 7 != 5 {
+                    0
+                } else if short {
+                    0x40
+                } else {
+                    0x80
+                };
+                match index {
+                    Some((index, scale)) => self.bytes(&[
+                        mode | reg | 4,
+                        scale << 6 | (index.number & 7) << 3 | base.number & 7,
+                    ])?,
+                    None if base.number & 7 == 4 => self.bytes(&[mode | reg | 4, 0x24])?,
+                    None => self.byte(mode | reg | base.number & 7)?,
+                }
+                return match mode {
+                    0 => Ok(()),
+                    0x40 => self.imm8(disp),
+                    _ => self.imm32(disp),
+                };
+            }
+        }
+        self.imm32(disp)
+    }
+
+    /// Relative jump or call to a label of the text section
+    fn relative(&mut self, opcode: &[u8], target: &Expr) -> Result<()> {
+        self.bytes(opcode)?;
+        let next = self.addresses[Section::Text as usize] as Number + self.offset() as Number + 4;
+        let target = self.value(target)?;
+        self.imm32(target - next)
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<()> {
+        match statement {
+            Statement::Section(section) => self.section = *section,
+            Statement::Label(name) => {
+                let label = Symbol::Label(self.section, self.offset());
+                self.symbols.insert(name.clone(), label);
+            }
+            Statement::Equ(name, expr) => {
+                let value = Symbol::Value(self.value(expr)?);
+                self.symbols.insert(name.clone(), value);
+            }
+            Statement::Data(size, values) => {
+                for value in values.iter() {
+                    match value {
+                        Data::String(bytes) => self.bytes(bytes)?,
+                        Data::Value(expr) => {
+                            let value = self.value(expr)?.to_le_bytes();
+                            self.bytes(&value[..*size])?;
+                        }
+                    }
+                }
+            }
+            Statement::Reserve(size, count) => match self.section {
+                Section::Bss => self.sections.bss += size * count,
+                _ => self.bytes(&vec![0; size * count])?,
+            },
+            Statement::Instruction(mnemonic, operands) => {
+                if self.section != Section::Text {
+                    bail!("Instructions are only allowed in the text section");
+                }
+                self.instruction(mnemonic, operands)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn instruction(&mut self, mnemonic: &str, operands: &[Operand]) -> Result<()> {
+        use Operand::{Immediate, Memory, Register};
+
+        let size = |operand: &Operand| match operand {
+            Register(r) => Ok(r.size),
+            Memory(m) => match m.size {
+                Some(size) => Ok(size),
+                None => bail!("Operation size not specified for `{}`", mnemonic),
+            },
+            Immediate(_) => bail!("Expected a register or memory operand for `{}`", mnemonic),
+        };
+        let arithmetic = ARITHMETIC
+            .iter()
+            .find(|(name, _)| *name == mnemonic)
+            .map(|(_, extension)| *extension);
+
+        match (mnemonic, operands) {
+            ("ret", []) => self.byte(0xc3),
+            ("syscall", []) => self.bytes(&[0x0f, 0x05]),
+            ("int", [Immediate(e)]) => {
+                self.byte(0xcd)?;
+                let value = self.value(e)?;
+                self.byte(value as u8)
+            }
+            ("cdq", []) => self.byte(0x99),
+            ("cqo", []) => self.bytes(&[0x48, 0x99]),
+            ("rep movsd", []) => self.bytes(&[0xf3, 0xa5]),
+            ("rep movsq", []) => self.bytes(&[0xf3, 0x48, 0xa5]),
+            ("rep stosd", []) => self.bytes(&[0xf3, 0xab]),
+            ("rep stosq", []) => self.bytes(&[0xf3, 0x48, 0xab]),
+
+            ("push", [Register(r)]) if r.size == self.word => {
+                self.rex(false, 0, 0, r.number)?;
+                self.byte(0x50 | r.number & 7)
+            }
+            ("pop", [Register(r)]) if r.size == self.word => {
+                self.rex(false, 0, 0, r.number)?;
+                self.byte(0x58 | r.number & 7)
+            }
+
+            ("mov", [Register(d), Immediate(e)]) => {
+                let value = self.value(e)?;
+                match d.size {
+                    8 if e.is_symbolic() || i32::try_from(value).is_ok() => {
+                        self.rm(8, &[0xc7], 0, &operands[0])?;
+                        self.imm32(value)
+                    }
+                    8 => {
+                        self.rex(true, 0, 0, d.number)?;
+                        self.byte(0xb8 | d.number & 7)?;
+                        self.bytes(&value.to_le_bytes())
+                    }
+                    4 => {
+                        self.rex(false, 0, 0, d.number)?;
+                        self.byte(0xb8 | d.number & 7)?;
+                        self.imm32(value)
+                    }
+                    _ => {
+                        self.byte(0xb0 | d.number & 7)?;
+                        self.byte(value as u8)
+                    }
+                }
+            }
+            ("mov", [d @ Memory(_), Immediate(e)]) => {
+                let size = size(d)?;
+                let value = self.value(e)?;
+                if size == 1 {
+                    self.rm(1, &[0xc6], 0, d)?;
+                    self.byte(value as u8)
+                } else {
+                    self.rm(size, &[0xc7], 0, d)?;
+                    self.imm32(value)
+                }
+            }
+            ("mov", [d, Register(s)]) => {
+                let opcode = if s.size == 1 { 0x88 } else { 0x89 };
+                self.rm(s.size, &[opcode], s.number, d)
+            }
+            ("mov", [Register(d), s @ Memory(_)]) => {
+                let opcode = if d.size == 1 { 0x8a } else { 0x8b };
+                self.rm(d.size, &[opcode], d.number, s)
+            }
+            ("lea", [Register(d), s @ Memory(_)]) => self.rm(d.size, &[0x8d], d.number, s),
+            ("movzx", [Register(d), s]) if size(s)? == 1 => {
+                self.rm(d.size, &[0x0f, 0xb6], d.number, s)
+            }
+
+            (_, [d, Register(s)]) if arithmetic.is_some() => {
+                let opcode = arithmetic.unwrap() << 3 | (s.size != 1) as u8;
+                self.rm(s.size, &[opcode], s.number, d)
+            }
+            (_, [Register(d), s @ Memory(_)]) if arithmetic.is_some() => {
+                let opcode = arithmetic.unwrap() << 3 | 2 | (d.size != 1) as u8;
+                self.rm(d.size, &[opcode], d.number, s)
+            }
+            (_, [d, Immediate(e)]) if arithmetic.is_some() => {
+                let extension = arithmetic.unwrap();
+                let size = size(d)?;
+                let value = self.value(e)?;
+                if size == 1 {
+                    self.rm(1, &[0x80], extension, d)?;
+                    self.byte(value as u8)
+                } else if !e.is_symbolic() && i8::try_from(value).is_ok() {
+                    self.rm(size, &[0x83], extension, d)?;
+                    self.imm8(value)
+                } else {
+                    self.rm(size, &[0x81], extension, d)?;
+                    self.imm32(value)
+                }
+            }
+
+            ("imul", [Register(d), s, Immediate(e)]) => {
+                let value = self.value(e)?;
+                if !e.is_symbolic() && i8::try_from(value).is_ok() {
+                    self.rm(d.size, &[0x6b], d.number, s)?;
+                    self.imm8(value)
+                } else {
+                    self.rm(d.size, &[0x69], d.number, s)?;
+                    self.imm32(value)
+                }
+            }
+            ("not" | "neg" | "mul" | "imul" | "div" | "idiv", [operand]) => {
+                let extension = match mnemonic {
+                    "not" => 2,
+                    "neg" => 3,
+                    "mul" => 4,
+                    "imul" => 5,
+                    "div" => 6,
+                    _ => 7,
+                };
+                let size = size(operand)?;
+                let opcode = if size == 1 { 0xf6 } else { 0xf7 };
+                self.rm(size, &[opcode], extension, operand)
+            }
+            ("inc" | "dec", [operand]) => {
+                let size = size(operand)?;
+                let opcode = if size == 1 { 0xfe } else { 0xff };
+                self.rm(size, &[opcode], (mnemonic == "dec") as u8, operand)
+            }
+            ("shl" | "shr" | "sar", [operand, Register(cl)]) if cl.number == 1 && cl.size == 1 => {
+                let extension = match mnemonic {
+                    "shl" => 4,
+                    "shr" => 5,
+                    _ => 7,
+                };
+                let size = size(operand)?;
+                let opcode = if size == 1 { 0xd2 } else { 0xd3 };
+                self.rm(size, &[opcode], extension, operand)
+            }
+
+            ("jmp", [Immediate(e)]) => self.relative(&[0xe9], e),
+            ("call", [Immediate(e)]) => self.relative(&[0xe8], e),
+            // Indirect jumps take a whole word without a REX prefix
+            ("jmp", [operand @ Memory(_)]) => self.rm(4, &[0xff], 4, operand),
+            (_, [Immediate(e)]) if mnemonic.starts_with('j') => match condition(&mnemonic[1..]) {
+                Some(code) => self.relative(&[0x0f, 0x80 | code], e),
+                None => bail!("Unknown instruction `{}`", mnemonic),
+            },
+            (_, [operand]) if mnemonic.starts_with("set") && size(operand)? == 1 => {
+                match condition(&mnemonic[3..]) {
+                    Some(code) => self.rm(1, &[0x0f, 0x90 | code], 0, operand),
+                    None => bail!("Unknown instruction `{}`", mnemonic),
+                }
+            }
+            (_, [Register(d), s]) if mnemonic.starts_with("cmov") => {
+                match condition(&mnemonic[4..]) {
+                    Some(code) => self.rm(d.size, &[0x0f, 0x40 | code], d.number, s),
+                    None => bail!("Unknown instruction `{}`", mnemonic),
+                }
+            }
+
+            _ => bail!(
+                "Unsupported instruction `{}` with {} operands",
+                mnemonic,
+                operands.len()
+            ),
+        }
+    }
+}
@@ -0,0 +1,1419 @@
+use crate::ast::{
+    resolve_field, BlockNode, CondNode, DeclareNode, Flow, ForNode, FuncCallNode, FuncDefNode,
+    Node, NodeRef, SwitchNode, UnaryNodeKind,
+};
+use crate::console::Console;
+use crate::error::RuntimeError;
+use crate::operator::{CondOp, Op};
+use crate::token::{Number, Position};
+use crate::variable::{Scopes, VariableData, VariableKind};
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::rc::Rc;
+
+use color_eyre::eyre::{bail, eyre, Report, Result};
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::{
+    types, AbiParam, Block, FuncRef, InstBuilder, MemFlags, Signature, StackSlot, StackSlotData,
+    StackSlotKind, UserFuncName, Value,
+};
+use cranelift_codegen::Context;
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Switch};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, FuncOrDataId, Linkage, Module};
+
+const WORD: i64 = 8;
+
+/// Where a variable is stored, every int, bool, enum and string taking a word
+#[derive(Debug, Clone, Copy)]
+pub enum Place {
+    Slot(StackSlot),
+    /// Address of the memory kept by the `Jit` for a global
+    Global(Number),
+}
+
+thread_local! {
+    /// Null terminated arguments of the program being run, its own name first
+    static ARGS: RefCell<Vec<CString>> = const { RefCell::new(vec![]) };
+    /// Where the program being run reads and writes its lines
+    static CONSOLE: RefCell<Option<Box<dyn Console>>> = const { RefCell::new(None) };
+    /// Error stopping the program being run, like the exit status of `exit`
+    static FAULT: RefCell<Option<Report>> = const { RefCell::new(None) };
+    /// Whether `FAULT` is set, which the compiled code reads after each call that can fail to
+    /// return from every function. It runs on the thread that compiled it, so the address stays
+    /// the same
+    static FAILED: Cell<Number> = const { Cell::new(0) };
+}
+
+/// Stops the program, its frames returning one after the other as they cannot be unwound
+fn fail(error: impl Into<Report>) {
+    FAULT.with(|fault| fault.replace(Some(error.into())));
+    FAILED.with(|failed| failed.set(1));
+}
+
+fn write_line(line: &str) {
+    let result = CONSOLE.with(|console| console.borrow_mut().as_mut().unwrap().write_line(line));
+    if let Err(e) = result {
+        fail(e);
+    }
+}
+
+extern "C" fn println_int(n: Number) {
+    write_line(&n.to_string());
+}
+
+extern "C" fn println_string(s: *const c_char) {
+    write_line(&string(s));
+}
+
+extern "C" fn readln() -> Number {
+    let input = CONSOLE.with(|console| console.borrow_mut().as_mut().unwrap().read_line());
+    let result = match input {
+        Ok(Some(input)) => input
+            .trim()
+            .parse()
+            .map_err(|_| RuntimeError::InvalidInput(input.trim().to_string()).into()),
+        Ok(None) => Err(RuntimeError::EndOfInput.into()),
+        Err(e) => Err(e),
+    };
+    result.unwrap_or_else(|e: Report| {
+        fail(e);
+        0
+    })
+}
+
+extern "C" fn string_eq(a: *const c_char, b: *const c_char) -> Number {
+    (string(a) == string(b)) as Number
+}
+
+extern "C" fn index_error(
+    index: Number,
+    len: Number,
+    name: *const c_char,
+    line: Number,
+    col: Number,
+) {
+    let position = Position {
+        line: line as usize,
+        col: col as usize,
+    };
+    let name = string(name).into_owned();
+    fail(RuntimeError::IndexOutOfBounds(
+        index,
+        name,
+        len as usize,
+        position,
+    ));
+}
+
+extern "C" fn division_by_zero(line: Number, col: Number) {
+    let position = Position {
+        line: line as usize,
+        col: col as usize,
+    };
+    fail(RuntimeError::DivisionByZero(position));
+}
+
+extern "C" fn argc() -> Number {
+    ARGS.with(|args| args.borrow().len() as Number)
+}
+
+extern "C" fn arg(index: Number) -> *const c_char {
+    let arg = ARGS.with(|args| {
+        let args = args.borrow();
+        match usize::try_from(index).ok().and_then(|idx| args.get(idx)) {
+            Some(arg) => Ok(arg.as_ptr()),
+            None => Err(RuntimeError::Argument(index, args.len())),
+        }
+    });
+    arg.unwrap_or_else(|e| {
+        fail(e);
+        std::ptr::null()
+    })
+}
+
+/// Stops the program like an error, `run` returning the status instead
+extern "C" fn exit(status: Number) {
+    fail(RuntimeError::Exit(status));
+}
+
+/// Strings never assigned are null and hold ""
+fn string<'a>(s: *const c_char) -> std::borrow::Cow<'a, str> {
+    if s.is_null() {
+        return "".into();
+    }
+    // Strings all come from the literals kept alive by the `Jit` while the program runs
+    unsafe { CStr::from_ptr(s) }.to_string_lossy()
+}
+
+/// Functions of Rust the program calls, with their number of parameters and whether they return
+/// a value
+const RUNTIME: [(&str, usize, bool); 9] = [
+    ("logik_println_int", 1, false),
+    ("logik_println_string", 1, false),
+    ("logik_readln", 0, true),
+    ("logik_string_eq", 2, true),
+    ("logik_index_error", 5, false),
+    ("logik_division_by_zero", 2, false),
+    ("logik_exit", 1, false),
+    ("logik_argc", 0, true),
+    ("logik_arg", 1, true),
+];
+
+/// Structs and arrays are passed around as the address of their words
+fn is_aggregate(kind: &VariableKind) -> bool {
+    matches!(kind, VariableKind::Struct(_) | VariableKind::Array(_))
+}
+
+/// Signature of a function of the program, every value taking a word. Structs and arrays are
+/// passed by address, the caller passing the address of the one returned as the first argument
+fn signature<'a>(
+    module: &JITModule,
+    kind: &VariableKind,
+    args: impl Iterator<Item = &'a VariableKind>,
+) -> Signature {
+    let mut signature = module.make_signature();
+    if is_aggregate(kind) {
+        signature.params.push(AbiParam::new(types::I64));
+    }
+    for _ in args {
+        signature.params.push(AbiParam::new(types::I64));
+    }
+    if !is_aggregate(kind) && *kind != VariableKind::None {
+        signature.returns.push(AbiParam::new(types::I64));
+    }
+    signature
+}
+
+/// Compiles the AST to Cranelift IR and runs it in this process
+pub struct Jit {
+    funcs: Rc<RefCell<HashMap<String, FuncDefNode>>>,
+    module: JITModule,
+    context: Context,
+    builder_context: FunctionBuilderContext,
+    globals: Scopes<(VariableKind, Place)>,
+    /// Words of every global, which stay at the same address while the program runs
+    memory: Vec<Box<[Number]>>,
+    /// Null terminated bytes of each string literal
+    strings: HashMap<String, Box<[u8]>>,
+    main_kind: VariableKind,
+}
+
+impl Jit {
+    pub fn new(funcs: &Rc<RefCell<HashMap<String, FuncDefNode>>>) -> Result<Self> {
+        let mut builder = JITBuilder::new(default_libcall_names())?;
+        builder.symbol("logik_println_int", println_int as *const u8);
+        builder.symbol("logik_println_string", println_string as *const u8);
+        builder.symbol("logik_readln", readln as *const u8);
+        builder.symbol("logik_string_eq", string_eq as *const u8);
+        builder.symbol("logik_index_error", index_error as *const u8);
+        builder.symbol("logik_division_by_zero", division_by_zero as *const u8);
+        builder.symbol("logik_exit", exit as *const u8);
+        builder.symbol("logik_argc", argc as *const u8);
+        builder.symbol("logik_arg", arg as *const u8);
+        let module = JITModule::new(builder);
+
+        Ok(Self {
+            funcs: funcs.clone(),
+            context: module.make_context(),
+            module,
+            builder_context: FunctionBuilderContext::new(),
+            globals: Scopes::new(),
+            memory: vec![],
+            strings: HashMap::new(),
+            main_kind: VariableKind::None,
+        })
+    }
+
+    /// Compiles the whole program and calls its `main` with the arguments `args`, reading and
+    /// writing through `console`, returning what it returned or the status passed to `exit`
+    pub fn run<'a>(
+        mut self,
+        globals: &[DeclareNode],
+        args: Vec<String>,
+        console: Box<dyn Console + 'a>,
+    ) -> Result<Number> {
+        for global in globals.iter() {
+            self.global(global);
+        }
+
+        let funcs = self.funcs.clone();
+        let funcs = funcs.borrow();
+        let mut names: Vec<&String> = funcs.keys().collect();
+        names.sort();
+        for name in names {
+            self.compile(&funcs[name])?;
+        }
+        self.module.finalize_definitions()?;
+
+        let main = match self.module.get_name("func_main") {
+            Some(FuncOrDataId::Func(id)) => id,
+            _ => bail!("No main function defined"),
+        };
+        let main = self.module.get_finalized_function(main);
+        let args = args
+            .into_iter()
+            .map(CString::new)
+            .collect::<Result<Vec<_>, _>>()?;
+        ARGS.with(|cell| cell.replace(args));
+        // The console is dropped before returning, while what it borrows is still alive
+        let console = unsafe {
+            std::mem::transmute::<Box<dyn Console + 'a>, Box<dyn Console + 'static>>(console)
+        };
+        CONSOLE.with(|cell| cell.replace(Some(console)));
+        FAILED.with(|failed| failed.set(0));
+        // The code was just compiled with the signature of `main`
+        let result = unsafe {
+            match self.main_kind {
+                VariableKind::None => {
+                    std::mem::transmute::<*const u8, extern "C" fn()>(main)();
+                    0
+                }
+                _ => std::mem::transmute::<*const u8, extern "C" fn() -> Number>(main)(),
+            }
+        };
+        unsafe { self.module.free_memory() };
+        CONSOLE.with(|cell| cell.replace(None));
+
+        if FAILED.with(|failed| failed.replace(0)) == 0 {
+            return Ok(result);
+        }
+        let error = FAULT.with(|fault| fault.take()).unwrap();
+        match error.downcast_ref::<RuntimeError>() {
+            Some(RuntimeError::Exit(status)) => Ok(*status),
+            _ => Err(error),
+        }
+    }
+
+    pub fn declare_global(&mut self, name: String, kind: VariableKind, value: Option<Number>) {
+        let mut memory = vec![0; kind.words().max(1)].into_boxed_slice();
+        for (offset, value) in kind.initial_words() {
+            memory[offset] = value;
+        }
+        match (&kind, value) {
+            (VariableKind::Bool, Some(value)) => memory[0] = (value != 0) as Number,
+            (_, Some(value)) => memory[0] = value,
+            (_, None) => {}
+        }
+        let place = Place::Global(memory.as_ptr() as Number);
+        self.memory.push(memory);
+        self.globals.declare(name, (kind, place));
+    }
+
+    /// Places of the globals, the scope every function body starts from
+    pub fn globals(&self) -> Scopes<(VariableKind, Place)> {
+        self.globals.clone()
+    }
+
+    /// Starts a function, whose instructions are emitted through the returned `JitFunction`
+    pub fn function(
+        &mut self,
+        name: &str,
+        kind: &VariableKind,
+        args: &[(VariableKind, String)],
+    ) -> Result<JitFunction<'_>> {
+        if name == "main" {
+            self.main_kind = kind.clone();
+        }
+        let signature = signature(&self.module, kind, args.iter().map(|(kind, _)| kind));
+        let id =
+            self.module
+                .declare_function(&format!("func_{}", name), Linkage::Local, &signature)?;
+        self.context.func.signature = signature;
+        self.context.func.name = UserFuncName::user(0, id.as_u32());
+
+        let mut builder = FunctionBuilder::new(&mut self.context.func, &mut self.builder_context);
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        let result = match is_aggregate(kind) {
+            true => Some(builder.block_params(entry)[0]),
+            false => None,
+        };
+
+        Ok(JitFunction {
+            builder,
+            module: &mut self.module,
+            strings: &mut self.strings,
+            entry,
+            return_kind: kind.clone(),
+            result,
+            terminated: false,
+            loop_blocks: vec![],
+            array: None,
+            unwind: None,
+        })
+    }
+
+    /// Compiles the function whose instructions were emitted since `function`
+    pub fn define(&mut self, name: &str) -> Result<()> {
+        let id = match self.module.get_name(&format!("func_{}", name)) {
+            Some(FuncOrDataId::Func(id)) => id,
+            _ => unreachable!(),
+        };
+        self.module
+            .define_function(id, &mut self.context)
+            .map_err(|e| eyre!("Could not compile {}: {:?}", name, e))?;
+        self.module.clear_context(&mut self.context);
+        Ok(())
+    }
+}
+
+/// Emits the instructions of a function, every variable living in memory. Ints, bools, enums and
+/// strings are `i64` values, structs and arrays the address of their words
+pub struct JitFunction<'a> {
+    builder: FunctionBuilder<'a>,
+    module: &'a mut JITModule,
+    strings: &'a mut HashMap<String, Box<[u8]>>,
+    entry: Block,
+    return_kind: VariableKind,
+    /// Address the struct or array returned is copied to
+    result: Option<Value>,
+    /// Whether the block being emitted already ended with a terminator
+    terminated: bool,
+    /// Blocks `(continue, break)` of the loops being emitted
+    loop_blocks: Vec<(Block, Block)>,
+    /// Address and kind of the array being declared, where its array literal stores the elements
+    array: Option<(Value, VariableKind)>,
+    /// Block returning from the function once the program failed, emitted when first needed
+    unwind: Option<Block>,
+}
+
+impl<'a> JitFunction<'a> {
+    /// Builder of the next instruction, starting a block that no branch reaches when the current
+    /// one already ended, like after a `return`
+    fn ins(&mut self) -> cranelift_frontend::FuncInstBuilder<'_, 'a> {
+        if self.terminated {
+            let block = self.builder.create_block();
+            self.builder.switch_to_block(block);
+            self.terminated = false;
+        }
+        self.builder.ins()
+    }
+
+    /// Ends the function, returning zero when it falls off its end like the other targets
+    pub fn finish(mut self) {
+        if !self.terminated {
+            match self.return_kind.clone() {
+                VariableKind::None => self.ret(None),
+                kind if is_aggregate(&kind) => {
+                    let result = self.result.unwrap();
+                    self.zero(result, &kind);
+                    self.ret(None);
+                }
+                _ => {
+                    let zero = self.int(0);
+                    self.ret(Some(zero));
+                }
+            }
+        }
+        // What a failed function returns is never used
+        if let Some(unwind) = self.unwind {
+            self.label(unwind);
+            match is_aggregate(&self.return_kind) || self.return_kind == VariableKind::None {
+                true => self.ins().return_(&[]),
+                false => {
+                    let zero = self.int(0);
+                    self.ins().return_(&[zero])
+                }
+            };
+        }
+        self.builder.seal_all_blocks();
+        self.builder.finalize();
+    }
+
+    pub fn return_kind(&self) -> &VariableKind {
+        &self.return_kind
+    }
+
+    pub fn int(&mut self, value: Number) -> Value {
+        self.ins().iconst(types::I64, value)
+    }
+
+    /// New local of the current function
+    pub fn local(&mut self, kind: &VariableKind) -> Place {
+        let size = (kind.words().max(1) as i64 * WORD) as u32;
+        let slot = self.builder.create_sized_stack_slot(StackSlotData::new(
+            StackSlotKind::ExplicitSlot,
+            size,
+            3,
+        ));
+        Place::Slot(slot)
+    }
+
+    /// Argument `idx` of the current function, copied to a local so that it can be assigned
+    pub fn param(&mut self, idx: usize, kind: &VariableKind) -> Place {
+        let idx = idx + self.result.is_some() as usize;
+        let value = self.builder.block_params(self.entry)[idx];
+        let place = self.local(kind);
+        let address = self.address(place);
+        self.store(value, kind, address);
+        place
+    }
+
+    /// Address of a variable, computed where it is used since a local may be declared in a block
+    /// that does not dominate all of its uses, like a case of a switch
+    pub fn address(&mut self, place: Place) -> Value {
+        match place {
+            Place::Slot(slot) => self.ins().stack_addr(types::I64, slot, 0),
+            Place::Global(address) => self.int(address),
+        }
+    }
+
+    /// Address of a new local holding a struct or an array being computed
+    pub fn temporary(&mut self, kind: &VariableKind) -> Value {
+        let place = self.local(kind);
+        self.address(place)
+    }
+
+    /// Address `words` words after `address`
+    pub fn offset(&mut self, address: Value, words: usize) -> Value {
+        self.ins().iadd_imm(address, words as i64 * WORD)
+    }
+
+    /// Value of the variable at `address`, structs and arrays being their address
+    pub fn load(&mut self, address: Value, kind: &VariableKind) -> Value {
+        match is_aggregate(kind) {
+            true => address,
+            false => self.ins().load(types::I64, MemFlags::trusted(), address, 0),
+        }
+    }
+
+    /// Assigns a value, copying the words of structs and arrays
+    pub fn store(&mut self, value: Value, kind: &VariableKind, address: Value) {
+        if is_aggregate(kind) {
+            let config = self.module.target_config();
+            let size = self.int(kind.words() as i64 * WORD);
+            self.builder.call_memcpy(config, address, value, size);
+        } else {
+            self.ins().store(MemFlags::trusted(), value, address, 0);
+        }
+    }
+
+    /// Gives a variable the value of one never assigned, uninitialized strings being null and enums
+    /// their first variant
+    pub fn zero(&mut self, address: Value, kind: &VariableKind) {
+        if is_aggregate(kind) {
+            let config = self.module.target_config();
+            let zero = self.ins().iconst(types::I8, 0);
+            let size = self.int(kind.words() as i64 * WORD);
+            self.builder.call_memset(config, address, zero, size);
+        } else {
+            let zero = self.int(0);
+            self.store(zero, kind, address);
+        }
+        for (offset, value) in kind.initial_words() {
+            let value = self.int(value);
+            let field = self.offset(address, offset);
+            self.store(value, &VariableKind::Number, field);
+        }
+    }
+
+    /// Value of an argument, structs and arrays being copied right away so that the following
+    /// arguments cannot modify them
+    pub fn argument(&mut self, value: Value, kind: &VariableKind) -> Value {
+        if !is_aggregate(kind) {
+            return value;
+        }
+        let copy = self.temporary(kind);
+        self.store(value, kind, copy);
+        copy
+    }
+
+    /// Points the array literal of the declaration being emitted at the array at `address`
+    pub fn set_array(&mut self, address: Value, kind: VariableKind) {
+        self.array = Some((address, kind));
+    }
+
+    pub fn take_array(&mut self) -> (Value, VariableKind) {
+        self.array
+            .take()
+            .expect("array literal outside of a declaration")
+    }
+
+    /// Converts between ints and bools, bools becoming 1 for any int but zero
+    pub fn convert(&mut self, value: Value, from: &VariableKind, to: &VariableKind) -> Value {
+        match (from, to) {
+            (VariableKind::Number | VariableKind::Enum(_), VariableKind::Bool) => {
+                let cond = self.ins().icmp_imm(IntCC::NotEqual, value, 0);
+                self.ins().uextend(types::I64, cond)
+            }
+            _ => value,
+        }
+    }
+
+    /// Operator at `position` on two ints, wrapping around like the interpreter and stopping the
+    /// program on a division by zero
+    pub fn binop(&mut self, op: Op, lhs: Value, rhs: Value, position: Position) -> Value {
+        if let Op::Div | Op::Mod = op {
+            return self.divide(op, lhs, rhs, position);
+        }
+        let ins = self.ins();
+        match op {
+            Op::Mul => ins.imul(lhs, rhs),
+            Op::Add => ins.iadd(lhs, rhs),
+            Op::Sub => ins.isub(lhs, rhs),
+            Op::BitAnd => ins.band(lhs, rhs),
+            Op::BitOr => ins.bor(lhs, rhs),
+            Op::BitXor => ins.bxor(lhs, rhs),
+            Op::Shl => ins.ishl(lhs, rhs),
+            Op::Shr => ins.sshr(lhs, rhs),
+            Op::Div | Op::Mod | Op::Not | Op::BitNot => unreachable!(),
+        }
+    }
+
+    /// Quotient or remainder of two ints. The smallest int divided by -1 traps on the hardware,
+    /// its quotient wraps around to itself and its remainder is 0 like for any other int
+    fn divide(&mut self, op: Op, lhs: Value, rhs: Value, position: Position) -> Value {
+        let error = self.block();
+        let ok = self.block();
+        self.branch(rhs, ok, &[], error, &[]);
+        self.label(error);
+        let args = [
+            self.int(position.line as Number),
+            self.int(position.col as Number),
+        ];
+        self.fail("logik_division_by_zero", &args);
+        self.label(ok);
+
+        let minus_one = self.ins().icmp_imm(IntCC::Equal, rhs, -1);
+        let one = self.int(1);
+        let divisor = self.ins().select(minus_one, one, rhs);
+        match op {
+            Op::Div => {
+                let quotient = self.ins().sdiv(lhs, divisor);
+                let negated = self.ins().ineg(lhs);
+                self.ins().select(minus_one, negated, quotient)
+            }
+            _ => self.ins().srem(lhs, divisor),
+        }
+    }
+
+    /// Compares two ints, `&&` and `||` being emitted with branches instead
+    pub fn compare(&mut self, cond: CondOp, lhs: Value, rhs: Value) -> Value {
+        let cond = match cond {
+            CondOp::LT => IntCC::SignedLessThan,
+            CondOp::LEQ => IntCC::SignedLessThanOrEqual,
+            CondOp::GT => IntCC::SignedGreaterThan,
+            CondOp::GEQ => IntCC::SignedGreaterThanOrEqual,
+            CondOp::EQ => IntCC::Equal,
+            CondOp::NEQ => IntCC::NotEqual,
+            CondOp::And | CondOp::Or => unreachable!(),
+        };
+        let value = self.ins().icmp(cond, lhs, rhs);
+        self.ins().uextend(types::I64, value)
+    }
+
+    pub fn negate(&mut self, value: Value) -> Value {
+        self.ins().ineg(value)
+    }
+
+    /// Logical not of a bool
+    pub fn not(&mut self, value: Value) -> Value {
+        self.ins().bxor_imm(value, 1)
+    }
+
+    pub fn bit_not(&mut self, value: Value) -> Value {
+        self.ins().bnot(value)
+    }
+
+    /// Address of the first byte of a string literal
+    pub fn string(&mut self, value: &str) -> Value {
+        let bytes = self.strings.entry(value.to_string()).or_insert_with(|| {
+            let mut bytes = value.as_bytes().to_vec();
+            bytes.push(0);
+            bytes.into_boxed_slice()
+        });
+        let address = bytes.as_ptr() as Number;
+        self.int(address)
+    }
+
+    /// Calls a function of the runtime
+    fn runtime(&mut self, name: &str, args: &[Value]) -> Option<Value> {
+        let &(_, params, returns) = RUNTIME.iter().find(|(n, _, _)| *n == name).unwrap();
+        let mut signature = self.module.make_signature();
+        for _ in 0..params {
+            signature.params.push(AbiParam::new(types::I64));
+        }
+        if returns {
+            signature.returns.push(AbiParam::new(types::I64));
+        }
+        let id = self
+            .module
+            .declare_function(name, Linkage::Import, &signature)
+            .unwrap();
+        let func = self.module.declare_func_in_func(id, self.builder.func);
+        self.call_ref(func, args)
+    }
+
+    fn call_ref(&mut self, func: FuncRef, args: &[Value]) -> Option<Value> {
+        let call = self.ins().call(func, args);
+        self.builder.inst_results(call).first().copied()
+    }
+
+    /// Block returning from the function once the program failed
+    fn unwind(&mut self) -> Block {
+        match self.unwind {
+            Some(block) => block,
+            None => *self.unwind.insert(self.builder.create_block()),
+        }
+    }
+
+    /// Returns from the function when the call just emitted failed the program
+    fn check_failed(&mut self) {
+        let failed = FAILED.with(|failed| failed.as_ptr() as Number);
+        let failed = self.int(failed);
+        let failed = self.ins().load(types::I64, MemFlags::trusted(), failed, 0);
+        let unwind = self.unwind();
+        let ok = self.block();
+        self.branch(failed, unwind, &[], ok, &[]);
+        self.label(ok);
+    }
+
+    /// Stops the program with the error raised by a function of the runtime
+    fn fail(&mut self, name: &str, args: &[Value]) {
+        self.runtime(name, args);
+        let unwind = self.unwind();
+        self.jump(unwind, &[]);
+    }
+
+    pub fn println(&mut self, value: Value, kind: &VariableKind) {
+        match kind {
+            VariableKind::String => self.runtime("logik_println_string", &[value]),
+            _ => self.runtime("logik_println_int", &[value]),
+        };
+        self.check_failed();
+    }
+
+    pub fn readln(&mut self) -> Value {
+        let value = self.runtime("logik_readln", &[]).unwrap();
+        self.check_failed();
+        value
+    }
+
+    /// Ends the program with `status`, the code after the call never running
+    pub fn exit(&mut self, status: Value) {
+        self.fail("logik_exit", &[status]);
+    }
+
+    pub fn argc(&mut self) -> Value {
+        self.runtime("logik_argc", &[]).unwrap()
+    }
+
+    /// Address of the argument of the program at `index`, stopping the program outside of them
+    pub fn arg(&mut self, index: Value) -> Value {
+        let value = self.runtime("logik_arg", &[index]).unwrap();
+        self.check_failed();
+        value
+    }
+
+    pub fn string_eq(&mut self, lhs: Value, rhs: Value) -> Value {
+        self.runtime("logik_string_eq", &[lhs, rhs]).unwrap()
+    }
+
+    /// Calls a function of the program, passing the address of a new local for the struct or
+    /// array it returns
+    pub fn call(
+        &mut self,
+        name: &str,
+        kind: &VariableKind,
+        args: &[(Value, VariableKind)],
+    ) -> Option<Value> {
+        let signature = signature(self.module, kind, args.iter().map(|(_, kind)| kind));
+        let id = self
+            .module
+            .declare_function(&format!("func_{}", name), Linkage::Local, &signature)
+            .unwrap();
+        let func = self.module.declare_func_in_func(id, self.builder.func);
+
+        let mut values = vec![];
+        let result = match is_aggregate(kind) {
+            true => Some(self.temporary(kind)),
+            false => None,
+        };
+        values.extend(result);
+        values.extend(args.iter().map(|(value, _)| *value));
+        let value = self.call_ref(func, &values);
+        self.check_failed();
+        result.or(value)
+    }
+
+    /// Returns from the function, copying structs and arrays to the address given by the caller
+    pub fn ret(&mut self, value: Option<Value>) {
+        match (value, self.result) {
+            (Some(value), Some(result)) => {
+                let kind = self.return_kind.clone();
+                self.store(value, &kind, result);
+                self.ins().return_(&[]);
+            }
+            (Some(value), None) => {
+                self.ins().return_(&[value]);
+            }
+            (None, _) => {
+                self.ins().return_(&[]);
+            }
+        }
+        self.terminated = true;
+    }
+
+    /// Index stopping the program when it is out of the bounds of the array `name` of kind
+    /// `kind`, indexed at `position`
+    pub fn bounds_check(
+        &mut self,
+        index: Value,
+        name: &str,
+        kind: &VariableKind,
+        position: Position,
+    ) -> Value {
+        let len = match kind {
+            VariableKind::Array(len) => *len as i64,
+            _ => unreachable!(),
+        };
+        // Compared as unsigned, a negative index is huge and branches to the error block as well
+        let out = self
+            .ins()
+            .icmp_imm(IntCC::UnsignedGreaterThanOrEqual, index, len);
+        let error = self.block();
+        let ok = self.block();
+        self.branch(out, error, &[], ok, &[]);
+        self.label(error);
+        let args = [
+            index,
+            self.int(len),
+            self.string(name),
+            self.int(position.line as Number),
+            self.int(position.col as Number),
+        ];
+        self.fail("logik_index_error", &args);
+        self.label(ok);
+        index
+    }
+
+    /// Address of an element of an array, the index being already checked
+    pub fn element(&mut self, address: Value, index: Value) -> Value {
+        let offset = self.ins().imul_imm(index, WORD);
+        self.ins().iadd(address, offset)
+    }
+
+    pub fn block(&mut self) -> Block {
+        self.builder.create_block()
+    }
+
+    /// Adds a parameter to a block, taking the value passed by each jump to it
+    pub fn block_param(&mut self, block: Block) -> Value {
+        self.builder.append_block_param(block, types::I64)
+    }
+
+    /// Starts emitting a block, which the current one falls through into when it did not end yet
+    pub fn label(&mut self, block: Block) {
+        if !self.terminated {
+            self.builder.ins().jump(block, &[]);
+        }
+        self.builder.switch_to_block(block);
+        self.terminated = false;
+    }
+
+    pub fn jump(&mut self, block: Block, args: &[Value]) {
+        if !self.terminated {
+            self.builder.ins().jump(block, args);
+            self.terminated = true;
+        }
+    }
+
+    /// Jumps to `then_block` if `cond` is not zero, to `else_block` otherwise
+    pub fn branch(
+        &mut self,
+        cond: Value,
+        then_block: Block,
+        then_args: &[Value],
+        else_block: Block,
+        else_args: &[Value],
+    ) {
+        self.ins()
+            .brif(cond, then_block, then_args, else_block, else_args);
+        self.terminated = true;
+    }
+
+    /// Jumps to the block of the case matching `value`, or to `default`
+    pub fn switch(&mut self, value: Value, cases: &[(Number, Block)], default: Block) {
+        let mut switch = Switch::new();
+        for (case, block) in cases.iter() {
+            switch.set_entry(*case as u64 as u128, *block);
+        }
+        if self.terminated {
+            self.ins();
+        }
+        switch.emit(&mut self.builder, value, default);
+        self.terminated = true;
+    }
+
+    /// Enters a loop, `continue_block` and `break_block` being where its jumps go
+    pub fn push_loop(&mut self, continue_block: Block, break_block: Block) {
+        self.loop_blocks.push((continue_block, break_block));
+    }
+
+    /// Enters a switch, which `break` leaves while `continue` still targets the enclosing loop
+    pub fn push_switch(&mut self, break_block: Block) {
+        let continue_block = self
+            .loop_blocks
+            .last()
+            .map(|(block, _)| *block)
+            .unwrap_or(break_block);
+        self.loop_blocks.push((continue_block, break_block));
+    }
+
+    pub fn pop_loop(&mut self) {
+        self.loop_blocks.pop();
+    }
+
+    pub fn loop_blocks(&self) -> (Block, Block) {
+        *self
+            .loop_blocks
+            .last()
+            .expect("loop jump outside of a loop")
+    }
+}
+
+// Lowering of the nodes, each returning the value it evaluates to along with its kind
+impl Jit {
+    fn global(&mut self, global: &DeclareNode) {
+        if global.constant {
+            return;
+        }
+        let value = global.expression.as_ref().and_then(|e| e.const_eval());
+        self.declare_global(global.name.clone(), global.kind.clone(), value);
+    }
+
+    fn compile(&mut self, func: &FuncDefNode) -> Result<()> {
+        let mut vars = self.globals();
+        vars.push_scope();
+
+        let mut function = self.function(&func.name, &func.kind, &func.args)?;
+        for (idx, (kind, name)) in func.args.iter().enumerate() {
+            let place = function.param(idx, kind);
+            vars.declare(name.clone(), (kind.clone(), place));
+        }
+
+        function.block_node(&func.code, &mut vars)?;
+        function.finish();
+        self.define(&func.name)
+    }
+}
+
+impl<'a> JitFunction<'a> {
+    fn node(
+        &mut self,
+        node: &dyn Node,
+        vars: &mut Scopes<(VariableKind, Place)>,
+    ) -> Result<Option<(Value, VariableKind)>> {
+        match node.node_ref() {
+            NodeRef::Binary(node) => {
+                let lhs = self.number(node.left_child.as_ref(), vars)?;
+                let rhs = self.number(node.right_child.as_ref(), vars)?;
+                let value = self.binop(node.op, lhs, rhs, node.position);
+                Ok(Some((value, VariableKind::Number)))
+            }
+            NodeRef::Unary(node) => {
+                let (child, kind) = self.value(node.child.as_ref(), vars)?;
+                Ok(Some(match node.kind {
+                    UnaryNodeKind::Pos => (child, kind),
+                    UnaryNodeKind::Neg => {
+                        let child = self.convert(child, &kind, &VariableKind::Number);
+                        (self.negate(child), VariableKind::Number)
+                    }
+                    UnaryNodeKind::Not => {
+                        let child = self.convert(child, &kind, &VariableKind::Bool);
+                        (self.not(child), VariableKind::Bool)
+                    }
+                    UnaryNodeKind::BitNot => {
+                        let child = self.convert(child, &kind, &VariableKind::Number);
+                        (self.bit_not(child), VariableKind::Number)
+                    }
+                }))
+            }
+            NodeRef::Number(node) => Ok(Some((
+                self.number(node.child.as_ref(), vars)?,
+                VariableKind::Number,
+            ))),
+            NodeRef::NumberLiteral(node) => Ok(Some((self.int(node.value), VariableKind::Number))),
+            NodeRef::SimpleVariable(node) => Ok(Some(match node.value {
+                VariableData::Bool(b) => (self.int(b as Number), VariableKind::Bool),
+                _ => (self.int(node.value.to_number()), VariableKind::Number),
+            })),
+            NodeRef::Bool(node) => Ok(Some((
+                self.condition(node.child.as_ref(), vars)?,
+                VariableKind::Bool,
+            ))),
+            NodeRef::BoolLiteral(node) => {
+                Ok(Some((self.int(node.value as Number), VariableKind::Bool)))
+            }
+            NodeRef::String(node) => self.node(node.child.as_ref(), vars),
+            NodeRef::StringLiteral(node) => {
+                Ok(Some((self.string(&node.value), VariableKind::String)))
+            }
+            NodeRef::Declare(node) => {
+                if node.constant {
+                    return Ok(None);
+                }
+
+                let place = self.local(&node.kind);
+                match (&node.kind, &node.expression) {
+                    (VariableKind::Array(_), Some(e)) => {
+                        let address = self.address(place);
+                        self.zero(address, &node.kind);
+                        self.set_array(address, node.kind.clone());
+                        self.node(e.as_ref(), vars)?;
+                    }
+                    // Computed while the name still refers to the variable being shadowed
+                    (_, Some(e)) => {
+                        let value = self.value(e.as_ref(), vars)?;
+                        let address = self.address(place);
+                        self.update(address, &node.kind, None, value);
+                    }
+                    (_, None) => {
+                        let address = self.address(place);
+                        self.zero(address, &node.kind);
+                    }
+                }
+                vars.declare(node.name.clone(), (node.kind.clone(), place));
+                Ok(None)
+            }
+            NodeRef::Assign(node) => {
+                let value = self.value(node.expression.as_ref(), vars)?;
+                let (kind, place) = vars.get(&node.name).unwrap();
+                let address = self.address(*place);
+                let op = node.op.map(|op| (op, node.position));
+                self.update(address, kind, op, value);
+                Ok(None)
+            }
+            NodeRef::IncDec(node) => {
+                let (kind, place) = vars.get(&node.name).unwrap();
+                let address = self.address(*place);
+                let old = self.load(address, kind);
+                let old = self.convert(old, kind, &VariableKind::Number);
+                let one = self.int(1);
+                let new = self.binop(node.op, old, one, node.position);
+                let value = self.convert(new, &VariableKind::Number, kind);
+                self.store(value, kind, address);
+                Ok(Some((
+                    if node.prefix { new } else { old },
+                    VariableKind::Number,
+                )))
+            }
+            NodeRef::Variable(node) => {
+                let (kind, place) = vars.get(&node.name).unwrap();
+                let address = self.address(*place);
+                Ok(Some((self.load(address, kind), kind.clone())))
+            }
+            NodeRef::ArrayLiteral(node) => {
+                let (address, _) = self.take_array();
+                for (idx, element) in node.elements.iter().enumerate() {
+                    let value = self.number(element.as_ref(), vars)?;
+                    let element = self.offset(address, idx);
+                    self.store(value, &VariableKind::Number, element);
+                }
+                Ok(None)
+            }
+            NodeRef::Index(node) => {
+                let index = self.number(node.index.as_ref(), vars)?;
+                let (kind, place) = vars.get(&node.name).unwrap();
+                let element = self.checked_element(&node.name, *place, kind, index, node.position);
+                Ok(Some((
+                    self.load(element, &VariableKind::Number),
+                    VariableKind::Number,
+                )))
+            }
+            NodeRef::IndexAssign(node) => {
+                let value = self.value(node.expression.as_ref(), vars)?;
+                let index = self.number(node.index.as_ref(), vars)?;
+                let (kind, place) = vars.get(&node.name).unwrap();
+                let element = self.checked_element(&node.name, *place, kind, index, node.position);
+                let op = node.op.map(|op| (op, node.position));
+                self.update(element, &VariableKind::Number, op, value);
+                Ok(None)
+            }
+            NodeRef::Len(node) => match vars.get(&node.name) {
+                Some((VariableKind::Array(len), _)) => {
+                    Ok(Some((self.int(*len as Number), VariableKind::Number)))
+                }
+                _ => unreachable!(),
+            },
+            NodeRef::StructLiteral(node) => {
+                let kind = VariableKind::Struct(node.kind.clone());
+                let address = self.temporary(&kind);
+                let mut offset = 0;
+                for (field, (_, field_kind)) in node.fields.iter().zip(node.kind.fields.iter()) {
+                    let (field, field_value_kind) = self.value(field.as_ref(), vars)?;
+                    let field = self.convert(field, &field_value_kind, field_kind);
+                    let field_address = self.offset(address, offset);
+                    self.store(field, field_kind, field_address);
+                    offset += field_kind.words();
+                }
+                Ok(Some((address, kind)))
+            }
+            NodeRef::Field(node) => {
+                let (kind, place) = vars.get(&node.name).unwrap();
+                let (_, offset, field_kind) = resolve_field(kind, &node.path)?;
+                let address = self.address(*place);
+                let field = self.offset(address, offset);
+                Ok(Some((self.load(field, &field_kind), field_kind)))
+            }
+            NodeRef::FieldAssign(node) => {
+                let value = self.value(node.expression.as_ref(), vars)?;
+                let (kind, place) = vars.get(&node.name).unwrap();
+                let (_, offset, field_kind) = resolve_field(kind, &node.path)?;
+                let address = self.address(*place);
+                let field = self.offset(address, offset);
+                let op = node.op.map(|op| (op, node.position));
+                self.update(field, &field_kind, op, value);
+                Ok(None)
+            }
+            NodeRef::EnumVariant(node) => Ok(Some((
+                self.int(node.value),
+                VariableKind::Enum(node.kind.clone()),
+            ))),
+            NodeRef::Cond(node) => self.cond_node(node, vars),
+            NodeRef::Ternary(node) => {
+                let then_block = self.block();
+                let else_block = self.block();
+                let end_block = self.block();
+                let value = self.block_param(end_block);
+
+                let cond = self.condition(node.cond.as_ref(), vars)?;
+                self.branch(cond, then_block, &[], else_block, &[]);
+                self.label(then_block);
+                let (if_value, kind) = self.value(node.if_child.as_ref(), vars)?;
+                self.jump(end_block, &[if_value]);
+                self.label(else_block);
+                let (else_value, else_kind) = self.value(node.else_child.as_ref(), vars)?;
+                let else_value = self.convert(else_value, &else_kind, &kind);
+                self.jump(end_block, &[else_value]);
+                self.label(end_block);
+                Ok(Some((value, kind)))
+            }
+            NodeRef::If(node) => {
+                let then_block = self.block();
+                let else_block = self.block();
+                let end_block = self.block();
+
+                let cond = self.condition(node.cond.as_ref(), vars)?;
+                match node.else_child {
+                    Some(_) => self.branch(cond, then_block, &[], else_block, &[]),
+                    None => self.branch(cond, then_block, &[], end_block, &[]),
+                }
+                self.label(then_block);
+                self.node(node.if_child.as_ref(), vars)?;
+                if let Some(e) = &node.else_child {
+                    self.jump(end_block, &[]);
+                    self.label(else_block);
+                    self.node(e.as_ref(), vars)?;
+                }
+                self.label(end_block);
+                Ok(None)
+            }
+            NodeRef::While(node) => {
+                let cond_block = self.block();
+                let body_block = self.block();
+                let end_block = self.block();
+
+                self.label(cond_block);
+                let cond = self.condition(node.cond.as_ref(), vars)?;
+                self.branch(cond, body_block, &[], end_block, &[]);
+                self.label(body_block);
+                self.push_loop(cond_block, end_block);
+                self.node(node.child.as_ref(), vars)?;
+                self.pop_loop();
+                self.jump(cond_block, &[]);
+                self.label(end_block);
+                Ok(None)
+            }
+            NodeRef::For(node) => self.for_node(node, vars),
+            NodeRef::DoWhile(node) => {
+                let body_block = self.block();
+                let cond_block = self.block();
+                let end_block = self.block();
+
+                self.label(body_block);
+                self.push_loop(cond_block, end_block);
+                self.node(node.child.as_ref(), vars)?;
+                self.pop_loop();
+                self.label(cond_block);
+                let cond = self.condition(node.cond.as_ref(), vars)?;
+                self.branch(cond, body_block, &[], end_block, &[]);
+                self.label(end_block);
+                Ok(None)
+            }
+            NodeRef::Switch(node) => self.switch_node(node, vars),
+            NodeRef::LoopJump(node) => {
+                let (continue_block, break_block) = self.loop_blocks();
+                let block = match node.flow {
+                    Flow::Break => break_block,
+                    Flow::Continue => continue_block,
+                    Flow::Normal | Flow::Return(_) => unreachable!(),
+                };
+                self.jump(block, &[]);
+                Ok(None)
+            }
+            NodeRef::Block(node) => self.block_node(node, vars),
+            NodeRef::FuncCall(node) => self.func_call_node(node, vars),
+            NodeRef::Return(node) => {
+                let value = match &node.child {
+                    Some(child) => self.node(child.as_ref(), vars)?,
+                    None => None,
+                };
+                match value {
+                    Some((value, kind)) => {
+                        let return_kind = self.return_kind().clone();
+                        let value = self.convert(value, &kind, &return_kind);
+                        self.ret(Some(value));
+                    }
+                    None => self.ret(None),
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// Emits a node whose value is used
+    fn value(
+        &mut self,
+        node: &dyn Node,
+        vars: &mut Scopes<(VariableKind, Place)>,
+    ) -> Result<(Value, VariableKind)> {
+        Ok(self.node(node, vars)?.expect("node without a value"))
+    }
+
+    /// Emits a node whose value is used as an int
+    fn number(
+        &mut self,
+        node: &dyn Node,
+        vars: &mut Scopes<(VariableKind, Place)>,
+    ) -> Result<Value> {
+        let (value, kind) = self.value(node, vars)?;
+        Ok(self.convert(value, &kind, &VariableKind::Number))
+    }
+
+    /// Emits a condition, returning it as 0 or 1
+    fn condition(
+        &mut self,
+        node: &dyn Node,
+        vars: &mut Scopes<(VariableKind, Place)>,
+    ) -> Result<Value> {
+        let (value, kind) = self.value(node, vars)?;
+        Ok(self.convert(value, &kind, &VariableKind::Bool))
+    }
+
+    /// Stores `value` at `address`, combining it with the value already there for compound
+    /// assignments, whose operator is at the position given with it
+    fn update(
+        &mut self,
+        address: Value,
+        target: &VariableKind,
+        op: Option<(Op, Position)>,
+        (value, kind): (Value, VariableKind),
+    ) {
+        let (value, kind) = match op {
+            Some((op, position)) => {
+                let current = self.load(address, target);
+                let current = self.convert(current, target, &VariableKind::Number);
+                let value = self.convert(value, &kind, &VariableKind::Number);
+                (
+                    self.binop(op, current, value, position),
+                    VariableKind::Number,
+                )
+            }
+            None => (value, kind),
+        };
+        let value = self.convert(value, &kind, target);
+        self.store(value, target, address);
+    }
+
+    /// Address of an element of the array `name`, stopping the program when the index at
+    /// `position` is out of bounds
+    fn checked_element(
+        &mut self,
+        name: &str,
+        place: Place,
+        kind: &VariableKind,
+        index: Value,
+        position: Position,
+    ) -> Value {
+        let index = self.bounds_check(index, name, kind, position);
+        let address = self.address(place);
+        self.element(address, index)
+    }
+
+    fn cond_node(
+        &mut self,
+        node: &CondNode,
+        vars: &mut Scopes<(VariableKind, Place)>,
+    ) -> Result<Option<(Value, VariableKind)>> {
+        // `&&` and `||` evaluate their right side only when needed, like `eval` does
+        if let CondOp::And | CondOp::Or = node.cond {
+            let rhs_block = self.block();
+            let end_block = self.block();
+            let value = self.block_param(end_block);
+
+            let lhs = self.condition(node.left_child.as_ref(), vars)?;
+            if node.cond == CondOp::And {
+                self.branch(lhs, rhs_block, &[], end_block, &[lhs]);
+            } else {
+                self.branch(lhs, end_block, &[lhs], rhs_block, &[]);
+            }
+            self.label(rhs_block);
+            let rhs = self.condition(node.right_child.as_ref(), vars)?;
+            self.jump(end_block, &[rhs]);
+            self.label(end_block);
+            return Ok(Some((value, VariableKind::Bool)));
+        }
+
+        let (lhs, kind) = self.value(node.left_child.as_ref(), vars)?;
+        let (rhs, rhs_kind) = self.value(node.right_child.as_ref(), vars)?;
+        if kind == VariableKind::String {
+            let eq = self.string_eq(lhs, rhs);
+            let value = match node.cond {
+                CondOp::EQ => eq,
+                _ => self.not(eq),
+            };
+            return Ok(Some((value, VariableKind::Bool)));
+        }
+        let lhs = self.convert(lhs, &kind, &VariableKind::Number);
+        let rhs = self.convert(rhs, &rhs_kind, &VariableKind::Number);
+        Ok(Some((
+            self.compare(node.cond, lhs, rhs),
+            VariableKind::Bool,
+        )))
+    }
+
+    fn for_node(
+        &mut self,
+        node: &ForNode,
+        vars: &mut Scopes<(VariableKind, Place)>,
+    ) -> Result<Option<(Value, VariableKind)>> {
+        let cond_block = self.block();
+        let body_block = self.block();
+        let step_block = self.block();
+        let end_block = self.block();
+
+        vars.push_scope();
+        if let Some(init) = &node.init {
+            self.node(init.as_ref(), vars)?;
+        }
+        self.label(cond_block);
+        if let Some(cond) = &node.cond {
+            let cond = self.condition(cond.as_ref(), vars)?;
+            self.branch(cond, body_block, &[], end_block, &[]);
+        }
+        self.label(body_block);
+        self.push_loop(step_block, end_block);
+        self.node(node.child.as_ref(), vars)?;
+        self.pop_loop();
+        self.label(step_block);
+        if let Some(step) = &node.step {
+            self.node(step.as_ref(), vars)?;
+        }
+        self.jump(cond_block, &[]);
+        self.label(end_block);
+        vars.pop_scope();
+        Ok(None)
+    }
+
+    fn switch_node(
+        &mut self,
+        node: &SwitchNode,
+        vars: &mut Scopes<(VariableKind, Place)>,
+    ) -> Result<Option<(Value, VariableKind)>> {
+        let end_block = self.block();
+        let case_blocks: Vec<Block> = node.cases.iter().map(|_| self.block()).collect();
+
+        let value = self.number(node.expression.as_ref(), vars)?;
+        let default_block = match node.cases.iter().position(|(label, _)| label.is_none()) {
+            Some(idx) => case_blocks[idx],
+            None => end_block,
+        };
+        let cases: Vec<(Number, Block)> = node
+            .cases
+            .iter()
+            .zip(case_blocks.iter())
+            .filter_map(|((label, _), block)| label.map(|v| (v, *block)))
+            .collect();
+        self.switch(value, &cases, default_block);
+
+        self.push_switch(end_block);
+        vars.push_scope();
+        for ((_, children), block) in node.cases.iter().zip(case_blocks) {
+            self.label(block);
+            for child in children.iter() {
+                self.node(child.as_ref(), vars)?;
+            }
+        }
+        vars.pop_scope();
+        self.pop_loop();
+        self.label(end_block);
+        Ok(None)
+    }
+
+    fn block_node(
+        &mut self,
+        node: &BlockNode,
+        vars: &mut Scopes<(VariableKind, Place)>,
+    ) -> Result<Option<(Value, VariableKind)>> {
+        vars.push_scope();
+        for child in node.children.iter() {
+            self.node(child.as_ref(), vars)?;
+        }
+        vars.pop_scope();
+        Ok(None)
+    }
+
+    fn func_call_node(
+        &mut self,
+        node: &FuncCallNode,
+        vars: &mut Scopes<(VariableKind, Place)>,
+    ) -> Result<Option<(Value, VariableKind)>> {
+        let fborrow = node.funcs.borrow(); // NOTE: borrow
+        let params = node.params.borrow();
+
+        match node.name.as_ref() {
+            "println" | "print" => {
+                let (value, kind) = self.value(params[0].as_ref(), vars)?;
+                let value = match kind {
+                    VariableKind::String => value,
+                    _ => self.convert(value, &kind, &VariableKind::Number),
+                };
+                self.println(value, &kind);
+                Ok(None)
+            }
+            "readln" => Ok(Some((self.readln(), VariableKind::Number))),
+            "exit" => {
+                let value = self.number(params[0].as_ref(), vars)?;
+                self.exit(value);
+                Ok(None)
+            }
+            "argc" => Ok(Some((self.argc(), VariableKind::Number))),
+            "arg" => {
+                let value = self.number(params[0].as_ref(), vars)?;
+                Ok(Some((self.arg(value), VariableKind::String)))
+            }
+            _ => {
+                let func = fborrow.get(&node.name).unwrap();
+                let mut args = vec![];
+                for (param, (kind, _)) in params.iter().zip(func.args.iter()) {
+                    let (value, value_kind) = self.value(param.as_ref(), vars)?;
+                    let value = self.convert(value, &value_kind, kind);
+                    args.push((self.argument(value, kind), kind.clone()));
+                }
+
+                let value = self.call(&node.name, &func.kind, &args);
+                match func.kind {
+                    VariableKind::None => Ok(None),
+                    _ => Ok(Some((value.unwrap(), func.kind.clone()))),
+                }
+            }
+        }
+    }
+}
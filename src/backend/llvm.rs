@@ -0,0 +1,1113 @@
+use crate::ast::{
+    resolve_field, BlockNode, CondNode, DeclareNode, Flow, ForNode, FuncCallNode, FuncDefNode,
+    Node, NodeRef, SwitchNode, UnaryNodeKind,
+};
+use crate::operator::{CondOp, Op};
+use crate::token::{Number, Position};
+use crate::variable::{Scopes, StructType, VariableData, VariableKind};
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use color_eyre::eyre::Result;
+
+const RUNTIME: &str = include_str!("../base.ll");
+/// Globals of the runtime, which no global of the program may take
+const RESERVED: [&str; 6] = ["main", "printf", "scanf", "dprintf", "strcmp", "exit"];
+
+/// Emits an LLVM IR text module straight from the AST. Every variable lives in an `alloca` of the
+/// entry block, leaving `mem2reg` to promote them to registers
+pub struct Llvm {
+    funcs: Rc<RefCell<HashMap<String, FuncDefNode>>>,
+    globals: Scopes<(VariableKind, String)>,
+    global_names: HashSet<String>,
+    declarations: String,
+    /// Global holding each string literal, shared by the literals with the same value
+    strings: HashMap<String, String>,
+    constants: String,
+    functions: String,
+
+    /// Instructions of the function being emitted
+    code: Vec<String>,
+    allocas: Vec<String>,
+    params: Vec<String>,
+    names: HashSet<String>,
+    temporaries: usize,
+    /// Label of the basic block being emitted
+    block: String,
+    /// Whether the basic block being emitted already ended with a terminator
+    terminated: bool,
+    return_kind: VariableKind,
+    /// Kind returned by `main`, called by the `main` of the module
+    main_kind: VariableKind,
+    id: usize,
+    loop_labels: Vec<(String, String)>,
+    /// Pointer to and kind of the array being declared, where its array literal stores the elements
+    array: Option<(String, VariableKind)>,
+}
+
+/// Name of a global in LLVM, those clashing with the runtime being renamed
+fn identifier(name: &str) -> String {
+    if RESERVED.contains(&name) || name.starts_with("logik_") || name.starts_with("func_") {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Type of a value in LLVM, ints being `i64` and bools `i1`
+fn llvm_type(kind: &VariableKind) -> String {
+    match kind {
+        VariableKind::Number | VariableKind::Enum(_) => "i64".to_string(),
+        VariableKind::Bool => "i1".to_string(),
+        VariableKind::String => "i8*".to_string(),
+        VariableKind::Struct(t) => format!("%struct.{}", t.name),
+        VariableKind::Array(len) => format!("[{} x i64]", len),
+        VariableKind::None => "void".to_string(),
+    }
+}
+
+/// Value of a variable never assigned, uninitialized strings being null and enums their first
+/// variant
+fn zero(kind: &VariableKind) -> String {
+    match kind {
+        VariableKind::Number => "0".to_string(),
+        VariableKind::Enum(t) => t.first().to_string(),
+        VariableKind::Bool => "false".to_string(),
+        VariableKind::String => "null".to_string(),
+        VariableKind::Struct(t) if !kind.initial_words().is_empty() => {
+            let fields: Vec<String> = t
+                .fields
+                .iter()
+                .map(|(_, kind)| format!("{} {}", llvm_type(kind), zero(kind)))
+                .collect();
+            format!("{{ {} }}", fields.join(", "))
+        }
+        VariableKind::Struct(_) | VariableKind::Array(_) => "zeroinitializer".to_string(),
+        VariableKind::None => unreachable!(),
+    }
+}
+
+/// Contents of a string constant, terminated by a null byte, along with its length
+fn string_constant(value: &str) -> (String, usize) {
+    let mut literal = String::from("c\"");
+    for byte in value.bytes() {
+        match byte {
+            0x20..=0x7e if byte != b'"' && byte != b'\\' => literal.push(byte as char),
+            _ => literal.push_str(&format!("\\{:02X}", byte)),
+        }
+    }
+    literal.push_str("\\00\"");
+    (literal, value.len() + 1)
+}
+
+fn define_struct(t: &StructType) -> String {
+    let fields: Vec<String> = t.fields.iter().map(|(_, kind)| llvm_type(kind)).collect();
+    if fields.is_empty() {
+        format!("%struct.{} = type {{}}\n", t.name)
+    } else {
+        format!("%struct.{} = type {{ {} }}\n", t.name, fields.join(", "))
+    }
+}
+
+impl Llvm {
+    pub fn new(funcs: &Rc<RefCell<HashMap<String, FuncDefNode>>>) -> Self {
+        Self {
+            funcs: funcs.clone(),
+            globals: Scopes::new(),
+            global_names: HashSet::new(),
+            declarations: String::new(),
+            strings: HashMap::new(),
+            constants: String::new(),
+            functions: String::new(),
+            code: vec![],
+            allocas: vec![],
+            params: vec![],
+            names: HashSet::new(),
+            temporaries: 0,
+            block: String::new(),
+            terminated: false,
+            return_kind: VariableKind::None,
+            main_kind: VariableKind::None,
+            id: 0,
+            loop_labels: vec![],
+            array: None,
+        }
+    }
+
+    /// Emits the whole module, whose `main` calls the `main` of logik
+    pub fn module(
+        &mut self,
+        structs: &HashMap<String, Rc<StructType>>,
+        globals: &[DeclareNode],
+    ) -> Result<String> {
+        for global in globals.iter() {
+            self.global(global);
+        }
+
+        let funcs = self.funcs.clone();
+        let funcs = funcs.borrow();
+        let mut names: Vec<&String> = funcs.keys().collect();
+        names.sort();
+        for name in names {
+            self.function(&funcs[name])?;
+        }
+
+        let mut module = String::from(RUNTIME);
+        let mut struct_names: Vec<&String> = structs.keys().collect();
+        struct_names.sort();
+        if !struct_names.is_empty() {
+            module.push('\n');
+        }
+        for name in struct_names {
+            module.push_str(&define_struct(&structs[name]));
+        }
+
+        if !self.constants.is_empty() || !self.declarations.is_empty() {
+            module.push('\n');
+        }
+        module.push_str(&self.constants);
+        module.push_str(&self.declarations);
+        module.push_str(&self.functions);
+
+        // What `main` returns is the exit status of the program
+        let call = format!("call {} @func_main()", llvm_type(&self.main_kind));
+        let body = match &self.main_kind {
+            VariableKind::None => format!("  {}\n  ret i32 0", call),
+            kind => {
+                let convert = match kind {
+                    VariableKind::Bool => "zext i1",
+                    _ => "trunc i64",
+                };
+                format!(
+                    "  %result = {}\n  %status = {} %result to i32\n  ret i32 %status",
+                    call, convert
+                )
+            }
+        };
+        module.push_str("\ndefine i32 @main(i32 %argc, i8** %argv) {\n");
+        module.push_str("  %count = sext i32 %argc to i64\n  store i64 %count, i64* @logik_argc\n");
+        module.push_str("  store i8** %argv, i8*** @logik_argv\n");
+        module.push_str(&format!("{}\n}}\n", body));
+        Ok(module)
+    }
+
+    /// Appends an instruction, starting a block that no branch reaches when the current one
+    /// already ended, like after a `return`
+    pub fn line(&mut self, s: &str) {
+        if self.terminated {
+            let label = format!("dead.{}", self.next_id());
+            self.code.push(format!("{}:", label));
+            self.block = label;
+            self.terminated = false;
+        }
+        self.code.push(format!("  {}", s));
+    }
+
+    /// Appends an instruction producing a value, returning the temporary holding it
+    pub fn assign(&mut self, s: &str) -> String {
+        self.temporaries += 1;
+        let temporary = format!("%t.{}", self.temporaries);
+        self.line(&format!("{} = {}", temporary, s));
+        temporary
+    }
+
+    /// Ends the current block with a terminator
+    pub fn terminate(&mut self, s: &str) {
+        self.line(s);
+        self.terminated = true;
+    }
+
+    /// Starts a new block, which the current one falls through into when it did not end yet
+    pub fn label(&mut self, label: &str) {
+        if !self.terminated {
+            self.code.push(format!("  br label %{}", label));
+        }
+        self.code.push(format!("{}:", label));
+        self.block = label.to_string();
+        self.terminated = false;
+    }
+
+    /// Label of the block being emitted, which a `phi` names as the origin of its value
+    pub fn block(&self) -> String {
+        self.block.clone()
+    }
+
+    pub fn jump(&mut self, label: &str) {
+        if !self.terminated {
+            self.terminate(&format!("br label %{}", label));
+        }
+    }
+
+    pub fn branch(&mut self, cond: &str, then_label: &str, else_label: &str) {
+        self.terminate(&format!(
+            "br i1 {}, label %{}, label %{}",
+            cond, then_label, else_label
+        ));
+    }
+
+    /// Starts a function, only the name of its entry block being taken
+    pub fn begin_function(&mut self, kind: VariableKind) {
+        self.allocas.clear();
+        self.params.clear();
+        self.names = ["entry"].iter().map(|name| name.to_string()).collect();
+        self.temporaries = 0;
+        self.block = "entry".to_string();
+        self.terminated = false;
+        self.return_kind = kind;
+    }
+
+    /// Unused name of a local based on `name`, temporaries and labels all having a `.` which
+    /// variables cannot have
+    fn unique(&mut self, name: &str) -> String {
+        let mut unique = name.to_string();
+        let mut n = 0;
+        while self.names.contains(&unique) {
+            n += 1;
+            unique = format!("{}_{}", name, n);
+        }
+        self.names.insert(unique.clone());
+        unique
+    }
+
+    /// Argument of the current function, stored in a local so that it can be assigned
+    pub fn param(&mut self, name: &str, kind: &VariableKind) -> String {
+        let name = self.unique(name);
+        let t = llvm_type(kind);
+        self.params.push(format!("{} %{}.arg", t, name));
+        let pointer = format!("%{}", name);
+        self.allocas.push(format!("  {} = alloca {}", pointer, t));
+        self.line(&format!("store {} %{}.arg, {}* {}", t, name, t, pointer));
+        pointer
+    }
+
+    /// New local of the current function, shadowed variables getting a name of their own
+    pub fn local(&mut self, name: &str, kind: &VariableKind) -> String {
+        let pointer = format!("%{}", self.unique(name));
+        self.allocas
+            .push(format!("  {} = alloca {}", pointer, llvm_type(kind)));
+        pointer
+    }
+
+    /// Ends the function whose instructions were emitted since `begin_function`
+    pub fn push_function(&mut self, name: &str) {
+        if name == "main" {
+            self.main_kind = self.return_kind.clone();
+        }
+        // Falling off the end returns zero like in the other targets
+        match self.return_kind.clone() {
+            VariableKind::None => self.jump_return("ret void"),
+            kind => self.jump_return(&format!("ret {} {}", llvm_type(&kind), zero(&kind))),
+        }
+
+        let f = &mut self.functions;
+        f.push_str(&format!(
+            "\ndefine internal {} @func_{}({}) {{\nentry:\n",
+            llvm_type(&self.return_kind),
+            name,
+            self.params.join(", ")
+        ));
+        for alloca in self.allocas.iter() {
+            f.push_str(alloca);
+            f.push('\n');
+        }
+        for line in self.code.drain(..) {
+            f.push_str(&line);
+            f.push('\n');
+        }
+        f.push_str("}\n");
+    }
+
+    fn jump_return(&mut self, s: &str) {
+        if !self.terminated {
+            self.terminate(s);
+        }
+    }
+
+    pub fn return_kind(&self) -> &VariableKind {
+        &self.return_kind
+    }
+
+    pub fn declare_global(&mut self, name: String, kind: VariableKind, value: Option<Number>) {
+        let mut global = identifier(&name);
+        let mut n = 0;
+        while self.global_names.contains(&global) {
+            n += 1;
+            global = format!("{}_{}", identifier(&name), n);
+        }
+        self.global_names.insert(global.clone());
+
+        let value = match (&kind, value) {
+            (VariableKind::Bool, Some(value)) => (value != 0).to_string(),
+            (_, Some(value)) => value.to_string(),
+            (_, None) => zero(&kind),
+        };
+        self.declarations.push_str(&format!(
+            "@{} = internal global {} {}\n",
+            global,
+            llvm_type(&kind),
+            value
+        ));
+        self.globals.declare(name, (kind, format!("@{}", global)));
+    }
+
+    /// Pointers to the globals, the scope every function body starts from
+    pub fn globals(&self) -> Scopes<(VariableKind, String)> {
+        self.globals.clone()
+    }
+
+    /// Pointer to the first byte of a string literal, stored in a constant of the module
+    pub fn string(&mut self, value: &str) -> String {
+        let (literal, len) = string_constant(value);
+        let count = self.strings.len();
+        let constants = &mut self.constants;
+        let global = self.strings.entry(value.to_string()).or_insert_with(|| {
+            let global = format!("@.str.{}", count);
+            constants.push_str(&format!(
+                "{} = private unnamed_addr constant [{} x i8] {}\n",
+                global, len, literal
+            ));
+            global
+        });
+        format!(
+            "getelementptr inbounds ([{} x i8], [{} x i8]* {}, i64 0, i64 0)",
+            len, len, global
+        )
+    }
+
+    pub fn next_id(&mut self) -> usize {
+        self.id += 1;
+        self.id
+    }
+
+    /// Enters a loop, `continue_label` and `break_label` being where its jumps go
+    pub fn push_loop(&mut self, continue_label: String, break_label: String) {
+        self.loop_labels.push((continue_label, break_label));
+    }
+
+    /// Enters a switch, which `break` leaves while `continue` still targets the enclosing loop
+    pub fn push_switch(&mut self, break_label: String) {
+        let continue_label = self
+            .loop_labels
+            .last()
+            .map(|(label, _)| label.clone())
+            .unwrap_or_default();
+        self.loop_labels.push((continue_label, break_label));
+    }
+
+    pub fn pop_loop(&mut self) {
+        self.loop_labels.pop();
+    }
+
+    pub fn loop_labels(&self) -> (String, String) {
+        self.loop_labels
+            .last()
+            .cloned()
+            .expect("loop jump outside of a loop")
+    }
+
+    /// Points the array literal of the declaration being emitted at the array behind `pointer`
+    pub fn set_array(&mut self, pointer: String, kind: VariableKind) {
+        self.array = Some((pointer, kind));
+    }
+
+    pub fn take_array(&mut self) -> (String, VariableKind) {
+        self.array
+            .take()
+            .expect("array literal outside of a declaration")
+    }
+
+    pub fn load(&mut self, pointer: &str, kind: &VariableKind) -> String {
+        let t = llvm_type(kind);
+        self.assign(&format!("load {}, {}* {}", t, t, pointer))
+    }
+
+    pub fn store(&mut self, value: &str, kind: &VariableKind, pointer: &str) {
+        let t = llvm_type(kind);
+        self.line(&format!("store {} {}, {}* {}", t, value, t, pointer));
+    }
+
+    /// Gives a variable the value of one never assigned
+    pub fn zero(&mut self, pointer: &str, kind: &VariableKind) {
+        self.store(&zero(kind), kind, pointer);
+    }
+
+    /// Converts between ints and bools, bools becoming `true` for any int but zero
+    pub fn convert(&mut self, value: String, from: &VariableKind, to: &VariableKind) -> String {
+        match (from, to) {
+            (VariableKind::Bool, VariableKind::Number | VariableKind::Enum(_)) => {
+                self.assign(&format!("zext i1 {} to i64", value))
+            }
+            (VariableKind::Number | VariableKind::Enum(_), VariableKind::Bool) => {
+                self.assign(&format!("icmp ne i64 {}, 0", value))
+            }
+            _ => value,
+        }
+    }
+
+    /// Pointer to an element of an array, the index being already checked
+    pub fn element(&mut self, pointer: &str, kind: &VariableKind, index: &str) -> String {
+        let t = llvm_type(kind);
+        self.assign(&format!(
+            "getelementptr inbounds {}, {}* {}, i64 0, i64 {}",
+            t, t, pointer, index
+        ))
+    }
+
+    /// Index aborting the program when it is out of the bounds of an array of kind `kind`
+    pub fn bounds_check(&mut self, index: &str, kind: &VariableKind) -> String {
+        match kind {
+            VariableKind::Array(len) => self.assign(&format!(
+                "call i64 @logik_index(i64 {}, i64 {})",
+                index, len
+            )),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Pointer to a field of a struct, through the index of every field on the way
+    pub fn field(&mut self, pointer: &str, kind: &VariableKind, indexes: &[usize]) -> String {
+        if indexes.is_empty() {
+            return pointer.to_string();
+        }
+        let t = llvm_type(kind);
+        let indexes: Vec<String> = indexes.iter().map(|idx| format!("i32 {}", idx)).collect();
+        self.assign(&format!(
+            "getelementptr inbounds {}, {}* {}, i32 0, {}",
+            t,
+            t,
+            pointer,
+            indexes.join(", ")
+        ))
+    }
+
+    /// Operator at `position` on two ints, wrapping around like the interpreter. Divisions go
+    /// through the runtime, which stops the program on a division by zero
+    pub fn binop(&mut self, op: Op, lhs: &str, rhs: &str, position: Position) -> String {
+        let instruction = match op {
+            Op::Mul => "mul",
+            Op::Div | Op::Mod => {
+                let name = if op == Op::Div { "div" } else { "mod" };
+                let position = self.string(&position.to_string());
+                return self.assign(&format!(
+                    "call i64 @logik_{}(i64 {}, i64 {}, i8* {})",
+                    name, lhs, rhs, position
+                ));
+            }
+            Op::Add => "add",
+            Op::Sub => "sub",
+            Op::BitAnd => "and",
+            Op::BitOr => "or",
+            Op::BitXor => "xor",
+            // Shifting by 64 bits or more is undefined, only the low 6 bits count
+            Op::Shl | Op::Shr => {
+                let rhs = self.assign(&format!("and i64 {}, 63", rhs));
+                let instruction = if op == Op::Shl { "shl" } else { "ashr" };
+                return self.assign(&format!("{} i64 {}, {}", instruction, lhs, rhs));
+            }
+            Op::Not | Op::BitNot => unreachable!(),
+        };
+        self.assign(&format!("{} i64 {}, {}", instruction, lhs, rhs))
+    }
+
+    /// Compares two ints, `&&` and `||` being emitted with branches instead
+    pub fn compare(&mut self, cond: CondOp, lhs: &str, rhs: &str) -> String {
+        let predicate = match cond {
+            CondOp::LT => "slt",
+            CondOp::LEQ => "sle",
+            CondOp::GT => "sgt",
+            CondOp::GEQ => "sge",
+            CondOp::EQ => "eq",
+            CondOp::NEQ => "ne",
+            CondOp::And | CondOp::Or => unreachable!(),
+        };
+        self.assign(&format!("icmp {} i64 {}, {}", predicate, lhs, rhs))
+    }
+}
+
+// Lowering of the nodes, each returning the value it evaluates to along with its kind
+impl Llvm {
+    fn global(&mut self, global: &DeclareNode) {
+        if global.constant {
+            return;
+        }
+        let value = global.expression.as_ref().and_then(|e| e.const_eval());
+        self.declare_global(global.name.clone(), global.kind.clone(), value);
+    }
+
+    fn function(&mut self, func: &FuncDefNode) -> Result<()> {
+        let mut vars = self.globals();
+        vars.push_scope();
+
+        self.begin_function(func.kind.clone());
+        for (kind, name) in func.args.iter() {
+            let pointer = self.param(name, kind);
+            vars.declare(name.clone(), (kind.clone(), pointer));
+        }
+
+        self.block_node(&func.code, &mut vars)?;
+        self.push_function(&func.name);
+        Ok(())
+    }
+
+    fn node(
+        &mut self,
+        node: &dyn Node,
+        vars: &mut Scopes<(VariableKind, String)>,
+    ) -> Result<Option<(String, VariableKind)>> {
+        match node.node_ref() {
+            NodeRef::Binary(node) => {
+                let lhs = self.number(node.left_child.as_ref(), vars)?;
+                let rhs = self.number(node.right_child.as_ref(), vars)?;
+                Ok(Some((
+                    self.binop(node.op, &lhs, &rhs, node.position),
+                    VariableKind::Number,
+                )))
+            }
+            NodeRef::Unary(node) => {
+                let (child, kind) = self.value(node.child.as_ref(), vars)?;
+                let (instruction, kind) = match node.kind {
+                    UnaryNodeKind::Pos => return Ok(Some((child, kind))),
+                    UnaryNodeKind::Neg => {
+                        let child = self.convert(child, &kind, &VariableKind::Number);
+                        (format!("sub i64 0, {}", child), VariableKind::Number)
+                    }
+                    UnaryNodeKind::Not => {
+                        let child = self.convert(child, &kind, &VariableKind::Bool);
+                        (format!("xor i1 {}, true", child), VariableKind::Bool)
+                    }
+                    UnaryNodeKind::BitNot => {
+                        let child = self.convert(child, &kind, &VariableKind::Number);
+                        (format!("xor i64 {}, -1", child), VariableKind::Number)
+                    }
+                };
+                Ok(Some((self.assign(&instruction), kind)))
+            }
+            NodeRef::Number(node) => Ok(Some((
+                self.number(node.child.as_ref(), vars)?,
+                VariableKind::Number,
+            ))),
+            NodeRef::NumberLiteral(node) => {
+                Ok(Some((node.value.to_string(), VariableKind::Number)))
+            }
+            NodeRef::SimpleVariable(node) => Ok(Some(match node.value {
+                VariableData::Bool(b) => (b.to_string(), VariableKind::Bool),
+                _ => (node.value.to_number().to_string(), VariableKind::Number),
+            })),
+            NodeRef::Bool(node) => Ok(Some((
+                self.condition(node.child.as_ref(), vars)?,
+                VariableKind::Bool,
+            ))),
+            NodeRef::BoolLiteral(node) => Ok(Some((node.value.to_string(), VariableKind::Bool))),
+            NodeRef::String(node) => self.node(node.child.as_ref(), vars),
+            NodeRef::StringLiteral(node) => {
+                Ok(Some((self.string(&node.value), VariableKind::String)))
+            }
+            NodeRef::Declare(node) => {
+                if node.constant {
+                    return Ok(None);
+                }
+
+                let pointer = self.local(&node.name, &node.kind);
+                match (&node.kind, &node.expression) {
+                    (VariableKind::Array(_), Some(e)) => {
+                        self.zero(&pointer, &node.kind);
+                        self.set_array(pointer.clone(), node.kind.clone());
+                        self.node(e.as_ref(), vars)?;
+                    }
+                    // Read before the new pointer is declared, so it sees the shadowed variable
+                    (_, Some(e)) => {
+                        let value = self.value(e.as_ref(), vars)?;
+                        self.update(&pointer, &node.kind, None, value);
+                    }
+                    (_, None) => self.zero(&pointer, &node.kind),
+                }
+                vars.declare(node.name.clone(), (node.kind.clone(), pointer));
+                Ok(None)
+            }
+            NodeRef::Assign(node) => {
+                let value = self.value(node.expression.as_ref(), vars)?;
+                let (kind, pointer) = vars.get(&node.name).unwrap();
+                let op = node.op.map(|op| (op, node.position));
+                self.update(pointer, kind, op, value);
+                Ok(None)
+            }
+            NodeRef::IncDec(node) => {
+                let (kind, pointer) = vars.get(&node.name).unwrap();
+                let old = self.load(pointer, kind);
+                let old = self.convert(old, kind, &VariableKind::Number);
+                let new = self.binop(node.op, &old, "1", node.position);
+                let value = self.convert(new.clone(), &VariableKind::Number, kind);
+                self.store(&value, kind, pointer);
+                Ok(Some((
+                    if node.prefix { new } else { old },
+                    VariableKind::Number,
+                )))
+            }
+            NodeRef::Variable(node) => {
+                let (kind, pointer) = vars.get(&node.name).unwrap();
+                Ok(Some((self.load(pointer, kind), kind.clone())))
+            }
+            NodeRef::ArrayLiteral(node) => {
+                // Stores the elements into the array being declared
+                let (pointer, kind) = self.take_array();
+                for (idx, element) in node.elements.iter().enumerate() {
+                    let value = self.number(element.as_ref(), vars)?;
+                    let element = self.element(&pointer, &kind, &idx.to_string());
+                    self.store(&value, &VariableKind::Number, &element);
+                }
+                Ok(None)
+            }
+            NodeRef::Index(node) => {
+                let index = self.number(node.index.as_ref(), vars)?;
+                let (kind, pointer) = vars.get(&node.name).unwrap();
+                let element = self.checked_element(pointer, kind, &index);
+                Ok(Some((
+                    self.load(&element, &VariableKind::Number),
+                    VariableKind::Number,
+                )))
+            }
+            NodeRef::IndexAssign(node) => {
+                let value = self.value(node.expression.as_ref(), vars)?;
+                let index = self.number(node.index.as_ref(), vars)?;
+                let (kind, pointer) = vars.get(&node.name).unwrap();
+                let element = self.checked_element(pointer, kind, &index);
+                let op = node.op.map(|op| (op, node.position));
+                self.update(&element, &VariableKind::Number, op, value);
+                Ok(None)
+            }
+            NodeRef::Len(node) => match vars.get(&node.name) {
+                Some((VariableKind::Array(len), _)) => {
+                    Ok(Some((len.to_string(), VariableKind::Number)))
+                }
+                _ => unreachable!(),
+            },
+            NodeRef::StructLiteral(node) => {
+                // Builds the struct one field after the other with `insertvalue`
+                let kind = VariableKind::Struct(node.kind.clone());
+                let mut value = "zeroinitializer".to_string();
+                for (idx, (field, (_, field_kind))) in
+                    node.fields.iter().zip(node.kind.fields.iter()).enumerate()
+                {
+                    let (field, field_value_kind) = self.value(field.as_ref(), vars)?;
+                    let field = self.convert(field, &field_value_kind, field_kind);
+                    value = self.assign(&format!(
+                        "insertvalue {} {}, {} {}, {}",
+                        llvm_type(&kind),
+                        value,
+                        llvm_type(field_kind),
+                        field,
+                        idx
+                    ));
+                }
+                Ok(Some((value, kind)))
+            }
+            NodeRef::Field(node) => {
+                let (kind, pointer) = vars.get(&node.name).unwrap();
+                let (indexes, _, field_kind) = resolve_field(kind, &node.path)?;
+                let field = self.field(pointer, kind, &indexes);
+                Ok(Some((self.load(&field, &field_kind), field_kind)))
+            }
+            NodeRef::FieldAssign(node) => {
+                let value = self.value(node.expression.as_ref(), vars)?;
+                let (kind, pointer) = vars.get(&node.name).unwrap();
+                let (indexes, _, field_kind) = resolve_field(kind, &node.path)?;
+                let field = self.field(pointer, kind, &indexes);
+                let op = node.op.map(|op| (op, node.position));
+                self.update(&field, &field_kind, op, value);
+                Ok(None)
+            }
+            NodeRef::EnumVariant(node) => Ok(Some((
+                node.value.to_string(),
+                VariableKind::Enum(node.kind.clone()),
+            ))),
+            NodeRef::Cond(node) => self.cond_node(node, vars),
+            NodeRef::Ternary(node) => {
+                let id = self.next_id();
+                let then_label = format!("ternary.then.{}", id);
+                let else_label = format!("ternary.else.{}", id);
+                let end_label = format!("ternary.end.{}", id);
+
+                let cond = self.condition(node.cond.as_ref(), vars)?;
+                self.branch(&cond, &then_label, &else_label);
+                self.label(&then_label);
+                let (if_value, kind) = self.value(node.if_child.as_ref(), vars)?;
+                let if_block = self.block();
+                self.jump(&end_label);
+                self.label(&else_label);
+                let (else_value, _) = self.value(node.else_child.as_ref(), vars)?;
+                let else_block = self.block();
+                self.label(&end_label);
+                let value = self.assign(&format!(
+                    "phi {} [ {}, %{} ], [ {}, %{} ]",
+                    llvm_type(&kind),
+                    if_value,
+                    if_block,
+                    else_value,
+                    else_block
+                ));
+                Ok(Some((value, kind)))
+            }
+            NodeRef::If(node) => {
+                let id = self.next_id();
+                let then_label = format!("if.then.{}", id);
+                let else_label = format!("if.else.{}", id);
+                let end_label = format!("if.end.{}", id);
+
+                let cond = self.condition(node.cond.as_ref(), vars)?;
+                match node.else_child {
+                    Some(_) => self.branch(&cond, &then_label, &else_label),
+                    None => self.branch(&cond, &then_label, &end_label),
+                }
+                self.label(&then_label);
+                self.node(node.if_child.as_ref(), vars)?;
+                if let Some(e) = &node.else_child {
+                    self.jump(&end_label);
+                    self.label(&else_label);
+                    self.node(e.as_ref(), vars)?;
+                }
+                self.label(&end_label);
+                Ok(None)
+            }
+            NodeRef::While(node) => {
+                let id = self.next_id();
+                let cond_label = format!("while.{}", id);
+                let body_label = format!("while.body.{}", id);
+                let end_label = format!("while.end.{}", id);
+
+                self.label(&cond_label);
+                let cond = self.condition(node.cond.as_ref(), vars)?;
+                self.branch(&cond, &body_label, &end_label);
+                self.label(&body_label);
+                self.push_loop(cond_label.clone(), end_label.clone());
+                self.node(node.child.as_ref(), vars)?;
+                self.pop_loop();
+                self.jump(&cond_label);
+                self.label(&end_label);
+                Ok(None)
+            }
+            NodeRef::For(node) => self.for_node(node, vars),
+            NodeRef::DoWhile(node) => {
+                let id = self.next_id();
+                let body_label = format!("do.{}", id);
+                let cond_label = format!("do.cond.{}", id);
+                let end_label = format!("do.end.{}", id);
+
+                self.label(&body_label);
+                self.push_loop(cond_label.clone(), end_label.clone());
+                self.node(node.child.as_ref(), vars)?;
+                self.pop_loop();
+                self.label(&cond_label);
+                let cond = self.condition(node.cond.as_ref(), vars)?;
+                self.branch(&cond, &body_label, &end_label);
+                self.label(&end_label);
+                Ok(None)
+            }
+            NodeRef::Switch(node) => self.switch_node(node, vars),
+            NodeRef::LoopJump(node) => {
+                let (continue_label, break_label) = self.loop_labels();
+                let label = match node.flow {
+                    Flow::Break => break_label,
+                    Flow::Continue => continue_label,
+                    Flow::Normal | Flow::Return(_) => unreachable!(),
+                };
+                self.jump(&label);
+                Ok(None)
+            }
+            NodeRef::Block(node) => self.block_node(node, vars),
+            NodeRef::FuncCall(node) => self.func_call_node(node, vars),
+            NodeRef::Return(node) => {
+                let value = match &node.child {
+                    Some(child) => self.node(child.as_ref(), vars)?,
+                    None => None,
+                };
+                match value {
+                    Some((value, kind)) => {
+                        let return_kind = self.return_kind().clone();
+                        let value = self.convert(value, &kind, &return_kind);
+                        self.terminate(&format!("ret {} {}", llvm_type(&return_kind), value));
+                    }
+                    None => self.terminate("ret void"),
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// Emits a node whose value is used
+    fn value(
+        &mut self,
+        node: &dyn Node,
+        vars: &mut Scopes<(VariableKind, String)>,
+    ) -> Result<(String, VariableKind)> {
+        Ok(self.node(node, vars)?.expect("node without a value"))
+    }
+
+    /// Emits a node whose value is used as an int, bools being extended to `i64`
+    fn number(
+        &mut self,
+        node: &dyn Node,
+        vars: &mut Scopes<(VariableKind, String)>,
+    ) -> Result<String> {
+        let (value, kind) = self.value(node, vars)?;
+        Ok(self.convert(value, &kind, &VariableKind::Number))
+    }
+
+    /// Emits a condition, returning it as an `i1`
+    fn condition(
+        &mut self,
+        node: &dyn Node,
+        vars: &mut Scopes<(VariableKind, String)>,
+    ) -> Result<String> {
+        let (value, kind) = self.value(node, vars)?;
+        Ok(self.convert(value, &kind, &VariableKind::Bool))
+    }
+
+    /// Stores `value` at `pointer`, combining it with the value already there for compound
+    /// assignments, whose operator is at the position given with it
+    fn update(
+        &mut self,
+        pointer: &str,
+        target: &VariableKind,
+        op: Option<(Op, Position)>,
+        (value, kind): (String, VariableKind),
+    ) {
+        let (value, kind) = match op {
+            Some((op, position)) => {
+                let current = self.load(pointer, target);
+                let current = self.convert(current, target, &VariableKind::Number);
+                let value = self.convert(value, &kind, &VariableKind::Number);
+                (
+                    self.binop(op, &current, &value, position),
+                    VariableKind::Number,
+                )
+            }
+            None => (value, kind),
+        };
+        let value = self.convert(value, &kind, target);
+        self.store(&value, target, pointer);
+    }
+
+    /// Pointer to an element of an array, aborting when the index is out of bounds
+    fn checked_element(&mut self, pointer: &str, kind: &VariableKind, index: &str) -> String {
+        let index = self.bounds_check(index, kind);
+        self.element(pointer, kind, &index)
+    }
+
+    fn cond_node(
+        &mut self,
+        node: &CondNode,
+        vars: &mut Scopes<(VariableKind, String)>,
+    ) -> Result<Option<(String, VariableKind)>> {
+        // `&&` and `||` evaluate their right side only when needed, like `eval` does
+        if let CondOp::And | CondOp::Or = node.cond {
+            let id = self.next_id();
+            let name = if node.cond == CondOp::And {
+                "and"
+            } else {
+                "or"
+            };
+            let rhs_label = format!("{}.rhs.{}", name, id);
+            let end_label = format!("{}.end.{}", name, id);
+
+            let lhs = self.condition(node.left_child.as_ref(), vars)?;
+            let lhs_block = self.block();
+            if node.cond == CondOp::And {
+                self.branch(&lhs, &rhs_label, &end_label);
+            } else {
+                self.branch(&lhs, &end_label, &rhs_label);
+            }
+            self.label(&rhs_label);
+            let rhs = self.condition(node.right_child.as_ref(), vars)?;
+            let rhs_block = self.block();
+            self.label(&end_label);
+            let value = self.assign(&format!(
+                "phi i1 [ {}, %{} ], [ {}, %{} ]",
+                node.cond == CondOp::Or,
+                lhs_block,
+                rhs,
+                rhs_block
+            ));
+            return Ok(Some((value, VariableKind::Bool)));
+        }
+
+        let (lhs, kind) = self.value(node.left_child.as_ref(), vars)?;
+        let (rhs, rhs_kind) = self.value(node.right_child.as_ref(), vars)?;
+        if kind == VariableKind::String {
+            let eq = self.assign(&format!(
+                "call i1 @logik_string_eq(i8* {}, i8* {})",
+                lhs, rhs
+            ));
+            let value = match node.cond {
+                CondOp::EQ => eq,
+                _ => self.assign(&format!("xor i1 {}, true", eq)),
+            };
+            return Ok(Some((value, VariableKind::Bool)));
+        }
+        let lhs = self.convert(lhs, &kind, &VariableKind::Number);
+        let rhs = self.convert(rhs, &rhs_kind, &VariableKind::Number);
+        Ok(Some((
+            self.compare(node.cond, &lhs, &rhs),
+            VariableKind::Bool,
+        )))
+    }
+
+    fn for_node(
+        &mut self,
+        node: &ForNode,
+        vars: &mut Scopes<(VariableKind, String)>,
+    ) -> Result<Option<(String, VariableKind)>> {
+        let id = self.next_id();
+        let cond_label = format!("for.{}", id);
+        let body_label = format!("for.body.{}", id);
+        let step_label = format!("for.step.{}", id);
+        let end_label = format!("for.end.{}", id);
+
+        vars.push_scope();
+        if let Some(init) = &node.init {
+            self.node(init.as_ref(), vars)?;
+        }
+        self.label(&cond_label);
+        if let Some(cond) = &node.cond {
+            let cond = self.condition(cond.as_ref(), vars)?;
+            self.branch(&cond, &body_label, &end_label);
+        }
+        self.label(&body_label);
+        self.push_loop(step_label.clone(), end_label.clone());
+        self.node(node.child.as_ref(), vars)?;
+        self.pop_loop();
+        self.label(&step_label);
+        if let Some(step) = &node.step {
+            self.node(step.as_ref(), vars)?;
+        }
+        self.jump(&cond_label);
+        self.label(&end_label);
+        vars.pop_scope();
+        Ok(None)
+    }
+
+    /// Branches to the block of each case, which falls through into the block of the next one
+    fn switch_node(
+        &mut self,
+        node: &SwitchNode,
+        vars: &mut Scopes<(VariableKind, String)>,
+    ) -> Result<Option<(String, VariableKind)>> {
+        let id = self.next_id();
+        let end_label = format!("switch.end.{}", id);
+        let case_label = |idx: usize| format!("switch.{}.case.{}", id, idx);
+
+        let value = self.number(node.expression.as_ref(), vars)?;
+        let default_label = match node.cases.iter().position(|(label, _)| label.is_none()) {
+            Some(idx) => case_label(idx),
+            None => end_label.clone(),
+        };
+        let cases: Vec<String> = node
+            .cases
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, (label, _))| {
+                label.map(|v| format!("i64 {}, label %{}", v, case_label(idx)))
+            })
+            .collect();
+        self.terminate(&format!(
+            "switch i64 {}, label %{} [{}]",
+            value,
+            default_label,
+            cases.join(" ")
+        ));
+
+        self.push_switch(end_label.clone());
+        vars.push_scope();
+        for (idx, (_, children)) in node.cases.iter().enumerate() {
+            self.label(&case_label(idx));
+            for child in children.iter() {
+                self.node(child.as_ref(), vars)?;
+            }
+        }
+        vars.pop_scope();
+        self.pop_loop();
+        self.label(&end_label);
+        Ok(None)
+    }
+
+    fn block_node(
+        &mut self,
+        node: &BlockNode,
+        vars: &mut Scopes<(VariableKind, String)>,
+    ) -> Result<Option<(String, VariableKind)>> {
+        vars.push_scope();
+        for child in node.children.iter() {
+            self.node(child.as_ref(), vars)?;
+        }
+        vars.pop_scope();
+        Ok(None)
+    }
+
+    fn func_call_node(
+        &mut self,
+        node: &FuncCallNode,
+        vars: &mut Scopes<(VariableKind, String)>,
+    ) -> Result<Option<(String, VariableKind)>> {
+        let fborrow = node.funcs.borrow(); // NOTE: borrow
+        let params = node.params.borrow();
+
+        match node.name.as_ref() {
+            "println" | "print" => {
+                let (value, kind) = self.value(params[0].as_ref(), vars)?;
+                match kind {
+                    VariableKind::String => {
+                        self.line(&format!("call void @logik_println_string(i8* {})", value))
+                    }
+                    _ => {
+                        let value = self.convert(value, &kind, &VariableKind::Number);
+                        self.line(&format!("call void @logik_println_int(i64 {})", value));
+                    }
+                }
+                Ok(None)
+            }
+            "readln" => Ok(Some((
+                self.assign("call i64 @logik_readln()"),
+                VariableKind::Number,
+            ))),
+            "exit" => {
+                let value = self.number(params[0].as_ref(), vars)?;
+                let status = self.assign(&format!("trunc i64 {} to i32", value));
+                self.line(&format!("call void @exit(i32 {})", status));
+                Ok(None)
+            }
+            "argc" => Ok(Some((
+                self.assign("load i64, i64* @logik_argc"),
+                VariableKind::Number,
+            ))),
+            "arg" => {
+                let value = self.number(params[0].as_ref(), vars)?;
+                let call = format!("call i8* @logik_arg(i64 {})", value);
+                Ok(Some((self.assign(&call), VariableKind::String)))
+            }
+            _ => {
+                let func = fborrow.get(&node.name).unwrap();
+                let mut args = vec![];
+                for (param, (kind, _)) in params.iter().zip(func.args.iter()) {
+                    let (value, value_kind) = self.value(param.as_ref(), vars)?;
+                    let value = self.convert(value, &value_kind, kind);
+                    args.push(format!("{} {}", llvm_type(kind), value));
+                }
+
+                let call = format!(
+                    "call {} @func_{}({})",
+                    llvm_type(&func.kind),
+                    node.name,
+                    args.join(", ")
+                );
+                match func.kind {
+                    VariableKind::None => {
+                        self.line(&call);
+                        Ok(None)
+                    }
+                    _ => Ok(Some((self.assign(&call), func.kind.clone()))),
+                }
+            }
+        }
+    }
+}
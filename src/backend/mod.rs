@@ -0,0 +1,98 @@
+pub mod c;
+mod dwarf;
+mod elf;
+mod encoder;
+pub mod jit;
+pub mod llvm;
+mod riscv;
+pub mod wasm;
+mod x86;
+
+use crate::ir::Program;
+
+use color_eyre::eyre::{bail, Result};
+
+use clap::ArgEnum;
+
+/// Architecture the assembly is emitted for
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    /// 32 bit x86, assembled with `nasm -f elf32` and linked with `ld -m elf_i386` or built by
+    /// `logik build`
+    X86,
+    /// x86-64 System V, assembled with `nasm -f elf64` and linked with `ld` or built by
+    /// `logik build`
+    #[clap(name = "x86_64")]
+    X86_64,
+    /// RV64IM Linux, assembled with `riscv64-linux-gnu-as` and linked with `riscv64-linux-gnu-ld`
+    #[clap(name = "riscv64")]
+    Riscv64,
+    /// WebAssembly text module importing `print_int` and `read_int` from `env`, run by `logik run`
+    Wasm,
+    /// Standalone C file, compiled with any C99 compiler
+    C,
+    /// LLVM IR text module calling `printf` and `scanf`, compiled with `llc` or `clang`
+    Llvm,
+}
+
+impl Target {
+    /// Target matching the machine running the compiler, x86_64 on architectures without a
+    /// backend of their own
+    pub fn host() -> Self {
+        if cfg!(target_arch = "x86") {
+            Target::X86
+        } else if cfg!(target_arch = "riscv64") {
+            Target::Riscv64
+        } else {
+            Target::X86_64
+        }
+    }
+
+    pub fn emit(self, program: &Program) -> String {
+        match self {
+            Target::X86 => x86::emit(program, &x86::X86_32),
+            Target::X86_64 => x86::emit(program, &x86::X86_64),
+            Target::Riscv64 => riscv::emit(program),
+            // Emitted straight from the AST to keep its structured control flow
+            Target::Wasm | Target::C | Target::Llvm => unreachable!(),
+        }
+    }
+
+    /// Statically linked executable of the assembly of an x86 target, encoded without `nasm` and
+    /// `ld`
+    pub fn executable(self, asm: &str) -> Result<Vec<u8>> {
+        match self {
+            Target::X86 => elf::executable(asm, 4),
+            Target::X86_64 => elf::executable(asm, 8),
+            _ => bail!("Only the x86 and x86_64 targets can be built into an executable"),
+        }
+    }
+
+    /// Name of the target on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            Target::X86 => "x86",
+            Target::X86_64 => "x86_64",
+            Target::Riscv64 => "riscv64",
+            Target::Wasm => "wasm",
+            Target::C => "c",
+            Target::Llvm => "llvm",
+        }
+    }
+
+    /// File the generated code is written to
+    pub fn output(self) -> &'static str {
+        match self {
+            Target::Wasm => "out.wat",
+            Target::C => "out.c",
+            Target::Llvm => "out.ll",
+            _ => "out.asm",
+        }
+    }
+}
+
+impl Default for Target {
+    fn default() -> Self {
+        Self::host()
+    }
+}
@@ -0,0 +1,422 @@
+use crate::error::RuntimeError;
+use crate::ir::{Function, Instr, Location, Program};
+use crate::operator::{CondOp, Op};
+use crate::token::Position;
+
+const RUNTIME: &str = include_str!("../baseriscv64.s");
+const WORD: usize = 8;
+/// Registers taking the first arguments of a call, the others being pushed
+const ARG_REGISTERS: [&str; 8] = ["a0", "a1", "a2", "a3", "a4", "a5", "a6", "a7"];
+/// The return address and the frame pointer of the caller are saved right below the frame pointer
+const SAVED: usize = 2 * WORD;
+
+// The accumulator is a0, so that it already holds the first argument of the runtime subroutines
+// and the value returned by calls, the operand register is a1 and the index of arrays is kept in
+// s1. t0 to t3 are scratch registers, t5 and t6 compute addresses too far for an immediate
+
+pub fn emit(program: &Program) -> String {
+    let mut emitter = Emitter {
+        text: String::new(),
+        data: String::new(),
+        bss: String::new(),
+        spilled: 0,
+        faults: String::new(),
+        fault_labels: 0,
+    };
+
+    emitter.line(RUNTIME);
+    // What `main` returns is the exit status of the program
+    emitter.line("call func_main\nj exit");
+
+    for global in program.globals.iter() {
+        match global.data() {
+            Some(words) => {
+                let words: Vec<String> = words.iter().map(|word| word.to_string()).collect();
+                let line = format!("{}: .dword {}\n", global.label, words.join(", "));
+                emitter.data.push_str(&line);
+            }
+            None => emitter.bss.push_str(&format!(
+                "{}: .zero {}\n",
+                global.label,
+                global.kind.words() * WORD
+            )),
+        }
+    }
+
+    for function in program.functions.iter() {
+        emitter.line("");
+        emitter.function(function);
+    }
+    if !emitter.faults.is_empty() {
+        let faults = std::mem::take(&mut emitter.faults);
+        emitter.line("");
+        emitter.line(&faults);
+    }
+
+    let mut text = emitter.text;
+    if !emitter.data.is_empty() {
+        text.push_str("\n.section .data\n.align 3\n");
+        text.push_str(&emitter.data);
+    }
+    if !emitter.bss.is_empty() {
+        text.push_str("\n.section .bss\n.align 3\n");
+        text.push_str(&emitter.bss);
+    }
+    text
+}
+
+/// Whether a value fits in the 12 bit immediate of the I and S instruction formats
+fn fits(value: i64) -> bool {
+    (-2048..2048).contains(&value)
+}
+
+struct Emitter {
+    text: String,
+    data: String,
+    bss: String,
+    /// Argument registers saved below the frame pointer by the current function
+    spilled: usize,
+    /// Code aborting the program when arithmetic faults, emitted after the functions
+    faults: String,
+    /// Labels of faults emitted so far, numbering the next one
+    fault_labels: usize,
+}
+
+impl Emitter {
+    fn line(&mut self, s: &str) {
+        self.text.push_str(s);
+        self.text.push('\n');
+    }
+
+    /// Offset of a local from the frame pointer
+    fn local(&self, offset: usize) -> i64 {
+        -(((offset + self.spilled) * WORD + SAVED) as i64)
+    }
+
+    /// `dst = src + value`
+    fn add_immediate(&mut self, dst: &str, src: &str, value: i64) {
+        if fits(value) {
+            self.line(&format!("addi {}, {}, {}", dst, src, value));
+        } else {
+            self.line(&format!("li t6, {}\nadd {}, {}, t6", value, dst, src));
+        }
+    }
+
+    /// Memory operand of a location, computing its address first when needed
+    fn location(&mut self, location: &Location) -> String {
+        match location {
+            Location::Local(offset) if fits(self.local(*offset)) => {
+                format!("{}(s0)", self.local(*offset))
+            }
+            _ => {
+                self.address("t5", location);
+                "0(t5)".to_string()
+            }
+        }
+    }
+
+    /// Loads the address of a location into `register`
+    fn address(&mut self, register: &str, location: &Location) {
+        match location {
+            Location::Local(offset) => {
+                let offset = self.local(*offset);
+                self.add_immediate(register, "s0", offset);
+            }
+            Location::Global(label, offset) => {
+                self.line(&format!("la {}, {}", register, label));
+                if *offset > 0 {
+                    self.add_immediate(register, register, (offset * WORD) as i64);
+                }
+            }
+        }
+    }
+
+    /// Element of the array at `location` whose index is in `register`
+    fn indexed(&mut self, location: &Location, register: &str) -> String {
+        self.address("t0", location);
+        self.line(&format!("slli t1, {}, 3\nadd t0, t0, t1", register));
+        "0(t0)".to_string()
+    }
+
+    /// Argument `idx` of the current function, either spilled from its register or pushed by
+    /// the caller right above the frame pointer
+    fn arg(&self, idx: usize) -> String {
+        if idx < ARG_REGISTERS.len() {
+            format!("{}(s0)", -(((idx + 1) * WORD + SAVED) as i64))
+        } else {
+            format!("{}(s0)", (idx - ARG_REGISTERS.len()) * WORD)
+        }
+    }
+
+    /// Copies t2 words from the address in t0 to the address in t1
+    fn copy(&mut self) {
+        self.line("beqz t2, 2f\n1:\nld t3, 0(t0)\nsd t3, 0(t1)");
+        self.line("addi t0, t0, 8\naddi t1, t1, 8\naddi t2, t2, -1\nbnez t2, 1b\n2:");
+    }
+
+    /// Label of code aborting the program with `error`, whose message is written to the standard
+    /// error by `runtime_error`. The data following the message stays aligned on words
+    fn fault(&mut self, error: RuntimeError) -> String {
+        let label = format!("fault_{}", self.fault_labels);
+        self.fault_labels += 1;
+        self.data.push_str(&format!(
+            "{}_msg: .ascii \"Error: {}\\n\"\n{}_msg_end:\n.align 3\n",
+            label, error, label
+        ));
+        self.faults.push_str(&format!(
+            "{}:\nla a1, {}_msg\nla a2, {}_msg_end\nj runtime_error\n",
+            label, label, label
+        ));
+        label
+    }
+
+    /// Applies a binary operator to a1 and a0 into a0, jumping to code aborting the program on
+    /// overflow. Without flags, the operands tell whether the result in t0 wrapped around
+    fn checked_binop(&mut self, op: Op, position: Position) {
+        let label = self.fault(RuntimeError::Overflow(position));
+        match op {
+            // The sum is below a1 exactly when a0 is negative
+            Op::Add => self.line(&format!(
+                "add t0, a1, a0\nslt t1, t0, a1\nslti t2, a0, 0\nbne t1, t2, {}",
+                label
+            )),
+            Op::Sub => self.line(&format!(
+                "sub t0, a1, a0\nslt t1, a1, t0\nslti t2, a0, 0\nbne t1, t2, {}",
+                label
+            )),
+            // The high half of the product only holds the sign of the low one
+            Op::Mul => self.line(&format!(
+                "mul t0, a1, a0\nmulh t1, a1, a0\nsrai t2, t0, 63\nbne t1, t2, {}",
+                label
+            )),
+            // Only the smallest number divided by -1 overflows
+            Op::Div | Op::Mod => {
+                self.line("li t1, -1\nbne a0, t1, 1f\nli t1, 1\nslli t1, t1, 63");
+                self.line(&format!("beq a1, t1, {}\n1:", label));
+                let instr = if op == Op::Div { "div" } else { "rem" };
+                self.line(&format!("{} t0, a1, a0", instr));
+            }
+            _ => unreachable!(),
+        }
+        self.line("mv a0, t0");
+    }
+
+    fn push(&mut self, register: &str) {
+        self.line(&format!("addi sp, sp, -8\nsd {}, 0(sp)", register));
+    }
+
+    fn pop(&mut self, register: &str) {
+        self.line(&format!("ld {}, 0(sp)\naddi sp, sp, 8", register));
+    }
+
+    fn function(&mut self, function: &Function) {
+        self.line(&format!("func_{}:", function.name));
+        self.line("addi sp, sp, -16\nsd ra, 8(sp)\nsd s0, 0(sp)\naddi s0, sp, 16");
+
+        self.spilled = function.args.min(ARG_REGISTERS.len());
+        let frame = ((self.spilled + function.frame) * WORD + SAVED) as i64;
+        if frame > SAVED as i64 {
+            self.add_immediate("sp", "s0", -frame);
+        }
+        for (idx, register) in ARG_REGISTERS[..self.spilled].iter().enumerate() {
+            self.line(&format!("sd {}, {}", register, self.arg(idx)));
+        }
+
+        for instr in function.code.iter() {
+            self.instr(instr);
+        }
+    }
+
+    fn instr(&mut self, instr: &Instr) {
+        match instr {
+            // Debug info is only emitted for the x86 targets
+            Instr::Line(_) => {}
+            Instr::Label(label) => self.line(&format!("{}:", label)),
+            Instr::Jump(label) => self.line(&format!("j {}", label)),
+            Instr::JumpIfFalse(label) => self.line(&format!("beqz a0, {}", label)),
+            Instr::JumpIfTrue(label) => self.line(&format!("bnez a0, {}", label)),
+            Instr::JumpIfEqual(value, label) => {
+                self.line(&format!("li t0, {}\nbeq a0, t0, {}", value, label))
+            }
+            Instr::JumpTable {
+                name,
+                min,
+                labels,
+                default,
+            } => {
+                self.data
+                    .push_str(&format!("{}: .dword {}\n", name, labels.join(", ")));
+                self.line(&format!("li t0, {}\nsub a0, a0, t0", min));
+                // Values below the table are huge when compared as unsigned
+                self.line(&format!(
+                    "li t0, {}\nbgtu a0, t0, {}",
+                    labels.len() - 1,
+                    default
+                ));
+                self.line(&format!("la t0, {}\nslli t1, a0, 3\nadd t0, t0, t1", name));
+                self.line("ld t0, 0(t0)\njr t0");
+            }
+
+            Instr::Imm(value) => self.line(&format!("li a0, {}", value)),
+            Instr::Load(location) => {
+                let location = self.location(location);
+                self.line(&format!("ld a0, {}", location));
+            }
+            Instr::LoadAddress(location) => self.address("a0", location),
+            Instr::Store(location) => {
+                let location = self.location(location);
+                self.line(&format!("sd a0, {}", location));
+            }
+            Instr::LoadOperand(location) => {
+                let location = self.location(location);
+                self.line(&format!("ld a1, {}", location));
+            }
+            Instr::Push => self.push("a0"),
+            Instr::PushAddress(location) => {
+                self.address("t0", location);
+                self.push("t0");
+            }
+            Instr::Pop => self.pop("a0"),
+            Instr::PopOperand => self.pop("a1"),
+
+            Instr::BinOp(op, Some(position)) => self.checked_binop(*op, *position),
+            Instr::BinOp(op, None) => {
+                let instr = match op {
+                    Op::Mul => "mul",
+                    Op::Div => "div",
+                    Op::Mod => "rem",
+                    Op::Add => "add",
+                    Op::Sub => "sub",
+                    Op::BitAnd => "and",
+                    Op::BitOr => "or",
+                    Op::BitXor => "xor",
+                    Op::Shl => "sll",
+                    Op::Shr => "sra",
+                    Op::Not | Op::BitNot => unreachable!(),
+                };
+                self.line(&format!("{} a0, a1, a0", instr));
+            }
+            // Division by zero does not trap
+            Instr::CheckDivisor(position) => {
+                let label = self.fault(RuntimeError::DivisionByZero(*position));
+                self.line(&format!("beqz a0, {}", label));
+            }
+            Instr::Compare(cond) => {
+                let s = match cond {
+                    CondOp::LT => "slt a0, a1, a0",
+                    CondOp::LEQ => "slt a0, a0, a1\nxori a0, a0, 1",
+                    CondOp::GT => "slt a0, a0, a1",
+                    CondOp::GEQ => "slt a0, a1, a0\nxori a0, a0, 1",
+                    CondOp::EQ => "sub a0, a1, a0\nseqz a0, a0",
+                    CondOp::NEQ => "sub a0, a1, a0\nsnez a0, a0",
+                    // Any non zero value is true
+                    CondOp::And => "snez a1, a1\nsnez a0, a0\nand a0, a1, a0",
+                    CondOp::Or => "or a0, a1, a0\nsnez a0, a0",
+                };
+                self.line(s);
+            }
+            Instr::Negate(checked) => {
+                self.line("neg a0, a0");
+                // Only the smallest number is its own negation, besides 0
+                if let Some(position) = checked {
+                    let label = self.fault(RuntimeError::Overflow(*position));
+                    self.line(&format!("li t1, 1\nslli t1, t1, 63\nbeq a0, t1, {}", label));
+                }
+            }
+            Instr::Not => self.line("seqz a0, a0"),
+            Instr::BitNot => self.line("not a0, a0"),
+            Instr::Select => {
+                self.line("ld a1, 0(sp)\nld t0, 8(sp)\naddi sp, sp, 16");
+                self.line("beqz t0, 1f\nmv a0, a1\n1:");
+            }
+            Instr::AddTo(location, delta, checked) => {
+                let location = self.location(location);
+                self.line(&format!("ld t0, {}", location));
+                self.add_immediate("t1", "t0", *delta);
+                // Adding a positive constant wraps around below the old value
+                if let Some(position) = checked {
+                    let label = self.fault(RuntimeError::Overflow(*position));
+                    let (lhs, rhs) = if *delta > 0 {
+                        ("t1", "t0")
+                    } else {
+                        ("t0", "t1")
+                    };
+                    self.line(&format!("blt {}, {}, {}", lhs, rhs, label));
+                }
+                self.line(&format!("sd t1, {}", location));
+            }
+
+            Instr::BoundsCheck(len) => {
+                // bgeu compares as unsigned, so a negative index fails the check too
+                self.line(&format!("li t0, {}\nbgeu a0, t0, bounds_error", len));
+            }
+            Instr::LoadElement(location) => {
+                let element = self.indexed(location, "a0");
+                self.line(&format!("ld a0, {}", element));
+            }
+            Instr::SetIndex => self.line("mv s1, a0"),
+            Instr::LoadOperandElement(location) => {
+                let element = self.indexed(location, "s1");
+                self.line(&format!("ld a1, {}", element));
+            }
+            Instr::StoreElement(location) => {
+                let element = self.indexed(location, "s1");
+                self.line(&format!("sd a0, {}", element));
+            }
+
+            Instr::Copy(location, words) => {
+                self.address("t1", location);
+                self.line(&format!("mv t0, a0\nli t2, {}", words));
+                self.copy();
+            }
+            Instr::Zero(location, 1) => {
+                let location = self.location(location);
+                self.line(&format!("sd zero, {}", location));
+            }
+            Instr::Zero(location, words) => {
+                self.address("t1", location);
+                self.line(&format!("li t2, {}", words));
+                self.line("1:\nsd zero, 0(t1)\naddi t1, t1, 8\naddi t2, t2, -1\nbnez t2, 1b");
+            }
+            Instr::PopArray(location) => {
+                self.address("t1", location);
+                self.line("mv t0, sp\nmv t2, a0");
+                self.copy();
+                self.line("slli t0, a0, 3\nadd sp, sp, t0");
+            }
+
+            Instr::Print => self.line("call print"),
+            Instr::PrintString => self.line("call print_string"),
+            Instr::Read => self.line("call read"),
+            Instr::Exit => self.line("j exit"),
+            Instr::ArgCount => self.line("ld a0, arg_count"),
+            Instr::ProgramArg => self.line("call argument"),
+            Instr::LoadArg(idx) => {
+                let arg = self.arg(*idx);
+                self.line(&format!("ld a0, {}", arg));
+            }
+            Instr::Call(name, args) => {
+                let in_registers = (*args).min(ARG_REGISTERS.len());
+                for (idx, register) in ARG_REGISTERS[..in_registers].iter().enumerate() {
+                    self.line(&format!("ld {}, {}(sp)", register, idx * WORD));
+                }
+                if in_registers > 0 {
+                    self.line(&format!("addi sp, sp, {}", in_registers * WORD));
+                }
+                self.line(&format!("call func_{}", name));
+                if args > &in_registers {
+                    self.add_immediate("sp", "sp", ((args - in_registers) * WORD) as i64);
+                }
+            }
+            Instr::ReturnStruct(words) => {
+                let address = self.arg(0);
+                self.line(&format!("mv t0, a0\nld t1, {}\nli t2, {}", address, words));
+                self.copy();
+                self.line(&format!("ld a0, {}", address));
+            }
+            Instr::Return => {
+                self.line("addi sp, s0, -16\nld ra, 8(sp)\nld s0, 0(sp)\naddi sp, sp, 16\nret")
+            }
+        }
+    }
+}
@@ -0,0 +1,1127 @@
+use crate::ast::{
+    resolve_field, BlockNode, DeclareNode, Flow, ForNode, FuncCallNode, FuncDefNode,
+    IndexAssignNode, Node, NodeRef, SwitchNode, UnaryNode, UnaryNodeKind,
+};
+use crate::operator::{CondOp, Op};
+use crate::token::Number;
+use crate::variable::{Scopes, VariableKind};
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::rc::Rc;
+
+use color_eyre::eyre::{bail, eyre, Result};
+use wasmi::{Engine, Linker, Module, Store, Val};
+
+const WORD: usize = 8;
+const PAGE: usize = 1 << 16;
+/// Linear memory above the globals taken by the frames of the functions
+const STACK: usize = 1 << 20;
+/// Placeholder for the instructions restoring the stack pointer, the frame of a function only
+/// being known once its whole body was emitted
+const LEAVE: &str = ";; leave";
+
+/// WebAssembly value type, ints and bools being `i64` and addresses `i32`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Type {
+    I32,
+    I64,
+}
+
+impl Type {
+    fn name(self) -> &'static str {
+        match self {
+            Type::I32 => "i32",
+            Type::I64 => "i64",
+        }
+    }
+}
+
+/// Where the value of a variable is stored
+#[derive(Debug, Clone, PartialEq)]
+pub enum Slot {
+    /// Local of the current function, only used for scalars
+    Local(String),
+    Global(String),
+    /// Linear memory at a byte offset from the frame pointer of the current function
+    Frame(usize),
+    /// Linear memory at a fixed address
+    Static(usize),
+}
+
+impl Slot {
+    /// Slot of the field `words` words after the start of a struct
+    pub fn field(&self, words: usize) -> Slot {
+        match self {
+            Slot::Frame(offset) => Slot::Frame(offset + words * WORD),
+            Slot::Static(address) => Slot::Static(address + words * WORD),
+            Slot::Local(_) | Slot::Global(_) => unreachable!("aggregates live in memory"),
+        }
+    }
+}
+
+/// Value of a scalar declared without an initializer
+fn initial(kind: &VariableKind) -> Number {
+    match kind {
+        VariableKind::Enum(t) => t.first(),
+        _ => 0,
+    }
+}
+
+/// Emits a WebAssembly text module straight from the AST, so that control flow keeps the
+/// structure of the source
+pub struct Wasm {
+    funcs: Rc<RefCell<HashMap<String, FuncDefNode>>>,
+    globals: Scopes<(VariableKind, Slot)>,
+    /// Declarations of the wasm globals
+    fields: String,
+    /// Bytes of memory taken by globals
+    statics: usize,
+    functions: String,
+
+    /// Instructions of the function being emitted
+    code: Vec<String>,
+    depth: usize,
+    params: Vec<(String, Type)>,
+    locals: Vec<(String, Type)>,
+    names: HashSet<String>,
+    /// Bytes of memory taken by the frame of the function being emitted
+    frame: usize,
+    return_kind: VariableKind,
+    id: usize,
+    loop_labels: Vec<(String, String)>,
+    /// Slot of the array being declared, where its array literal stores the elements
+    array: Option<Slot>,
+}
+
+impl Wasm {
+    pub fn new(funcs: &Rc<RefCell<HashMap<String, FuncDefNode>>>) -> Self {
+        Self {
+            funcs: funcs.clone(),
+            globals: Scopes::new(),
+            fields: String::new(),
+            statics: 0,
+            functions: String::new(),
+            code: vec![],
+            depth: 0,
+            params: vec![],
+            locals: vec![],
+            names: HashSet::new(),
+            frame: 0,
+            return_kind: VariableKind::None,
+            id: 0,
+            loop_labels: vec![],
+            array: None,
+        }
+    }
+
+    /// Emits the whole program, exporting `main`
+    pub fn module(&mut self, globals: &[DeclareNode]) -> Result<String> {
+        for global in globals.iter() {
+            self.global(global);
+        }
+
+        let funcs = self.funcs.clone();
+        let funcs = funcs.borrow();
+        let mut names: Vec<&String> = funcs.keys().collect();
+        names.sort();
+        for name in names {
+            self.function(&funcs[name])?;
+        }
+
+        let pages = (self.statics + STACK).div_ceil(PAGE);
+        let mut module = String::from("(module\n");
+        module.push_str("  (import \"env\" \"print_int\" (func $print_int (param i64)))\n");
+        module.push_str("  (import \"env\" \"read_int\" (func $read_int (result i64)))\n");
+        module.push_str("  (import \"env\" \"bounds_error\" (func $bounds_error))\n");
+        module.push_str("  (import \"env\" \"exit\" (func $exit (param i64)))\n");
+        module.push_str("  (import \"env\" \"argc\" (func $argc (result i64)))\n");
+        module.push_str(&format!("  (memory (export \"memory\") {})\n", pages));
+        module.push_str(&format!(
+            "  (global $sp (mut i32) (i32.const {}))\n",
+            pages * PAGE
+        ));
+        module.push_str(&self.fields);
+        module.push_str(&self.functions);
+        module.push_str("  (export \"main\" (func $func_main))\n)\n");
+        Ok(module)
+    }
+
+    pub fn line(&mut self, s: &str) {
+        for line in s.lines() {
+            if line.starts_with("end") || line.starts_with("else") {
+                self.depth -= 1;
+            }
+            self.code
+                .push(format!("    {}{}", "  ".repeat(self.depth), line));
+            if ["block", "loop", "if", "else"]
+                .iter()
+                .any(|start| line.starts_with(start))
+            {
+                self.depth += 1;
+            }
+        }
+    }
+
+    /// Index of the next instruction, to complete it with `set_result`
+    pub fn mark(&self) -> usize {
+        self.code.len()
+    }
+
+    /// Gives a result type to the `if` at `mark`, known only after its first arm was emitted
+    pub fn set_result(&mut self, mark: usize, t: Type) {
+        let line = &mut self.code[mark];
+        *line = line.replacen("if", &format!("if (result {})", t.name()), 1);
+    }
+
+    /// Starts a function, none of the names of its locals being taken yet
+    pub fn begin_function(&mut self, kind: VariableKind) {
+        self.params.clear();
+        self.locals.clear();
+        self.names = ["fp", "frame_base", "scratch_i32", "scratch_i64"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        self.frame = 0;
+        self.return_kind = kind;
+    }
+
+    /// Unused name of a local based on `name`
+    fn unique(&mut self, name: &str) -> String {
+        let mut unique = name.to_string();
+        let mut n = 0;
+        while self.names.contains(&unique) {
+            n += 1;
+            unique = format!("{}_{}", name, n);
+        }
+        self.names.insert(unique.clone());
+        format!("${}", unique)
+    }
+
+    pub fn param(&mut self, name: &str, t: Type) -> String {
+        let name = self.unique(name);
+        self.params.push((name.clone(), t));
+        name
+    }
+
+    /// New local of the current function, shadowed variables getting a name of their own
+    pub fn local(&mut self, name: &str, t: Type) -> String {
+        let name = self.unique(name);
+        self.locals.push((name.clone(), t));
+        name
+    }
+
+    /// Local of the current function only holding a value between two instructions, no code
+    /// being emitted in between
+    pub fn scratch(&mut self, t: Type) -> String {
+        let name = format!("$scratch_{}", t.name());
+        if !self.locals.iter().any(|(local, _)| *local == name) {
+            self.locals.push((name.clone(), t));
+        }
+        name
+    }
+
+    /// Reserves memory in the frame of the current function
+    pub fn frame(&mut self, words: usize) -> Slot {
+        let slot = Slot::Frame(self.frame);
+        self.frame += words * WORD;
+        slot
+    }
+
+    /// Restores the stack pointer before leaving the function
+    pub fn leave(&mut self) {
+        self.line(LEAVE);
+    }
+
+    /// Ends the function whose instructions were emitted since `begin_function`
+    pub fn push_function(&mut self, name: &str, result: Option<Type>) {
+        let code = std::mem::take(&mut self.code);
+        let f = &mut self.functions;
+
+        f.push_str(&format!("  (func $func_{}", name));
+        for (param, t) in self.params.iter() {
+            f.push_str(&format!(" (param {} {})", param, t.name()));
+        }
+        if let Some(t) = result {
+            f.push_str(&format!(" (result {})", t.name()));
+        }
+        f.push('\n');
+
+        let mut locals = self.locals.clone();
+        if self.frame > 0 {
+            locals.push(("$fp".to_string(), Type::I32));
+            locals.push(("$frame_base".to_string(), Type::I32));
+        }
+        for (local, t) in locals.iter() {
+            f.push_str(&format!("    (local {} {})\n", local, t.name()));
+        }
+
+        // The frame is taken below the stack pointer and given back on every exit
+        if self.frame > 0 {
+            f.push_str("    global.get $sp\n    local.tee $frame_base\n");
+            f.push_str(&format!("    i32.const {}\n    i32.sub\n", self.frame));
+            f.push_str("    local.tee $fp\n    global.set $sp\n");
+        }
+        for line in code.iter() {
+            match line.trim() {
+                LEAVE if self.frame > 0 => {
+                    let indent = &line[..line.len() - LEAVE.len()];
+                    f.push_str(&format!(
+                        "{}local.get $frame_base\n{}global.set $sp\n",
+                        indent, indent
+                    ));
+                }
+                LEAVE => {}
+                _ => {
+                    f.push_str(line);
+                    f.push('\n');
+                }
+            }
+        }
+        f.push_str("  )\n");
+    }
+
+    pub fn declare_global(&mut self, name: String, kind: VariableKind, value: Option<Number>) {
+        let slot = match kind {
+            VariableKind::Array(_) | VariableKind::Struct(_) => {
+                let slot = Slot::Static(self.statics);
+                for (offset, value) in kind.initial_words() {
+                    let bytes: String = value
+                        .to_le_bytes()
+                        .iter()
+                        .map(|byte| format!("\\{:02x}", byte))
+                        .collect();
+                    let address = self.statics + offset * WORD;
+                    self.fields
+                        .push_str(&format!("  (data (i32.const {}) \"{}\")\n", address, bytes));
+                }
+                self.statics += kind.words() * WORD;
+                slot
+            }
+            _ => {
+                let label = format!("$global_{}", name);
+                self.fields.push_str(&format!(
+                    "  (global {} (mut i64) (i64.const {}))\n",
+                    label,
+                    value.unwrap_or_else(|| initial(&kind))
+                ));
+                Slot::Global(label)
+            }
+        };
+        self.globals.declare(name, (kind, slot));
+    }
+
+    /// Slots of the globals in linear memory, the scope every function body starts from
+    pub fn globals(&self) -> Scopes<(VariableKind, Slot)> {
+        self.globals.clone()
+    }
+
+    /// Return kind of the function being emitted
+    pub fn return_kind(&self) -> &VariableKind {
+        &self.return_kind
+    }
+
+    pub fn next_id(&mut self) -> usize {
+        self.id += 1;
+        self.id
+    }
+
+    /// Enters a loop whose `continue` and `break` branch to the given labels
+    pub fn push_loop(&mut self, continue_label: String, break_label: String) {
+        self.loop_labels.push((continue_label, break_label));
+    }
+
+    /// Enters a switch, `break` leaves it while `continue` still targets the enclosing loop
+    pub fn push_switch(&mut self, break_label: String) {
+        let continue_label = self
+            .loop_labels
+            .last()
+            .map(|(continue_label, _)| continue_label.clone())
+            .unwrap_or_default();
+        self.loop_labels.push((continue_label, break_label));
+    }
+
+    pub fn pop_loop(&mut self) {
+        self.loop_labels.pop();
+    }
+
+    /// Labels `(continue, break)` of the innermost loop being emitted
+    pub fn loop_labels(&self) -> &(String, String) {
+        self.loop_labels
+            .last()
+            .expect("break/continue outside of a loop")
+    }
+
+    /// Makes the array literal of the declaration being emitted store into `slot`
+    pub fn set_array(&mut self, slot: Slot) {
+        self.array = Some(slot);
+    }
+
+    pub fn take_array(&mut self) -> Slot {
+        self.array
+            .take()
+            .expect("array literal outside of a declaration")
+    }
+
+    /// Pushes the address of a slot in memory
+    pub fn address(&mut self, slot: &Slot) {
+        match slot {
+            Slot::Frame(0) => self.line("local.get $fp"),
+            Slot::Frame(offset) => {
+                self.line(&format!("local.get $fp\ni32.const {}\ni32.add", offset))
+            }
+            Slot::Static(address) => self.line(&format!("i32.const {}", address)),
+            Slot::Local(_) | Slot::Global(_) => unreachable!("scalars have no address"),
+        }
+    }
+
+    /// Pushes a value, structs being handled through their address
+    pub fn load(&mut self, slot: &Slot, kind: &VariableKind) -> Option<Type> {
+        match (slot, kind) {
+            (_, VariableKind::Struct(_)) => {
+                self.address(slot);
+                return Some(Type::I32);
+            }
+            (Slot::Local(name), _) => self.line(&format!("local.get {}", name)),
+            (Slot::Global(name), _) => self.line(&format!("global.get {}", name)),
+            (Slot::Frame(offset), _) => {
+                self.line(&format!("local.get $fp\ni64.load offset={}", offset))
+            }
+            (Slot::Static(address), _) => self.line(&format!("i32.const {}\ni64.load", address)),
+        }
+        Some(Type::I64)
+    }
+
+    /// Pops a value into a slot, copying the struct it points to for struct kinds
+    pub fn store(&mut self, slot: &Slot, kind: &VariableKind) {
+        match (slot, kind) {
+            (_, VariableKind::Struct(t)) => {
+                let source = self.scratch(Type::I32);
+                self.line(&format!("local.set {}", source));
+                self.address(slot);
+                self.line(&format!(
+                    "local.get {}\ni32.const {}\nmemory.copy",
+                    source,
+                    t.words() * WORD
+                ));
+            }
+            (Slot::Local(name), _) => self.line(&format!("local.set {}", name)),
+            (Slot::Global(name), _) => self.line(&format!("global.set {}", name)),
+            (Slot::Frame(_) | Slot::Static(_), _) => {
+                let value = self.scratch(Type::I64);
+                self.line(&format!("local.set {}", value));
+                self.address(slot);
+                self.line(&format!("local.get {}\ni64.store", value));
+            }
+        }
+    }
+
+    pub fn zero(&mut self, slot: &Slot, kind: &VariableKind) {
+        match slot {
+            Slot::Frame(_) | Slot::Static(_) => {
+                self.address(slot);
+                self.line(&format!(
+                    "i32.const 0\ni32.const {}\nmemory.fill",
+                    kind.words() * WORD
+                ));
+                for (offset, value) in kind.initial_words() {
+                    self.line(&format!("i64.const {}", value));
+                    self.store(&slot.field(offset), &VariableKind::Number);
+                }
+            }
+            _ => {
+                self.line(&format!("i64.const {}", initial(kind)));
+                self.store(slot, kind);
+            }
+        }
+    }
+
+    /// Aborts unless the index on top of the stack is valid for an array of this length
+    pub fn bounds_check(&mut self, len: usize) {
+        let index = self.scratch(Type::I64);
+        self.line(&format!("local.tee {}\nlocal.get {}", index, index));
+        // i64.ge_u also rejects negative indexes, which are huge as unsigned numbers
+        self.line(&format!(
+            "i64.const {}\ni64.ge_u\nif\ncall $bounds_error\nend",
+            len
+        ));
+    }
+
+    /// Applies an operator to the two values on top of the stack
+    pub fn binop(&mut self, op: Op) {
+        if let Op::Div | Op::Mod = op {
+            return self.divide(op);
+        }
+        self.line(match op {
+            Op::Mul => "i64.mul",
+            Op::Add => "i64.add",
+            Op::Sub => "i64.sub",
+            Op::BitAnd => "i64.and",
+            Op::BitOr => "i64.or",
+            Op::BitXor => "i64.xor",
+            Op::Shl => "i64.shl",
+            Op::Shr => "i64.shr_s",
+            Op::Div | Op::Mod | Op::Not | Op::BitNot => unreachable!(),
+        });
+    }
+
+    /// Divides the two values on top of the stack. Dividing the smallest int by -1 traps in wasm,
+    /// so -1 is replaced by 1 and the quotient negated, wrapping around like the interpreter
+    fn divide(&mut self, op: Op) {
+        let divisor = self.scratch(Type::I64);
+        let if_minus_one = format!("local.get {}\ni64.const -1\ni64.eq\nselect", divisor);
+        self.line(&format!(
+            "local.set {}\ni64.const 1\nlocal.get {}\n{}",
+            divisor, divisor, if_minus_one
+        ));
+        match op {
+            Op::Div => self.line(&format!(
+                "i64.div_s\ni64.const -1\ni64.const 1\n{}\ni64.mul",
+                if_minus_one
+            )),
+            _ => self.line("i64.rem_s"),
+        }
+    }
+
+    /// Compares the two values on top of the stack, `&&` and `||` expecting them as `i32` bools
+    pub fn compare(&mut self, cond: CondOp) {
+        self.line(match cond {
+            CondOp::LT => "i64.lt_s",
+            CondOp::LEQ => "i64.le_s",
+            CondOp::GT => "i64.gt_s",
+            CondOp::GEQ => "i64.ge_s",
+            CondOp::EQ => "i64.eq",
+            CondOp::NEQ => "i64.ne",
+            CondOp::And => "i32.and",
+            CondOp::Or => "i32.or",
+        });
+        self.line("i64.extend_i32_u");
+    }
+
+    /// Replaces the index on top of the stack by the address of that element of an array
+    pub fn element(&mut self, slot: &Slot) {
+        self.line("i32.wrap_i64\ni32.const 3\ni32.shl");
+        self.address(slot);
+        self.line("i32.add");
+    }
+}
+
+// Lowering of the nodes, each emitting its instructions and returning the type of the value it
+// leaves on the stack
+impl Wasm {
+    /// Declares a wasm global for scalars, or static memory for arrays and structs
+    fn global(&mut self, global: &DeclareNode) {
+        if global.constant {
+            return;
+        }
+        let value = global.expression.as_ref().and_then(|e| e.const_eval());
+        self.declare_global(global.name.clone(), global.kind.clone(), value);
+    }
+
+    fn function(&mut self, func: &FuncDefNode) -> Result<()> {
+        let mut vars = self.globals();
+        vars.push_scope();
+
+        self.begin_function(func.kind.clone());
+        if let VariableKind::Struct(_) = func.kind {
+            self.param("return", Type::I32);
+        }
+
+        // Struct arguments are addresses into the frame of the caller, copied into the frame
+        let mut copies = vec![];
+        for (kind, name) in func.args.iter() {
+            let slot = match kind {
+                VariableKind::Struct(_) => {
+                    let param = self.param(name, Type::I32);
+                    let slot = self.frame(kind.words());
+                    copies.push((param, slot.clone(), kind));
+                    slot
+                }
+                _ => Slot::Local(self.param(name, Type::I64)),
+            };
+            vars.declare(name.clone(), (kind.clone(), slot));
+        }
+        for (param, slot, kind) in copies.iter() {
+            self.line(&format!("local.get {}", param));
+            self.store(slot, kind);
+        }
+
+        self.block_node(&func.code, &mut vars)?;
+        self.leave();
+
+        // Falling off the end returns zero, or the struct as left by the caller
+        let result = match func.kind {
+            VariableKind::None => None,
+            VariableKind::Struct(_) => {
+                self.line("local.get $return");
+                Some(Type::I32)
+            }
+            _ => {
+                self.line("i64.const 0");
+                Some(Type::I64)
+            }
+        };
+        self.push_function(&func.name, result);
+        Ok(())
+    }
+
+    fn node(
+        &mut self,
+        node: &dyn Node,
+        vars: &mut Scopes<(VariableKind, Slot)>,
+    ) -> Result<Option<Type>> {
+        match node.node_ref() {
+            NodeRef::Binary(node) => {
+                self.node(node.left_child.as_ref(), vars)?;
+                self.node(node.right_child.as_ref(), vars)?;
+                self.binop(node.op);
+                Ok(Some(Type::I64))
+            }
+            NodeRef::Unary(node) => self.unary_node(node, vars),
+            NodeRef::Number(node) => self.node(node.child.as_ref(), vars),
+            NodeRef::NumberLiteral(node) => {
+                self.line(&format!("i64.const {}", node.value));
+                Ok(Some(Type::I64))
+            }
+            NodeRef::SimpleVariable(node) => {
+                self.line(&format!("i64.const {}", node.value.to_number()));
+                Ok(Some(Type::I64))
+            }
+            NodeRef::Bool(node) => self.node(node.child.as_ref(), vars),
+            NodeRef::BoolLiteral(node) => {
+                self.line(&format!("i64.const {}", node.value as Number));
+                Ok(Some(Type::I64))
+            }
+            NodeRef::String(_) | NodeRef::StringLiteral(_) => {
+                bail!("Strings are not supported by the wasm target")
+            }
+            NodeRef::Declare(node) => self.declare_node(node, vars),
+            NodeRef::Assign(node) => {
+                self.node(node.expression.as_ref(), vars)?;
+                let (kind, slot) = vars.get(&node.name).unwrap();
+                if let Some(op) = node.op {
+                    self.update(slot, op);
+                }
+                self.store(slot, kind);
+                Ok(None)
+            }
+            NodeRef::IncDec(node) => {
+                let (kind, slot) = vars.get(&node.name).unwrap();
+                if !node.prefix {
+                    self.load(slot, kind);
+                }
+                self.load(slot, kind);
+                self.line("i64.const 1");
+                self.binop(node.op);
+                self.store(slot, kind);
+                if node.prefix {
+                    self.load(slot, kind);
+                }
+                Ok(Some(Type::I64))
+            }
+            NodeRef::Variable(node) => {
+                let (kind, slot) = vars.get(&node.name).unwrap();
+                Ok(self.load(slot, kind))
+            }
+            NodeRef::ArrayLiteral(node) => {
+                // Stores the elements into the array being declared
+                let slot = self.take_array();
+                for (idx, element) in node.elements.iter().enumerate() {
+                    self.node(element.as_ref(), vars)?;
+                    self.store(&slot.field(idx), &VariableKind::Number);
+                }
+                Ok(None)
+            }
+            NodeRef::Index(node) => {
+                self.node(node.index.as_ref(), vars)?;
+                let (kind, slot) = vars.get(&node.name).unwrap();
+                if let VariableKind::Array(len) = kind {
+                    self.bounds_check(*len);
+                }
+                self.element(slot);
+                self.line("i64.load");
+                Ok(Some(Type::I64))
+            }
+            NodeRef::IndexAssign(node) => self.index_assign_node(node, vars),
+            NodeRef::Len(node) => {
+                let (kind, _) = vars.get(&node.name).unwrap();
+                if let VariableKind::Array(len) = kind {
+                    self.line(&format!("i64.const {}", len));
+                }
+                Ok(Some(Type::I64))
+            }
+            NodeRef::StructLiteral(node) => {
+                // Builds the struct in a temporary of the current frame and pushes its address
+                let temporary = self.frame(node.kind.words());
+                for (field, (name, kind)) in node.fields.iter().zip(node.kind.fields.iter()) {
+                    self.node(field.as_ref(), vars)?;
+                    let (_, offset, _) = node.kind.field(name).unwrap();
+                    self.store(&temporary.field(offset), kind);
+                }
+                self.address(&temporary);
+                Ok(Some(Type::I32))
+            }
+            NodeRef::Field(node) => {
+                let (kind, slot) = vars.get(&node.name).unwrap();
+                let (_, offset, kind) = resolve_field(kind, &node.path)?;
+                Ok(self.load(&slot.field(offset), &kind))
+            }
+            NodeRef::FieldAssign(node) => {
+                self.node(node.expression.as_ref(), vars)?;
+                let (kind, slot) = vars.get(&node.name).unwrap();
+                let (_, offset, kind) = resolve_field(kind, &node.path)?;
+                let slot = slot.field(offset);
+                if let Some(op) = node.op {
+                    self.update(&slot, op);
+                }
+                self.store(&slot, &kind);
+                Ok(None)
+            }
+            NodeRef::EnumVariant(node) => {
+                self.line(&format!("i64.const {}", node.value));
+                Ok(Some(Type::I64))
+            }
+            NodeRef::Cond(node) => {
+                self.node(node.left_child.as_ref(), vars)?;
+                if matches!(node.cond, CondOp::And | CondOp::Or) {
+                    self.line("i64.const 0\ni64.ne");
+                }
+                self.node(node.right_child.as_ref(), vars)?;
+                if matches!(node.cond, CondOp::And | CondOp::Or) {
+                    self.line("i64.const 0\ni64.ne");
+                }
+                self.compare(node.cond);
+                Ok(Some(Type::I64))
+            }
+            NodeRef::Ternary(node) => {
+                self.node(node.cond.as_ref(), vars)?;
+                self.line("i64.const 0\ni64.ne");
+                let mark = self.mark();
+                self.line("if");
+                let t = self.node(node.if_child.as_ref(), vars)?.unwrap();
+                self.set_result(mark, t);
+                self.line("else");
+                self.node(node.else_child.as_ref(), vars)?;
+                self.line("end");
+                Ok(Some(t))
+            }
+            NodeRef::If(node) => {
+                self.node(node.cond.as_ref(), vars)?;
+                self.line("i64.const 0\ni64.ne\nif");
+                self.statement(node.if_child.as_ref(), vars)?;
+                if let Some(e) = &node.else_child {
+                    self.line("else");
+                    self.statement(e.as_ref(), vars)?;
+                }
+                self.line("end");
+                Ok(None)
+            }
+            NodeRef::While(node) => {
+                let id = self.next_id();
+                self.line(&format!("block $while_end_{}\nloop $while_{}", id, id));
+                self.node(node.cond.as_ref(), vars)?;
+                self.line(&format!("i64.eqz\nbr_if $while_end_{}", id));
+                self.push_loop(format!("$while_{}", id), format!("$while_end_{}", id));
+                self.statement(node.child.as_ref(), vars)?;
+                self.pop_loop();
+                self.line(&format!("br $while_{}\nend\nend", id));
+                Ok(None)
+            }
+            NodeRef::For(node) => self.for_node(node, vars),
+            NodeRef::DoWhile(node) => {
+                let id = self.next_id();
+                self.line(&format!("block $do_end_{}\nloop $do_{}", id, id));
+                self.line(&format!("block $do_cond_{}", id));
+                self.push_loop(format!("$do_cond_{}", id), format!("$do_end_{}", id));
+                self.statement(node.child.as_ref(), vars)?;
+                self.pop_loop();
+                self.line("end");
+                self.node(node.cond.as_ref(), vars)?;
+                self.line(&format!("i64.const 0\ni64.ne\nbr_if $do_{}\nend\nend", id));
+                Ok(None)
+            }
+            NodeRef::Switch(node) => self.switch_node(node, vars),
+            NodeRef::LoopJump(node) => {
+                let (continue_label, break_label) = self.loop_labels();
+                let label = match node.flow {
+                    Flow::Break => break_label,
+                    Flow::Continue => continue_label,
+                    Flow::Normal | Flow::Return(_) => unreachable!(),
+                };
+                let jump = format!("br {}", label);
+                self.line(&jump);
+                Ok(None)
+            }
+            NodeRef::Block(node) => self.block_node(node, vars),
+            NodeRef::FuncCall(node) => self.func_call_node(node, vars),
+            NodeRef::Return(node) => {
+                if let Some(c) = &node.child {
+                    self.node(c.as_ref(), vars)?;
+                }
+                if let VariableKind::Struct(t) = self.return_kind().clone() {
+                    let source = self.scratch(Type::I32);
+                    self.line(&format!("local.set {}\nlocal.get $return", source));
+                    self.line(&format!(
+                        "local.get {}\ni32.const {}\nmemory.copy\nlocal.get $return",
+                        source,
+                        t.words() * WORD
+                    ));
+                }
+                self.leave();
+                self.line("return");
+                Ok(None)
+            }
+        }
+    }
+
+    /// Emits a node used as a statement, dropping the value it may leave on the stack
+    fn statement(
+        &mut self,
+        node: &dyn Node,
+        vars: &mut Scopes<(VariableKind, Slot)>,
+    ) -> Result<()> {
+        if self.node(node, vars)?.is_some() {
+            self.line("drop");
+        }
+        Ok(())
+    }
+
+    /// Stores the value on top of the stack into a new variable, or zeroes it without initializer
+    fn initialize(&mut self, slot: &Slot, kind: &VariableKind, initialized: bool) {
+        if initialized {
+            self.store(slot, kind);
+        } else {
+            self.zero(slot, kind);
+        }
+    }
+
+    /// Replaces the value on top of the stack by `slot op value`, for compound assignments
+    fn update(&mut self, slot: &Slot, op: Op) {
+        let value = self.scratch(Type::I64);
+        self.line(&format!("local.set {}", value));
+        self.load(slot, &VariableKind::Number);
+        self.line(&format!("local.get {}", value));
+        self.binop(op);
+    }
+
+    fn unary_node(
+        &mut self,
+        node: &UnaryNode,
+        vars: &mut Scopes<(VariableKind, Slot)>,
+    ) -> Result<Option<Type>> {
+        match node.kind {
+            UnaryNodeKind::Pos => {
+                self.node(node.child.as_ref(), vars)?;
+            }
+            UnaryNodeKind::Neg => {
+                self.line("i64.const 0");
+                self.node(node.child.as_ref(), vars)?;
+                self.line("i64.sub");
+            }
+            UnaryNodeKind::Not => {
+                self.node(node.child.as_ref(), vars)?;
+                self.line("i64.eqz\ni64.extend_i32_u");
+            }
+            UnaryNodeKind::BitNot => {
+                self.node(node.child.as_ref(), vars)?;
+                self.line("i64.const -1\ni64.xor");
+            }
+        }
+        Ok(Some(Type::I64))
+    }
+
+    fn declare_node(
+        &mut self,
+        node: &DeclareNode,
+        vars: &mut Scopes<(VariableKind, Slot)>,
+    ) -> Result<Option<Type>> {
+        if node.constant {
+            return Ok(None);
+        }
+
+        let slot = match node.kind {
+            VariableKind::Array(_) => {
+                let slot = self.frame(node.kind.words());
+                self.zero(&slot, &node.kind);
+                if let Some(e) = &node.expression {
+                    self.set_array(slot.clone());
+                    self.node(e.as_ref(), vars)?;
+                }
+                slot
+            }
+            VariableKind::Struct(_) => {
+                if let Some(e) = &node.expression {
+                    self.node(e.as_ref(), vars)?;
+                }
+                let slot = self.frame(node.kind.words());
+                self.initialize(&slot, &node.kind, node.expression.is_some());
+                slot
+            }
+            _ => {
+                if let Some(e) = &node.expression {
+                    self.node(e.as_ref(), vars)?;
+                }
+                let slot = Slot::Local(self.local(&node.name, Type::I64));
+                self.initialize(&slot, &node.kind, node.expression.is_some());
+                slot
+            }
+        };
+        vars.declare(node.name.clone(), (node.kind.clone(), slot));
+        Ok(None)
+    }
+
+    fn index_assign_node(
+        &mut self,
+        node: &IndexAssignNode,
+        vars: &mut Scopes<(VariableKind, Slot)>,
+    ) -> Result<Option<Type>> {
+        // The value is computed first like the other targets do, and kept while the index is
+        self.node(node.expression.as_ref(), vars)?;
+        let value = self.local("value", Type::I64);
+        self.line(&format!("local.set {}", value));
+        self.node(node.index.as_ref(), vars)?;
+
+        let (kind, slot) = vars.get(&node.name).unwrap();
+        if let VariableKind::Array(len) = kind {
+            self.bounds_check(*len);
+        }
+        self.element(slot);
+        if let Some(op) = node.op {
+            let address = self.scratch(Type::I32);
+            self.line(&format!(
+                "local.tee {}\nlocal.get {}\ni64.load",
+                address, address
+            ));
+            self.line(&format!("local.get {}", value));
+            self.binop(op);
+        } else {
+            self.line(&format!("local.get {}", value));
+        }
+        self.line("i64.store");
+        Ok(None)
+    }
+
+    fn for_node(
+        &mut self,
+        node: &ForNode,
+        vars: &mut Scopes<(VariableKind, Slot)>,
+    ) -> Result<Option<Type>> {
+        let id = self.next_id();
+        vars.push_scope();
+        if let Some(init) = &node.init {
+            self.statement(init.as_ref(), vars)?;
+        }
+        self.line(&format!("block $for_end_{}\nloop $for_{}", id, id));
+        if let Some(cond) = &node.cond {
+            self.node(cond.as_ref(), vars)?;
+            self.line(&format!("i64.eqz\nbr_if $for_end_{}", id));
+        }
+        // `continue` leaves this block to run the step
+        self.line(&format!("block $for_step_{}", id));
+        self.push_loop(format!("$for_step_{}", id), format!("$for_end_{}", id));
+        self.statement(node.child.as_ref(), vars)?;
+        self.pop_loop();
+        self.line("end");
+        if let Some(step) = &node.step {
+            self.statement(step.as_ref(), vars)?;
+        }
+        self.line(&format!("br $for_{}\nend\nend", id));
+        vars.pop_scope();
+        Ok(None)
+    }
+
+    /// Nests a block per case, the innermost one for the first case, so that branching to the
+    /// block of a case lands right before its statements, which fall through into the next ones
+    fn switch_node(
+        &mut self,
+        node: &SwitchNode,
+        vars: &mut Scopes<(VariableKind, Slot)>,
+    ) -> Result<Option<Type>> {
+        let id = self.next_id();
+        let end_label = format!("$switch_end_{}", id);
+        let case_label = |idx: usize| format!("$switch_{}_case_{}", id, idx);
+
+        self.node(node.expression.as_ref(), vars)?;
+        let value = self.scratch(Type::I64);
+        self.line(&format!("local.set {}", value));
+
+        self.line(&format!("block {}", end_label));
+        for idx in (0..node.cases.len()).rev() {
+            self.line(&format!("block {}", case_label(idx)));
+        }
+
+        let default_label = match node.cases.iter().position(|(label, _)| label.is_none()) {
+            Some(idx) => case_label(idx),
+            None => end_label.clone(),
+        };
+        let values: Vec<(Number, usize)> = node
+            .cases
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, (label, _))| label.map(|v| (v, idx)))
+            .collect();
+
+        let min = values.iter().map(|(v, _)| *v).min().unwrap_or(0);
+        let max = values.iter().map(|(v, _)| *v).max().unwrap_or(0);
+        let range = max as i128 - min as i128 + 1;
+
+        // Same choice of a jump table as the other targets
+        if values.len() >= 3 && range <= 2 * values.len() as i128 {
+            let labels: Vec<String> = (min..=max)
+                .map(|v| match values.iter().find(|(value, _)| *value == v) {
+                    Some((_, idx)) => case_label(*idx),
+                    None => default_label.clone(),
+                })
+                .collect();
+            self.line(&format!("local.get {}\ni64.const {}\ni64.sub", value, min));
+            self.line(&format!(
+                "local.tee {}\ni64.const {}\ni64.ge_u",
+                value, range
+            ));
+            self.line(&format!("br_if {}", default_label));
+            self.line(&format!("local.get {}\ni32.wrap_i64", value));
+            self.line(&format!("br_table {} {}", labels.join(" "), default_label));
+        } else {
+            for (v, idx) in values.iter() {
+                self.line(&format!("local.get {}\ni64.const {}\ni64.eq", value, v));
+                self.line(&format!("br_if {}", case_label(*idx)));
+            }
+            self.line(&format!("br {}", default_label));
+        }
+
+        self.push_switch(end_label);
+        vars.push_scope();
+        for (_, children) in node.cases.iter() {
+            self.line("end");
+            for child in children.iter() {
+                self.statement(child.as_ref(), vars)?;
+            }
+        }
+        vars.pop_scope();
+        self.pop_loop();
+        self.line("end");
+        Ok(None)
+    }
+
+    fn block_node(
+        &mut self,
+        node: &BlockNode,
+        vars: &mut Scopes<(VariableKind, Slot)>,
+    ) -> Result<Option<Type>> {
+        vars.push_scope();
+        for child in node.children.iter() {
+            self.statement(child.as_ref(), vars)?;
+        }
+        vars.pop_scope();
+        Ok(None)
+    }
+
+    fn func_call_node(
+        &mut self,
+        node: &FuncCallNode,
+        vars: &mut Scopes<(VariableKind, Slot)>,
+    ) -> Result<Option<Type>> {
+        let fborrow = node.funcs.borrow(); // NOTE: borrow
+        let params = node.params.borrow();
+
+        match node.name.as_ref() {
+            "println" | "print" => {
+                self.node(params[0].as_ref(), vars)?;
+                self.line("call $print_int");
+                Ok(None)
+            }
+            "readln" => {
+                self.line("call $read_int");
+                Ok(Some(Type::I64))
+            }
+            "exit" => {
+                self.node(params[0].as_ref(), vars)?;
+                self.line("call $exit");
+                Ok(None)
+            }
+            "argc" => {
+                self.line("call $argc");
+                Ok(Some(Type::I64))
+            }
+            "arg" => bail!("Strings are not supported by the wasm target"),
+            _ => {
+                let func = fborrow.get(&node.name).unwrap();
+
+                // Structs are returned into a temporary of the caller, passed as a hidden first argument
+                if let VariableKind::Struct(t) = &func.kind {
+                    let temporary = self.frame(t.words());
+                    self.address(&temporary);
+                }
+                // Struct arguments are passed as addresses, the callee copies them
+                for param in params.iter() {
+                    self.node(param.as_ref(), vars)?;
+                }
+                self.line(&format!("call $func_{}", node.name));
+
+                Ok(match func.kind {
+                    VariableKind::None => None,
+                    VariableKind::Struct(_) => Some(Type::I32),
+                    _ => Some(Type::I64),
+                })
+            }
+        }
+    }
+}
+
+/// Runs a module emitted by `Wasm::module` with the arguments `args`, its host functions reading
+/// and printing through the standard input and output, returning the exit status of the program
+pub fn run(module: &str, args: &[String]) -> Result<Number> {
+    let binary = wat::parse_str(module)?;
+    let engine = Engine::default();
+    let module = Module::new(&engine, &binary[..]).map_err(|e| eyre!("{}", e))?;
+    let mut store = Store::new(&engine, ());
+    let mut linker = <Linker<()>>::new(&engine);
+
+    linker
+        .func_wrap("env", "print_int", |n: i64| println!("{}", n))
+        .map_err(|e| eyre!("{}", e))?;
+    linker
+        .func_wrap("env", "read_int", || -> Result<i64, wasmi::Error> {
+            let mut input = String::new();
+            io::stdin()
+                .read_line(&mut input)
+                .map_err(|e| wasmi::Error::new(e.to_string()))?;
+            input
+                .trim()
+                .parse()
+                .map_err(|_| wasmi::Error::new(format!("Invalid number {:?}", input.trim())))
+        })
+        .map_err(|e| eyre!("{}", e))?;
+    linker
+        .func_wrap("env", "bounds_error", || -> Result<(), wasmi::Error> {
+            Err(wasmi::Error::new("array index out of bounds"))
+        })
+        .map_err(|e| eyre!("{}", e))?;
+    linker
+        .func_wrap("env", "exit", |status: i64| -> Result<(), wasmi::Error> {
+            Err(wasmi::Error::i32_exit(status as i32))
+        })
+        .map_err(|e| eyre!("{}", e))?;
+    // Strings are not supported, the program only sees how many arguments it got
+    let count = args.len() as i64;
+    linker
+        .func_wrap("env", "argc", move || count)
+        .map_err(|e| eyre!("{}", e))?;
+
+    let instance = linker
+        .instantiate(&mut store, &module)
+        .and_then(|instance| instance.start(&mut store))
+        .map_err(|e| eyre!("{}", e))?;
+    let main = instance
+        .get_func(&store, "main")
+        .ok_or_else(|| eyre!("No main function exported"))?;
+    let mut results = vec![Val::I64(0); main.ty(&store).results().len()];
+    match main.call(&mut store, &[], &mut results) {
+        Ok(()) => Ok(results.first().and_then(Val::i64).unwrap_or(0)),
+        Err(e) => match e.i32_exit_status() {
+            Some(status) => Ok(status as Number),
+            None => Err(eyre!("{}", e)),
+        },
+    }
+}
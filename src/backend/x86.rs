@@ -0,0 +1,483 @@
+use super::dwarf::{self, Dwarf};
+use crate::error::RuntimeError;
+use crate::ir::{Function, Instr, Location, Program};
+use crate::operator::{CondOp, Op};
+use crate::token::{Number, Position};
+
+use std::convert::TryFrom;
+
+/// Registers and directives of 32 bit x86 or x86-64, which otherwise share their instructions
+pub struct Mode {
+    /// Runtime with `print`, `divide`, `exit`, the comparison helpers and `_start`
+    base: &'static str,
+    word: usize,
+    /// Operand register, the accumulator being `b`
+    a: &'static str,
+    b: &'static str,
+    c: &'static str,
+    d: &'static str,
+    si: &'static str,
+    di: &'static str,
+    bp: &'static str,
+    sp: &'static str,
+    /// Size of a word in memory operands
+    size: &'static str,
+    define: &'static str,
+    reserve: &'static str,
+    /// Suffix of the string instructions moving a word
+    string: char,
+    /// Registers taking the first arguments of a call, the others being pushed
+    arg_registers: &'static [&'static str],
+    /// Number of the frame pointer in DWARF
+    frame_register: u8,
+}
+
+pub const X86_32: Mode = Mode {
+    base: include_str!("../base.asm"),
+    word: 4,
+    a: "eax",
+    b: "ebx",
+    c: "ecx",
+    d: "edx",
+    si: "esi",
+    di: "edi",
+    bp: "ebp",
+    sp: "esp",
+    size: "dword",
+    define: "dd",
+    reserve: "resd",
+    string: 'd',
+    arg_registers: &[],
+    frame_register: 5,
+};
+
+pub const X86_64: Mode = Mode {
+    base: include_str!("../base64.asm"),
+    word: 8,
+    a: "rax",
+    b: "rbx",
+    c: "rcx",
+    d: "rdx",
+    si: "rsi",
+    di: "rdi",
+    bp: "rbp",
+    sp: "rsp",
+    size: "qword",
+    define: "dq",
+    reserve: "resq",
+    string: 'q',
+    arg_registers: &["rdi", "rsi", "rdx", "rcx", "r8", "r9"],
+    frame_register: 6,
+};
+
+pub fn emit(program: &Program, mode: &Mode) -> String {
+    let mut emitter = Emitter {
+        mode,
+        text: String::new(),
+        data: String::new(),
+        bss: String::new(),
+        spilled: 0,
+        debug: program.source.is_some(),
+        rows: vec![],
+        row_labels: 0,
+        functions: vec![],
+        faults: String::new(),
+        fault_labels: 0,
+    };
+
+    emitter.line(mode.base);
+    // What `main` returns is the exit status of the program
+    emitter.line("call func_main\njmp exit");
+
+    for global in program.globals.iter() {
+        match global.data() {
+            Some(words) => {
+                let words: Vec<String> = words.iter().map(|word| word.to_string()).collect();
+                let line = format!("{}: {} {}\n", global.label, mode.define, words.join(", "));
+                emitter.data.push_str(&line);
+            }
+            None => emitter.bss.push_str(&format!(
+                "{}: {} {}\n",
+                global.label, mode.reserve, global.kind.words()
+            )),
+        }
+    }
+
+    for function in program.functions.iter() {
+        emitter.line("");
+        emitter.function(function);
+    }
+    if !emitter.faults.is_empty() {
+        let faults = std::mem::take(&mut emitter.faults);
+        emitter.line("");
+        emitter.line(&faults);
+    }
+
+    let mut text = emitter.text;
+    if !emitter.data.is_empty() {
+        text.push_str("\nsection .data\n");
+        text.push_str(&emitter.data);
+    }
+    if !emitter.bss.is_empty() {
+        text.push_str("\nsection .bss\n");
+        text.push_str(&emitter.bss);
+    }
+    if let Some(source) = &program.source {
+        let dwarf = Dwarf {
+            source,
+            word: mode.word,
+            frame_register: mode.frame_register,
+            globals: &program.globals,
+            functions: emitter.functions,
+        };
+        text.push_str(&dwarf.sections());
+    }
+    text
+}
+
+struct Emitter<'a> {
+    mode: &'a Mode,
+    text: String,
+    data: String,
+    bss: String,
+    /// Argument registers saved below the frame pointer by the current function
+    spilled: usize,
+    /// Whether the statements are labeled for the debug info
+    debug: bool,
+    /// Labels of the statements of the current function and their line
+    rows: Vec<(String, usize)>,
+    /// Labels of statements emitted so far, numbering the next one
+    row_labels: usize,
+    functions: Vec<dwarf::Function<'a>>,
+    /// Code aborting the program when arithmetic faults, emitted after the functions
+    faults: String,
+    /// Labels of faults emitted so far, numbering the next one
+    fault_labels: usize,
+}
+
+impl<'a> Emitter<'a> {
+    fn line(&mut self, s: &str) {
+        self.text.push_str(s);
+        self.text.push('\n');
+    }
+
+    fn location(&self, location: &Location) -> String {
+        match location {
+            Location::Local(offset) => format!(
+                "[{} - {}]",
+                self.mode.bp,
+                (offset + self.spilled) * self.mode.word
+            ),
+            Location::Global(label, 0) => format!("[{}]", label),
+            Location::Global(label, offset) => {
+                format!("[{} + {}]", label, offset * self.mode.word)
+            }
+        }
+    }
+
+    /// Element of the array at `location` whose index is in `register`
+    fn indexed(&self, location: &Location, register: &str) -> String {
+        let word = self.mode.word;
+        match location {
+            Location::Local(offset) => format!(
+                "[{} + {} * {} - {}]",
+                self.mode.bp,
+                register,
+                word,
+                (offset + self.spilled) * word
+            ),
+            Location::Global(label, 0) => format!("[{} + {} * {}]", label, register, word),
+            Location::Global(label, offset) => {
+                format!("[{} + {} + {} * {}]", label, offset * word, register, word)
+            }
+        }
+    }
+
+    /// Argument `idx` of the current function, either spilled from its register or pushed by
+    /// the caller above the return address
+    fn arg(&self, idx: usize) -> String {
+        let word = self.mode.word;
+        let registers = self.mode.arg_registers.len();
+        if idx < registers {
+            format!("[{} - {}]", self.mode.bp, (idx + 1) * word)
+        } else {
+            format!("[{} + {}]", self.mode.bp, (idx - registers + 2) * word)
+        }
+    }
+
+    /// Operand for an immediate, which x86-64 only encodes in 32 bits outside of `mov`
+    fn immediate(&mut self, value: Number) -> String {
+        if self.mode.word == 8 && i32::try_from(value).is_err() {
+            self.line(&format!("mov {}, {}", self.mode.a, value));
+            return self.mode.a.to_string();
+        }
+        value.to_string()
+    }
+
+    /// Label of code aborting the program with `error`, whose message is written to the standard
+    /// error by `runtime_error`
+    fn fault(&mut self, error: RuntimeError) -> String {
+        let label = format!("fault_{}", self.fault_labels);
+        self.fault_labels += 1;
+        self.data.push_str(&format!(
+            "{}_msg db \"Error: {}\", 10\n{}_msg_len equ $ - {}_msg\n",
+            label, error, label, label
+        ));
+        self.faults.push_str(&format!(
+            "{}:\nmov {}, {}_msg\nmov {}, {}_msg_len\njmp runtime_error\n",
+            label, self.mode.si, label, self.mode.d, label
+        ));
+        label
+    }
+
+    /// Jumps to code aborting the program when the last arithmetic instruction overflowed
+    fn check_overflow(&mut self, checked: Option<Position>) {
+        if let Some(position) = checked {
+            let label = self.fault(RuntimeError::Overflow(position));
+            self.line(&format!("jo {}", label));
+        }
+    }
+
+    fn function(&mut self, function: &'a Function) {
+        let m = self.mode;
+        self.line(&format!("func_{}:", function.name));
+        self.line(&format!("push {}\nmov {}, {}", m.bp, m.bp, m.sp));
+
+        self.spilled = function.args.min(m.arg_registers.len());
+        for register in m.arg_registers[..self.spilled].iter() {
+            self.line(&format!("push {}", register));
+        }
+        if function.frame > 0 {
+            self.line(&format!("sub {}, {}", m.sp, function.frame * m.word));
+        }
+
+        for instr in function.code.iter() {
+            self.instr(instr);
+        }
+
+        if self.debug {
+            let end = format!("debug_end_{}", function.name);
+            self.line(&format!("{}:", end));
+            let word = m.word as i64;
+            let locals = function
+                .locals
+                .iter()
+                .map(|local| match local.location {
+                    Location::Local(offset) => (local, -((offset + self.spilled) as i64) * word),
+                    Location::Global(..) => unreachable!(),
+                })
+                .collect();
+            self.functions.push(dwarf::Function {
+                name: &function.name,
+                line: function.line,
+                start: format!("func_{}", function.name),
+                end,
+                rows: std::mem::take(&mut self.rows),
+                locals,
+            });
+        }
+    }
+
+    fn instr(&mut self, instr: &Instr) {
+        let m = self.mode;
+        let (a, b, c) = (m.a, m.b, m.c);
+        match instr {
+            Instr::Line(line) => {
+                if self.debug {
+                    let label = format!("debug_row_{}", self.row_labels);
+                    self.row_labels += 1;
+                    self.line(&format!("{}:", label));
+                    self.rows.push((label, *line));
+                }
+            }
+            Instr::Label(label) => self.line(&format!("{}:", label)),
+            Instr::Jump(label) => self.line(&format!("jmp {}", label)),
+            Instr::JumpIfFalse(label) => self.line(&format!("cmp {}, False\nje {}", b, label)),
+            Instr::JumpIfTrue(label) => self.line(&format!("cmp {}, False\njne {}", b, label)),
+            Instr::JumpIfEqual(value, label) => {
+                let value = self.immediate(*value);
+                self.line(&format!("cmp {}, {}\nje {}", b, value, label));
+            }
+            Instr::JumpTable {
+                name,
+                min,
+                labels,
+                default,
+            } => {
+                self.data
+                    .push_str(&format!("{}: {} {}\n", name, m.define, labels.join(", ")));
+                let min = self.immediate(*min);
+                self.line(&format!("sub {}, {}", b, min));
+                self.line(&format!("cmp {}, {}", b, labels.len() - 1));
+                self.line(&format!("ja {}", default));
+                self.line(&format!("jmp [{} + {} * {}]", name, b, m.word));
+            }
+
+            Instr::Imm(value) => self.line(&format!("mov {}, {}", b, value)),
+            Instr::Load(location) => self.line(&format!("mov {}, {}", b, self.location(location))),
+            Instr::LoadAddress(location) => {
+                self.line(&format!("lea {}, {}", b, self.location(location)))
+            }
+            Instr::Store(location) => self.line(&format!("mov {}, {}", self.location(location), b)),
+            Instr::LoadOperand(location) => {
+                self.line(&format!("mov {}, {}", a, self.location(location)))
+            }
+            Instr::Push => self.line(&format!("push {}", b)),
+            Instr::PushAddress(location) => {
+                self.line(&format!("lea {}, {}", a, self.location(location)));
+                self.line(&format!("push {}", a));
+            }
+            Instr::Pop => self.line(&format!("pop {}", b)),
+            Instr::PopOperand => self.line(&format!("pop {}", a)),
+
+            Instr::BinOp(op, checked) => {
+                let s = match op {
+                    Op::Mul => format!("imul {}", b),
+                    // Sets the overflow flag instead of faulting when dividing the smallest
+                    // number by -1
+                    Op::Div | Op::Mod => "call divide".to_string(),
+                    Op::Add => format!("add {}, {}", a, b),
+                    Op::Sub => format!("sub {}, {}", a, b),
+                    Op::BitAnd => format!("and {}, {}", a, b),
+                    Op::BitOr => format!("or {}, {}", a, b),
+                    Op::BitXor => format!("xor {}, {}", a, b),
+                    Op::Shl => format!("mov {}, {}\nshl {}, cl", c, b, a),
+                    Op::Shr => format!("mov {}, {}\nsar {}, cl", c, b, a),
+                    Op::Not | Op::BitNot => unreachable!(),
+                };
+                self.line(&s);
+                self.check_overflow(*checked);
+                let result = if *op == Op::Mod { m.d } else { a };
+                self.line(&format!("mov {}, {}", b, result));
+            }
+            Instr::CheckDivisor(position) => {
+                let label = self.fault(RuntimeError::DivisionByZero(*position));
+                self.line(&format!("cmp {}, 0\nje {}", b, label));
+            }
+            // Any non zero value is true
+            Instr::Compare(cond @ (CondOp::And | CondOp::Or)) => {
+                let op = if *cond == CondOp::And { "and" } else { "or" };
+                self.line(&format!(
+                    "cmp {}, False\nsetne al\ncmp {}, False\nsetne bl",
+                    a, b
+                ));
+                self.line(&format!("{} bl, al\nmovzx {}, bl", op, b));
+            }
+            Instr::Compare(cond) => {
+                let helper = match cond {
+                    CondOp::LT => "binop_jl",
+                    CondOp::LEQ => "binop_jle",
+                    CondOp::GT => "binop_jg",
+                    CondOp::GEQ => "binop_jge",
+                    CondOp::EQ => "binop_je",
+                    CondOp::NEQ => "binop_jne",
+                    CondOp::And | CondOp::Or => unreachable!(),
+                };
+                self.line(&format!("cmp {}, {}\ncall {}", a, b, helper));
+            }
+            Instr::Negate(checked) => {
+                self.line(&format!("neg {}", b));
+                self.check_overflow(*checked);
+            }
+            Instr::Not => self.line(&format!("cmp {}, False\ncall binop_je", b)),
+            Instr::BitNot => self.line(&format!("not {}", b)),
+            Instr::Select => self.line(&format!(
+                "pop {}\npop {}\ncmp {}, False\ncmovne {}, {}",
+                a, c, c, b, a
+            )),
+            Instr::AddTo(location, delta, checked) => {
+                let instr = if *delta < 0 { "sub" } else { "add" };
+                let location = self.location(location);
+                self.line(&format!(
+                    "{} {} {}, {}",
+                    instr,
+                    m.size,
+                    location,
+                    delta.abs()
+                ));
+                self.check_overflow(*checked);
+            }
+
+            Instr::BoundsCheck(len) => {
+                // jae is taken for negative indexes too, which are huge as unsigned
+                self.line(&format!("cmp {}, {}\njae bounds_error", b, len));
+            }
+            Instr::LoadElement(location) => {
+                self.line(&format!("mov {}, {}", b, self.indexed(location, b)))
+            }
+            // The index register is left alone by the operators, which may use c and d
+            Instr::SetIndex => self.line(&format!("mov {}, {}", m.si, b)),
+            Instr::LoadOperandElement(location) => {
+                self.line(&format!("mov {}, {}", a, self.indexed(location, m.si)))
+            }
+            Instr::StoreElement(location) => {
+                self.line(&format!("mov {}, {}", self.indexed(location, m.si), b))
+            }
+
+            Instr::Copy(location, words) => {
+                self.line(&format!("mov {}, {}", m.si, b));
+                self.line(&format!("lea {}, {}", m.di, self.location(location)));
+                self.line(&format!("mov {}, {}", c, words));
+                self.line(&format!("rep movs{}", m.string));
+            }
+            Instr::Zero(location, 1) => {
+                self.line(&format!("mov {} {}, 0", m.size, self.location(location)))
+            }
+            Instr::Zero(location, words) => {
+                self.line(&format!("lea {}, {}", m.di, self.location(location)));
+                self.line(&format!("mov {}, {}", c, words));
+                self.line(&format!("xor {}, {}\nrep stos{}", a, a, m.string));
+            }
+            Instr::PopArray(location) => {
+                self.line(&format!("mov {}, {}", m.si, m.sp));
+                self.line(&format!("lea {}, {}", m.di, self.location(location)));
+                self.line(&format!("mov {}, {}\nrep movs{}", c, b, m.string));
+                self.line(&format!("lea {}, [{} + {} * {}]", m.sp, m.sp, b, m.word));
+            }
+
+            Instr::Print | Instr::PrintString => {
+                let routine = match instr {
+                    Instr::Print => "print",
+                    _ => "print_string",
+                };
+                match m.arg_registers.first() {
+                    Some(register) => self.line(&format!(
+                        "push {}\nmov {}, {}\ncall {}\npop {}",
+                        b, register, b, routine, b
+                    )),
+                    None => self.line(&format!("push {}\ncall {}\npop {}", b, routine, b)),
+                }
+            }
+            Instr::Read => self.line("call read"),
+            Instr::Exit => self.line("jmp exit"),
+            Instr::ArgCount => self.line(&format!("mov {}, [arg_count]", b)),
+            Instr::ProgramArg => self.line("call argument"),
+            Instr::LoadArg(idx) => self.line(&format!("mov {}, {}", b, self.arg(*idx))),
+            Instr::Call(name, args) => {
+                let in_registers = (*args).min(m.arg_registers.len());
+                for register in m.arg_registers[..in_registers].iter() {
+                    self.line(&format!("pop {}", register));
+                }
+                self.line(&format!("call func_{}", name));
+                if args > &in_registers {
+                    self.line(&format!("add {}, {}", m.sp, (args - in_registers) * m.word));
+                }
+                if !m.arg_registers.is_empty() {
+                    self.line(&format!("mov {}, {}", b, a));
+                }
+            }
+            Instr::ReturnStruct(words) => {
+                let address = self.arg(0);
+                self.line(&format!("mov {}, {}\nmov {}, {}", m.si, b, m.di, address));
+                self.line(&format!("mov {}, {}", c, words));
+                self.line(&format!("rep movs{}\nmov {}, {}", m.string, b, address));
+            }
+            Instr::Return => {
+                if !m.arg_registers.is_empty() {
+                    self.line(&format!("mov {}, {}", a, b));
+                }
+                self.line(&format!("mov {}, {}\npop {}\nret", m.sp, m.bp, m.bp));
+            }
+        }
+    }
+}
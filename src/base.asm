@@ -1,96 +1,251 @@
-; constantes
-SYS_EXIT equ 1
-SYS_READ equ 3
-SYS_WRITE equ 4
-STDIN equ 0
-STDOUT equ 1
-True equ 1
-False equ 0
-
-segment .data
-
-segment .bss  ; variaveis
-  res RESB 1
-
-section .text
-  global _start
-
-print:  ; subrotina print
-
-  PUSH EBP ; guarda o base pointer
-  MOV EBP, ESP ; estabelece um novo base pointer
-
-  MOV EAX, [EBP+8] ; 1 argumento antes do RET e EBP
-  XOR ESI, ESI
-
-print_dec: ; empilha todos os digitos
-  MOV EDX, 0
-  MOV EBX, 0x000A
-  DIV EBX
-  ADD EDX, '0'
-  PUSH EDX
-  INC ESI ; contador de digitos
-  CMP EAX, 0
-  JZ print_next ; quando acabar pula
-  JMP print_dec
-
-print_next:
-  CMP ESI, 0
-  JZ print_exit ; quando acabar de imprimir
-  DEC ESI
-
-  MOV EAX, SYS_WRITE
-  MOV EBX, STDOUT
-
-  POP ECX
-  MOV [res], ECX
-  MOV ECX, res
-
-  MOV EDX, 1
-  INT 0x80
-
-  JMP print_next
-
-print_exit:
-  POP EBP
-  RET
-
-; subrotinas if/while
-binop_je:
-  JE binop_true
-  JMP binop_false
-
-binop_jne:
-  JNE binop_true
-  JMP binop_false
-
-binop_jg:
-  JG binop_true
-  JMP binop_false
-
-binop_jge:
-  JGE binop_true
-  JMP binop_false
-
-binop_jl:
-  JL binop_true
-  JMP binop_false
-
-binop_jle:
-  JLE binop_true
-  JMP binop_false
-
-binop_false:
-  MOV EBX, False
-  JMP binop_exit
-binop_true:
-  MOV EBX, True
-binop_exit:
-  RET
-
-_start:
-
-  PUSH EBP ; guarda o base pointer
-  MOV EBP, ESP ; estabelece um novo base pointer
-
-  ; codigo gerado pelo compilador
+; constantes
+SYS_EXIT equ 1
+SYS_READ equ 3
+SYS_WRITE equ 4
+STDIN equ 0
+STDOUT equ 1
+STDERR equ 2
+True equ 1
+False equ 0
+
+segment .data
+  newline db 10
+  bounds_msg db "Error: array index out of bounds", 10
+  bounds_msg_len equ $ - bounds_msg
+  argument_msg db "Error: argument index out of bounds", 10
+  argument_msg_len equ $ - argument_msg
+
+segment .bss  ; variaveis
+  res RESB 1
+  arg_count RESD 1 ; argc do programa
+  arg_values RESD 1 ; endereco de argv
+
+section .text
+  global _start
+
+print:  ; subrotina print, imprime o numero com sinal seguido de uma quebra de linha
+
+  PUSH EBP ; guarda o base pointer
+  MOV EBP, ESP ; estabelece um novo base pointer
+
+  MOV EAX, [EBP+8] ; 1 argumento antes do RET e EBP
+  MOV ESI, EAX ; guarda o sinal
+  MOV EBX, 10
+  DEC ESP ; o texto e montado na pilha, de tras para frente
+  MOV BYTE [ESP], 10 ; quebra de linha
+
+print_dec: ; empilha todos os digitos
+  CDQ
+  IDIV EBX ; o resto tem o sinal do numero
+  CMP EDX, 0
+  JGE print_digit
+  NEG EDX
+
+print_digit:
+  ADD EDX, '0'
+  DEC ESP
+  MOV [ESP], DL
+  CMP EAX, 0
+  JNE print_dec ; ate acabarem os digitos
+
+  CMP ESI, 0
+  JGE print_write
+  DEC ESP
+  MOV BYTE [ESP], '-'
+
+print_write:
+  MOV EAX, SYS_WRITE
+  MOV EBX, STDOUT
+  MOV ECX, ESP
+  MOV EDX, EBP
+  SUB EDX, ESP ; tamanho do texto
+  INT 0x80
+
+  MOV ESP, EBP
+  POP EBP
+  RET
+
+print_string:  ; subrotina print_string, imprime uma string terminada em zero e quebra a linha
+
+  PUSH EBP ; guarda o base pointer
+  MOV EBP, ESP ; estabelece um novo base pointer
+
+  MOV ECX, [EBP+8] ; 1 argumento antes do RET e EBP
+  MOV EDX, 0 ; tamanho da string
+  CMP ECX, 0
+  JE print_string_exit ; strings nulas valem ""
+
+print_string_len: ; conta os caracteres ate o zero
+  MOVZX EAX, BYTE [ECX + EDX]
+  CMP EAX, 0
+  JE print_string_write
+  INC EDX
+  JMP print_string_len
+
+print_string_write:
+  MOV EAX, SYS_WRITE
+  MOV EBX, STDOUT
+  INT 0x80
+
+print_string_exit:
+  MOV EAX, SYS_WRITE
+  MOV EBX, STDOUT
+  MOV ECX, newline
+  MOV EDX, 1
+  INT 0x80
+
+  POP EBP
+  RET
+
+read:  ; subrotina read, le uma linha e devolve em EBX o numero no seu inicio, como o strtoll
+
+  PUSH EBP ; guarda o base pointer
+  MOV EBP, ESP ; estabelece um novo base pointer
+
+  XOR ESI, ESI ; valor lido
+  XOR EDI, EDI ; 1 se for negativo
+
+read_space: ; ignora os espacos antes do numero
+  CALL read_byte
+  CMP EAX, 10
+  JE read_end ; fim da linha
+  CMP EAX, ' '
+  JE read_space
+  MOV ECX, EAX
+  SUB ECX, 9
+  CMP ECX, 4
+  JBE read_space ; '\t', '\v', '\f' e '\r' tambem sao espacos
+  CMP EAX, '+'
+  JE read_sign
+  CMP EAX, '-'
+  JNE read_digit
+  MOV EDI, 1
+
+read_sign: ; o sinal e seguido pelos digitos
+  CALL read_byte
+
+read_digit: ; acumula os digitos ate o primeiro caractere que nao e digito
+  MOV ECX, EAX
+  SUB ECX, '0'
+  CMP ECX, 9
+  JA read_rest ; caracteres antes do '0' ficam enormes sem sinal
+  IMUL ESI, ESI, 10
+  ADD ESI, ECX
+  CALL read_byte
+  JMP read_digit
+
+read_rest: ; ignora o resto da linha
+  CMP EAX, 10
+  JE read_end
+  CALL read_byte
+  JMP read_rest
+
+read_end:
+  MOV EBX, ESI
+  CMP EDI, 0
+  JE read_exit
+  NEG EBX
+
+read_exit:
+  POP EBP
+  RET
+
+read_byte: ; le um caractere em EAX, o fim da entrada valendo uma quebra de linha
+  MOV EAX, SYS_READ
+  MOV EBX, STDIN
+  MOV ECX, res
+  MOV EDX, 1
+  INT 0x80
+
+  CMP EAX, 1
+  MOV EAX, 10
+  JNE read_byte_exit ; fim da entrada
+  MOVZX EAX, BYTE [res]
+
+read_byte_exit:
+  RET
+
+; subrotinas if/while
+binop_je:
+  JE binop_true
+  JMP binop_false
+
+binop_jne:
+  JNE binop_true
+  JMP binop_false
+
+binop_jg:
+  JG binop_true
+  JMP binop_false
+
+binop_jge:
+  JGE binop_true
+  JMP binop_false
+
+binop_jl:
+  JL binop_true
+  JMP binop_false
+
+binop_jle:
+  JLE binop_true
+  JMP binop_false
+
+binop_false:
+  MOV EBX, False
+  JMP binop_exit
+binop_true:
+  MOV EBX, True
+binop_exit:
+  RET
+
+divide: ; divide EAX por EBX, com o quociente em EAX e o resto em EDX
+  CMP EBX, -1
+  JE divide_negate ; o menor inteiro dividido por -1 nao cabe em EAX
+  CDQ
+  IDIV EBX
+  CMP EDX, EDX ; limpa a flag de overflow
+  RET
+divide_negate:
+  MOV EDX, 0
+  NEG EAX ; seta a flag de overflow quando EAX e o menor inteiro
+  RET
+
+argument: ; devolve em EBX o endereco do argumento do programa de indice EBX
+  CMP EBX, [arg_count]
+  JAE argument_error ; indices negativos sao enormes sem sinal
+  MOV EAX, [arg_values]
+  MOV EBX, [EAX + EBX * 4]
+  RET
+
+argument_error: ; indice fora dos argumentos do programa, aborta o programa
+  MOV ESI, argument_msg
+  MOV EDX, argument_msg_len
+  JMP runtime_error
+
+bounds_error: ; indice fora dos limites do array, aborta o programa
+  MOV ESI, bounds_msg
+  MOV EDX, bounds_msg_len
+
+runtime_error: ; escreve a mensagem em ESI, de tamanho EDX, e aborta o programa
+  MOV EAX, SYS_WRITE
+  MOV EBX, STDERR
+  MOV ECX, ESI
+  INT 0x80
+
+  MOV EBX, 1
+
+exit: ; termina o programa com o codigo em EBX
+  MOV EAX, SYS_EXIT
+  INT 0x80
+
+_start:
+
+  PUSH EBP ; guarda o base pointer
+  MOV EBP, ESP ; estabelece um novo base pointer
+
+  MOV EAX, [EBP+4] ; argc esta no topo da pilha inicial
+  MOV [arg_count], EAX
+  LEA EAX, [EBP+8] ; seguido pelos enderecos de argv
+  MOV [arg_values], EAX
+
+  ; codigo gerado pelo compilador
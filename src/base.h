@@ -0,0 +1,99 @@
+/* runtime dos programas compilados para C */
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+/* imprime um int seguido de uma quebra de linha */
+static inline void logik_println_int(long long n) {
+    printf("%lld\n", n);
+}
+
+/* strings nao inicializadas sao nulas e valem "" */
+static inline void logik_println_string(const char *s) {
+    puts(s ? s : "");
+}
+
+static inline _Bool logik_string_eq(const char *a, const char *b) {
+    return strcmp(a ? a : "", b ? b : "") == 0;
+}
+
+/* le uma linha da entrada padrao e devolve o numero nela, 0 no fim da entrada */
+static inline long long logik_readln(void) {
+    char line[64];
+    if (!fgets(line, sizeof line, stdin)) {
+        return 0;
+    }
+    return strtoll(line, NULL, 10);
+}
+
+/* indice fora dos limites do array, aborta o programa */
+static inline long long logik_index(long long index, long long len) {
+    if (index < 0 || index >= len) {
+        fputs("Error: array index out of bounds\n", stderr);
+        exit(1);
+    }
+    return index;
+}
+
+/* operadores aritmeticos, que dao a volta no overflow como nos outros alvos em vez de
+   deixar o comportamento indefinido */
+static inline long long logik_add(long long a, long long b) {
+    return (long long) ((unsigned long long) a + (unsigned long long) b);
+}
+
+static inline long long logik_sub(long long a, long long b) {
+    return (long long) ((unsigned long long) a - (unsigned long long) b);
+}
+
+static inline long long logik_mul(long long a, long long b) {
+    return (long long) ((unsigned long long) a * (unsigned long long) b);
+}
+
+static inline long long logik_neg(long long a) {
+    return logik_sub(0, a);
+}
+
+/* os shifts so usam os 6 bits de baixo da quantidade, como no x86 */
+static inline long long logik_shl(long long a, long long b) {
+    return (long long) ((unsigned long long) a << (b & 63));
+}
+
+static inline long long logik_shr(long long a, long long b) {
+    return a < 0 ? ~(~a >> (b & 63)) : a >> (b & 63);
+}
+
+/* divisao por zero, aborta o programa com a posicao do operador */
+static inline void logik_division_by_zero(const char *position) {
+    fprintf(stderr, "Error: Division by zero @ %s\n", position);
+    exit(1);
+}
+
+/* o menor numero dividido por -1 da a volta e fica igual */
+static inline long long logik_div(long long a, long long b, const char *position) {
+    if (b == 0) {
+        logik_division_by_zero(position);
+    }
+    return b == -1 ? logik_neg(a) : a / b;
+}
+
+static inline long long logik_mod(long long a, long long b, const char *position) {
+    if (b == 0) {
+        logik_division_by_zero(position);
+    }
+    return b == -1 ? 0 : a % b;
+}
+
+/* argumentos do programa, guardados pelo main */
+static long long logik_argc;
+static char **logik_argv;
+
+/* argumento do programa de indice i, aborta o programa fora deles */
+static inline const char *logik_arg(long long i) {
+    if (i < 0 || i >= logik_argc) {
+        fputs("Error: argument index out of bounds\n", stderr);
+        exit(1);
+    }
+    return logik_argv[i];
+}
+
+/* codigo gerado pelo compilador */
@@ -0,0 +1,128 @@
+; runtime dos programas compilados para LLVM IR
+@.fmt.int = private unnamed_addr constant [6 x i8] c"%lld\0A\00"
+@.fmt.string = private unnamed_addr constant [4 x i8] c"%s\0A\00"
+@.fmt.read = private unnamed_addr constant [5 x i8] c"%lld\00"
+@.empty = private unnamed_addr constant [1 x i8] c"\00"
+@.bounds = private unnamed_addr constant [34 x i8] c"Error: array index out of bounds\0A\00"
+@.argument = private unnamed_addr constant [37 x i8] c"Error: argument index out of bounds\0A\00"
+@.division = private unnamed_addr constant [30 x i8] c"Error: Division by zero @ %s\0A\00"
+
+; argumentos do programa, guardados pelo main
+@logik_argc = internal global i64 0
+@logik_argv = internal global i8** null
+
+declare i32 @printf(i8*, ...)
+declare i32 @scanf(i8*, ...)
+declare i32 @dprintf(i32, i8*, ...)
+declare i32 @strcmp(i8*, i8*)
+declare void @exit(i32) noreturn
+
+; imprime um int seguido de uma quebra de linha
+define internal void @logik_println_int(i64 %n) {
+  %fmt = getelementptr inbounds [6 x i8], [6 x i8]* @.fmt.int, i64 0, i64 0
+  call i32 (i8*, ...) @printf(i8* %fmt, i64 %n)
+  ret void
+}
+
+; strings nao inicializadas sao nulas e valem ""
+define internal i8* @logik_string(i8* %s) {
+  %null = icmp eq i8* %s, null
+  %empty = getelementptr inbounds [1 x i8], [1 x i8]* @.empty, i64 0, i64 0
+  %string = select i1 %null, i8* %empty, i8* %s
+  ret i8* %string
+}
+
+define internal void @logik_println_string(i8* %s) {
+  %string = call i8* @logik_string(i8* %s)
+  %fmt = getelementptr inbounds [4 x i8], [4 x i8]* @.fmt.string, i64 0, i64 0
+  call i32 (i8*, ...) @printf(i8* %fmt, i8* %string)
+  ret void
+}
+
+define internal i1 @logik_string_eq(i8* %a, i8* %b) {
+  %left = call i8* @logik_string(i8* %a)
+  %right = call i8* @logik_string(i8* %b)
+  %cmp = call i32 @strcmp(i8* %left, i8* %right)
+  %eq = icmp eq i32 %cmp, 0
+  ret i1 %eq
+}
+
+; le um numero da entrada padrao, 0 no fim da entrada
+define internal i64 @logik_readln() {
+  %n = alloca i64
+  store i64 0, i64* %n
+  %fmt = getelementptr inbounds [5 x i8], [5 x i8]* @.fmt.read, i64 0, i64 0
+  call i32 (i8*, ...) @scanf(i8* %fmt, i64* %n)
+  %value = load i64, i64* %n
+  ret i64 %value
+}
+
+; indice fora dos limites do array, aborta o programa
+define internal i64 @logik_index(i64 %index, i64 %len) {
+  %out = icmp uge i64 %index, %len
+  br i1 %out, label %error, label %ok
+ok:
+  ret i64 %index
+error:
+  %message = getelementptr inbounds [34 x i8], [34 x i8]* @.bounds, i64 0, i64 0
+  call i32 (i32, i8*, ...) @dprintf(i32 2, i8* %message)
+  call void @exit(i32 1)
+  unreachable
+}
+
+; divisao por zero na posicao dada, aborta o programa
+define internal void @logik_division_by_zero(i8* %position) noreturn {
+  %message = getelementptr inbounds [30 x i8], [30 x i8]* @.division, i64 0, i64 0
+  call i32 (i32, i8*, ...) @dprintf(i32 2, i8* %message, i8* %position)
+  call void @exit(i32 1)
+  unreachable
+}
+
+; o divisor zero nunca chega ao sdiv, e o menor numero dividido por -1 da a volta e fica igual,
+; em vez de parar o processador
+define internal i64 @logik_div(i64 %a, i64 %b, i8* %position) {
+  %zero = icmp eq i64 %b, 0
+  br i1 %zero, label %error, label %ok
+ok:
+  %minus = icmp eq i64 %b, -1
+  %divisor = select i1 %minus, i64 1, i64 %b
+  %quotient = sdiv i64 %a, %divisor
+  %negated = sub i64 0, %quotient
+  %result = select i1 %minus, i64 %negated, i64 %quotient
+  ret i64 %result
+error:
+  call void @logik_division_by_zero(i8* %position)
+  unreachable
+}
+
+define internal i64 @logik_mod(i64 %a, i64 %b, i8* %position) {
+  %zero = icmp eq i64 %b, 0
+  br i1 %zero, label %error, label %ok
+ok:
+  %minus = icmp eq i64 %b, -1
+  %divisor = select i1 %minus, i64 1, i64 %b
+  %remainder = srem i64 %a, %divisor
+  ret i64 %remainder
+error:
+  call void @logik_division_by_zero(i8* %position)
+  unreachable
+}
+
+; argumento do programa de indice i, aborta o programa fora deles
+define internal i8* @logik_arg(i64 %index) {
+  %argc = load i64, i64* @logik_argc
+  %out = icmp uge i64 %index, %argc
+  br i1 %out, label %error, label %ok
+ok:
+  %argv = load i8**, i8*** @logik_argv
+  %pointer = getelementptr inbounds i8*, i8** %argv, i64 %index
+  %arg = load i8*, i8** %pointer
+  ret i8* %arg
+error:
+  %message = getelementptr inbounds [37 x i8], [37 x i8]* @.argument, i64 0, i64 0
+  call i32 (i32, i8*, ...) @dprintf(i32 2, i8* %message)
+  call void @exit(i32 1)
+  unreachable
+}
+
+; codigo gerado pelo compilador
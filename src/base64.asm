@@ -0,0 +1,251 @@
+; constantes
+SYS_READ equ 0
+SYS_WRITE equ 1
+SYS_EXIT equ 60
+STDIN equ 0
+STDOUT equ 1
+STDERR equ 2
+True equ 1
+False equ 0
+
+segment .data
+  newline db 10
+  bounds_msg db "Error: array index out of bounds", 10
+  bounds_msg_len equ $ - bounds_msg
+  argument_msg db "Error: argument index out of bounds", 10
+  argument_msg_len equ $ - argument_msg
+
+segment .bss  ; variaveis
+  res RESB 1
+  arg_count RESQ 1 ; argc do programa
+  arg_values RESQ 1 ; endereco de argv
+
+section .text
+  global _start
+
+print:  ; subrotina print, imprime o numero com sinal em RDI seguido de uma quebra de linha
+
+  PUSH RBP ; guarda o base pointer
+  MOV RBP, RSP ; estabelece um novo base pointer
+
+  MOV RAX, RDI ; 1 argumento em RDI
+  MOV R8, RDI ; guarda o sinal
+  MOV RBX, 10
+  DEC RSP ; o texto e montado na pilha, de tras para frente
+  MOV BYTE [RSP], 10 ; quebra de linha
+
+print_dec: ; empilha todos os digitos
+  CQO
+  IDIV RBX ; o resto tem o sinal do numero
+  CMP RDX, 0
+  JGE print_digit
+  NEG RDX
+
+print_digit:
+  ADD RDX, '0'
+  DEC RSP
+  MOV [RSP], DL
+  CMP RAX, 0
+  JNE print_dec ; ate acabarem os digitos
+
+  CMP R8, 0
+  JGE print_write
+  DEC RSP
+  MOV BYTE [RSP], '-'
+
+print_write:
+  MOV RAX, SYS_WRITE
+  MOV RDI, STDOUT
+  MOV RSI, RSP
+  MOV RDX, RBP
+  SUB RDX, RSP ; tamanho do texto
+  SYSCALL ; altera RCX e R11
+
+  MOV RSP, RBP
+  POP RBP
+  RET
+
+print_string:  ; subrotina print_string, imprime a string terminada em zero em RDI e quebra a linha
+
+  PUSH RBP ; guarda o base pointer
+  MOV RBP, RSP ; estabelece um novo base pointer
+
+  MOV RSI, RDI ; RSI e usado pela syscall
+  MOV RDX, 0 ; tamanho da string
+  CMP RSI, 0
+  JE print_string_exit ; strings nulas valem ""
+
+print_string_len: ; conta os caracteres ate o zero
+  MOVZX RAX, BYTE [RSI + RDX]
+  CMP RAX, 0
+  JE print_string_write
+  INC RDX
+  JMP print_string_len
+
+print_string_write:
+  MOV RAX, SYS_WRITE
+  MOV RDI, STDOUT
+  SYSCALL ; altera RCX e R11
+
+print_string_exit:
+  MOV RAX, SYS_WRITE
+  MOV RDI, STDOUT
+  MOV RSI, newline
+  MOV RDX, 1
+  SYSCALL
+
+  POP RBP
+  RET
+
+read:  ; subrotina read, le uma linha e devolve em RBX o numero no seu inicio, como o strtoll
+
+  PUSH RBP ; guarda o base pointer
+  MOV RBP, RSP ; estabelece um novo base pointer
+
+  XOR R8, R8 ; valor lido
+  XOR R9, R9 ; 1 se for negativo
+
+read_space: ; ignora os espacos antes do numero
+  CALL read_byte
+  CMP RAX, 10
+  JE read_end ; fim da linha
+  CMP RAX, ' '
+  JE read_space
+  MOV RCX, RAX
+  SUB RCX, 9
+  CMP RCX, 4
+  JBE read_space ; '\t', '\v', '\f' e '\r' tambem sao espacos
+  CMP RAX, '+'
+  JE read_sign
+  CMP RAX, '-'
+  JNE read_digit
+  MOV R9, 1
+
+read_sign: ; o sinal e seguido pelos digitos
+  CALL read_byte
+
+read_digit: ; acumula os digitos ate o primeiro caractere que nao e digito
+  MOV RCX, RAX
+  SUB RCX, '0'
+  CMP RCX, 9
+  JA read_rest ; caracteres antes do '0' ficam enormes sem sinal
+  IMUL R8, R8, 10
+  ADD R8, RCX
+  CALL read_byte
+  JMP read_digit
+
+read_rest: ; ignora o resto da linha
+  CMP RAX, 10
+  JE read_end
+  CALL read_byte
+  JMP read_rest
+
+read_end:
+  MOV RBX, R8
+  CMP R9, 0
+  JE read_exit
+  NEG RBX
+
+read_exit:
+  POP RBP
+  RET
+
+read_byte: ; le um caractere em RAX, o fim da entrada valendo uma quebra de linha
+  MOV RAX, SYS_READ
+  MOV RDI, STDIN
+  MOV RSI, res
+  MOV RDX, 1
+  SYSCALL ; altera RCX e R11
+
+  CMP RAX, 1
+  MOV RAX, 10
+  JNE read_byte_exit ; fim da entrada
+  MOVZX RAX, BYTE [res]
+
+read_byte_exit:
+  RET
+
+; subrotinas if/while
+binop_je:
+  JE binop_true
+  JMP binop_false
+
+binop_jne:
+  JNE binop_true
+  JMP binop_false
+
+binop_jg:
+  JG binop_true
+  JMP binop_false
+
+binop_jge:
+  JGE binop_true
+  JMP binop_false
+
+binop_jl:
+  JL binop_true
+  JMP binop_false
+
+binop_jle:
+  JLE binop_true
+  JMP binop_false
+
+binop_false:
+  MOV RBX, False
+  JMP binop_exit
+binop_true:
+  MOV RBX, True
+binop_exit:
+  RET
+
+divide: ; divide RAX por RBX, com o quociente em RAX e o resto em RDX
+  CMP RBX, -1
+  JE divide_negate ; o menor inteiro dividido por -1 nao cabe em RAX
+  CQO
+  IDIV RBX
+  CMP RDX, RDX ; limpa a flag de overflow
+  RET
+divide_negate:
+  MOV RDX, 0
+  NEG RAX ; seta a flag de overflow quando RAX e o menor inteiro
+  RET
+
+argument: ; devolve em RBX o endereco do argumento do programa de indice RBX
+  CMP RBX, [arg_count]
+  JAE argument_error ; indices negativos sao enormes sem sinal
+  MOV RAX, [arg_values]
+  MOV RBX, [RAX + RBX * 8]
+  RET
+
+argument_error: ; indice fora dos argumentos do programa, aborta o programa
+  MOV RSI, argument_msg
+  MOV RDX, argument_msg_len
+  JMP runtime_error
+
+bounds_error: ; indice fora dos limites do array, aborta o programa
+  MOV RSI, bounds_msg
+  MOV RDX, bounds_msg_len
+
+runtime_error: ; escreve a mensagem em RSI, de tamanho RDX, e aborta o programa
+  MOV RAX, SYS_WRITE
+  MOV RDI, STDERR
+  SYSCALL
+
+  MOV RBX, 1
+
+exit: ; termina o programa com o codigo em RBX
+  MOV RAX, SYS_EXIT
+  MOV RDI, RBX
+  SYSCALL
+
+_start:
+
+  PUSH RBP ; guarda o base pointer
+  MOV RBP, RSP ; estabelece um novo base pointer
+
+  MOV RAX, [RBP+8] ; argc esta no topo da pilha inicial
+  MOV [arg_count], RAX
+  LEA RAX, [RBP+16] ; seguido pelos enderecos de argv
+  MOV [arg_values], RAX
+
+  ; codigo gerado pelo compilador
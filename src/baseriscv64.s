@@ -0,0 +1,194 @@
+# constantes
+.equ SYS_READ, 63
+.equ SYS_WRITE, 64
+.equ SYS_EXIT, 93
+.equ STDIN, 0
+.equ STDOUT, 1
+.equ STDERR, 2
+
+.section .data
+  newline: .ascii "\n"
+  bounds_msg: .ascii "Error: array index out of bounds\n"
+  bounds_msg_end:
+  argument_msg: .ascii "Error: argument index out of bounds\n"
+  argument_msg_end:
+
+.section .bss  # variaveis
+  res: .zero 1
+  .align 3
+  arg_count: .zero 8 # argc do programa
+  arg_values: .zero 8 # endereco de argv
+
+.section .text
+  .globl _start
+
+print:  # subrotina print, imprime o numero com sinal em a0 seguido de uma quebra de linha
+
+  addi sp, sp, -32 # espaco para o sinal, os digitos e a quebra de linha
+  addi t0, sp, 31 # o texto e guardado de tras para frente
+  li t1, 10
+  sb t1, 0(t0) # quebra de linha
+  mv t3, a0 # guarda o sinal
+
+print_dec: # guarda todos os digitos
+  rem t2, a0, t1 # o resto tem o sinal do numero
+  div a0, a0, t1
+  bgez t2, print_digit
+  neg t2, t2
+
+print_digit:
+  addi t2, t2, 48 # '0'
+  addi t0, t0, -1
+  sb t2, 0(t0)
+  bnez a0, print_dec
+
+  bgez t3, print_write
+  li t2, 45 # '-'
+  addi t0, t0, -1
+  sb t2, 0(t0)
+
+print_write:
+  li a7, SYS_WRITE
+  li a0, STDOUT
+  mv a1, t0
+  addi a2, sp, 32
+  sub a2, a2, t0 # tamanho do texto
+  ecall
+
+  addi sp, sp, 32
+  ret
+
+print_string:  # subrotina print_string, imprime a string terminada em zero em a0 e quebra a linha
+
+  mv a1, a0
+  li a2, 0 # tamanho da string
+  beqz a1, print_string_exit # strings nulas valem ""
+
+print_string_len: # conta os caracteres ate o zero
+  add t0, a1, a2
+  lbu t0, 0(t0)
+  beqz t0, print_string_write
+  addi a2, a2, 1
+  j print_string_len
+
+print_string_write:
+  li a7, SYS_WRITE
+  li a0, STDOUT
+  ecall
+
+print_string_exit:
+  li a7, SYS_WRITE
+  li a0, STDOUT
+  la a1, newline
+  li a2, 1
+  ecall
+  ret
+
+read:  # subrotina read, le uma linha e devolve em a0 o numero no seu inicio, como o strtoll
+
+  addi sp, sp, -16
+  sd ra, 0(sp) # alterado pelas chamadas a read_byte
+  li t0, 0 # valor lido
+  li t1, 0 # 1 se for negativo
+
+read_space: # ignora os espacos antes do numero
+  call read_byte
+  li t2, 10
+  beq a0, t2, read_end # fim da linha
+  li t2, 32 # ' '
+  beq a0, t2, read_space
+  addi t2, a0, -9
+  li t3, 4
+  bleu t2, t3, read_space # '\t', '\v', '\f' e '\r' tambem sao espacos
+  li t2, 43 # '+'
+  beq a0, t2, read_sign
+  li t2, 45 # '-'
+  bne a0, t2, read_digit
+  li t1, 1
+
+read_sign: # o sinal e seguido pelos digitos
+  call read_byte
+
+read_digit: # acumula os digitos ate o primeiro caractere que nao e digito
+  addi t2, a0, -48 # '0'
+  li t3, 9
+  bgtu t2, t3, read_rest # caracteres antes do '0' ficam enormes sem sinal
+  li t3, 10
+  mul t0, t0, t3
+  add t0, t0, t2
+  call read_byte
+  j read_digit
+
+read_rest: # ignora o resto da linha
+  li t2, 10
+  beq a0, t2, read_end
+  call read_byte
+  j read_rest
+
+read_end:
+  mv a0, t0
+  beqz t1, read_exit
+  neg a0, a0
+
+read_exit:
+  ld ra, 0(sp)
+  addi sp, sp, 16
+  ret
+
+read_byte: # le um caractere em a0, o fim da entrada valendo uma quebra de linha
+  li a7, SYS_READ
+  li a0, STDIN
+  la a1, res
+  li a2, 1
+  ecall
+
+  li t2, 1
+  bne a0, t2, read_byte_end # fim da entrada
+  lbu a0, res
+  ret
+
+read_byte_end:
+  li a0, 10
+  ret
+
+argument: # devolve em a0 o endereco do argumento do programa de indice a0
+  ld t0, arg_count
+  bgeu a0, t0, argument_error # indices negativos sao enormes sem sinal
+  ld t0, arg_values
+  slli a0, a0, 3
+  add a0, a0, t0
+  ld a0, 0(a0)
+  ret
+
+argument_error: # indice fora dos argumentos do programa, aborta o programa
+  la a1, argument_msg
+  la a2, argument_msg_end
+  j runtime_error
+
+bounds_error: # indice fora dos limites do array, aborta o programa
+  la a1, bounds_msg
+  la a2, bounds_msg_end
+
+runtime_error: # escreve a mensagem de a1 ate a2 e aborta o programa
+  li a7, SYS_WRITE
+  li a0, STDERR
+  sub a2, a2, a1 # tamanho da mensagem
+  ecall
+
+  li a0, 1
+  j exit
+
+exit: # termina o programa com o codigo em a0
+  li a7, SYS_EXIT
+  ecall
+
+_start:
+
+  ld t0, 0(sp) # argc esta no topo da pilha inicial
+  la t1, arg_count
+  sd t0, 0(t1)
+  addi t0, sp, 8 # seguido pelos enderecos de argv
+  la t1, arg_values
+  sd t0, 0(t1)
+
+  # codigo gerado pelo compilador
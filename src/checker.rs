@@ -0,0 +1,183 @@
+use crate::ast::{DeclareNode, FuncDefNode, Node};
+use crate::backend::Target;
+use crate::variable::{Scopes, VariableKind};
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use color_eyre::eyre::{bail, Result};
+
+/// Functions provided by the language, whose names programs cannot declare
+const BUILTINS: &[&str] = &["println", "readln", "len", "exit", "argc", "arg"];
+
+/// Static information gathered while checking the globals and then each function
+pub struct Checker {
+    /// Kind of every visible variable and whether it is a constant
+    vars: Scopes<(VariableKind, bool)>,
+    return_kind: VariableKind,
+    /// Target the program is compiled for, `None` when the interpreter or the JIT runs it
+    target: Option<Target>,
+}
+
+impl Checker {
+    pub fn new(target: Option<Target>) -> Self {
+        Self {
+            vars: Scopes::new(),
+            return_kind: VariableKind::None,
+            target,
+        }
+    }
+
+    pub fn declare(&mut self, name: &str, kind: VariableKind, constant: bool) -> Result<()> {
+        if BUILTINS.contains(&name) {
+            bail!("Cannot declare variable named {}", name);
+        }
+        self.expect_supported(&kind)?;
+        if !self.vars.declare(name.to_string(), (kind, constant)) {
+            bail!(
+                "Variable {} declared more than once in the same scope",
+                name
+            );
+        }
+        Ok(())
+    }
+
+    pub fn lookup(&self, name: &str) -> Result<VariableKind> {
+        match self.vars.get(name) {
+            Some((kind, _)) => Ok(kind.clone()),
+            None => bail!("Variable {} used before declaration", name),
+        }
+    }
+
+    /// Like `lookup` for a variable about to be modified
+    pub fn lookup_mut(&self, name: &str) -> Result<VariableKind> {
+        match self.vars.get(name) {
+            Some((_, true)) => bail!("Cannot assign to constant {}", name),
+            Some((kind, false)) => Ok(kind.clone()),
+            None => bail!("Variable {} used before declaration", name),
+        }
+    }
+
+    pub fn enter_function(&mut self, return_kind: VariableKind) {
+        self.return_kind = return_kind;
+        self.vars.push_scope();
+    }
+
+    pub fn leave_function(&mut self) {
+        self.vars.pop_scope();
+    }
+
+    pub fn push_scope(&mut self) {
+        self.vars.push_scope();
+    }
+
+    pub fn pop_scope(&mut self) {
+        self.vars.pop_scope();
+    }
+
+    pub fn return_kind(&self) -> &VariableKind {
+        &self.return_kind
+    }
+
+    /// Fails when the target cannot hold values of this kind, the wasm target having no strings
+    pub fn expect_supported(&self, kind: &VariableKind) -> Result<()> {
+        if self.target == Some(Target::Wasm) && has_strings(kind) {
+            bail!("Strings are not supported by the wasm target");
+        }
+        Ok(())
+    }
+
+    /// Fails when the target has no string literals, the assembly targets only getting strings
+    /// from the arguments of the program
+    pub fn expect_string_literals(&self) -> Result<()> {
+        match self.target {
+            Some(Target::Wasm) => bail!("Strings are not supported by the wasm target"),
+            Some(target @ (Target::X86 | Target::X86_64 | Target::Riscv64)) => bail!(
+                "String literals are not supported by the {} target",
+                target.name()
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails when the target cannot pass the arguments of the program as strings
+    pub fn expect_program_args(&self) -> Result<()> {
+        match self.target {
+            Some(Target::Wasm) => bail!("Strings are not supported by the wasm target"),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails when the target cannot compare two strings
+    pub fn expect_string_comparison(&self) -> Result<()> {
+        match self.target {
+            Some(target @ (Target::X86 | Target::X86_64 | Target::Riscv64)) => bail!(
+                "Comparing strings is not supported by the {} target",
+                target.name()
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Numbers and bools convert into each other, strings only into strings and enums into ints
+pub fn is_assignable(target: &VariableKind, value: &VariableKind) -> bool {
+    match (target, value) {
+        // Array literals may be shorter than the array
+        (VariableKind::Array(len), VariableKind::Array(initialized)) => initialized <= len,
+        // Enums lower to their int value but ints are not enums
+        (VariableKind::Number, VariableKind::Enum(_)) => true,
+        _ => target == value || (is_numeric(target) && is_numeric(value)),
+    }
+}
+
+/// Whether a value of this kind is or holds a string
+fn has_strings(kind: &VariableKind) -> bool {
+    match kind {
+        VariableKind::String => true,
+        VariableKind::Struct(t) => t.fields.iter().any(|(_, kind)| has_strings(kind)),
+        _ => false,
+    }
+}
+
+pub fn is_numeric(kind: &VariableKind) -> bool {
+    matches!(kind, VariableKind::Number | VariableKind::Bool)
+}
+
+pub fn expect_numeric(kind: &VariableKind, context: &str) -> Result<()> {
+    if !is_numeric(kind) {
+        bail!("Expected int or bool in {}, found {:?}", context, kind);
+    }
+    Ok(())
+}
+
+/// Checks the whole program, rejecting what `target` does not support when it is compiled
+pub fn check(
+    globals: &[DeclareNode],
+    funcs: &Rc<RefCell<HashMap<String, FuncDefNode>>>,
+    target: Option<Target>,
+) -> Result<()> {
+    let mut checker = Checker::new(target);
+    for global in globals.iter() {
+        if let (VariableKind::Array(_), Some(_)) = (&global.kind, &global.expression) {
+            bail!(
+                "Global array {} cannot be initialized, assign its elements in main",
+                global.name
+            );
+        }
+        global.check(&mut checker)?;
+    }
+
+    let funcs = funcs.borrow();
+    let mut names: Vec<&String> = funcs.keys().collect();
+    names.sort();
+
+    for name in names {
+        if BUILTINS.contains(&name.as_str()) {
+            bail!("Cannot declare function named {}", name);
+        }
+        funcs[name].check(&mut checker)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use color_eyre::eyre::Result;
+use std::io::{self, BufRead, Write};
+
+/// Input and output of the programs run by the interpreter
+pub trait Console {
+    /// Next line of the input without its line break, `None` once the input ended
+    fn read_line(&mut self) -> Result<Option<String>>;
+
+    fn write_line(&mut self, line: &str) -> Result<()>;
+}
+
+/// Standard input and output of the process
+pub struct Stdio;
+
+impl Console for Stdio {
+    fn read_line(&mut self) -> Result<Option<String>> {
+        Streams::new(io::stdin().lock(), io::sink()).read_line()
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<()> {
+        writeln!(io::stdout(), "{}", line)?;
+        Ok(())
+    }
+}
+
+/// Lines read from `input` and written to `output`, to embed the interpreter or script its input
+pub struct Streams<R, W> {
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> Streams<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Self { input, output }
+    }
+}
+
+impl<R: BufRead, W: Write> Console for Streams<R, W> {
+    fn read_line(&mut self) -> Result<Option<String>> {
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let len = line.trim_end_matches(&['\n', '\r'][..]).len();
+        line.truncate(len);
+        Ok(Some(line))
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<()> {
+        writeln!(self.output, "{}", line)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,308 @@
+use crate::ast::Node;
+use crate::checker::Checker;
+use crate::variable::{Environment, VariableData, VariableKind};
+
+use std::collections::BTreeSet;
+use std::io::{BufRead, Write};
+
+use color_eyre::eyre::{bail, Result};
+
+/// Hooks the interpreter calls as it executes the program
+pub trait Debugger {
+    /// Called before executing the statement starting on `line`, counted from 1
+    fn statement(&mut self, line: usize, vars: &mut Environment);
+
+    /// Called once the function `name` was entered, its arguments being declared
+    fn call(&mut self, name: &str);
+
+    /// Called when the innermost function returns `value` of the given kind
+    fn ret(&mut self, kind: &VariableKind, value: &VariableData);
+}
+
+const HELP: &str = "\
+step, s             Runs until the next statement, entering calls
+next, n             Runs until the next statement of this function or its callers
+finish, f           Runs until the current function returns
+continue, c         Runs until a breakpoint is reached
+break, b LINE       Stops before the statements starting on LINE
+delete, d LINE      Removes the breakpoint on LINE
+print, p EXPR       Evaluates EXPR in the current environment
+watch, w EXPR       Prints the value of EXPR every time the program stops
+vars, v             Prints the visible variables
+backtrace, bt       Prints the functions being executed, innermost first
+quit, q             Stops the program
+An empty line repeats the previous command";
+
+/// Parses an expression in the context of the program
+pub type Parse<'a> = Box<dyn Fn(&str) -> Result<Box<dyn Node>> + 'a>;
+
+/// Where the program runs to before stopping again
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Step,
+    /// Until a statement of a function at most this deep in the call stack
+    Next(usize),
+    /// Until a statement of a function less deep than this one
+    Finish(usize),
+    Continue,
+}
+
+/// Function being executed and the line of the statement it is executing
+struct Frame {
+    name: String,
+    line: usize,
+}
+
+/// Debugger reading commands from a prompt, stopping on the first statement of `main`
+pub struct Session<'a, R, W> {
+    /// Lines of the program, to show the statement the program stopped on
+    source: Vec<&'a str>,
+    parse: Parse<'a>,
+    commands: R,
+    output: W,
+    mode: Mode,
+    breakpoints: BTreeSet<usize>,
+    watches: Vec<(String, Box<dyn Node>)>,
+    frames: Vec<Frame>,
+    previous: String,
+}
+
+impl<'a, R: BufRead, W: Write> Session<'a, R, W> {
+    pub fn new(source: &'a str, parse: Parse<'a>, commands: R, output: W) -> Self {
+        Self {
+            source: source.lines().collect(),
+            parse,
+            commands,
+            output,
+            mode: Mode::Step,
+            breakpoints: BTreeSet::new(),
+            watches: vec![],
+            frames: vec![],
+            previous: String::new(),
+        }
+    }
+
+    fn say(&mut self, text: &str) {
+        writeln!(self.output, "{}", text).unwrap();
+    }
+
+    /// Checks and evaluates an expression in the current environment, returning its value as
+    /// shown to the user
+    fn evaluate(node: &dyn Node, vars: &mut Environment) -> Result<String> {
+        let mut checker = Checker::new(None);
+        for (name, variable) in vars.scopes().visible() {
+            checker.declare(name, variable.kind.clone(), false)?;
+        }
+        let kind = node.check(&mut checker)?;
+        if kind == VariableKind::None {
+            bail!("Expression has no value");
+        }
+        Ok(show(&kind, &node.eval(vars)?))
+    }
+
+    fn print(&mut self, expression: &str, vars: &mut Environment) {
+        let result = (self.parse)(expression).and_then(|node| Self::evaluate(node.as_ref(), vars));
+        match result {
+            Ok(value) => self.say(&format!("{} = {}", expression, value)),
+            Err(e) => self.say(&format!("Error: {}", e)),
+        }
+    }
+
+    fn watch(&mut self, expression: &str, vars: &mut Environment) {
+        match (self.parse)(expression) {
+            Ok(node) => {
+                self.watches.push((expression.to_string(), node));
+                self.print(expression, vars);
+            }
+            Err(e) => self.say(&format!("Error: {}", e)),
+        }
+    }
+
+    fn show_watches(&mut self, vars: &mut Environment) {
+        let watches = std::mem::take(&mut self.watches);
+        for (expression, node) in watches.iter() {
+            match Self::evaluate(node.as_ref(), vars) {
+                Ok(value) => self.say(&format!("{} = {}", expression, value)),
+                Err(e) => self.say(&format!("{}: {}", expression, e)),
+            }
+        }
+        self.watches = watches;
+    }
+
+    fn breakpoint(&mut self, argument: &str, add: bool) {
+        let line = match argument.parse::<usize>() {
+            Ok(line) if line > 0 => line,
+            _ => return self.say("Expected a line number"),
+        };
+        if add {
+            self.breakpoints.insert(line);
+            self.say(&format!("Breakpoint on line {}", line));
+        } else if self.breakpoints.remove(&line) {
+            self.say(&format!("Deleted the breakpoint on line {}", line));
+        } else {
+            self.say(&format!("No breakpoint on line {}", line));
+        }
+    }
+
+    fn variables(&mut self, vars: &Environment) {
+        let lines: Vec<String> = vars
+            .scopes()
+            .visible()
+            .into_iter()
+            .map(|(name, variable)| match &variable.data {
+                Some(data) => format!("{} = {}", name, show(&variable.kind, data)),
+                None => format!("{} = <uninitialized>", name),
+            })
+            .collect();
+        for line in lines {
+            self.say(&line);
+        }
+    }
+
+    fn backtrace(&mut self) {
+        let lines: Vec<String> = self
+            .frames
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(idx, frame)| format!("#{} {} at line {}", idx, frame.name, frame.line))
+            .collect();
+        for line in lines {
+            self.say(&line);
+        }
+    }
+
+    /// Reads commands until one resumes the program
+    fn prompt(&mut self, vars: &mut Environment) {
+        loop {
+            write!(self.output, "(logik) ").unwrap();
+            self.output.flush().unwrap();
+            let mut line = String::new();
+            if self.commands.read_line(&mut line).unwrap() == 0 {
+                // Without commands left the program runs to its end
+                self.breakpoints.clear();
+                self.mode = Mode::Continue;
+                return;
+            }
+            let mut line = line.trim().to_string();
+            if line.is_empty() {
+                line = self.previous.clone();
+            }
+            self.previous = line.clone();
+
+            let (command, argument) = match line.split_once(char::is_whitespace) {
+                Some((command, argument)) => (command, argument.trim()),
+                None => (line.as_str(), ""),
+            };
+            let depth = self.frames.len();
+            match command {
+                "step" | "s" => self.mode = Mode::Step,
+                "next" | "n" => self.mode = Mode::Next(depth),
+                "finish" | "f" => self.mode = Mode::Finish(depth),
+                "continue" | "c" => self.mode = Mode::Continue,
+                "break" | "b" => {
+                    self.breakpoint(argument, true);
+                    continue;
+                }
+                "delete" | "d" => {
+                    self.breakpoint(argument, false);
+                    continue;
+                }
+                "print" | "p" => {
+                    self.print(argument, vars);
+                    continue;
+                }
+                "watch" | "w" => {
+                    self.watch(argument, vars);
+                    continue;
+                }
+                "vars" | "v" => {
+                    self.variables(vars);
+                    continue;
+                }
+                "backtrace" | "bt" => {
+                    self.backtrace();
+                    continue;
+                }
+                "quit" | "q" => std::process::exit(0),
+                "help" | "h" => {
+                    self.say(HELP);
+                    continue;
+                }
+                "" => continue,
+                _ => {
+                    self.say(&format!("Unknown command {}, h lists them", command));
+                    continue;
+                }
+            }
+            return;
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> Debugger for Session<'_, R, W> {
+    fn statement(&mut self, line: usize, vars: &mut Environment) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.line = line;
+        }
+        let depth = self.frames.len();
+        let stop = match self.mode {
+            Mode::Step => true,
+            Mode::Next(next) => depth <= next,
+            Mode::Finish(finish) => depth < finish,
+            Mode::Continue => false,
+        };
+        if !stop && !self.breakpoints.contains(&line) {
+            return;
+        }
+
+        let name = self.frames.last().map_or("", |frame| frame.name.as_str());
+        let text = self.source.get(line - 1).map_or("", |text| text.trim());
+        let location = format!("{}:{} {}", name, line, text);
+        self.say(&location);
+        self.show_watches(vars);
+        self.prompt(vars);
+    }
+
+    fn call(&mut self, name: &str) {
+        self.frames.push(Frame {
+            name: name.to_string(),
+            line: 0,
+        });
+    }
+
+    fn ret(&mut self, kind: &VariableKind, value: &VariableData) {
+        if self.mode == Mode::Finish(self.frames.len()) && *kind != VariableKind::None {
+            let name = &self.frames.last().unwrap().name;
+            let returned = format!("{} returned {}", name, show(kind, value));
+            self.say(&returned);
+        }
+        self.frames.pop();
+    }
+}
+
+/// Value of the given kind as written in the source
+fn show(kind: &VariableKind, data: &VariableData) -> String {
+    match (kind, data) {
+        (VariableKind::Bool, data) => (data.to_number() != 0).to_string(),
+        (VariableKind::Enum(t), VariableData::Number(n)) => match t.variant(*n) {
+            Some(variant) => format!("{}::{}", t.name, variant),
+            None => n.to_string(),
+        },
+        (VariableKind::Struct(t), VariableData::Struct(values)) => {
+            let fields: Vec<String> = t
+                .fields
+                .iter()
+                .zip(values.iter())
+                .map(|((name, kind), value)| format!("{} = {}", name, show(kind, value)))
+                .collect();
+            format!("{{{}}}", fields.join(", "))
+        }
+        (_, VariableData::Array(values)) => {
+            let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+            format!("{{{}}}", values.join(", "))
+        }
+        (_, VariableData::String(s)) => format!("{:?}", s),
+        (_, data) => data.to_number().to_string(),
+    }
+}
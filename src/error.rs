@@ -1,13 +1,42 @@
+use crate::operator::Fault;
+use crate::token::{Number, Position};
+
 use thiserror::Error;
 
-#[derive(Error, Debug)]
-pub enum CompilerError {
-    // #[error("data store disconnected")]
-    // Disconnect(#[from] std:;io::Error),
-    #[error("the data for key `{0}` is not available")]
-    Redaction(String),
-    #[error("invalid header (expected {expected:?}, found {found:?})")]
-    InvalidHeader { expected: String, found: String },
-    #[error("unknown data store error")]
-    Unknown,
+/// Error stopping a program run by the interpreter, on top of those found before running it
+#[derive(Error, Debug, PartialEq)]
+pub enum RuntimeError {
+    #[error("Program exceeded the limit of {0} executed steps")]
+    Steps(usize),
+    #[error("Program exceeded the limit of {0} nested calls")]
+    CallDepth(usize),
+    #[error("Program exceeded the limit of {0} bytes of strings")]
+    StringBytes(usize),
+    #[error("readln cannot read the input in deterministic mode")]
+    Input,
+    #[error("readln reached the end of the input")]
+    EndOfInput,
+    #[error("readln expected an int, got {0:?}")]
+    InvalidInput(String),
+    #[error("Division by zero @ {0}")]
+    DivisionByZero(Position),
+    #[error("Integer overflow @ {0}")]
+    Overflow(Position),
+    #[error("Index {0} out of bounds for array {1} of length {2} @ {3}")]
+    IndexOutOfBounds(Number, String, usize, Position),
+    #[error("arg expected an index below {1}, got {0}")]
+    Argument(Number, usize),
+    /// Raised by `exit` to unwind every call, the program ending with this status
+    #[error("Program exited with status {0}")]
+    Exit(Number),
+}
+
+impl RuntimeError {
+    /// Error of an operator at `position` of the source
+    pub fn fault(fault: Fault, position: Position) -> Self {
+        match fault {
+            Fault::DivisionByZero => RuntimeError::DivisionByZero(position),
+            Fault::Overflow => RuntimeError::Overflow(position),
+        }
+    }
 }
@@ -0,0 +1,172 @@
+use crate::operator::{CondOp, Op};
+use crate::token::{Number, Position};
+use crate::variable::VariableKind;
+
+/// Where the value of a variable is stored, offsets being counted in words so that each target
+/// picks its own word size
+#[derive(Debug, Clone, PartialEq)]
+pub enum Location {
+    /// Offset below the frame pointer in the frame of the current function
+    Local(usize),
+    /// Label of a global and offset from its start
+    Global(String, usize),
+}
+
+impl Location {
+    /// Location of the field `words` words after the start of a struct
+    pub fn field(&self, words: usize) -> Location {
+        match self {
+            Location::Local(offset) => Location::Local(offset - words),
+            Location::Global(label, offset) => Location::Global(label.clone(), offset + words),
+        }
+    }
+}
+
+/// Instructions of an accumulator machine with a stack, which every target lowers to its own
+/// assembly. Values are computed into the accumulator, the operand register holds the left side
+/// of binary operators. Arithmetic wraps around on overflow unless given the position of the
+/// source to abort the program with
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instr {
+    /// Start of the instructions of a statement on this line of the source, counted from 1
+    Line(usize),
+    Label(String),
+    Jump(String),
+    JumpIfFalse(String),
+    JumpIfTrue(String),
+    /// Jumps when the accumulator holds the value
+    JumpIfEqual(Number, String),
+    /// Jumps to `labels[accumulator - min]`, or to `default` outside of the table
+    JumpTable {
+        name: String,
+        min: Number,
+        labels: Vec<String>,
+        default: String,
+    },
+
+    /// Sets the accumulator to a constant
+    Imm(Number),
+    Load(Location),
+    LoadAddress(Location),
+    Store(Location),
+    LoadOperand(Location),
+    Push,
+    /// Pushes the address of a location, leaving the accumulator alone
+    PushAddress(Location),
+    Pop,
+    PopOperand,
+
+    /// accumulator = operand op accumulator
+    BinOp(Op, Option<Position>),
+    /// Aborts the program, reporting the position, when the accumulator is zero before dividing
+    /// by it
+    CheckDivisor(Position),
+    /// accumulator = operand cond accumulator, as a bool
+    Compare(CondOp),
+    Negate(Option<Position>),
+    Not,
+    BitNot,
+    /// Pops the value of the true arm and then the condition, keeping the accumulator (false arm)
+    /// only if the condition is false
+    Select,
+    /// Adds a constant to a variable in place
+    AddTo(Location, Number, Option<Position>),
+
+    /// Aborts the program unless the accumulator is a valid index for an array of this length
+    BoundsCheck(usize),
+    /// Loads the element of an array whose index is in the accumulator
+    LoadElement(Location),
+    /// Keeps the accumulator as the index of the following `*Element` instructions
+    SetIndex,
+    LoadOperandElement(Location),
+    StoreElement(Location),
+
+    /// Copies a struct of the given size from the address in the accumulator
+    Copy(Location, usize),
+    Zero(Location, usize),
+    /// Moves as many words as the accumulator holds from the stack, first one on top, to an array
+    PopArray(Location),
+
+    Print,
+    /// Prints the string whose address is in the accumulator
+    PrintString,
+    /// Reads a line holding a number from the standard input into the accumulator
+    Read,
+    /// Ends the program with the accumulator as its exit status
+    Exit,
+    /// Loads the number of arguments of the program, its own name included
+    ArgCount,
+    /// Loads the address of the argument of the program whose index is in the accumulator,
+    /// aborting the program outside of them
+    ProgramArg,
+    /// Loads an argument of the current function, the first one being pushed last
+    LoadArg(usize),
+    /// Calls a function after its arguments have been pushed
+    Call(String, usize),
+    /// Copies the struct at the accumulator to the address passed as the hidden first argument,
+    /// returning that address
+    ReturnStruct(usize),
+    Return,
+}
+
+/// Variable of a function, which debug info describes to the debugger
+#[derive(Debug)]
+pub struct Local {
+    pub name: String,
+    pub kind: VariableKind,
+    pub location: Location,
+    pub argument: bool,
+}
+
+/// Code of a function and the size of its frame
+#[derive(Debug)]
+pub struct Function {
+    pub name: String,
+    /// Line of the source the function is defined on
+    pub line: usize,
+    /// Number of arguments, including the hidden one of functions returning structs
+    pub args: usize,
+    /// Words taken by the locals of every scope of the function
+    pub frame: usize,
+    pub code: Vec<Instr>,
+    /// Variables of every scope of the function, arguments first
+    pub locals: Vec<Local>,
+}
+
+/// Global variable, initialized with a constant or zeroed
+#[derive(Debug)]
+pub struct Global {
+    pub name: String,
+    pub label: String,
+    pub value: Option<Number>,
+    pub kind: VariableKind,
+}
+
+impl Global {
+    /// Starting value of every word of the global, None when they are all zero
+    pub fn data(&self) -> Option<Vec<Number>> {
+        if let Some(value) = self.value {
+            return Some(vec![value]);
+        }
+        let initial = self.kind.initial_words();
+        if initial.is_empty() {
+            return None;
+        }
+        let mut words = vec![0; self.kind.words()];
+        for (offset, value) in initial {
+            words[offset] = value;
+        }
+        Some(words)
+    }
+}
+
+/// Everything a target needs to emit a program
+#[derive(Debug, Default)]
+pub struct Program {
+    pub globals: Vec<Global>,
+    /// In the order they were assembled, the target emits the call to `main`
+    pub functions: Vec<Function>,
+    /// File the program was read from, when the target emits debug info mapping the code back to
+    /// it
+    pub source: Option<String>,
+}
@@ -1,24 +1,69 @@
+#![allow(clippy::upper_case_acronyms)]
+
 mod assembler;
 mod ast;
+mod backend;
+mod checker;
+mod console;
+mod debugger;
+mod error;
+mod ir;
 mod operator;
 mod parser;
 mod tests;
 mod token;
 mod variable;
 
-use parser::eval;
+use parser::{
+    assemble_program, assemble_source, debug_program, eval, interpret_program, jit_program,
+};
+use backend::Target;
+use console::Stdio;
+use token::Number;
+use variable::{Limits, DEFAULT_CALL_DEPTH};
 
 use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
 use std::path::PathBuf;
+use std::thread;
 
 use clap::Parser;
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{bail, eyre, Result};
 
 /// Logik
 #[derive(Parser, Debug)]
-#[clap(author, about, rename_all = "kebab-case")]
+#[clap(
+    author,
+    about,
+    rename_all = "kebab-case",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 /// Simple command line calculator
 struct Opt {
+    #[clap(flatten)]
+    source: Source,
+
+    /// Architecture of the generated assembly, defaults to the one of this machine
+    #[clap(short, long, arg_enum, default_value_t)]
+    target: Target,
+
+    /// Emits DWARF debug info mapping the assembly back to the source, for the x86 targets
+    #[clap(short = 'g', long)]
+    debug: bool,
+
+    /// Aborts the program on signed overflow instead of wrapping around, for the x86 and riscv64
+    /// targets
+    #[clap(long)]
+    overflow_checks: bool,
+
+    #[clap(subcommand)]
+    action: Option<Action>,
+}
+
+#[derive(clap::Args, Debug)]
+struct Source {
     /// Program passed in as a string instead of using a file
     #[clap(short, long)]
     command: Option<String>,
@@ -28,28 +73,208 @@ struct Opt {
     input_file: Option<PathBuf>,
 }
 
+impl Source {
+    /// Name of the source in the debug info
+    fn name(&self) -> String {
+        match &self.input_file {
+            Some(ifp) => ifp.display().to_string(),
+            None => "<command>".to_string(),
+        }
+    }
+
+    /// Arguments of the program, the first one being its name
+    fn args(&self, args: Vec<String>) -> Vec<String> {
+        std::iter::once(self.name()).chain(args).collect()
+    }
+
+    fn read(self) -> Result<String> {
+        match self.input_file {
+            Some(ifp) => Ok(fs::read_to_string(ifp)?),
+            None => Ok(self.command.unwrap()),
+        }
+    }
+}
+
+/// Bounds on what a program run by the interpreter may use, for programs that are not trusted
+#[derive(clap::Args, Debug)]
+struct Sandbox {
+    /// Most statements and loop iterations the program may execute
+    #[clap(long, requires = "interpret")]
+    max_steps: Option<usize>,
+
+    /// Most calls the program may have in progress at once [default: 1000]
+    #[clap(long, requires = "interpret")]
+    max_call_depth: Option<usize>,
+
+    /// Most bytes the strings held in variables may take
+    #[clap(long, requires = "interpret")]
+    max_string_bytes: Option<usize>,
+
+    /// Forbids reading the input, so that runs only depend on the program
+    #[clap(long, requires = "interpret")]
+    deterministic: bool,
+
+    /// Aborts the program on signed overflow instead of wrapping around
+    #[clap(long, requires = "interpret")]
+    overflow_checks: bool,
+}
+
+impl Sandbox {
+    fn limits(self) -> Limits {
+        Limits {
+            steps: self.max_steps,
+            call_depth: self.max_call_depth.unwrap_or(DEFAULT_CALL_DEPTH),
+            string_bytes: self.max_string_bytes,
+            deterministic: self.deterministic,
+            overflow_checks: self.overflow_checks,
+        }
+    }
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Action {
+    /// Compiles the program and runs it locally
+    Run {
+        #[clap(flatten)]
+        source: Source,
+
+        /// Target the program is compiled for, only wasm can be run
+        #[clap(short, long, arg_enum, default_value = "wasm")]
+        target: Target,
+
+        /// Compiles the program to machine code of this host with Cranelift and runs it in
+        /// process instead
+        #[clap(long, conflicts_with = "target")]
+        jit: bool,
+
+        /// Runs the program in the interpreter instead
+        #[clap(long, conflicts_with_all = &["target", "jit"])]
+        interpret: bool,
+
+        #[clap(flatten)]
+        sandbox: Sandbox,
+
+        /// Arguments of the program, after `--`
+        #[clap(last = true)]
+        args: Vec<String>,
+    },
+    /// Compiles the program into a static executable, without `nasm` or `ld`
+    Build {
+        #[clap(flatten)]
+        source: Source,
+
+        /// Architecture of the executable, only x86 and x86_64 can be built
+        #[clap(short, long, arg_enum, default_value_t)]
+        target: Target,
+
+        /// Path of the executable
+        #[clap(short, long, default_value = "out")]
+        output: PathBuf,
+
+        /// Includes DWARF debug info mapping the code back to the source
+        #[clap(short = 'g', long)]
+        debug: bool,
+
+        /// Aborts the program on signed overflow instead of wrapping around
+        #[clap(long)]
+        overflow_checks: bool,
+    },
+    /// Runs the program in the interpreter, stopping on its first statement to step through it
+    Debug {
+        #[clap(flatten)]
+        source: Source,
+
+        /// Arguments of the program, after `--`
+        #[clap(last = true)]
+        args: Vec<String>,
+    },
+}
+
+/// Stack the interpreter may take for each call of the program, enough for deeply nested
+/// statements in debug builds
+const STACK_PER_CALL: usize = 64 * 1024;
+
 fn main() {
-    match run() {
+    let opt: Opt = Opt::parse();
+
+    // The interpreter recurses on the stack of the host for each call of the program
+    let call_depth = match &opt.action {
+        Some(Action::Run { sandbox, .. }) => sandbox.max_call_depth,
+        _ => None,
+    };
+    let stack = call_depth.unwrap_or(DEFAULT_CALL_DEPTH) * STACK_PER_CALL;
+    let result = match thread::Builder::new().stack_size(stack).spawn(|| run(opt)) {
+        Ok(compiler) => compiler.join().unwrap(),
+        Err(e) => Err(eyre!("Could not reserve {} bytes of stack: {}", stack, e)),
+    };
+    match result {
+        Ok(status) => {
+            io::stdout().flush().ok();
+            std::process::exit(status as i32);
+        }
         Err(e) => {
             eprintln!("Error: {}", e);
             std::process::exit(1);
         }
-        _ => {}
     }
 }
 
-fn run() -> Result<()> {
+/// Runs the action of the command line, returning the exit status of the program it ran, 0 when
+/// it only compiled it
+fn run(opt: Opt) -> Result<Number> {
     color_eyre::install()?;
 
-    let opt: Opt = Opt::parse();
-
-    let input = {
-        if let Some(ifp) = opt.input_file {
-            fs::read_to_string(ifp)?
-        } else {
-            opt.command.unwrap()
+    match opt.action {
+        Some(Action::Run {
+            source,
+            target,
+            jit,
+            interpret,
+            sandbox,
+            args,
+        }) => {
+            let args = source.args(args);
+            if interpret {
+                let limits = sandbox.limits();
+                return interpret_program(source.read()?, args, limits, Box::new(Stdio));
+            }
+            if jit {
+                return jit_program(source.read()?, args, Box::new(Stdio));
+            }
+            if target != Target::Wasm {
+                bail!("Only the wasm target can be run");
+            }
+            let module = assemble_program(source.read()?, target)?;
+            backend::wasm::run(&module, &args)
         }
-    };
-
-    eval(input)
+        Some(Action::Build {
+            source,
+            target,
+            output,
+            debug,
+            overflow_checks,
+        }) => {
+            let name = debug.then(|| source.name());
+            let asm = assemble_source(source.read()?, target, name, overflow_checks)?;
+            let executable = target.executable(&asm)?;
+            fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .mode(0o755)
+                .open(output)?
+                .write_all(&executable)?;
+            Ok(0)
+        }
+        Some(Action::Debug { source, args }) => {
+            let args = source.args(args);
+            let source = source.read()?;
+            debug_program(&source, args, io::stdin().lock(), io::stdout())
+        }
+        None => {
+            let name = opt.debug.then(|| opt.source.name());
+            eval(opt.source.read()?, opt.target, name, opt.overflow_checks)?;
+            Ok(0)
+        }
+    }
 }
@@ -1,30 +1,29 @@
+use crate::token::Number;
+
 use std::convert::TryFrom;
 use std::fmt;
-use std::ops;
 
 pub fn is_operator_char(c: char) -> bool {
-    match c {
-        '*' => true,
-        '/' => true,
-        '+' => true,
-        '-' => true,
-        '!' => true,
-        '>' => true,
-        '<' => true,
-        '=' => true,
-        '&' => true,
-        '|' => true,
-        _ => false,
-    }
+    matches!(
+        c,
+        '*' | '/' | '%' | '+' | '-' | '!' | '~' | '>' | '<' | '=' | '&' | '|' | '^'
+    )
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Op {
     Mul,
     Div,
+    Mod,
     Add,
     Sub,
     Not,
+    BitNot,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -88,40 +87,61 @@ impl Op {
         match c {
             '*' => Some(Mul),
             '/' => Some(Div),
+            '%' => Some(Mod),
             '+' => Some(Add),
             '-' => Some(Sub),
             '!' => Some(Not),
+            '~' => Some(BitNot),
+            '&' => Some(BitAnd),
+            '|' => Some(BitOr),
+            '^' => Some(BitXor),
             _ => None,
         }
     }
 
-    pub fn execute<T>(self, lhs: T, rhs: T) -> T
-    where
-        T: ops::Mul<Output = T>,
-        T: ops::Div<Output = T>,
-        T: ops::Add<Output = T>,
-        T: ops::Sub<Output = T>,
-    {
-        match self {
-            Op::Mul => lhs * rhs,
-            Op::Div => lhs / rhs,
-            Op::Add => lhs + rhs,
-            Op::Sub => lhs - rhs,
-            Op::Not => unimplemented!(),
+    pub fn from_chars(c1: char, c2: char) -> Option<Self> {
+        use Op::*;
+        match (c1, c2) {
+            ('<', '<') => Some(Shl),
+            ('>', '>') => Some(Shr),
+            _ => None,
         }
     }
 
-    pub fn assemble<'a>(self) -> &'a str {
-        match self {
-            Op::Mul => "imul ebx\nmov ebx, eax",
-            Op::Div => "mov edx, 0\nidiv ebx\nmov ebx, eax",
-            Op::Add => "add eax, ebx\nmov ebx, eax",
-            Op::Sub => "sub eax, ebx\nmov ebx, eax",
-            Op::Not => unimplemented!(),
+    /// Applies the operator to numbers, wrapping around on overflow unless `checked`
+    pub fn execute(self, lhs: Number, rhs: Number, checked: bool) -> Result<Number, Fault> {
+        if matches!(self, Op::Div | Op::Mod) && rhs == 0 {
+            return Err(Fault::DivisionByZero);
         }
+        // Shifts only take the low bits of their amount, like x86
+        let (value, overflow) = match self {
+            Op::Mul => lhs.overflowing_mul(rhs),
+            Op::Div => lhs.overflowing_div(rhs),
+            Op::Mod => lhs.overflowing_rem(rhs),
+            Op::Add => lhs.overflowing_add(rhs),
+            Op::Sub => lhs.overflowing_sub(rhs),
+            Op::BitAnd => (lhs & rhs, false),
+            Op::BitOr => (lhs | rhs, false),
+            Op::BitXor => (lhs ^ rhs, false),
+            Op::Shl => (lhs.wrapping_shl(rhs as u32), false),
+            Op::Shr => (lhs.wrapping_shr(rhs as u32), false),
+            Op::Not | Op::BitNot => unimplemented!(),
+        };
+        if overflow && checked {
+            return Err(Fault::Overflow);
+        }
+        Ok(value)
     }
 }
 
+/// Why an operator has no result, which aborts the program
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Fault {
+    DivisionByZero,
+    /// The result does not fit in a `Number`, only reported when overflow is checked
+    Overflow,
+}
+
 impl TryFrom<char> for Op {
     type Error = &'static str;
 
@@ -130,10 +150,15 @@ impl TryFrom<char> for Op {
         match c {
             '*' => Ok(Mul),
             '/' => Ok(Div),
+            '%' => Ok(Mod),
             '+' => Ok(Add),
             '-' => Ok(Sub),
             '!' => Ok(Not),
-            _ => Err("Op: Char not in \"*/+-!\""),
+            '~' => Ok(BitNot),
+            '&' => Ok(BitAnd),
+            '|' => Ok(BitOr),
+            '^' => Ok(BitXor),
+            _ => Err("Op: Char not in \"*/%+-!~&|^\""),
         }
     }
 }
@@ -144,11 +169,18 @@ impl fmt::Display for Op {
             f,
             "{}",
             match self {
-                Op::Mul => '*',
-                Op::Div => '/',
-                Op::Add => '+',
-                Op::Sub => '-',
-                Op::Not => '!',
+                Op::Mul => "*",
+                Op::Div => "/",
+                Op::Mod => "%",
+                Op::Add => "+",
+                Op::Sub => "-",
+                Op::Not => "!",
+                Op::BitNot => "~",
+                Op::BitAnd => "&",
+                Op::BitOr => "|",
+                Op::BitXor => "^",
+                Op::Shl => "<<",
+                Op::Shr => ">>",
             },
         )
     }
@@ -1,11 +1,22 @@
 use crate::assembler::Assembler;
 use crate::ast::*;
-use crate::operator::Op;
+use crate::checker;
+use crate::console::{Console, Stdio};
+use crate::debugger::Session;
+use crate::error::RuntimeError;
+use crate::operator::{CondOp, Op};
+use crate::backend::c::C;
+use crate::backend::jit::Jit;
+use crate::backend::llvm::Llvm;
+use crate::backend::wasm::Wasm;
+use crate::backend::Target;
 use crate::token::*;
 use crate::variable::*;
 
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, Write};
 use std::rc::Rc;
 
 use color_eyre::eyre::{bail, Result};
@@ -14,6 +25,15 @@ pub struct Parser {
     tokens: Vec<Token>,
     idx: usize,
     funcs: Rc<RefCell<HashMap<String, FuncDefNode>>>,
+    globals: Vec<DeclareNode>,
+    structs: HashMap<String, Rc<StructType>>,
+    enums: HashMap<String, Rc<EnumType>>,
+    /// Value of every visible constant, `None` for the variables shadowing them
+    constants: Scopes<Option<(VariableKind, Number)>>,
+    loop_depth: usize,
+    switch_depth: usize,
+    /// Target the program is compiled for, `None` when it is run without being compiled
+    target: Option<Target>,
 }
 
 impl Parser {
@@ -22,21 +42,50 @@ impl Parser {
             tokens,
             idx: 0usize.wrapping_sub(1),
             funcs: Rc::new(RefCell::new(HashMap::new())),
+            globals: vec![],
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            constants: Scopes::new(),
+            loop_depth: 0,
+            switch_depth: 0,
+            target: None,
         }
     }
 
-    pub fn parse(tokens: Vec<Token>) -> Result<()> {
+    /// Compiles the program for `target`, with debug info mapping the code back to the file
+    /// `source` when given, aborting on signed overflow with `overflow_checks`
+    pub fn parse(
+        tokens: Vec<Token>,
+        target: Target,
+        source: Option<String>,
+        overflow_checks: bool,
+    ) -> Result<String> {
+        if source.is_some() && !matches!(target, Target::X86 | Target::X86_64) {
+            bail!("Debug info is only emitted for the x86 and x86_64 targets");
+        }
+        if overflow_checks && !matches!(target, Target::X86 | Target::X86_64 | Target::Riscv64) {
+            bail!("Overflow is only checked by the x86, x86_64 and riscv64 targets");
+        }
         let mut parser = Parser::new(tokens);
-
-        #[cfg(not(feature = "not v3.0.0"))] // So code for functions does not compile for now
-        {
-            parser.parse_func_def()?;
-            let mut a = Assembler::new(String::from("base.asm"), &parser.funcs);
-            a.assemble();
+        parser.target = Some(target);
+
+        parser.parse_program()?;
+        match target {
+            Target::Wasm => return Wasm::new(&parser.funcs).module(&parser.globals),
+            Target::C => return C::new(&parser.funcs).program(&parser.structs, &parser.globals),
+            Target::Llvm => {
+                return Llvm::new(&parser.funcs).module(&parser.structs, &parser.globals)
+            }
+            _ => {}
         }
-        #[cfg(feature = "not v3.0.0")]
-        parser.parse_func_def()?.eval(&mut HashMap::new());
-        Ok(())
+        let mut a = Assembler::new(target, &parser.funcs);
+        if let Some(source) = source {
+            a.debug(source);
+        }
+        if overflow_checks {
+            a.check_overflow();
+        }
+        Ok(a.assemble(&parser.globals))
     }
 
     fn cur_token(&mut self) -> Result<Token> {
@@ -59,6 +108,10 @@ impl Parser {
         self.cur_token()
     }
 
+    fn peek_token(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.idx.wrapping_add(offset))
+    }
+
     fn parse_factor(&mut self) -> Result<Box<dyn Node>> {
         let tk = self.next_token()?;
         match &tk.kind {
@@ -82,6 +135,23 @@ impl Parser {
                 Ok(Box::new(BoolLiteralNode::new(false)))
             }
 
+            TokenKind::Identifier(name) if name == "len" => {
+                let ptk = self.next_token()?;
+                let itk = self.next_token()?;
+                let ctk = self.next_token()?;
+                match (ptk.kind, itk.kind, ctk.kind) {
+                    (
+                        TokenKind::ParenthesisOpen,
+                        TokenKind::Identifier(array),
+                        TokenKind::ParenthesisClose,
+                    ) => {
+                        self.select_next();
+                        Ok(Box::new(LenNode::new(array)))
+                    }
+                    _ => bail!("Expected len(array) @ {}:{}", tk.line, tk.col),
+                }
+            }
+
             TokenKind::Identifier(name) => {
                 let ntk = self.next_token()?;
                 if ntk.kind == TokenKind::ParenthesisOpen {
@@ -90,24 +160,85 @@ impl Parser {
                         self.get_func_args()?,
                         &self.funcs,
                     )))
+                } else if let TokenKind::IncDec(op) = ntk.kind {
+                    // Postfix x++ / x--
+                    self.select_next();
+                    Ok(Box::new(IncDecNode::new(
+                        name.clone(),
+                        op,
+                        ntk.position(),
+                        false,
+                    )))
+                } else if ntk.kind == TokenKind::SquareBracketOpen {
+                    let index = self.parse_index()?;
+                    self.select_next();
+                    Ok(Box::new(IndexNode::new(name.clone(), index, ntk.position())))
+                } else if ntk.kind == TokenKind::Dot {
+                    let path = self.parse_field_path()?;
+                    Ok(Box::new(FieldNode::new(name.clone(), path)))
+                } else if ntk.kind == TokenKind::Colon && self.enums.contains_key(name) {
+                    Ok(Box::new(self.parse_enum_variant(name)?))
+                } else if let Some(Some((kind, value))) = self.constants.get(name) {
+                    // Constants are inlined
+                    Ok(match kind {
+                        VariableKind::Bool => Box::new(BoolLiteralNode::new(*value != 0)),
+                        VariableKind::Enum(t) => Box::new(EnumVariantNode::new(t.clone(), *value)),
+                        _ => Box::new(NumberLiteralNode::new(*value)),
+                    })
                 } else {
                     Ok(Box::new(VariableNode::new(name.clone())))
                 }
             }
 
+            TokenKind::IncDec(op) => {
+                let name = match self.peek_token(1).map(|tk| &tk.kind) {
+                    Some(TokenKind::Identifier(name)) => name.clone(),
+                    _ => "".to_string(),
+                };
+                match self.peek_token(2).map(|tk| &tk.kind) {
+                    _ if name.is_empty() => {}
+                    Some(TokenKind::ParenthesisOpen) | Some(TokenKind::Colon) => {}
+                    Some(TokenKind::SquareBracketOpen) | Some(TokenKind::Dot) => bail!(
+                        "{} only applies to variables inside expressions @ {}:{}",
+                        tk.kind,
+                        tk.line,
+                        tk.col
+                    ),
+                    _ => {
+                        // Prefix ++x / --x
+                        self.select_next();
+                        self.select_next();
+                        return Ok(Box::new(IncDecNode::new(name, *op, tk.position(), true)));
+                    }
+                }
+
+                // Before anything but a variable `--` negates twice, like `- -`
+                let kind = || match op {
+                    Op::Add => UnaryNodeKind::Pos,
+                    _ => UnaryNodeKind::Neg,
+                };
+                let child = Box::new(UnaryNode::new(kind(), tk.position(), self.parse_factor()?));
+                Ok(Box::new(UnaryNode::new(kind(), tk.position(), child)))
+            }
+
             TokenKind::Op(op) => {
                 let kind = match op {
                     Op::Add => UnaryNodeKind::Pos,
                     Op::Sub => UnaryNodeKind::Neg,
                     Op::Not => UnaryNodeKind::Not,
+                    Op::BitNot => UnaryNodeKind::BitNot,
                     _ => bail!("Expected '+' or '-' or '!' found '{}'", op),
                 };
 
-                Ok(Box::new(UnaryNode::new(kind, self.parse_factor()?)))
+                Ok(Box::new(UnaryNode::new(
+                    kind,
+                    tk.position(),
+                    self.parse_factor()?,
+                )))
             }
 
             TokenKind::ParenthesisOpen => {
-                let r = self.parse_cond()?;
+                let r = self.parse_ternary()?;
 
                 if self.cur_token()?.kind != TokenKind::ParenthesisClose {
                     bail!("Unclosed parenthesis");
@@ -125,14 +256,31 @@ impl Parser {
             | TokenKind::ParenthesisClose
             | TokenKind::BracketOpen
             | TokenKind::BracketClose
+            | TokenKind::SquareBracketOpen
+            | TokenKind::SquareBracketClose
             | TokenKind::TypeNumber
             | TokenKind::TypeString
             | TokenKind::TypeBool
+            | TokenKind::TypeVoid
             | TokenKind::Return
+            | TokenKind::Const
+            | TokenKind::Struct
+            | TokenKind::Enum
+            | TokenKind::Dot
             | TokenKind::SemiColon
+            | TokenKind::Colon
+            | TokenKind::Question
             | TokenKind::Comma
+            | TokenKind::Switch
+            | TokenKind::Case
+            | TokenKind::Default
             | TokenKind::Assign
+            | TokenKind::OpAssign(_)
             | TokenKind::While
+            | TokenKind::For
+            | TokenKind::Do
+            | TokenKind::Break
+            | TokenKind::Continue
             | TokenKind::If
             | TokenKind::Else => {
                 bail!("Expected number, variable, operator or '(', found {}", tk)
@@ -148,8 +296,9 @@ impl Parser {
             match tk.kind {
                 TokenKind::Op(op) => {
                     match op {
-                        Op::Div | Op::Mul => {
-                            c = Box::new(BinaryNode::new(op, c, self.parse_factor()?));
+                        Op::Div | Op::Mul | Op::Mod => {
+                            let rhs = self.parse_factor()?;
+                            c = Box::new(BinaryNode::new(op, tk.position(), c, rhs));
                         }
 
                         _ => break,
@@ -170,7 +319,118 @@ impl Parser {
                 TokenKind::Op(op) => {
                     match op {
                         Op::Add | Op::Sub => {
-                            c = Box::new(BinaryNode::new(op, c, self.parse_term()?));
+                            let rhs = self.parse_term()?;
+                            c = Box::new(BinaryNode::new(op, tk.position(), c, rhs));
+                        }
+                        _ => break,
+                    };
+                }
+
+                _ => break,
+            }
+        }
+
+        Ok(c)
+    }
+
+    fn parse_shift(&mut self) -> Result<Box<dyn Node>> {
+        let mut c = self.parse_expression()?;
+
+        loop {
+            let tk = self.cur_token()?;
+            match tk.kind {
+                TokenKind::Op(op) => {
+                    match op {
+                        Op::Shl | Op::Shr => {
+                            let rhs = self.parse_expression()?;
+                            c = Box::new(BinaryNode::new(op, tk.position(), c, rhs));
+                        }
+                        _ => break,
+                    };
+                }
+
+                _ => break,
+            }
+        }
+
+        Ok(c)
+    }
+
+    /// Comparisons bind tighter than the bitwise operators, like in C
+    fn parse_comparison(&mut self) -> Result<Box<dyn Node>> {
+        let mut c = self.parse_shift()?;
+
+        loop {
+            let tk = self.cur_token()?;
+            match tk.kind {
+                TokenKind::CondOp(cop) if !matches!(cop, CondOp::And | CondOp::Or) => {
+                    c = Box::new(CondNode::new(cop, c, self.parse_shift()?));
+                }
+
+                _ => break,
+            }
+        }
+
+        Ok(c)
+    }
+
+    fn parse_bitand(&mut self) -> Result<Box<dyn Node>> {
+        let mut c = self.parse_comparison()?;
+
+        loop {
+            let tk = self.cur_token()?;
+            match tk.kind {
+                TokenKind::Op(op) => {
+                    match op {
+                        Op::BitAnd => {
+                            let rhs = self.parse_comparison()?;
+                            c = Box::new(BinaryNode::new(op, tk.position(), c, rhs));
+                        }
+                        _ => break,
+                    };
+                }
+
+                _ => break,
+            }
+        }
+
+        Ok(c)
+    }
+
+    fn parse_bitxor(&mut self) -> Result<Box<dyn Node>> {
+        let mut c = self.parse_bitand()?;
+
+        loop {
+            let tk = self.cur_token()?;
+            match tk.kind {
+                TokenKind::Op(op) => {
+                    match op {
+                        Op::BitXor => {
+                            let rhs = self.parse_bitand()?;
+                            c = Box::new(BinaryNode::new(op, tk.position(), c, rhs));
+                        }
+                        _ => break,
+                    };
+                }
+
+                _ => break,
+            }
+        }
+
+        Ok(c)
+    }
+
+    fn parse_bitor(&mut self) -> Result<Box<dyn Node>> {
+        let mut c = self.parse_bitxor()?;
+
+        loop {
+            let tk = self.cur_token()?;
+            match tk.kind {
+                TokenKind::Op(op) => {
+                    match op {
+                        Op::BitOr => {
+                            let rhs = self.parse_bitxor()?;
+                            c = Box::new(BinaryNode::new(op, tk.position(), c, rhs));
                         }
                         _ => break,
                     };
@@ -191,7 +451,7 @@ impl Parser {
                 break;
             }
             self.select_prev();
-            let arg = self.parse_cond()?;
+            let arg = self.parse_ternary()?;
             v.push(arg);
 
             let tk = self.cur_token()?;
@@ -216,35 +476,20 @@ impl Parser {
         let tk = self.cur_token()?;
         let ret = match &tk.kind {
             TokenKind::TypeNumber | TokenKind::TypeBool | TokenKind::TypeString => {
-                let ntk = self.next_token()?;
-                if let TokenKind::Identifier(name) = &ntk.kind {
-                    let kind = match tk.kind {
-                        TokenKind::TypeNumber => VariableKind::Number,
-                        TokenKind::TypeBool => VariableKind::Bool,
-                        TokenKind::TypeString => VariableKind::String,
-                        _ => unreachable!(),
-                    };
-
-                    let ntk = self.next_token()?;
-                    if ntk.kind == TokenKind::Assign {
-                        let node = self.parse_cond()?;
-                        // self.select_next(); // WARNING check if necessary
-
-                        let v: Box<dyn Node> = match tk.kind {
-                            TokenKind::TypeNumber => Box::new(NumberNode::new(node)),
-                            TokenKind::TypeBool => Box::new(StringNode::new(node)),
-                            TokenKind::TypeString => Box::new(BoolNode::new(node)),
-                            _ => unreachable!(),
-                        };
-                        Box::new(DeclareNode::new(name.clone(), Some(v), kind))
-                    } else {
-                        self.select_prev();
-
-                        Box::new(DeclareNode::new(name.clone(), None, kind))
-                    }
-                } else {
-                    bail!("Expected identifier after {}, got {}", tk.kind, ntk.kind)
-                }
+                Box::new(self.parse_declaration(false, false)?)
+            }
+            TokenKind::Const => {
+                self.select_next();
+                Box::new(self.parse_declaration(true, false)?)
+            }
+            TokenKind::Identifier(_)
+                if self.parse_type(&tk).is_some()
+                    && matches!(
+                        self.peek_token(1).map(|tk| &tk.kind),
+                        Some(TokenKind::Identifier(_))
+                    ) =>
+            {
+                Box::new(self.parse_declaration(false, false)?)
             }
             TokenKind::Identifier(name) => {
                 let ntk = self.next_token()?;
@@ -257,20 +502,96 @@ impl Parser {
                             &self.funcs,
                         ))
                     }
-                    TokenKind::Assign => {
-                        Box::new(AssignNode::new(name.clone(), self.parse_cond()?))
+                    TokenKind::SquareBracketOpen => {
+                        let index = self.parse_index()?;
+                        let atk = self.next_token()?;
+                        let (op, expression): (Option<Op>, Box<dyn Node>) = match atk.kind {
+                            TokenKind::Assign => (None, self.parse_ternary()?),
+                            TokenKind::OpAssign(op) => (Some(op), self.parse_ternary()?),
+                            TokenKind::IncDec(op) => {
+                                self.select_next();
+                                (Some(op), Box::new(NumberLiteralNode::new(1)))
+                            }
+                            _ => bail!("Expected =, op=, ++ or -- after {}[...]", name),
+                        };
+                        Box::new(IndexAssignNode::new(
+                            name.clone(),
+                            index,
+                            op,
+                            atk.position(),
+                            expression,
+                        ))
                     }
-                    _ => bail!("Expected = or (...) after {}", name),
+                    TokenKind::Dot => {
+                        let path = self.parse_field_path()?;
+                        let atk = self.cur_token()?;
+                        let (op, expression): (Option<Op>, Box<dyn Node>) = match atk.kind {
+                            TokenKind::Assign => (None, self.parse_ternary()?),
+                            TokenKind::OpAssign(op) => (Some(op), self.parse_ternary()?),
+                            TokenKind::IncDec(op) => {
+                                self.select_next();
+                                (Some(op), Box::new(NumberLiteralNode::new(1)))
+                            }
+                            _ => bail!(
+                                "Expected =, op=, ++ or -- after {}.{}",
+                                name,
+                                path.join(".")
+                            ),
+                        };
+                        Box::new(FieldAssignNode::new(
+                            name.clone(),
+                            path,
+                            op,
+                            atk.position(),
+                            expression,
+                        ))
+                    }
+                    TokenKind::Assign => Box::new(AssignNode::new(
+                        name.clone(),
+                        None,
+                        ntk.position(),
+                        self.parse_ternary()?,
+                    )),
+                    TokenKind::OpAssign(op) => Box::new(AssignNode::new(
+                        name.clone(),
+                        Some(op),
+                        ntk.position(),
+                        self.parse_ternary()?,
+                    )),
+                    TokenKind::IncDec(op) => {
+                        self.select_next();
+                        Box::new(AssignNode::new(
+                            name.clone(),
+                            Some(op),
+                            ntk.position(),
+                            Box::new(NumberLiteralNode::new(1)),
+                        ))
+                    }
+                    _ => bail!("Expected =, op=, ++, -- or (...) after {}", name),
                 };
                 r
             }
+            TokenKind::IncDec(op) => {
+                let ntk = self.next_token()?;
+                if let TokenKind::Identifier(name) = ntk.kind {
+                    self.select_next();
+                    Box::new(AssignNode::new(
+                        name,
+                        Some(*op),
+                        tk.position(),
+                        Box::new(NumberLiteralNode::new(1)),
+                    ))
+                } else {
+                    bail!("Expected identifier after {}{}, got {}", op, op, ntk)
+                }
+            }
             TokenKind::If => {
                 let ntk = self.next_token()?;
                 if ntk.kind != TokenKind::ParenthesisOpen {
                     bail!("Expected '(' after if");
                 }
 
-                let cond = self.parse_cond()?;
+                let cond = self.parse_ternary()?;
 
                 let ntk = self.cur_token()?;
                 if ntk.kind != TokenKind::ParenthesisClose {
@@ -297,7 +618,7 @@ impl Parser {
                     bail!("Expected '(' after while");
                 }
 
-                let cond = self.parse_cond()?;
+                let cond = self.parse_ternary()?;
 
                 let ntk = self.cur_token()?;
                 if ntk.kind != TokenKind::ParenthesisClose {
@@ -305,10 +626,100 @@ impl Parser {
                 }
                 self.select_next();
 
-                let child = self.parse_command()?;
+                let child = self.parse_loop_body()?;
 
                 Box::new(WhileNode::new(cond, child))
             }
+            TokenKind::For => {
+                let ntk = self.next_token()?;
+                if ntk.kind != TokenKind::ParenthesisOpen {
+                    bail!("Expected '(' after for");
+                }
+                self.constants.push_scope();
+
+                let init = if self.next_token()?.kind == TokenKind::SemiColon {
+                    None
+                } else {
+                    Some(self.parse_command()?)
+                };
+                let ntk = self.cur_token()?;
+                if ntk.kind != TokenKind::SemiColon {
+                    bail!("Expected ';' after for initializer, got {}", ntk);
+                }
+
+                let cond = if self.next_token()?.kind == TokenKind::SemiColon {
+                    None
+                } else {
+                    self.select_prev();
+                    Some(self.parse_ternary()?)
+                };
+                let ntk = self.cur_token()?;
+                if ntk.kind != TokenKind::SemiColon {
+                    bail!("Expected ';' after for condition, got {}", ntk);
+                }
+
+                let step = if self.next_token()?.kind == TokenKind::ParenthesisClose {
+                    None
+                } else {
+                    Some(self.parse_command()?)
+                };
+                let ntk = self.cur_token()?;
+                if ntk.kind != TokenKind::ParenthesisClose {
+                    bail!("Expected ')' closing for, got {}", ntk);
+                }
+                self.select_next();
+
+                let child = self.parse_loop_body()?;
+                self.constants.pop_scope();
+
+                Box::new(ForNode::new(init, cond, step, child))
+            }
+            TokenKind::Do => {
+                self.select_next();
+                let child = self.parse_loop_body()?;
+
+                let ntk = self.next_token()?;
+                if ntk.kind != TokenKind::While {
+                    bail!("Expected while after do body, got {}", ntk);
+                }
+                let ntk = self.next_token()?;
+                if ntk.kind != TokenKind::ParenthesisOpen {
+                    bail!("Expected '(' after while");
+                }
+
+                let cond = self.parse_ternary()?;
+
+                let ntk = self.cur_token()?;
+                if ntk.kind != TokenKind::ParenthesisClose {
+                    bail!("Expected ')' closing while");
+                }
+                let ntk = self.next_token()?;
+                if ntk.kind != TokenKind::SemiColon {
+                    bail!("Expected ';' after do-while, got {}", ntk);
+                }
+
+                Box::new(DoWhileNode::new(cond, child))
+            }
+            TokenKind::Switch => Box::new(self.parse_switch()?),
+            TokenKind::Break | TokenKind::Continue => {
+                let allowed = match tk.kind {
+                    TokenKind::Break => self.loop_depth + self.switch_depth > 0,
+                    _ => self.loop_depth > 0,
+                };
+                if !allowed {
+                    bail!("'{}' outside of a loop @ {}:{}", tk.kind, tk.line, tk.col);
+                }
+                let ntk = self.next_token()?;
+                if ntk.kind != TokenKind::SemiColon {
+                    bail!("Expected ';' after {}, got {}", tk.kind, ntk);
+                }
+
+                let flow = match tk.kind {
+                    TokenKind::Break => Flow::Break,
+                    _ => Flow::Continue,
+                };
+                Box::new(LoopJumpNode::new(flow))
+            }
 
             TokenKind::Return => {
                 self.select_next();
@@ -316,7 +727,7 @@ impl Parser {
                     Box::new(ReturnNode::new(None))
                 } else {
                     self.select_prev();
-                    Box::new(ReturnNode::new(Some(self.parse_cond()?)))
+                    Box::new(ReturnNode::new(Some(self.parse_ternary()?)))
                 }
             }
 
@@ -333,15 +744,119 @@ impl Parser {
         Ok(ret)
     }
 
+    fn parse_switch(&mut self) -> Result<SwitchNode> {
+        let ntk = self.next_token()?;
+        if ntk.kind != TokenKind::ParenthesisOpen {
+            bail!("Expected '(' after switch");
+        }
+
+        let expression = self.parse_ternary()?;
+
+        let ntk = self.cur_token()?;
+        if ntk.kind != TokenKind::ParenthesisClose {
+            bail!("Expected ')' closing switch, got {}", ntk);
+        }
+        let ntk = self.next_token()?;
+        if ntk.kind != TokenKind::BracketOpen {
+            bail!("Expected '{{' after switch, got {}", ntk);
+        }
+
+        let mut cases: Vec<SwitchCase> = vec![];
+        self.switch_depth += 1;
+        self.constants.push_scope();
+        loop {
+            let tk = self.next_token()?;
+            match tk.kind {
+                TokenKind::BracketClose => break,
+                TokenKind::Case => {
+                    let label = self.parse_bitor()?;
+                    let value = match label.const_eval() {
+                        Some(value) => value,
+                        None => bail!("Case label must be a constant @ {}:{}", tk.line, tk.col),
+                    };
+                    let ntk = self.cur_token()?;
+                    if ntk.kind != TokenKind::Colon {
+                        bail!("Expected ':' after case label, got {}", ntk);
+                    }
+                    cases.push((Some(value), vec![]));
+                }
+                TokenKind::Default => {
+                    let ntk = self.next_token()?;
+                    if ntk.kind != TokenKind::Colon {
+                        bail!("Expected ':' after default, got {}", ntk);
+                    }
+                    cases.push((None, vec![]));
+                }
+                _ => match cases.last_mut() {
+                    Some((_, children)) => children.push(self.parse_command()?),
+                    None => bail!("Expected case or default in switch, got {}", tk),
+                },
+            }
+        }
+        self.constants.pop_scope();
+        self.switch_depth -= 1;
+
+        Ok(SwitchNode::new(expression, cases))
+    }
+
+    /// Parses the `.a.b` fields after a variable starting on the first `.` and stopping after the
+    /// last field
+    fn parse_field_path(&mut self) -> Result<Vec<String>> {
+        let mut path = vec![];
+        while self.cur_token()?.kind == TokenKind::Dot {
+            let tk = self.next_token()?;
+            match tk.kind {
+                TokenKind::Identifier(field) => path.push(field),
+                _ => bail!("Expected field name after '.', got {}", tk),
+            }
+            self.select_next();
+        }
+        Ok(path)
+    }
+
+    /// Parses the index after a `[`, stopping on the `]`
+    fn parse_index(&mut self) -> Result<Box<dyn Node>> {
+        let index = self.parse_ternary()?;
+        let tk = self.cur_token()?;
+        if tk.kind != TokenKind::SquareBracketClose {
+            bail!("Expected ']' after index, got {}", tk);
+        }
+        Ok(index)
+    }
+
+    fn parse_loop_body(&mut self) -> Result<Box<dyn Node>> {
+        self.loop_depth += 1;
+        let child = self.parse_command();
+        self.loop_depth -= 1;
+        child
+    }
+
+    fn parse_ternary(&mut self) -> Result<Box<dyn Node>> {
+        let cond = self.parse_cond()?;
+        if self.cur_token()?.kind != TokenKind::Question {
+            return Ok(cond);
+        }
+
+        let if_child = self.parse_ternary()?;
+
+        let tk = self.cur_token()?;
+        if tk.kind != TokenKind::Colon {
+            bail!("Expected ':' in conditional expression, got {}", tk);
+        }
+        let else_child = self.parse_ternary()?;
+
+        Ok(Box::new(TernaryNode::new(cond, if_child, else_child)))
+    }
+
     fn parse_cond(&mut self) -> Result<Box<dyn Node>> {
-        let expr = self.parse_expression()?;
+        let expr = self.parse_bitor()?;
         let tk = self.cur_token()?;
-        if let TokenKind::CondOp(cop) = tk.kind {
-            let mut cn = Box::new(CondNode::new(cop, expr, self.parse_expression()?));
+        if let TokenKind::CondOp(cop @ (CondOp::And | CondOp::Or)) = tk.kind {
+            let mut cn = Box::new(CondNode::new(cop, expr, self.parse_bitor()?));
             loop {
                 let ltk = self.cur_token()?;
-                if let TokenKind::CondOp(cop) = ltk.kind {
-                    cn = Box::new(CondNode::new(cop, cn, self.parse_expression()?));
+                if let TokenKind::CondOp(cop @ (CondOp::And | CondOp::Or)) = ltk.kind {
+                    cn = Box::new(CondNode::new(cop, cn, self.parse_bitor()?));
                 } else {
                     break;
                 }
@@ -354,25 +869,405 @@ impl Parser {
 
     fn parse_block(&mut self) -> Result<BlockNode> {
         let mut commands = vec![];
+        let mut lines = vec![];
 
+        self.constants.push_scope();
         loop {
             let tk = self.next_token()?;
             match tk.kind {
                 TokenKind::EOF | TokenKind::BracketClose => break,
-                _ => commands.push(self.parse_command()?),
+                _ => {
+                    lines.push(tk.line);
+                    commands.push(self.parse_command()?);
+                }
             }
         }
-        Ok(BlockNode::new(commands))
+        self.constants.pop_scope();
+        Ok(BlockNode::new(commands, lines))
     }
 
-    fn parse_func_def(&mut self) -> Result<FuncCallNode> {
-        #[cfg(not(feature = "not v3.0.0"))] // So code for functions does not compile for now
+    /// Parses `type name [= expression];` or `int name[size] [= {...}];` starting on the type and
+    /// stopping on the `;`
+    ///
+    /// Constants and globals need initializers that can be computed at compile time
+    fn parse_declaration(&mut self, constant: bool, global: bool) -> Result<DeclareNode> {
+        let tk = self.cur_token()?;
+        let mut kind = match self.parse_type(&tk) {
+            Some(kind) if kind != VariableKind::None => kind,
+            _ => bail!("Expected type, got {}", tk),
+        };
+
+        let ntk = self.next_token()?;
+        let name = match &ntk.kind {
+            TokenKind::Identifier(name) => name.clone(),
+            _ => bail!("Expected identifier after {}, got {}", tk.kind, ntk.kind),
+        };
+
+        // Size of an array, None when it comes from the initializer as in `int a[] = {1, 2};`
+        let mut array_size = None;
+        let is_array = self.next_token()?.kind == TokenKind::SquareBracketOpen;
+        if is_array {
+            if kind != VariableKind::Number || constant {
+                bail!(
+                    "Only int variables can be arrays, found {} {}",
+                    tk.kind,
+                    name
+                );
+            }
+            if self.next_token()?.kind != TokenKind::SquareBracketClose {
+                self.select_prev();
+                let size = self.parse_ternary()?;
+                match size.const_eval() {
+                    Some(size) if size > 0 => array_size = Some(size as usize),
+                    _ => bail!("Size of array {} must be a positive constant", name),
+                }
+                let stk = self.cur_token()?;
+                if stk.kind != TokenKind::SquareBracketClose {
+                    bail!("Expected ']' after size of array {}, got {}", name, stk);
+                }
+            }
+            self.select_next();
+        }
+
+        let expression: Option<Box<dyn Node>> = if self.cur_token()?.kind != TokenKind::Assign {
+            None
+        } else if is_array {
+            let literal = self.parse_array_literal()?;
+            let len = literal.len();
+            match array_size {
+                Some(size) if len > size => bail!(
+                    "Array {} of size {} initialized with {} elements",
+                    name,
+                    size,
+                    len
+                ),
+                None => array_size = Some(len),
+                _ => {}
+            }
+            Some(Box::new(literal))
+        } else if let (VariableKind::Struct(t), Some(TokenKind::BracketOpen)) =
+            (&kind, self.peek_token(1).map(|tk| &tk.kind))
         {
+            let t = t.clone();
+            Some(Box::new(self.parse_struct_literal(&t)?))
+        } else {
+            let node = self.parse_ternary()?;
+            Some(match kind {
+                VariableKind::Number => Box::new(NumberNode::new(node)),
+                VariableKind::Bool => Box::new(BoolNode::new(node)),
+                VariableKind::String => Box::new(StringNode::new(node)),
+                VariableKind::Struct(_) | VariableKind::Enum(_) => node,
+                VariableKind::Array(_) | VariableKind::None => unreachable!(),
+            })
+        };
+        if is_array {
+            match array_size {
+                Some(size) => kind = VariableKind::Array(size),
+                None => bail!("Size of array {} is unknown", name),
+            }
+        }
+
+        let etk = self.cur_token()?;
+        if etk.kind != TokenKind::SemiColon {
+            bail!("Expected ';' after declaration of {}, got {}", name, etk);
+        }
+
+        let value = expression.as_ref().and_then(|e| e.const_eval());
+        // Initialized global arrays are left for the checker to report
+        if (constant || global) && !is_array && expression.is_some() && value.is_none() {
+            bail!(
+                "Initializer of {} is not a constant expression @ {}:{}",
+                name,
+                ntk.line,
+                ntk.col
+            );
+        }
+
+        // Duplicates are left for the checker to report
+        if constant {
+            let value = match value {
+                Some(value) => value,
+                None => bail!("Constant {} must be initialized", name),
+            };
+            self.constants
+                .declare(name.clone(), Some((kind.clone(), value)));
+            let expression = expression.unwrap();
+            Ok(DeclareNode::new_const(name, expression, kind))
+        } else {
+            self.constants.declare(name.clone(), None);
+            Ok(DeclareNode::new(name, expression, kind))
+        }
+    }
+
+    /// Parses `{a, b, ...}` with one value per field of `t` in order, the same way as
+    /// `parse_array_literal`
+    fn parse_struct_literal(&mut self, t: &Rc<StructType>) -> Result<StructLiteralNode> {
+        let tk = self.next_token()?;
+        if tk.kind != TokenKind::BracketOpen {
+            bail!("Expected '{{' starting {} literal, got {}", t.name, tk);
+        }
+
+        let mut fields: Vec<Box<dyn Node>> = vec![];
+        for (idx, (field, kind)) in t.fields.iter().enumerate() {
+            match kind {
+                VariableKind::Struct(inner)
+                    if self.peek_token(1).map(|tk| &tk.kind) == Some(&TokenKind::BracketOpen) =>
+                {
+                    fields.push(Box::new(self.parse_struct_literal(inner)?));
+                }
+                _ => fields.push(self.parse_ternary()?),
+            }
+
+            let ltk = self.cur_token()?;
+            let expected = if idx + 1 == t.fields.len() {
+                TokenKind::BracketClose
+            } else {
+                TokenKind::Comma
+            };
+            if ltk.kind != expected {
+                bail!(
+                    "Expected '{}' after field {} of {} literal, got {}",
+                    expected,
+                    field,
+                    t.name,
+                    ltk
+                );
+            }
+        }
+        if t.fields.is_empty() && self.next_token()?.kind != TokenKind::BracketClose {
+            bail!("Expected '}}' closing {} literal", t.name);
+        }
+        self.select_next();
+        Ok(StructLiteralNode::new(t.clone(), fields))
+    }
+
+    /// Parses `struct Name { type field; ... }` starting on `struct` and stopping on the `}`
+    /// or an optional `;` after it
+    fn parse_struct_def(&mut self) -> Result<()> {
+        let ntk = self.next_token()?;
+        let name = match &ntk.kind {
+            TokenKind::Identifier(name) => name.clone(),
+            _ => bail!("Expected struct name, got {}", ntk),
+        };
+        if self.structs.contains_key(&name) || self.enums.contains_key(&name) {
+            bail!("Type {} defined more than once", name);
+        }
+
+        let btk = self.next_token()?;
+        if btk.kind != TokenKind::BracketOpen {
+            bail!("Expected '{{' after struct {}, got {}", name, btk);
+        }
+
+        let mut fields: Vec<(String, VariableKind)> = vec![];
+        loop {
+            let tk = self.next_token()?;
+            if tk.kind == TokenKind::BracketClose {
+                break;
+            }
+            let kind = match self.parse_type(&tk) {
+                Some(kind) if kind != VariableKind::None => kind,
+                _ => bail!("Expected field type in struct {}, got {}", name, tk),
+            };
+            let ftk = self.next_token()?;
+            let field = match &ftk.kind {
+                TokenKind::Identifier(field) => field.clone(),
+                _ => bail!("Expected field name in struct {}, got {}", name, ftk),
+            };
+            if fields.iter().any(|(f, _)| *f == field) {
+                bail!("Field {} defined more than once in struct {}", field, name);
+            }
+            let stk = self.next_token()?;
+            if stk.kind != TokenKind::SemiColon {
+                bail!("Expected ';' after field {}, got {}", field, stk);
+            }
+            fields.push((field, kind));
+        }
+
+        if self.peek_token(1).map(|tk| &tk.kind) == Some(&TokenKind::SemiColon) {
+            self.select_next();
+        }
+
+        let t = StructType { name, fields };
+        self.structs.insert(t.name.clone(), Rc::new(t));
+        Ok(())
+    }
+
+    /// Parses `enum Name { A, B = value, ... }` starting on `enum` and stopping on the `}` or an
+    /// optional `;` after it. Variants without a value take the previous one plus one
+    fn parse_enum_def(&mut self) -> Result<()> {
+        let ntk = self.next_token()?;
+        let name = match &ntk.kind {
+            TokenKind::Identifier(name) => name.clone(),
+            _ => bail!("Expected enum name, got {}", ntk),
+        };
+        if self.structs.contains_key(&name) || self.enums.contains_key(&name) {
+            bail!("Type {} defined more than once", name);
+        }
+
+        let btk = self.next_token()?;
+        if btk.kind != TokenKind::BracketOpen {
+            bail!("Expected '{{' after enum {}, got {}", name, btk);
+        }
+
+        let mut variants: Vec<(String, Number)> = vec![];
+        let mut next_value = 0;
+        let mut tk = self.next_token()?;
+        while tk.kind != TokenKind::BracketClose {
+            let variant = match &tk.kind {
+                TokenKind::Identifier(variant) => variant.clone(),
+                _ => bail!("Expected variant name in enum {}, got {}", name, tk),
+            };
+            if variants.iter().any(|(v, _)| *v == variant) {
+                bail!(
+                    "Variant {} defined more than once in enum {}",
+                    variant,
+                    name
+                );
+            }
+
+            let value = if self.next_token()?.kind == TokenKind::Assign {
+                match self.parse_ternary()?.const_eval() {
+                    Some(value) => value,
+                    None => bail!(
+                        "Value of {}::{} is not a constant expression @ {}:{}",
+                        name,
+                        variant,
+                        tk.line,
+                        tk.col
+                    ),
+                }
+            } else {
+                next_value
+            };
+            if let Some((other, _)) = variants.iter().find(|(_, v)| *v == value) {
+                bail!(
+                    "Variants {} and {} of enum {} have the same value {}",
+                    other,
+                    variant,
+                    name,
+                    value
+                );
+            }
+            variants.push((variant, value));
+            next_value = value.wrapping_add(1);
+
+            let ltk = self.cur_token()?;
+            tk = match ltk.kind {
+                TokenKind::Comma => self.next_token()?,
+                TokenKind::BracketClose => ltk,
+                _ => bail!("Expected ',' or '}}' in enum {}, got {}", name, ltk),
+            };
+        }
+        if variants.is_empty() {
+            bail!("Enum {} has no variants", name);
+        }
+
+        if self.peek_token(1).map(|tk| &tk.kind) == Some(&TokenKind::SemiColon) {
+            self.select_next();
+        }
+
+        let t = EnumType { name, variants };
+        self.enums.insert(t.name.clone(), Rc::new(t));
+        Ok(())
+    }
+
+    /// Parses `::Variant` of enum `name` starting on the first `:` and stopping after the variant
+    fn parse_enum_variant(&mut self, name: &str) -> Result<EnumVariantNode> {
+        let t = self.enums[name].clone();
+        let ctk = self.next_token()?;
+        if ctk.kind != TokenKind::Colon {
+            bail!("Expected '::' after enum {}, got {}", name, ctk);
+        }
+        let vtk = self.next_token()?;
+        let value = match &vtk.kind {
+            TokenKind::Identifier(variant) => match t.value(variant) {
+                Some(value) => value,
+                None => bail!("Enum {} has no variant {}", name, variant),
+            },
+            _ => bail!("Expected variant of enum {}, got {}", name, vtk),
+        };
+        self.select_next();
+        Ok(EnumVariantNode::new(t, value))
+    }
+
+    /// Parses `{a, b, ...}` starting on the token before the `{` and stopping after the `}`
+    fn parse_array_literal(&mut self) -> Result<ArrayLiteralNode> {
+        let tk = self.next_token()?;
+        if tk.kind != TokenKind::BracketOpen {
+            bail!("Expected '{{' starting array literal, got {}", tk);
+        }
+
+        let mut elements = vec![];
+        if self.next_token()?.kind != TokenKind::BracketClose {
+            self.select_prev();
+            loop {
+                elements.push(self.parse_ternary()?);
+                let ltk = self.cur_token()?;
+                match ltk.kind {
+                    TokenKind::Comma => {}
+                    TokenKind::BracketClose => break,
+                    _ => bail!("Expected ',' or '}}' in array literal, got {}", ltk),
+                }
+            }
+        }
+        self.select_next();
+        Ok(ArrayLiteralNode::new(elements))
+    }
+
+    fn parse_type(&self, tk: &Token) -> Option<VariableKind> {
+        match &tk.kind {
+            TokenKind::TypeNumber
+            | TokenKind::TypeBool
+            | TokenKind::TypeString
+            | TokenKind::TypeVoid => Some(tk.kind.clone().into()),
+            TokenKind::Identifier(name) => match self.structs.get(name) {
+                Some(t) => Some(VariableKind::Struct(t.clone())),
+                None => self.enums.get(name).map(|t| VariableKind::Enum(t.clone())),
+            },
+            _ => None,
+        }
+    }
+
+    /// Whether any function is defined at the top level, otherwise the whole input is `main`
+    fn is_func_def_program(&self) -> bool {
+        let mut depth = 0;
+        self.tokens.windows(3).any(|window| {
+            match window[0].kind {
+                TokenKind::BracketOpen => depth += 1,
+                TokenKind::BracketClose => depth -= 1,
+                _ => {}
+            }
+            let kinds: Vec<&TokenKind> = window.iter().map(|tk| &tk.kind).collect();
+            depth == 0
+                && matches!(
+                    kinds.as_slice(),
+                    [
+                        TokenKind::TypeNumber
+                            | TokenKind::TypeBool
+                            | TokenKind::TypeString
+                            | TokenKind::TypeVoid
+                            | TokenKind::Identifier(_),
+                        TokenKind::Identifier(_),
+                        TokenKind::ParenthesisOpen,
+                    ] | [TokenKind::Struct | TokenKind::Enum, ..]
+                )
+        })
+    }
+
+    fn parse_program(&mut self) -> Result<FuncCallNode> {
+        let fc = self.parse_func_def()?;
+        checker::check(&self.globals, &self.funcs, self.target)?;
+        Ok(fc)
+    }
+
+    fn parse_func_def(&mut self) -> Result<FuncCallNode> {
+        if !self.is_func_def_program() {
             let func = FuncDefNode::new(
                 TokenKind::TypeNumber.into(),
                 "main".to_string(),
                 vec![],
                 self.parse_block()?,
+                1,
             );
             self.funcs.borrow_mut().insert("main".to_string(), func);
 
@@ -380,76 +1275,264 @@ impl Parser {
             return Ok(fc);
         }
 
-        #[cfg(feature = "not v3.0.0")]
         loop {
             let tk = self.next_token()?;
-            match tk.kind {
-                TokenKind::TypeNumber | TokenKind::TypeBool | TokenKind::TypeString => {
-                    let ntk = self.next_token()?;
-                    if let TokenKind::Identifier(func_name) = &ntk.kind {
-                        let ptk = self.next_token()?;
-                        if ptk.kind != TokenKind::ParenthesisOpen {
-                            bail!("Expected '(' got {}", ptk);
-                        }
+            if tk.kind == TokenKind::EOF {
+                break;
+            }
+            if tk.kind == TokenKind::Const {
+                self.select_next();
+                let global = self.parse_declaration(true, true)?;
+                self.globals.push(global);
+                continue;
+            }
+            if tk.kind == TokenKind::Struct {
+                self.parse_struct_def()?;
+                continue;
+            }
+            if tk.kind == TokenKind::Enum {
+                self.parse_enum_def()?;
+                continue;
+            }
+            let kind = match self.parse_type(&tk) {
+                Some(kind) => kind,
+                None => bail!("Expected function or global definition, got {}", tk),
+            };
+            if self.peek_token(2).map(|tk| &tk.kind) != Some(&TokenKind::ParenthesisOpen)
+                && kind != VariableKind::None
+            {
+                let global = self.parse_declaration(false, true)?;
+                self.globals.push(global);
+                continue;
+            }
 
-                        let mut args: Vec<(VariableKind, String)> = vec![];
-                        if self.next_token()?.kind != TokenKind::ParenthesisClose {
-                            self.select_prev();
-                            loop {
-                                let ttk = self.next_token()?;
-                                match ttk.kind {
-                                    TokenKind::TypeNumber
-                                    | TokenKind::TypeBool
-                                    | TokenKind::TypeString => {
-                                        let itk = self.next_token()?;
-                                        if let TokenKind::Identifier(id) = &itk.kind {
-                                            args.push((ttk.kind.into(), id.clone()));
-                                        } else {
-                                            bail!("no identifier after kind {}", ttk)
-                                        }
-                                    }
-                                    _ => bail!("not type after {}", func_name),
-                                };
-
-                                let ltk = self.next_token()?;
-                                match ltk.kind {
-                                    TokenKind::Comma => {}
-                                    TokenKind::ParenthesisClose => break,
-                                    _ => bail!("Expected ',' or ')' got {}", ltk),
-                                }
+            let ntk = self.next_token()?;
+            let func_name = match &ntk.kind {
+                TokenKind::Identifier(func_name) => func_name.clone(),
+                _ => bail!("No identifier after type, {}", ntk),
+            };
+
+            let ptk = self.next_token()?;
+            if ptk.kind != TokenKind::ParenthesisOpen {
+                bail!("Expected '(' got {}", ptk);
+            }
+
+            let mut args: Vec<(VariableKind, String)> = vec![];
+            self.constants.push_scope();
+            if self.next_token()?.kind != TokenKind::ParenthesisClose {
+                self.select_prev();
+                loop {
+                    let ttk = self.next_token()?;
+                    match self.parse_type(&ttk) {
+                        Some(kind) if kind != VariableKind::None => {
+                            let itk = self.next_token()?;
+                            if let TokenKind::Identifier(id) = &itk.kind {
+                                self.constants.declare(id.clone(), None);
+                                args.push((kind, id.clone()));
+                            } else {
+                                bail!("no identifier after kind {}", ttk)
                             }
                         }
-                        self.select_next();
-                        let func = FuncDefNode::new(
-                            tk.kind.into(),
-                            func_name.clone(),
-                            args,
-                            self.parse_block()?,
-                        );
-                        self.funcs.borrow_mut().insert(func_name.clone(), func);
-                    } else {
-                        bail!("No identifier after type, {}", ntk);
+                        _ => bail!("not type after {}", func_name),
+                    };
+
+                    let ltk = self.next_token()?;
+                    match ltk.kind {
+                        TokenKind::Comma => {}
+                        TokenKind::ParenthesisClose => break,
+                        _ => bail!("Expected ',' or ')' got {}", ltk),
                     }
                 }
-                TokenKind::EOF => break,
-                _ => bail!("parse func def, {}", tk),
             }
+
+            let btk = self.next_token()?;
+            if btk.kind != TokenKind::BracketOpen {
+                bail!("Expected '{{' after function signature, got {}", btk);
+            }
+            let func = FuncDefNode::new(
+                kind,
+                func_name.clone(),
+                args,
+                self.parse_block()?,
+                ntk.line,
+            );
+            self.constants.pop_scope();
+            if self
+                .funcs
+                .borrow_mut()
+                .insert(func_name.clone(), func)
+                .is_some()
+            {
+                bail!("Function {} defined more than once", func_name);
+            }
+        }
+
+        if !self.funcs.borrow().contains_key("main") {
+            bail!("No main function defined");
         }
 
-        #[cfg(feature = "not v3.0.0")]
         let fc = FuncCallNode::new("main".to_string(), vec![], &self.funcs);
-        #[cfg(feature = "not v3.0.0")]
         Ok(fc)
     }
 }
 
-pub fn eval<T>(input: T) -> Result<()>
+pub fn eval<T>(
+    input: T,
+    target: Target,
+    source: Option<String>,
+    overflow_checks: bool,
+) -> Result<()>
+where
+    T: Into<String>,
+{
+    let asm = assemble_source(input, target, source, overflow_checks)?;
+    fs::write(target.output(), asm)?;
+    Ok(())
+}
+
+pub(crate) fn assemble_program<T>(input: T, target: Target) -> Result<String>
+where
+    T: Into<String>,
+{
+    assemble_source(input, target, None, false)
+}
+
+/// Assembles the program, with debug info mapping the code back to the file `source` when given,
+/// aborting on signed overflow with `overflow_checks`
+pub(crate) fn assemble_source<T>(
+    input: T,
+    target: Target,
+    source: Option<String>,
+    overflow_checks: bool,
+) -> Result<String>
 where
     T: Into<String>,
 {
     let tokens = tokenize(input.into())?;
 
-    Parser::parse(tokens)
+    Parser::parse(tokens, target, source, overflow_checks)
+}
+
+/// Compiles the program to machine code of this host and runs it with the arguments `args`, its
+/// own name first, reading and writing through `console`, returning what `main` returned or the
+/// status passed to `exit`
+pub(crate) fn jit_program<'a, T>(
+    input: T,
+    args: Vec<String>,
+    console: Box<dyn Console + 'a>,
+) -> Result<Number>
+where
+    T: Into<String>,
+{
+    let tokens = tokenize(input.into())?;
+    let mut parser = Parser::new(tokens);
+
+    parser.parse_program()?;
+    Jit::new(&parser.funcs)?.run(&parser.globals, args, console)
+}
+
+/// Runs the program in the interpreter with the arguments `args`, stopping where the commands read
+/// from `commands` ask to and writing what the debugger shows to `output`, returning the exit
+/// status of the program
+pub(crate) fn debug_program<R, W>(
+    source: &str,
+    args: Vec<String>,
+    commands: R,
+    output: W,
+) -> Result<Number>
+where
+    R: BufRead,
+    W: Write,
+{
+    let tokens = tokenize(source.to_string())?;
+    let mut parser = Parser::new(tokens);
+
+    let main = parser.parse_program()?;
+
+    // Expressions of the debugger see the functions and types of the program
+    let (funcs, structs, enums) = (
+        parser.funcs.clone(),
+        parser.structs.clone(),
+        parser.enums.clone(),
+    );
+    let parse = move |expression: &str| -> Result<Box<dyn Node>> {
+        let mut parser = Parser::new(tokenize(expression.to_string())?);
+        parser.funcs = funcs.clone();
+        parser.structs = structs.clone();
+        parser.enums = enums.clone();
+
+        let tree = parser.parse_ternary()?;
+        if parser.cur_token()?.kind != TokenKind::EOF {
+            bail!("Unexpected {} after the expression", parser.cur_token()?);
+        }
+        Ok(tree)
+    };
+
+    let mut session = Session::new(source, Box::new(parse), commands, output);
+    let mut vars = Environment::with_debugger(&mut session);
+    vars.set_args(args);
+    exit_status(run_main(&parser.globals, &main, &mut vars))
+}
+
+fn run_main(
+    globals: &[DeclareNode],
+    main: &FuncCallNode,
+    vars: &mut Environment,
+) -> Result<VariableData> {
+    for global in globals.iter() {
+        global.eval(vars)?;
+    }
+    main.eval(vars)
+}
+
+/// Exit status of a program which ended with `result`, what `main` returned or the status passed
+/// to `exit`
+fn exit_status(result: Result<VariableData>) -> Result<Number> {
+    let data = match result {
+        Ok(data) => data,
+        Err(e) => match e.downcast_ref::<RuntimeError>() {
+            Some(RuntimeError::Exit(status)) => return Ok(*status),
+            _ => return Err(e),
+        },
+    };
+    match data {
+        VariableData::Number(n) => Ok(n),
+        VariableData::Bool(b) => Ok(b as Number),
+        VariableData::None => Ok(0),
+        _ => bail!("Wrong type"),
+    }
+}
+
+/// Runs the program in the interpreter with the arguments `args`, its own name first, without
+/// using more than `limits`, reading and writing through `console`, returning what `main`
+/// returned, 0 when it returns nothing, or the status passed to `exit`
+pub(crate) fn interpret_program<'a, T>(
+    input: T,
+    args: Vec<String>,
+    limits: Limits,
+    console: Box<dyn Console + 'a>,
+) -> Result<Number>
+where
+    T: Into<String>,
+{
+    let tokens = tokenize(input.into())?;
+    let mut parser = Parser::new(tokens);
+
+    let main = parser.parse_program()?;
+
+    let mut vars = Environment::new();
+    vars.set_limits(limits);
+    vars.set_console(console);
+    vars.set_args(args);
+    exit_status(run_main(&parser.globals, &main, &mut vars))
+}
+
+#[allow(dead_code)]
+pub(crate) fn eval_program<T>(input: T) -> Result<Number>
+where
+    T: Into<String>,
+{
+    interpret_program(input, vec![], Limits::default(), Box::new(Stdio))
 }
 
 #[allow(dead_code)]
@@ -460,13 +1543,13 @@ where
     let tokens = tokenize(input.into())?;
     let mut parser = Parser::new(tokens);
 
-    let tree = parser.parse_expression()?;
+    let tree = parser.parse_ternary()?;
 
     if parser.cur_token()?.kind != TokenKind::EOF {
         bail!("Finished parsing but not EOF")
     }
 
-    match tree.eval(&mut HashMap::new()) {
+    match tree.eval(&mut Environment::new())? {
         VariableData::Number(n) => Ok(n),
         VariableData::Bool(b) => Ok(b as Number),
         _ => bail!("Wrong type"),
@@ -0,0 +1,762 @@
+use crate::parser::{
+    assemble_program, assemble_source, eval_program, interpret_program, jit_program,
+};
+use crate::backend::Target;
+use crate::console::Streams;
+use crate::token::Number;
+use crate::variable::Limits;
+
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Empty directory of its own for each program a test builds, the tests running in parallel
+fn scratch_dir(name: &str) -> PathBuf {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let count = COUNT.fetch_add(1, Ordering::Relaxed);
+    let dir = std::env::temp_dir().join(format!("logik-{}-{}-{}", name, std::process::id(), count));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Runs the command feeding it `input`, returning what it printed
+fn run_with_input(command: &mut Command, input: &str) -> Output {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn x86_64_keeps_64_bit_values() {
+    let asm = assemble_program(
+        "int main() { int a = 3000000000; return a; }",
+        Target::X86_64,
+    )
+    .unwrap();
+    assert!(asm.contains("mov rbx, 3000000000\nmov [rbp - 8], rbx"));
+    assert!(asm.contains("call func_main\njmp exit"));
+    assert!(!asm.contains("ebx"));
+
+    let asm =
+        assemble_program("int main() { int a = 3000000000; return a; }", Target::X86).unwrap();
+    assert!(asm.contains("mov ebx, 3000000000\nmov [ebp - 4], ebx"));
+    assert!(asm.contains("call func_main\njmp exit"));
+}
+
+#[test]
+fn x86_64_passes_arguments_in_registers() {
+    let program = "int f(int a, int b, int c, int d, int e, int g, int h) { return h; }
+        int main() { return f(1, 2, 3, 4, 5, 6, 7); }";
+
+    let asm = assemble_program(program, Target::X86_64).unwrap();
+    assert!(asm.contains(
+        "pop rdi\npop rsi\npop rdx\npop rcx\npop r8\npop r9\ncall func_f\nadd rsp, 8\nmov rbx, rax"
+    ));
+    // The registers are saved below rbp, the seventh argument stays above the return address
+    assert!(asm.contains("push r9\nsub rsp, 56\nmov rbx, [rbp - 8]\nmov [rbp - 56], rbx"));
+    assert!(asm.contains("mov rbx, [rbp + 16]\nmov [rbp - 104], rbx"));
+
+    let asm = assemble_program(program, Target::X86).unwrap();
+    assert!(asm.contains("call func_f\nadd esp, 28"));
+    assert!(asm.contains("mov ebx, [ebp + 32]\nmov [ebp - 28], ebx"));
+}
+
+#[test]
+fn x86_64_jump_tables_use_quad_words() {
+    let program = "int main() { int x = 2; switch (x) { case 1: x = 5; case 2: break; case 3: x = 1; } return x; }";
+    let asm = assemble_program(program, Target::X86_64).unwrap();
+    assert!(asm.contains("jmp [switch_table_1 + rbx * 8]"));
+    assert!(asm.contains("switch_table_1: dq switch_1_case_0, switch_1_case_1, switch_1_case_2"));
+}
+
+#[test]
+fn riscv64_passes_arguments_in_registers() {
+    let program = "int f(int a, int b, int c, int d, int e, int g, int h, int i, int j) { return j; }
+        int main() { return f(1, 2, 3, 4, 5, 6, 7, 8, 9); }";
+    let asm = assemble_program(program, Target::Riscv64).unwrap();
+    assert!(asm.contains("ld a0, 0(sp)\nld a1, 8(sp)"));
+    assert!(asm.contains("ld a7, 56(sp)\naddi sp, sp, 64\ncall func_f\naddi sp, sp, 8"));
+    // The registers are saved below the return address and s0, the ninth argument stays above
+    assert!(asm.contains("addi s0, sp, 16\naddi sp, s0, -152\nsd a0, -24(s0)"));
+    assert!(asm.contains("ld a0, 0(s0)\nsd a0, -152(s0)"));
+    assert!(asm.contains("call func_main\nj exit"));
+}
+
+#[test]
+fn riscv64_addresses_large_frames() {
+    let program = "int main() { int a[300]; int x = 2; a[x] = 3; return x; }";
+    let asm = assemble_program(program, Target::Riscv64).unwrap();
+    // Offsets past the 12 bit immediates are computed into a register
+    assert!(asm.contains("li t6, -2424\nadd t5, s0, t6\nsd a0, 0(t5)"));
+    assert!(asm.contains("mv s1, a0"));
+    assert!(asm.contains("slli t1, s1, 3\nadd t0, t0, t1\nsd a0, 0(t0)"));
+}
+
+#[test]
+fn riscv64_jump_tables_and_readln() {
+    let program = "int main() { int x = readln(); switch (x) { case 1: x = 5; case 2: break; case 3: x = 1; } return x; }";
+    let asm = assemble_program(program, Target::Riscv64).unwrap();
+    assert!(asm.contains("call read\nsd a0, -24(s0)"));
+    assert!(asm.contains("la t0, switch_table_1\nslli t1, a0, 3"));
+    assert!(asm.contains("switch_table_1: .dword switch_1_case_0, switch_1_case_1, switch_1_case_2"));
+
+    let asm = assemble_program(program, Target::X86_64).unwrap();
+    assert!(asm.contains("call read\nmov [rbp - 8], rbx"));
+}
+
+/// Runs a program with `qemu-riscv64` when it and the cross binutils are installed
+fn run_riscv64(program: &str, input: &str) -> Option<String> {
+    let installed = |tool: &str| Command::new(tool).arg("--version").output().is_ok();
+    if !["qemu-riscv64", "riscv64-linux-gnu-as", "riscv64-linux-gnu-ld"]
+        .iter()
+        .all(|tool| installed(tool))
+    {
+        return None;
+    }
+
+    let dir = scratch_dir("riscv64");
+    fs::write(dir.join("out.s"), assemble_program(program, Target::Riscv64).unwrap()).unwrap();
+    let status = Command::new("riscv64-linux-gnu-as")
+        .current_dir(&dir)
+        .args(["out.s", "-o", "out.o"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let status = Command::new("riscv64-linux-gnu-ld")
+        .current_dir(&dir)
+        .args(["out.o", "-o", "out"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let mut child = Command::new("qemu-riscv64")
+        .arg(dir.join("out"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    Some(String::from_utf8(output.stdout).unwrap())
+}
+
+#[test]
+fn riscv64_runs_under_qemu() {
+    let program = "struct P { int x; int y; }
+        P mk(int x, int y) { P p = {x, y}; return p; }
+        int main() {
+            int a[4] = {1, 2, 3};
+            int n = readln();
+            a[3] = n;
+            int s = 0;
+            for (int i = 0; i < len(a); i++) { s += a[i]; }
+            println(s);
+            P p = mk(3, 4);
+            println(p.x * 10 + p.y);
+            return 0;
+        }";
+    if let Some(output) = run_riscv64(program, "-20 apples\n") {
+        assert_eq!(output, "-14\n34\n");
+    }
+}
+
+#[test]
+fn wasm_keeps_structured_control_flow() {
+    let program = "int main() { int s = 0; int i = 0;
+        while (i < 10) { i++; if (i == 3) { continue; } s += i; } return s; }";
+
+    let wat = assemble_program(program, Target::Wasm).unwrap();
+    assert!(wat.contains("(import \"env\" \"print_int\" (func $print_int (param i64)))"));
+    assert!(wat.contains("(func $func_main (result i64)\n    (local $s i64)\n    (local $i i64)"));
+    assert!(wat.contains("block $while_end_1\n      loop $while_1"));
+    assert!(wat.contains("i64.eqz\n        br_if $while_end_1"));
+    assert!(wat.contains("br $while_1\n      end\n    end"));
+    assert!(wat.contains("(export \"main\" (func $func_main))"));
+    wat::parse_str(&wat).unwrap();
+}
+
+#[test]
+fn wasm_keeps_aggregates_in_linear_memory() {
+    let program = "struct P { int x; int y; } P g;
+        P mk(int x, int y) { P p = {x, y}; return p; }
+        int main() { int a[3] = {1, 2}; g = mk(a[0], a[1]); return g.x + g.y; }";
+
+    let wat = assemble_program(program, Target::Wasm).unwrap();
+    // Struct results are written to an address passed as a hidden first parameter
+    assert!(wat
+        .contains("(func $func_mk (param $return i32) (param $x i64) (param $y i64) (result i32)"));
+    assert!(
+        wat.contains("global.get $sp\n    local.tee $frame_base\n    i32.const 32\n    i32.sub")
+    );
+    assert!(wat.contains("call $bounds_error"));
+    wat::parse_str(&wat).unwrap();
+}
+
+#[test]
+fn wasm_runs_in_the_embedded_runtime() {
+    let program = "int f(int n) { switch (n) { case 0: return 5; case 1: return 7; case 2: return 9; } return 0; }
+        int main() { int a[2]; a[f(1) - 7] = f(2); return a[0]; }";
+    let wat = assemble_program(program, Target::Wasm).unwrap();
+    assert!(
+        wat.contains("br_table $switch_1_case_0 $switch_1_case_1 $switch_1_case_2 $switch_end_1")
+    );
+    crate::backend::wasm::run(&wat, &[]).unwrap();
+
+    let wat =
+        assemble_program("int main() { int a[2]; a[2] = 1; return 0; }", Target::Wasm).unwrap();
+    let error = crate::backend::wasm::run(&wat, &[]).unwrap_err();
+    assert!(error.to_string().contains("array index out of bounds"));
+
+    // The smallest int divided by -1 wraps around like in the interpreter instead of trapping
+    let program = "int main() { int m = 9223372036854775807; int n = -m - 1; int d = -1;
+        return (n / d == n) + (n % d == 0) * 10 + (-7 / 2) * 100 + (-7 % 2) * 1000 + (n / 2 == -4611686018427387904) * 10000; }";
+    let wat = assemble_program(program, Target::Wasm).unwrap();
+    let expected = eval_program(program).unwrap();
+    assert_eq!(expected, 8711);
+    assert_eq!(crate::backend::wasm::run(&wat, &[]).unwrap(), expected);
+}
+
+#[test]
+fn wasm_reports_strings_as_errors() {
+    for program in [
+        "int main() { println(\"hi\"); return 0; }",
+        "int main() { string s = arg(0); return 0; }",
+        "int main() { string s; println(s); return 0; }",
+        "struct P { int x; string s; } int main() { P p; return p.x; }",
+        "string f() { return f(); } int main() { return 0; }",
+    ] {
+        let error = assemble_program(program, Target::Wasm).unwrap_err();
+        assert_eq!(error.to_string(), "Strings are not supported by the wasm target");
+    }
+}
+
+#[test]
+fn assembly_targets_report_strings_as_errors() {
+    for target in [Target::X86, Target::X86_64, Target::Riscv64] {
+        let program = "int main() { println(\"hi\"); return 0; }";
+        let error = assemble_program(program, target).unwrap_err();
+        let expected = format!(
+            "String literals are not supported by the {} target",
+            target.name()
+        );
+        assert_eq!(error.to_string(), expected);
+
+        let program = "int main() { return arg(0) == arg(1); }";
+        let error = assemble_program(program, target).unwrap_err();
+        let expected = format!(
+            "Comparing strings is not supported by the {} target",
+            target.name()
+        );
+        assert_eq!(error.to_string(), expected);
+
+        // The arguments of the program are still strings there
+        assemble_program(
+            "int main() { string s = arg(0); println(s); return 0; }",
+            target,
+        )
+        .unwrap();
+    }
+}
+
+#[test]
+fn c_maps_types_and_keeps_evaluation_order() {
+    let program = "struct P { int x; bool b; string s; } int g = 3; int x;
+        int f() { x = x * 10; return 1; }
+        int main() { P p = {g, true, \"ab\"}; x = 1; int y = x + f(); int a[2] = {y}; a[x - 10] += f(); return y; }";
+
+    let c = assemble_program(program, Target::C).unwrap();
+    assert!(c.contains("struct P {\n    long long x;\n    _Bool b;\n    const char *s;\n};"));
+    assert!(c.contains("static long long g = 3LL;\nstatic long long x = 0;"));
+    assert!(c.contains("p = (struct P){g, 1, \"ab\"};"));
+    // `eval` reads x before calling f, which changes it
+    assert!(c.contains("y = (tmp = x, logik_add(tmp, func_f()));"));
+    assert!(c.contains("memset(&a, 0, sizeof a), a[0] = y;"));
+    assert!(c.contains(
+        "(tmp_1 = func_f(), a[logik_index(logik_sub(x, 10LL), 2)] = logik_add(a[logik_index(logik_sub(x, 10LL), 2)], tmp_1));"
+    ));
+    assert!(c.contains(
+        "int main(int argc, char **argv) {\n    logik_argc = argc;\n    logik_argv = argv;\n    return (int) func_main();\n}"
+    ));
+}
+
+/// Compiles a program with `cc` when it is installed and runs it with the given input and
+/// arguments, returning what it printed
+fn run_c(program: &str, input: &str, args: &[&str]) -> Option<Output> {
+    if Command::new("cc").arg("--version").output().is_err() {
+        return None;
+    }
+
+    let dir = scratch_dir("c");
+    fs::write(
+        dir.join("out.c"),
+        assemble_program(program, Target::C).unwrap(),
+    )
+    .unwrap();
+    let status = Command::new("cc")
+        .current_dir(&dir)
+        .args(["-std=c99", "out.c", "-o", "out"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let output = run_with_input(Command::new(dir.join("out")).args(args), input);
+    fs::remove_dir_all(&dir).unwrap();
+    Some(output)
+}
+
+#[test]
+fn c_reports_division_by_zero() {
+    let program = "int main() {\n    int zero = 0;\n    println(7 / 2);\n    return 1 % zero;\n}";
+    if let Some(output) = run_c(program, "", &[]) {
+        assert_eq!(output.stdout, b"3\n");
+        assert_eq!(output.stderr, b"Error: Division by zero @ 4:14\n");
+        assert_eq!(output.status.code(), Some(1));
+    }
+}
+
+#[test]
+fn llvm_matches_golden_file() {
+    let program = "struct P { int x; bool b; } int g = 2;
+        int main() { P p = {g, true}; int s = 0; for (int i = 0; i < 3; i++) { if (p.b && (i != 1)) { s += i; } }
+            switch (s) { case 2: println(\"two\"); default: println(s); } return s; }";
+
+    let ll = assemble_program(program, Target::Llvm).unwrap();
+    // The runtime is copied as is, only the code of the program is compared
+    let (_, code) = ll.split_once("; codigo gerado pelo compilador\n").unwrap();
+    assert_eq!(code, include_str!("golden/llvm.ll"));
+}
+
+/// Checks the module with `llvm-as`, optimizes it with `opt -O2` so undefined behavior shows up and
+/// runs it with `lli` when they are installed, returning what it printed
+fn run_llvm(program: &str, input: &str, args: &[&str]) -> Option<Output> {
+    let installed = |tool: &str| Command::new(tool).arg("--version").output().is_ok();
+    if !["llvm-as", "opt", "lli"].iter().all(|tool| installed(tool)) {
+        return None;
+    }
+
+    let dir = scratch_dir("llvm");
+    fs::write(
+        dir.join("out.ll"),
+        assemble_program(program, Target::Llvm).unwrap(),
+    )
+    .unwrap();
+    let status = Command::new("llvm-as")
+        .current_dir(&dir)
+        .args(["out.ll", "-o", "out.bc"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let status = Command::new("opt")
+        .current_dir(&dir)
+        .args(["-O2", "out.bc", "-o", "out.bc"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let output = run_with_input(
+        Command::new("lli").arg(dir.join("out.bc")).args(args),
+        input,
+    );
+    fs::remove_dir_all(&dir).unwrap();
+    Some(output)
+}
+
+#[test]
+fn llvm_reports_runtime_errors() {
+    // The bounds error aborts after what was printed before it
+    let program = "int main() { int a[2]; println(readln() + readln()); return a[3]; }";
+    if let Some(output) = run_llvm(program, "4\n5\n", &[]) {
+        assert_eq!(output.stdout, b"9\n");
+    }
+
+    let program =
+        "int main() {\n    int zero = readln();\n    println(7 / 2);\n    return 1 % zero;\n}";
+    if let Some(output) = run_llvm(program, "0\n", &[]) {
+        assert_eq!(output.stdout, b"3\n");
+        assert_eq!(output.stderr, b"Error: Division by zero @ 4:14\n");
+        assert_eq!(output.status.code(), Some(1));
+    }
+
+    let program = "int main() {\n    int z = readln();\n    println(5 / z);\n    return 0;\n}";
+    if let Some(output) = run_llvm(program, "0\n", &[]) {
+        assert!(output.stdout.is_empty());
+        assert_eq!(output.stderr, b"Error: Division by zero @ 3:15\n");
+        assert_eq!(output.status.code(), Some(1));
+    }
+}
+
+#[test]
+fn x86_executables_are_encoded_without_nasm() {
+    let program = "int main() { int a = 3000000000; return a; }";
+    let contains = |executable: &[u8], bytes: &[u8]| {
+        executable
+            .windows(bytes.len())
+            .any(|window| window == bytes)
+    };
+
+    let asm = assemble_program(program, Target::X86_64).unwrap();
+    let executable = Target::X86_64.executable(&asm).unwrap();
+    assert_eq!(executable[..5], [0x7f, b'E', b'L', b'F', 2]);
+    assert_eq!(executable[18..20], 62u16.to_le_bytes());
+    // mov rbx, 3000000000 needs the 64 bit immediate, then mov [rbp - 8], rbx
+    assert!(contains(
+        &executable,
+        &[0x48, 0xbb, 0x00, 0x5e, 0xd0, 0xb2, 0, 0, 0, 0, 0x48, 0x89, 0x5d, 0xf8]
+    ));
+    // mov rax, 60 then mov rdi, rbx and syscall
+    assert!(contains(
+        &executable,
+        &[0x48, 0xc7, 0xc0, 60, 0, 0, 0, 0x48, 0x89, 0xdf, 0x0f, 0x05]
+    ));
+
+    let asm = assemble_program(program, Target::X86).unwrap();
+    let executable = Target::X86.executable(&asm).unwrap();
+    assert_eq!(executable[..5], [0x7f, b'E', b'L', b'F', 1]);
+    assert_eq!(executable[18..20], 3u16.to_le_bytes());
+    assert!(contains(
+        &executable,
+        &[0xbb, 0x00, 0x5e, 0xd0, 0xb2, 0x89, 0x5d, 0xfc]
+    ));
+    assert!(contains(&executable, &[0xcd, 0x80]));
+
+    assert!(Target::Riscv64.executable("").is_err());
+}
+
+#[test]
+fn x86_debug_info_maps_lines_and_variables() {
+    let program = "int main() {\n    int a = 2;\n    println(a);\n}";
+    let contains = |executable: &[u8], bytes: &[u8]| {
+        executable
+            .windows(bytes.len())
+            .any(|window| window == bytes)
+    };
+
+    let asm = assemble_source(program, Target::X86_64, Some("file.c".into()), false).unwrap();
+    assert!(asm.contains("section .debug_info"));
+    assert!(asm.contains("section .debug_line"));
+    assert!(asm.contains("debug_row_0:\nmov rbx, 2"));
+    assert!(asm.contains("debug_end_main:"));
+    assert!(asm.contains("\"file.c\""));
+    // Without debug info the assembly is unchanged
+    assert!(!assemble_program(program, Target::X86_64)
+        .unwrap()
+        .contains("debug_"));
+
+    for target in [Target::X86_64, Target::X86] {
+        let asm = assemble_source(program, target, Some("file.c".into()), false).unwrap();
+        let executable = target.executable(&asm).unwrap();
+        assert!(contains(&executable, b".debug_line\0"));
+        assert!(contains(&executable, b"file.c\0"));
+    }
+
+    assert!(assemble_source(program, Target::Riscv64, Some("file.c".into()), false).is_err());
+}
+
+/// Builds the program into an executable and runs it when this machine can, returning what it
+/// printed
+fn run_executable(program: &str, input: &str, target: Target) -> Option<String> {
+    let asm = assemble_program(program, target).unwrap();
+    let output = run_assembly(&asm, input, &[], target)?;
+    Some(String::from_utf8(output.stdout).unwrap())
+}
+
+/// Builds the assembly into an executable and runs it with the given arguments when this machine
+/// can
+fn run_assembly(asm: &str, input: &str, args: &[&str], target: Target) -> Option<Output> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    if !cfg!(all(target_os = "linux", target_arch = "x86_64")) {
+        return None;
+    }
+
+    let dir = scratch_dir(target.name());
+    fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o755)
+        .open(dir.join("out"))
+        .unwrap()
+        .write_all(&target.executable(asm).unwrap())
+        .unwrap();
+
+    let spawn = || {
+        Command::new(dir.join("out"))
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+    };
+    // Another test forking while the file was open for writing keeps it busy for a moment
+    let mut child = spawn();
+    for _ in 0..10 {
+        match child {
+            Err(ref e) if e.raw_os_error() == Some(26) => {
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                child = spawn();
+            }
+            _ => break,
+        }
+    }
+    // Kernels without 32 bit support refuse to run the x86 executables
+    let mut child = match child {
+        Ok(child) => child,
+        Err(_) if target == Target::X86 => return None,
+        Err(e) => panic!("{}", e),
+    };
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    Some(output)
+}
+
+#[test]
+fn x86_executables_report_bounds_errors() {
+    // The bounds error aborts after what was printed before it
+    let program = "int main() { int a[2]; println(readln() + readln()); return a[3]; }";
+    if let Some(output) = run_executable(program, "4\n5\n", Target::X86_64) {
+        assert_eq!(output, "9\n");
+    }
+}
+
+#[test]
+fn x86_executables_print_signed_numbers() {
+    let program = "int main() { int n = readln();
+        println(-7); println(n); println(0); println(-n - 1); return 0; }";
+    let cases = [
+        (
+            Target::X86_64,
+            "9223372036854775807\n",
+            "-7\n9223372036854775807\n0\n-9223372036854775808\n",
+        ),
+        (Target::X86, "2147483647\n", "-7\n2147483647\n0\n-2147483648\n"),
+    ];
+    for (target, input, expected) in cases.iter() {
+        if let Some(output) = run_executable(program, input, *target) {
+            assert_eq!(output, *expected);
+        }
+    }
+}
+
+#[test]
+fn x86_executables_read_numbers_like_strtoll() {
+    // Reading stops at the first character that is not a digit, like `logik_readln` in C
+    let program = "int main() { for (int i = 0; i < 6; i++) { println(readln()); } return 0; }";
+    let input = "  42abc\n-17\n+8\nxyz\n\t-3 4\n-\n";
+    for target in [Target::X86_64, Target::X86] {
+        if let Some(output) = run_executable(program, input, target) {
+            assert_eq!(output, "42\n-17\n8\n0\n-3\n0\n");
+        }
+    }
+}
+
+#[test]
+fn x86_executables_exit_with_the_status_of_main() {
+    let programs = [
+        ("int main() { println(1); return 300 + 3; }", 47),
+        ("void main() { println(2); return; }", 0),
+        ("int f(int x) { if (x > 5) { exit(x * 2); } return x; }
+            int main() { println(f(3)); f(21); return 7; }", 42),
+    ];
+    for (program, status) in programs.iter() {
+        for target in [Target::X86_64, Target::X86] {
+            let asm = assemble_program(*program, target).unwrap();
+            if let Some(output) = run_assembly(&asm, "", &[], target) {
+                assert_eq!(output.status.code(), Some(*status));
+            }
+        }
+    }
+}
+
+#[test]
+fn x86_executables_read_their_arguments() {
+    let program = "int main() { for (int i = 0; i < argc(); i++) { string s = arg(i); println(s); }
+        println(arg(argc())); return 1; }";
+    for target in [Target::X86_64, Target::X86] {
+        let asm = assemble_program(program, target).unwrap();
+        if let Some(output) = run_assembly(&asm, "", &["a", "bc", ""], target) {
+            assert!(output.stdout.ends_with(b"\na\nbc\n\n"));
+            assert_eq!(output.stderr, b"Error: argument index out of bounds\n");
+            assert_eq!(output.status.code(), Some(1));
+        }
+    }
+}
+
+#[test]
+fn x86_executables_report_arithmetic_faults() {
+    let program =
+        "int main() {\n    int zero = readln();\n    println(7 / 2);\n    return 1 % zero;\n}";
+    for target in [Target::X86_64, Target::X86] {
+        let asm = assemble_program(program, target).unwrap();
+        if let Some(output) = run_assembly(&asm, "0\n", &[], target) {
+            assert_eq!(output.stdout, b"3\n");
+            assert_eq!(output.stderr, b"Error: Division by zero @ 4:14\n");
+            assert_eq!(output.status.code(), Some(1));
+        }
+    }
+
+    // The smallest number divided by -1 wraps around like the other operators, unless checked
+    let program = "int main() {\n    int a = readln();\n    int m = -a - 1;\n    println((m / -1 == m) + (a + 1 == m) * 10);\n    return 0;\n}";
+    let input = "9223372036854775807\n";
+    let asm = assemble_program(program, Target::X86_64).unwrap();
+    if let Some(output) = run_assembly(&asm, input, &[], Target::X86_64) {
+        assert_eq!(output.stdout, b"11\n");
+        assert_eq!(output.status.code(), Some(0));
+    }
+    let asm = assemble_source(program, Target::X86_64, None, true).unwrap();
+    if let Some(output) = run_assembly(&asm, input, &[], Target::X86_64) {
+        assert_eq!(output.stderr, b"Error: Integer overflow @ 4:16\n");
+        assert_eq!(output.status.code(), Some(1));
+    }
+
+    // Only the arm of a ternary that is taken may overflow
+    let program = "int main() { int m = readln(); int r = m < 0 ? m + 1 : -m; println(r); return 0; }";
+    let asm = assemble_source(program, Target::X86_64, None, true).unwrap();
+    if let Some(output) = run_assembly(&asm, input, &[], Target::X86_64) {
+        assert_eq!(output.stdout, b"-9223372036854775807\n");
+        assert_eq!(output.status.code(), Some(0));
+    }
+
+    assert!(assemble_source(program, Target::Wasm, None, true).is_err());
+}
+
+/// Program, input and arguments of a run printing and returning the same in every target as in
+/// the interpreter
+type Case = (&'static str, &'static str, &'static [&'static str]);
+
+/// Runs every target supports
+const CASES: &[Case] = &[
+    ("int x = 1; int f() { x = x * 10; return 1; }
+        int main() { int r = x + f(); r = r * 100 + x; r = f() * 1000 + x + r; println(r); return r; }", "", &[]),
+    ("int calls = 0; bool t() { calls++; return true; }
+        int main() { bool a = false && t(); bool b = true || t(); bool c = 2 || t(); int r = a + b * 10 + c * 20 + calls * 100; println(r); return r; }", "", &[]),
+    ("int main() { int a[3] = {7}; int i = 0; a[i++] = i; a[2] -= 4; int r = a[0] * 100 + a[1] * 10 - a[2]; println(r); return r; }", "", &[]),
+    ("struct P { int x; int y; } P swap(P p) { int t = p.x; p.x = p.y; p.y = t; return p; }
+        int main() { P p = {1, 2}; P q = swap(p); int r = p.x * 1000 + p.y * 100 + q.x * 10 + q.y; println(r); return r; }", "", &[]),
+    ("struct P { int x; int y; } P p; int g(P a, P b) { a.x = 5; return a.x * 10 + b.x; }
+        int main() { p.x = 1; P q = p; q.y = 3; int r = g(p, p) * 100 + p.x * 10 + q.y; println(r); return r > 5000 ? r : -r; }", "", &[]),
+    ("int main() { int r = 0; for (int i = 0; i < 10; i++) { switch (i % 3) { case 0: continue; case 1: r += 1; default: r += 10; } r *= 2; } println(r); return r; }", "", &[]),
+    ("int f(int n) { if (n < 2) { return n; } return f(n - 1) + f(n - 2); }
+        int main() { int r = 0; int k = 0; while (true) { k++; if (k > 20) { break; } } do { r += f(k); k--; } while (k > 15); println(r); return r; }", "", &[]),
+    ("int f(int a, int b, int c, int d, int e, int g, int h) { return a * 7 - h + (-7 >> 1) * e; }
+        int main() { int r = f(1, 2, 3, 4, 5, 6, 7) + 20; println(r); return r; }", "", &[]),
+    ("int main() { int i = 3; int j = i++ - 1; int k = --i + i--; int r = j * 100 + k * 10 + i; println(r); return r; }", "", &[]),
+    ("enum Color { Red = 2, Green } struct S { int n; Color c; } Color g; S h;
+        int main() { Color c; S s; int a = c; int b = g; int d = s.c; int e = h.c; int r = a * 1000 + b * 100 + d * 10 + e; println(r); return r; }", "", &[]),
+    ("int main() { int n = readln(); string s = arg(2); println(s); println(n * 10 + argc()); return n - readln(); }", "4\n-3\n", &["a", "bc"]),
+    ("int f(int x) { if (x > 5) { exit(x * 2); } return x; }
+        int main() { println(f(3)); f(21); return 7; }", "", &[]),
+];
+
+/// Runs relying on ints of 64 bits, which the x86 target does not have
+const WIDE_CASES: &[Case] = &[
+    ("int main() { int m = 9223372036854775807; int n = -m - 1; int s = 65; int r = (m + 1 == n) + (n / -1 == n) * 10 + (n % -1 == 0) * 100
+        + (-n == n) * 1000 + (m * 2 == -2) * 10000 + ((1 << s) == 2) * 100000 + ((n >> s) == n / 2) * 1000000; println(r); return r; }", "", &[]),
+    ("int main() { int m = 9223372036854775807; int n = -m - 1; int r = (m + 1 == n) + (n / -1 == n) * 10 + (n % -1 == 0) * 100
+        + (-7 / 2) * 1000 + (-7 % 2) * 10000; n /= -1; println(r); println(n); return r; }", "", &[]),
+];
+
+/// Runs comparing string literals, which the assembly targets do not have
+const STRING_CASES: &[Case] = &[
+    ("int main() { string s = \"ab\"; bool b = true; b += 1; int r = (s == \"ab\") * 10 + (s != \"ab\") - (-7 >> 1) + (1 << 40) + b; println(r); return r; }", "", &[]),
+];
+
+/// What the interpreter, or the JIT, printed and returned running the program
+fn run_in_process(
+    program: &str,
+    input: &str,
+    args: &[&str],
+    jit: bool,
+) -> (Result<Number, String>, String) {
+    let mut output = vec![];
+    let console = Box::new(Streams::new(input.as_bytes(), &mut output));
+    let args = std::iter::once(&"logik")
+        .chain(args)
+        .map(|arg| arg.to_string())
+        .collect();
+    let result = match jit {
+        true => jit_program(program, args, console),
+        false => interpret_program(program, args, Limits::default(), console),
+    };
+    (
+        result.map_err(|e| e.to_string()),
+        String::from_utf8(output).unwrap(),
+    )
+}
+
+#[test]
+fn targets_match_eval() {
+    let runs: [(&[Case], &[Target]); 3] = [
+        (
+            CASES,
+            &[Target::C, Target::Llvm, Target::X86_64, Target::X86],
+        ),
+        (WIDE_CASES, &[Target::C, Target::Llvm, Target::X86_64]),
+        (STRING_CASES, &[Target::C, Target::Llvm]),
+    ];
+    for (cases, targets) in runs.iter() {
+        for (program, input, args) in cases.iter() {
+            let expected = run_in_process(program, input, args, false);
+            assert_eq!(run_in_process(program, input, args, true), expected);
+
+            // The exit status of a process keeps the low byte of what main returned
+            let (status, stdout) = expected;
+            let status = status.unwrap() as u8 as i32;
+            for target in targets.iter() {
+                let output = match target {
+                    Target::C => run_c(program, input, args),
+                    Target::Llvm => run_llvm(program, input, args),
+                    _ => {
+                        let asm = assemble_program(*program, *target).unwrap();
+                        run_assembly(&asm, input, args, *target)
+                    }
+                };
+                if let Some(output) = output {
+                    let context = format!("{} running {}", target.name(), program);
+                    let printed = String::from_utf8(output.stdout).unwrap();
+                    assert_eq!(printed, stdout, "{}", context);
+                    assert_eq!(output.status.code(), Some(status), "{}", context);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn jit_reports_runtime_errors_like_eval() {
+    let programs = [
+        "int f(int d) { println(d); return 10 / d; }\nint main() {\n    int x = f(2);\n    x %= f(0);\n    return x;\n}",
+        "int main() {\n    int a[3];\n    int i = 3;\n    println(1);\n    a[i] = 2;\n    return 0;\n}",
+        "int f() { println(\"f\"); exit(7); println(\"g\"); return 1; } int main() { int a[2]; a[f()] = f(); return 0; }",
+    ];
+    for program in programs.iter() {
+        assert_eq!(
+            run_in_process(program, "", &[], true),
+            run_in_process(program, "", &[], false)
+        );
+    }
+}
@@ -0,0 +1,77 @@
+use crate::parser::debug_program;
+
+/// Runs the program under the debugger with the given commands, returning what it showed
+fn debug(program: &str, commands: &str) -> String {
+    let mut output = vec![];
+    debug_program(program, vec![], commands.as_bytes(), &mut output).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+const PROGRAM: &str = "int square(int n) {
+    int s = n * n;
+    return s;
+}
+int main() {
+    int a = 3;
+    int b = square(a);
+    return a + b;
+}";
+
+#[test]
+fn steps_into_and_over_calls() {
+    let output = debug(PROGRAM, "n\nn\n");
+    assert_eq!(
+        output,
+        "main:6 int a = 3;
+(logik) main:7 int b = square(a);
+(logik) main:8 return a + b;
+(logik) "
+    );
+
+    let output = debug(PROGRAM, "n\ns\nbt\nn\nv\nfinish\nv\n");
+    assert_eq!(
+        output,
+        "main:6 int a = 3;
+(logik) main:7 int b = square(a);
+(logik) square:2 int s = n * n;
+(logik) #0 square at line 2
+#1 main at line 7
+(logik) square:3 return s;
+(logik) n = 3
+s = 9
+(logik) square returned 9
+main:8 return a + b;
+(logik) a = 3
+b = 9
+(logik) "
+    );
+}
+
+#[test]
+fn stops_on_breakpoints_and_shows_watches() {
+    let program = "int main() {
+    int sum = 0;
+    for (int i = 0; i < 3; i++) {
+        sum += i;
+    }
+    return sum;
+}";
+    let output = debug(program, "b 4\nw sum * 10\nc\nc\np i == 1\nd 4\nc\n");
+    assert_eq!(
+        output,
+        "main:2 int sum = 0;
+(logik) Breakpoint on line 4
+(logik) Error: Variable sum used before declaration
+(logik) main:4 sum += i;
+sum * 10 = 0
+(logik) main:4 sum += i;
+sum * 10 = 0
+(logik) i == 1 = true
+(logik) Deleted the breakpoint on line 4
+(logik) "
+    );
+
+    let output = debug(program, "p sum +\nq2\n");
+    assert!(output.contains("Error: "));
+    assert!(output.contains("Unknown command q2"));
+}
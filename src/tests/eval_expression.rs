@@ -78,21 +78,21 @@ fn errors() {
 
     assert_eq!(
         eval_expression("3+ /* a */").unwrap_err().to_string(),
-        "Expected number, variable, operator or '(', found EOF @ 0:11"
+        "Expected number, variable, operator or '(', found EOF @ 1:11"
     );
 
     assert_eq!(
         eval_expression("3+ /* a */-").unwrap_err().to_string(),
-        "Expected number, variable, operator or '(', found EOF @ 0:12"
+        "Expected number, variable, operator or '(', found EOF @ 1:12"
     );
 
     assert_eq!(
         eval_expression("/* */").unwrap_err().to_string(),
-        "Expected number, variable, operator or '(', found EOF @ 0:6"
+        "Expected number, variable, operator or '(', found EOF @ 1:6"
     );
     assert_eq!(
         eval_expression("/* 1 + 1*/").unwrap_err().to_string(),
-        "Expected number, variable, operator or '(', found EOF @ 0:11"
+        "Expected number, variable, operator or '(', found EOF @ 1:11"
     );
 
     assert_eq!(
@@ -100,3 +100,50 @@ fn errors() {
         "Expected '+' or '-' or '!' found '*'"
     );
 }
+
+#[test]
+fn modulo() {
+    assert_eq!(eval_expression("7 % 3").unwrap(), 1);
+    assert_eq!(eval_expression("-7 % 3").unwrap(), -1);
+    assert_eq!(eval_expression("2 + 9 % 4 * 3").unwrap(), 5);
+}
+
+#[test]
+fn bitwise() {
+    assert_eq!(eval_expression("6 & 3").unwrap(), 2);
+    assert_eq!(eval_expression("6 | 3").unwrap(), 7);
+    assert_eq!(eval_expression("6 ^ 3").unwrap(), 5);
+    assert_eq!(eval_expression("~5").unwrap(), -6);
+    assert_eq!(eval_expression("1 | 2 ^ 6 & 3").unwrap(), 1);
+    assert_eq!(eval_expression("(1 | 2) & 6").unwrap(), 2);
+}
+
+#[test]
+fn bitwise_precedence() {
+    // Like in C, comparisons bind tighter than & ^ | and those tighter than && and ||
+    assert_eq!(eval_expression("6 & 3 == 2").unwrap(), 0);
+    assert_eq!(eval_expression("(6 & 3) == 2").unwrap(), 1);
+    assert_eq!(eval_expression("2 ^ 3 < 2").unwrap(), 2);
+    assert_eq!(eval_expression("4 | 1 != 1").unwrap(), 4);
+    assert_eq!(eval_expression("5 & 4 && 0 | 1").unwrap(), 1);
+    assert_eq!(eval_expression("1 < 2 == 1").unwrap(), 1);
+}
+
+#[test]
+fn shift() {
+    assert_eq!(eval_expression("1 << 4").unwrap(), 16);
+    assert_eq!(eval_expression("-16 >> 2").unwrap(), -4);
+    assert_eq!(eval_expression("1 << 2 + 1").unwrap(), 8);
+    assert_eq!(eval_expression("1<<3|1").unwrap(), 9);
+}
+
+#[test]
+fn ternary() {
+    assert_eq!(eval_expression("1 < 2 ? 10 : 20").unwrap(), 10);
+    assert_eq!(eval_expression("1 > 2 ? 10 : 20").unwrap(), 20);
+    assert_eq!(eval_expression("0 ? 1 : 0 ? 2 : 3").unwrap(), 3);
+    assert_eq!(eval_expression("1 ? 0 ? 1 : 2 : 3").unwrap(), 2);
+    assert_eq!(eval_expression("(1 ? 2 : 3) + 1").unwrap(), 3);
+    assert_eq!(eval_expression("1 + 1 ? 5 : 6").unwrap(), 5);
+    assert!(eval_expression("1 ? 2").is_err());
+}
@@ -0,0 +1,718 @@
+use crate::console::{Stdio, Streams};
+use crate::error::RuntimeError;
+use crate::parser::{eval_program, interpret_program};
+use crate::token::Position;
+use crate::variable::Limits;
+
+#[test]
+fn compound_assign() {
+    assert_eq!(eval_program("int x = 5; x += 3; return x;").unwrap(), 8);
+    assert_eq!(eval_program("int x = 5; x -= 3; return x;").unwrap(), 2);
+    assert_eq!(
+        eval_program("int x = 5; x *= 3 + 1; return x;").unwrap(),
+        20
+    );
+    assert_eq!(eval_program("int x = 17; x /= 5; return x;").unwrap(), 3);
+    assert_eq!(eval_program("int x = 17; x %= 5; return x;").unwrap(), 2);
+    assert_eq!(eval_program("int x = 2; x*=-1; return x;").unwrap(), -2);
+}
+
+#[test]
+fn increment_decrement() {
+    assert_eq!(eval_program("int i = 0; i++; i++; return i;").unwrap(), 2);
+    assert_eq!(
+        eval_program("int i = 0; ++i; --i; i--; return i;").unwrap(),
+        -1
+    );
+    assert_eq!(
+        eval_program("int i = 0; while (i < 10) { i++; } return i;").unwrap(),
+        10
+    );
+    assert_eq!(
+        eval_program("int i = 3; int j = i++; return j * 10 + i;").unwrap(),
+        34
+    );
+    assert_eq!(
+        eval_program("int i = 3; int j = --i; return j * 10 + i;").unwrap(),
+        22
+    );
+    assert_eq!(eval_program("int i = 3; return i--;").unwrap(), 3);
+    assert_eq!(eval_program("int i = 3; return i++ * 2 + i;").unwrap(), 10);
+    assert!(eval_program("int i = 3; return i--1;").is_err());
+    assert_eq!(eval_program("int i = 3; return i - -1;").unwrap(), 4);
+    assert_eq!(
+        eval_program("int i = 3; int j = i++ - 1; return j * 10 + i;").unwrap(),
+        24
+    );
+    assert_eq!(
+        eval_program("int x = 5; int y = x++ + 1; return y * 10 + x;").unwrap(),
+        66
+    );
+    assert_eq!(
+        eval_program("int i = 3; int k = --i + i--; return k * 10 + i;").unwrap(),
+        41
+    );
+    assert!(eval_program("int a[2]; return ++a[0];").is_err());
+    assert_eq!(eval_program("int i = 3; return --3 + i;").unwrap(), 6);
+}
+
+#[test]
+fn loops() {
+    assert_eq!(
+        eval_program("int s = 0; for (int i = 0; i < 5; i++) { s += i; } return s;").unwrap(),
+        10
+    );
+    assert_eq!(
+        eval_program(
+            "int s = 0; int i = 0; for (;;) { i++; if (i > 3) { break; } s += i; } return s;"
+        )
+        .unwrap(),
+        6
+    );
+    assert_eq!(
+        eval_program("int i = 10; do { i++; } while (i < 5); return i;").unwrap(),
+        11
+    );
+    assert_eq!(
+        eval_program("int i = 0; do i += 2; while (i < 5); return i;").unwrap(),
+        6
+    );
+}
+
+#[test]
+fn break_continue() {
+    assert_eq!(
+        eval_program(
+            "int s = 0; for (int i = 0; i < 10; i++) { if (i % 2 == 0) { continue; } s += i; } return s;"
+        )
+        .unwrap(),
+        25
+    );
+    assert_eq!(
+        eval_program("int i = 0; while (true) { i++; if (i == 7) break; } return i;").unwrap(),
+        7
+    );
+    assert_eq!(
+        eval_program("int i = 0; do { i++; continue; i = 100; } while (i < 3); return i;").unwrap(),
+        3
+    );
+    assert_eq!(
+        eval_program("int x = 0; break;").unwrap_err().to_string(),
+        "'break' outside of a loop @ 1:12"
+    );
+    assert!(eval_program("if (true) { continue; }").is_err());
+}
+
+#[test]
+fn nested_return() {
+    assert_eq!(
+        eval_program("int i = 0; while (true) { i++; if (i == 5) { return i * 2; } } return 0;")
+            .unwrap(),
+        10
+    );
+    assert_eq!(
+        eval_program("for (int i = 0; i < 10; i++) { if (i > 2) return i; } return -1;").unwrap(),
+        3
+    );
+    assert_eq!(
+        eval_program("if (false) { return 1; } else { return 2; }").unwrap(),
+        2
+    );
+}
+
+#[test]
+fn typed_declarations() {
+    assert_eq!(eval_program("bool b = 1 < 2; return b;").unwrap(), 1);
+    assert_eq!(
+        eval_program("string s = \"a\"; if (s == \"a\") { return 1; } return 0;").unwrap(),
+        1
+    );
+}
+
+#[test]
+fn functions() {
+    assert_eq!(
+        eval_program(
+            "int fib(int n) { if (n < 2) { return n; } return fib(n - 1) + fib(n - 2); }
+            int main() { return fib(10); }"
+        )
+        .unwrap(),
+        55
+    );
+    assert_eq!(
+        eval_program(
+            "int find(int limit) { int i = 0; while (true) { if (i * i > limit) { return i; } i++; } }
+            int main() { return find(50); }"
+        )
+        .unwrap(),
+        8
+    );
+    assert_eq!(
+        eval_program(
+            "void check(int n) { if (n > 0) { return; } println(n); }
+            int main() { check(1); return 7; }"
+        )
+        .unwrap(),
+        7
+    );
+    assert_eq!(
+        eval_program(
+            "int one() { return 1; }
+            int main() { one(); one(); return one() + 1; }"
+        )
+        .unwrap(),
+        2
+    );
+    assert!(eval_program("int f() { return 1; }").is_err());
+}
+
+#[test]
+fn switch() {
+    let program = |n: i64| {
+        format!(
+            "int r = 0;
+            switch ({}) {{
+                case 1: r = 10; break;
+                case 2: r = 20;
+                case 3: r += 1; break;
+                case -4: return 40;
+                default: r = -1;
+            }}
+            return r;",
+            n
+        )
+    };
+    assert_eq!(eval_program(program(1)).unwrap(), 10);
+    assert_eq!(eval_program(program(2)).unwrap(), 21);
+    assert_eq!(eval_program(program(3)).unwrap(), 1);
+    assert_eq!(eval_program(program(-4)).unwrap(), 40);
+    assert_eq!(eval_program(program(9)).unwrap(), -1);
+
+    assert_eq!(
+        eval_program(
+            "int s = 0;
+            for (int i = 0; i < 6; i++) { switch (i % 3) { case 0: continue; case 1: s += i; } s += 100; }
+            return s;"
+        )
+        .unwrap(),
+        405
+    );
+    assert_eq!(
+        eval_program("switch (1) { case 1: break; case 2: break; case 1: break; }")
+            .unwrap_err()
+            .to_string(),
+        "Duplicate case 1 in switch"
+    );
+    assert!(eval_program("switch (1) { default: break; default: break; }").is_err());
+    assert!(eval_program("int x = 1; switch (1) { case x: break; }").is_err());
+    assert!(eval_program("switch (1) { case 1: continue; }").is_err());
+}
+
+#[test]
+fn checker() {
+    assert_eq!(
+        eval_program("int x = 1; x = \"a\";")
+            .unwrap_err()
+            .to_string(),
+        "Cannot assign String to Number variable x"
+    );
+    assert_eq!(
+        eval_program("return y;").unwrap_err().to_string(),
+        "Variable y used before declaration"
+    );
+    assert_eq!(
+        eval_program("int f(int a) { return a; } int main() { return f(1, 2); }")
+            .unwrap_err()
+            .to_string(),
+        "Function f expects 1 arguments, got 2"
+    );
+    assert!(eval_program("string s = \"a\"; return s + 1;").is_err());
+    assert!(eval_program("void f() { return 1; } int main() { f(); return 0; }").is_err());
+    assert_eq!(eval_program("bool b = 1 < 2; return b;").unwrap(), 1);
+    for name in ["println", "readln", "len", "exit", "argc", "arg"] {
+        assert_eq!(
+            eval_program(format!("int {} = 1;", name))
+                .unwrap_err()
+                .to_string(),
+            format!("Cannot declare variable named {}", name)
+        );
+    }
+    assert_eq!(
+        eval_program("int exit(int n) { return n; } int main() { return exit(1); }")
+            .unwrap_err()
+            .to_string(),
+        "Cannot declare function named exit"
+    );
+}
+
+#[test]
+fn ternary() {
+    assert_eq!(
+        eval_program("int x = 0; int y = x == 0 ? 1 : 10 / x; return y;").unwrap(),
+        1
+    );
+    assert_eq!(
+        eval_program("int i = 0; int y = i > 0 ? i++ : i--; return y * 10 + i;").unwrap(),
+        -1
+    );
+    assert_eq!(
+        eval_program("int y = true ? 1 : \"a\";")
+            .unwrap_err()
+            .to_string(),
+        "Conditional expression arms differ: Number and String"
+    );
+}
+
+#[test]
+fn scopes() {
+    assert_eq!(
+        eval_program("int x = 1; { int x = 2; x += 10; } return x;").unwrap(),
+        1
+    );
+    assert_eq!(
+        eval_program("int x = 1; { int y = x + 1; x = y * 10; } return x;").unwrap(),
+        20
+    );
+    assert_eq!(
+        eval_program(
+            "int s = 0; int i = 0; while (i < 3) { int d = i * 2; s += d; i++; } return s;"
+        )
+        .unwrap(),
+        6
+    );
+    assert_eq!(
+        eval_program("int i = 100; for (int i = 0; i < 3; i++) { int i = 7; } return i;").unwrap(),
+        100
+    );
+    assert_eq!(
+        eval_program("int x = 1; { int x = x + 1; return x; }").unwrap(),
+        2
+    );
+    assert_eq!(
+        eval_program("{ int y = 1; } return y;")
+            .unwrap_err()
+            .to_string(),
+        "Variable y used before declaration"
+    );
+    assert_eq!(
+        eval_program("int x = 1; int x = 2;")
+            .unwrap_err()
+            .to_string(),
+        "Variable x declared more than once in the same scope"
+    );
+}
+
+#[test]
+fn globals_and_constants() {
+    assert_eq!(
+        eval_program(
+            "int counter = 5;
+            void bump() { counter += 1; }
+            int main() { bump(); bump(); return counter; }"
+        )
+        .unwrap(),
+        7
+    );
+    assert_eq!(
+        eval_program(
+            "int x = 1;
+            int f() { return x; }
+            int main() { int x = 10; return x + f(); }"
+        )
+        .unwrap(),
+        11
+    );
+    assert_eq!(
+        eval_program(
+            "const int N = 4;
+            const int M = N * 2 + 1;
+            int main() { int s = 0; for (int i = 0; i < M; i++) s += N; return s; }"
+        )
+        .unwrap(),
+        36
+    );
+    assert_eq!(
+        eval_program("const int A = 2; switch (3) { case A + 1: return 1; } return 0;").unwrap(),
+        1
+    );
+    assert_eq!(
+        eval_program("const int N = 1; { int N = 5; N += 1; return N; }").unwrap(),
+        6
+    );
+    assert_eq!(
+        eval_program("const int N = 1; N = 2;")
+            .unwrap_err()
+            .to_string(),
+        "Cannot assign to constant N"
+    );
+    assert!(eval_program("const int N = 1; N++;").is_err());
+    assert!(eval_program("int x = 1; const int N = x;").is_err());
+    assert!(eval_program("int x = readln(); int main() { return x; }").is_err());
+}
+
+#[test]
+fn arrays() {
+    assert_eq!(
+        eval_program(
+            "int a[4]; a[1] = 5; a[2] += a[1] * 2; a[3]++; return a[0] + a[1] + a[2] + a[3];"
+        )
+        .unwrap(),
+        16
+    );
+    assert_eq!(
+        eval_program(
+            "int a[] = {3, 1, 4, 1, 5};
+            int s = 0;
+            for (int i = 0; i < len(a); i++) s += a[i];
+            return s * 10 + len(a);"
+        )
+        .unwrap(),
+        145
+    );
+    assert_eq!(
+        eval_program("const int N = 3; int a[N * 2] = {7}; return a[0] + a[5] + len(a);").unwrap(),
+        13
+    );
+    assert_eq!(
+        eval_program(
+            "int squares[5];
+            void fill() { for (int i = 0; i < len(squares); i++) squares[i] = i * i; }
+            int main() { fill(); return squares[4]; }"
+        )
+        .unwrap(),
+        16
+    );
+    assert_eq!(
+        eval_program("int x = 1; return x[0];")
+            .unwrap_err()
+            .to_string(),
+        "Variable x is not an array"
+    );
+    assert!(eval_program("int a[2] = {1, 2, 3};").is_err());
+    assert!(eval_program("int a[2]; int b[2]; a = b;").is_err());
+    assert!(eval_program("int n = 2; int a[n];").is_err());
+    assert!(eval_program("bool a[2];").is_err());
+    for program in ["int g[3] = {1, 2};", "int g[] = {1};"] {
+        assert_eq!(
+            eval_program(format!("{} int main() {{ return g[0]; }}", program))
+                .unwrap_err()
+                .to_string(),
+            "Global array g cannot be initialized, assign its elements in main"
+        );
+    }
+}
+
+#[test]
+fn array_out_of_bounds() {
+    let error = |program: &str| {
+        eval_program(program)
+            .unwrap_err()
+            .downcast::<RuntimeError>()
+            .unwrap()
+    };
+    assert_eq!(
+        error("int a[3]; int i = 3; return a[i];"),
+        RuntimeError::IndexOutOfBounds(3, "a".to_string(), 3, Position { line: 1, col: 30 })
+    );
+    assert_eq!(
+        error("int a[3];\na[-1] += 2;").to_string(),
+        "Index -1 out of bounds for array a of length 3 @ 2:7"
+    );
+}
+
+#[test]
+fn structs() {
+    assert_eq!(
+        eval_program(
+            "struct Point { int x; int y; }
+            int main() { Point p = {3, 4}; p.x += 10; p.y++; return p.x * 100 + p.y; }"
+        )
+        .unwrap(),
+        1305
+    );
+    assert_eq!(
+        eval_program(
+            "struct Point { int x; int y; };
+            struct Rect { Point min; Point max; }
+            int area(Rect r) { return (r.max.x - r.min.x) * (r.max.y - r.min.y); }
+            int main() { Rect r = {{1, 2}, {4, 6}}; return area(r); }"
+        )
+        .unwrap(),
+        12
+    );
+    assert_eq!(
+        eval_program(
+            "struct Point { int x; int y; }
+            Point make(int x, int y) { Point p = {x, y}; return p; }
+            void move(Point p) { p.x = 100; }
+            int main() { Point p = make(1, 2); Point q = p; q.y = 7; move(p); return p.x * 10 + p.y + q.y; }"
+        )
+        .unwrap(),
+        19
+    );
+    assert_eq!(
+        eval_program(
+            "struct Counter { int n; bool seen; }
+            Counter c;
+            void hit() { c.n += 1; c.seen = true; }
+            int main() { hit(); hit(); return c.n + c.seen; }"
+        )
+        .unwrap(),
+        3
+    );
+    assert_eq!(
+        eval_program("struct P { int x; } int main() { P p = {1}; return p.z; }")
+            .unwrap_err()
+            .to_string(),
+        "Struct P has no field z"
+    );
+    assert!(eval_program("struct P { int x; } int main() { P p = {\"a\"}; return 0; }").is_err());
+    assert!(eval_program("struct P { int x; } int main() { P p = {1, 2}; return 0; }").is_err());
+    assert!(eval_program("struct P { int x; } int main() { P p; int y = p; return y; }").is_err());
+    assert!(eval_program("struct P { int x; int x; } int main() { return 0; }").is_err());
+}
+
+#[test]
+fn enums() {
+    assert_eq!(
+        eval_program(
+            "enum Color { Red, Green = 5, Blue }
+            int code(Color c) {
+                switch (c) {
+                    case Color::Red: return 1;
+                    case Color::Green: return 2;
+                    case Color::Blue: return 3;
+                }
+                return 0;
+            }
+            int main() { Color c = Color::Blue; int n = c; return code(c) * 100 + n; }"
+        )
+        .unwrap(),
+        306
+    );
+    assert_eq!(
+        eval_program(
+            "enum State { Idle, Running, Done };
+            const State START = State::Running;
+            struct Task { State state; int steps; }
+            State step(State s) { return s == State::Running ? State::Done : s; }
+            int main() {
+                Task t = {START, 0};
+                while (t.state != State::Done) { t.state = step(t.state); t.steps++; }
+                switch (t.state) { case State::Done: break; default: return -1; }
+                int n = t.state;
+                return n * 10 + t.steps;
+            }"
+        )
+        .unwrap(),
+        21
+    );
+    // Enums declared without an initializer start as their first variant
+    assert_eq!(
+        eval_program(
+            "enum Color { Red = 2, Green } struct S { int n; Color c; } Color g; S h;
+            int main() { Color c; S s; int a = c; int b = g; int d = s.c; int e = h.c; return a * 1000 + b * 100 + d * 10 + e; }"
+        )
+        .unwrap(),
+        2222
+    );
+    assert_eq!(
+        eval_program(
+            "enum Color { Red, Green, Blue }
+            int main() { Color c = Color::Red; switch (c) { case Color::Red: return 1; } return 0; }"
+        )
+        .unwrap_err()
+        .to_string(),
+        "Switch on Color does not handle Color::Green, Color::Blue"
+    );
+    assert_eq!(
+        eval_program("enum E { A, B } int main() { return E::C; }")
+            .unwrap_err()
+            .to_string(),
+        "Enum E has no variant C"
+    );
+    assert!(eval_program("enum E { A, B } int main() { E e = 1; return 0; }").is_err());
+    assert!(eval_program("enum E { A, B } int main() { return E::A + 1; }").is_err());
+    assert!(eval_program("enum E { A } enum F { B } int main() { return E::A == F::B; }").is_err());
+    assert!(eval_program("enum E { A = 1, B = 1 } int main() { return 0; }").is_err());
+    assert!(eval_program(
+        "enum E { A, B } int main() { E e = E::A; switch (e) { case 5: return 1; default: return 0; } }"
+    )
+    .is_err());
+}
+
+#[test]
+fn limits() {
+    let run = |program: &str, limits: Limits| {
+        interpret_program(program, vec![], limits, Box::new(Stdio))
+            .unwrap_err()
+            .downcast::<RuntimeError>()
+            .unwrap()
+    };
+
+    let steps = Limits {
+        steps: Some(100),
+        ..Limits::default()
+    };
+    assert_eq!(
+        run("while (true) {}", steps.clone()),
+        RuntimeError::Steps(100)
+    );
+    assert_eq!(run("for (;;) {}", steps.clone()), RuntimeError::Steps(100));
+    assert_eq!(
+        interpret_program(
+            "int i = 0; while (i < 10) { i++; } return i;",
+            vec![],
+            steps,
+            Box::new(Stdio)
+        )
+        .unwrap(),
+        10
+    );
+
+    let depth = Limits {
+        call_depth: 10,
+        ..Limits::default()
+    };
+    let program = "int f(int n) { if (n == 0) { return 0; } return f(n - 1) + 1; }
+        int main() { return f(N); }";
+    assert_eq!(
+        interpret_program(
+            program.replace('N', "8"),
+            vec![],
+            depth.clone(),
+            Box::new(Stdio)
+        )
+        .unwrap(),
+        8
+    );
+    assert_eq!(
+        run(&program.replace('N', "9"), depth),
+        RuntimeError::CallDepth(10)
+    );
+
+    // Strings are freed with the scopes holding them
+    let strings = Limits {
+        string_bytes: Some(8),
+        ..Limits::default()
+    };
+    let program = "int i = 0; while (i < 10) { string s = \"abcd\"; string t = s; i++; } return i;";
+    assert_eq!(
+        interpret_program(program, vec![], strings.clone(), Box::new(Stdio)).unwrap(),
+        10
+    );
+    assert_eq!(
+        run("string s = \"abcd\"; s = \"abcdefghi\"; return 0;", strings),
+        RuntimeError::StringBytes(8)
+    );
+
+    let deterministic = Limits {
+        deterministic: true,
+        ..Limits::default()
+    };
+    assert_eq!(run("return readln();", deterministic), RuntimeError::Input);
+}
+
+#[test]
+fn console() {
+    let program = "int a = readln(); int b = readln(); println(\"sum\"); println(a + b); return a;";
+    let mut output = vec![];
+    let console = Streams::new("3\n 4 \n".as_bytes(), &mut output);
+    assert_eq!(
+        interpret_program(program, vec![], Limits::default(), Box::new(console)).unwrap(),
+        3
+    );
+    assert_eq!(String::from_utf8(output).unwrap(), "sum\n7\n");
+
+    let run = |input: &'static str| {
+        let console = Streams::new(input.as_bytes(), std::io::sink());
+        interpret_program(program, vec![], Limits::default(), Box::new(console))
+            .unwrap_err()
+            .downcast::<RuntimeError>()
+            .unwrap()
+    };
+    assert_eq!(run("3\nfour\n"), RuntimeError::InvalidInput("four".into()));
+    assert_eq!(run("3\n"), RuntimeError::EndOfInput);
+}
+
+#[test]
+fn arithmetic_faults() {
+    let run = |program: &str, limits: Limits| {
+        interpret_program(program, vec![], limits, Box::new(Stdio))
+            .unwrap_err()
+            .downcast::<RuntimeError>()
+            .unwrap()
+    };
+    let at = |line, col| Position { line, col };
+
+    let program = "int main() {\n    int zero = 0;\n    int a = 7 / 2;\n    return a % zero;\n}";
+    assert_eq!(
+        run(program, Limits::default()),
+        RuntimeError::DivisionByZero(at(4, 14))
+    );
+    assert_eq!(
+        run("int a = 1; a /= 0; return a;", Limits::default()),
+        RuntimeError::DivisionByZero(at(1, 14))
+    );
+
+    // Overflow wraps around unless checked
+    let program =
+        "int a = 9223372036854775807; int m = -a - 1; return (m / -1 == m) + (a + 1 == m) * 10;";
+    assert_eq!(eval_program(program).unwrap(), 11);
+    let checked = Limits {
+        overflow_checks: true,
+        ..Limits::default()
+    };
+    assert_eq!(
+        run(program, checked.clone()),
+        RuntimeError::Overflow(at(1, 56))
+    );
+    assert_eq!(
+        run(
+            "int a = 9223372036854775807; a++; return a;",
+            checked.clone()
+        ),
+        RuntimeError::Overflow(at(1, 31))
+    );
+    assert_eq!(
+        interpret_program(
+            "int a = 9223372036854775806; a++; return a;",
+            vec![],
+            checked,
+            Box::new(Stdio)
+        )
+        .unwrap(),
+        9223372036854775807
+    );
+}
+
+#[test]
+fn exit_status() {
+    let program = "int f(int x) { if (x > 5) { exit(x * 2); } return x; }
+        int main() { int a = f(3); f(21); return a; }";
+    assert_eq!(eval_program(program).unwrap(), 42);
+    assert_eq!(eval_program("void main() { return; }").unwrap(), 0);
+    assert_eq!(eval_program("bool main() { return true; }").unwrap(), 1);
+    assert!(eval_program("string main() { return \"a\"; }").is_err());
+    assert!(eval_program("int main() { exit(\"a\"); return 1; }").is_err());
+}
+
+#[test]
+fn program_arguments() {
+    let run = |program: &str, args: &[&str]| {
+        let args = args.iter().map(|arg| arg.to_string()).collect();
+        interpret_program(program, args, Limits::default(), Box::new(Stdio))
+    };
+    let program =
+        "int main() { string s = arg(argc() - 1); if (s == \"b c\") { return argc(); } return 0; }";
+    assert_eq!(run(program, &["prog", "a", "b c"]).unwrap(), 3);
+    assert_eq!(run(program, &["prog", "a"]).unwrap(), 0);
+    assert_eq!(
+        run("int main() { println(arg(2)); return 0; }", &["prog"])
+            .unwrap_err()
+            .downcast::<RuntimeError>()
+            .unwrap(),
+        RuntimeError::Argument(2, 1)
+    );
+    assert!(eval_program("int main() { return arg(\"a\"); }").is_err());
+}
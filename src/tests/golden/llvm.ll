@@ -0,0 +1,69 @@
+
+%struct.P = type { i64, i1 }
+
+@.str.0 = private unnamed_addr constant [4 x i8] c"two\00"
+@g = internal global i64 2
+
+define internal i64 @func_main() {
+entry:
+  %p = alloca %struct.P
+  %s = alloca i64
+  %i = alloca i64
+  %t.1 = load i64, i64* @g
+  %t.2 = insertvalue %struct.P zeroinitializer, i64 %t.1, 0
+  %t.3 = insertvalue %struct.P %t.2, i1 true, 1
+  store %struct.P %t.3, %struct.P* %p
+  store i64 0, i64* %s
+  store i64 0, i64* %i
+  br label %for.1
+for.1:
+  %t.4 = load i64, i64* %i
+  %t.5 = icmp slt i64 %t.4, 3
+  br i1 %t.5, label %for.body.1, label %for.end.1
+for.body.1:
+  %t.6 = getelementptr inbounds %struct.P, %struct.P* %p, i32 0, i32 1
+  %t.7 = load i1, i1* %t.6
+  br i1 %t.7, label %and.rhs.3, label %and.end.3
+and.rhs.3:
+  %t.8 = load i64, i64* %i
+  %t.9 = icmp ne i64 %t.8, 1
+  br label %and.end.3
+and.end.3:
+  %t.10 = phi i1 [ false, %for.body.1 ], [ %t.9, %and.rhs.3 ]
+  br i1 %t.10, label %if.then.2, label %if.end.2
+if.then.2:
+  %t.11 = load i64, i64* %i
+  %t.12 = load i64, i64* %s
+  %t.13 = add i64 %t.12, %t.11
+  store i64 %t.13, i64* %s
+  br label %if.end.2
+if.end.2:
+  br label %for.step.1
+for.step.1:
+  %t.14 = load i64, i64* %i
+  %t.15 = add i64 %t.14, 1
+  store i64 %t.15, i64* %i
+  br label %for.1
+for.end.1:
+  %t.16 = load i64, i64* %s
+  switch i64 %t.16, label %switch.4.case.1 [i64 2, label %switch.4.case.0]
+switch.4.case.0:
+  call void @logik_println_string(i8* getelementptr inbounds ([4 x i8], [4 x i8]* @.str.0, i64 0, i64 0))
+  br label %switch.4.case.1
+switch.4.case.1:
+  %t.17 = load i64, i64* %s
+  call void @logik_println_int(i64 %t.17)
+  br label %switch.end.4
+switch.end.4:
+  %t.18 = load i64, i64* %s
+  ret i64 %t.18
+}
+
+define i32 @main(i32 %argc, i8** %argv) {
+  %count = sext i32 %argc to i64
+  store i64 %count, i64* @logik_argc
+  store i8** %argv, i8*** @logik_argv
+  %result = call i64 @func_main()
+  %status = trunc i64 %result to i32
+  ret i32 %status
+}
@@ -1,2 +1,8 @@
 #[cfg(test)]
+mod assemble;
+#[cfg(test)]
+mod debugger;
+#[cfg(test)]
 mod eval_expression;
+#[cfg(test)]
+mod eval_program;
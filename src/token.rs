@@ -11,24 +11,43 @@ pub enum TokenKind {
     Number(Number),
     CondOp(CondOp),
     Assign,
+    OpAssign(Op),
+    /// `++` or `--`, holding `Add` or `Sub`
+    IncDec(Op),
     Op(Op),
     ParenthesisOpen,
     ParenthesisClose,
     BracketOpen,
     BracketClose,
+    SquareBracketOpen,
+    SquareBracketClose,
     Identifier(String),
     String(String),
     If,
     Else,
     While,
+    For,
+    Do,
+    Break,
+    Continue,
     TypeNumber,
     TypeBool,
     TypeString,
+    TypeVoid,
     Return,
+    Const,
+    Struct,
+    Enum,
     True,
     False,
     SemiColon,
+    Colon,
+    Question,
     Comma,
+    Dot,
+    Switch,
+    Case,
+    Default,
     EOF,
 }
 
@@ -41,23 +60,41 @@ impl fmt::Display for TokenKind {
             match self {
                 Number(n) => n.to_string(),
                 Op(op) => op.to_string(),
+                OpAssign(op) => format!("{}=", op),
+                IncDec(op) => format!("{}{}", op, op),
                 CondOp(cop) => cop.to_string(),
                 TypeNumber => "int".to_string(),
                 TypeBool => "bool".to_string(),
                 TypeString => "string".to_string(),
+                TypeVoid => "void".to_string(),
                 Return => "return".to_string(),
+                Const => "const".to_string(),
+                Struct => "struct".to_string(),
+                Enum => "enum".to_string(),
                 String(s) => format!("\"{}\"", s.clone()),
                 ParenthesisOpen => "(".into(),
                 ParenthesisClose => ")".into(),
                 BracketOpen => "{".into(),
                 BracketClose => "}".into(),
+                SquareBracketOpen => "[".into(),
+                SquareBracketClose => "]".into(),
                 Identifier(v) => v.into(),
                 Assign => "=".into(),
                 If => "If".into(),
                 Else => "Else".into(),
                 While => "While".into(),
+                For => "For".into(),
+                Do => "Do".into(),
+                Break => "break".into(),
+                Continue => "continue".into(),
                 SemiColon => ";".into(),
+                Colon => ":".into(),
+                Question => "?".into(),
+                Switch => "switch".into(),
+                Case => "case".into(),
+                Default => "default".into(),
                 Comma => ",".into(),
+                Dot => ".".into(),
                 EOF => "EOF".into(),
                 True => "true".into(),
                 False => "false".into(),
@@ -81,6 +118,13 @@ impl Token {
             kind: token_type,
         }
     }
+
+    pub fn position(&self) -> Position {
+        Position {
+            line: self.line,
+            col: self.col,
+        }
+    }
 }
 
 impl fmt::Display for Token {
@@ -89,6 +133,20 @@ impl fmt::Display for Token {
     }
 }
 
+/// Where a token is in the source, which errors found while running the program report. Lines
+/// and columns both count from 1
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Position {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 struct PreToken {
     line: usize,
@@ -110,17 +168,24 @@ enum TokenizerState {
     Comment,
     Parenthesis,
     Bracket,
+    SquareBracket,
     Text,
     String,
     SemiColon,
+    Colon,
+    Question,
     Comma,
+    Dot,
 }
 
-impl Into<TokenKind> for TokenizerState {
-    fn into(self) -> TokenKind {
-        match self {
+impl From<TokenizerState> for TokenKind {
+    fn from(state: TokenizerState) -> Self {
+        match state {
             TokenizerState::SemiColon => TokenKind::SemiColon,
+            TokenizerState::Colon => TokenKind::Colon,
+            TokenizerState::Question => TokenKind::Question,
             TokenizerState::Comma => TokenKind::Comma,
+            TokenizerState::Dot => TokenKind::Dot,
             _ => unreachable!(),
         }
     }
@@ -141,7 +206,10 @@ pub fn tokenize(input: String) -> Result<Vec<Token>> {
         let prev_state = *state;
         *state = match c {
             ';' => TokenizerState::SemiColon,
+            ':' => TokenizerState::Colon,
+            '?' => TokenizerState::Question,
             ',' => TokenizerState::Comma,
+            '.' => TokenizerState::Dot,
             c if c.is_numeric() => TokenizerState::Number,
 
             // Needs to start with alphabetic char or _ but can contain numbers after
@@ -150,13 +218,14 @@ pub fn tokenize(input: String) -> Result<Vec<Token>> {
             c if is_operator_char(c) => TokenizerState::Op,
             c if (c == '(' || c == ')') => TokenizerState::Parenthesis,
             c if (c == '{' || c == '}') => TokenizerState::Bracket,
-            c if (c == '"') => TokenizerState::String,
+            c if (c == '[' || c == ']') => TokenizerState::SquareBracket,
+            '"' => TokenizerState::String,
             _ => bail!("Unparsable char '{}'", c),
         };
         Ok(prev_state != *state)
     };
 
-    let mut line = 0;
+    let mut line = 1;
     let mut col = 0;
 
     for c in input.chars().chain(std::iter::once('\0')) {
@@ -212,10 +281,8 @@ pub fn tokenize(input: String) -> Result<Vec<Token>> {
                 },
 
                 TokenizerState::Text => {
-                    if determine_state(&mut state, c)? {
-                        if state == TokenizerState::Number {
-                            state = TokenizerState::Text;
-                        }
+                    if determine_state(&mut state, c)? && state == TokenizerState::Number {
+                        state = TokenizerState::Text;
                     }
                 }
 
@@ -238,7 +305,7 @@ pub fn tokenize(input: String) -> Result<Vec<Token>> {
                 }
 
                 TokenizerState::Op => {
-                    let mut it = buffer.iter().peekable().into_iter();
+                    let mut it = buffer.iter().peekable();
 
                     let mut skip_next = false;
                     while let Some(tk) = it.next() {
@@ -254,6 +321,34 @@ pub fn tokenize(input: String) -> Result<Vec<Token>> {
                                 continue;
                             }
 
+                            if tk.c == ntk.c && (tk.c == '+' || tk.c == '-') {
+                                skip_next = true;
+                                let op = Op::from_char(tk.c).unwrap();
+                                tokens.push(Token::new(tk.line, tk.col, TokenKind::IncDec(op)));
+                                continue;
+                            }
+
+                            if let Some(op) = Op::from_chars(tk.c, ntk.c) {
+                                skip_next = true;
+                                tokens.push(Token::new(tk.line, tk.col, TokenKind::Op(op)));
+                                continue;
+                            }
+
+                            if ntk.c == '=' {
+                                if let Some(
+                                    op @ (Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Mod),
+                                ) = Op::from_char(tk.c)
+                                {
+                                    skip_next = true;
+                                    tokens.push(Token::new(
+                                        tk.line,
+                                        tk.col,
+                                        TokenKind::OpAssign(op),
+                                    ));
+                                    continue;
+                                }
+                            }
+
                             // if let Some(bop) = BoolOp::from_chars(tk.c, ntk.c) {
                             //     skip_next = true;
                             //     tokens.push(Token::new(tk.line, tk.col, TokenKind::BoolOp(bop)));
@@ -317,8 +412,19 @@ pub fn tokenize(input: String) -> Result<Vec<Token>> {
                     }
                 }
 
+                TokenizerState::SquareBracket => {
+                    for tk in &buffer {
+                        let square_bracket = match tk.c {
+                            '[' => TokenKind::SquareBracketOpen,
+                            ']' => TokenKind::SquareBracketClose,
+                            _ => unreachable!(),
+                        };
+                        tokens.push(Token::new(tk.line, tk.col, square_bracket));
+                    }
+                }
+
                 TokenizerState::Number => {
-                    if buffer.len() != 0 {
+                    if !buffer.is_empty() {
                         let s = buffer.iter().map(|tk| tk.c).collect::<String>();
                         let tk = buffer[0];
                         match s.parse::<Number>() {
@@ -333,18 +439,29 @@ pub fn tokenize(input: String) -> Result<Vec<Token>> {
                 }
 
                 TokenizerState::Text => {
-                    if buffer.len() != 0 {
+                    if !buffer.is_empty() {
                         let s = buffer.iter().map(|tk| tk.c).collect::<String>();
                         let tkind = match s.as_str() {
                             "if" => TokenKind::If,
                             "else" => TokenKind::Else,
                             "while" => TokenKind::While,
+                            "for" => TokenKind::For,
+                            "do" => TokenKind::Do,
+                            "break" => TokenKind::Break,
+                            "continue" => TokenKind::Continue,
+                            "switch" => TokenKind::Switch,
+                            "case" => TokenKind::Case,
+                            "default" => TokenKind::Default,
                             "int" => TokenKind::TypeNumber,
                             "bool" => TokenKind::TypeBool,
                             "string" => TokenKind::TypeString,
+                            "void" => TokenKind::TypeVoid,
                             "true" => TokenKind::True,
                             "false" => TokenKind::False,
                             "return" => TokenKind::Return,
+                            "const" => TokenKind::Const,
+                            "struct" => TokenKind::Struct,
+                            "enum" => TokenKind::Enum,
                             _ => TokenKind::Identifier(s),
                         };
                         let tk = buffer[0];
@@ -352,7 +469,11 @@ pub fn tokenize(input: String) -> Result<Vec<Token>> {
                     }
                 }
 
-                TokenizerState::SemiColon | TokenizerState::Comma => {
+                TokenizerState::SemiColon
+                | TokenizerState::Colon
+                | TokenizerState::Question
+                | TokenizerState::Comma
+                | TokenizerState::Dot => {
                     for tk in &buffer {
                         tokens.push(Token::new(tk.line, tk.col, prev_state.into()))
                     }
@@ -1,7 +1,313 @@
+use crate::console::{Console, Stdio};
+use crate::debugger::Debugger;
+use crate::error::RuntimeError;
 use crate::token::{Number, TokenKind};
 use std::cmp::{Ordering, PartialEq, PartialOrd};
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
 
-#[derive(Clone, Debug, Hash, PartialEq, PartialOrd)]
+use color_eyre::eyre::Result;
+
+/// Stack of lexical scopes, the innermost one being the last
+#[derive(Debug, Clone)]
+pub struct Scopes<T> {
+    frames: Vec<HashMap<String, T>>,
+    /// First frame of the function being executed, frames below it other than the globals are hidden
+    base: usize,
+}
+
+impl<T> Scopes<T> {
+    pub fn new() -> Self {
+        Self {
+            frames: vec![HashMap::new()],
+            base: 0,
+        }
+    }
+
+    /// Starts a function call which only sees the outermost (global) scope, returning what
+    /// `leave_call` needs to restore the caller
+    pub fn enter_call(&mut self) -> usize {
+        let base = self.base;
+        self.frames.push(HashMap::new());
+        self.base = self.frames.len() - 1;
+        base
+    }
+
+    pub fn leave_call(&mut self, base: usize) {
+        self.frames.truncate(self.base);
+        self.base = base;
+    }
+
+    pub fn push_scope(&mut self) {
+        self.frames.push(HashMap::new());
+    }
+
+    pub fn pop_scope(&mut self) {
+        self.frames.pop();
+    }
+
+    /// Declares `name` in the innermost scope, returning false if it was already declared there
+    pub fn declare(&mut self, name: String, value: T) -> bool {
+        let frame = self.frames.last_mut().unwrap();
+        if frame.contains_key(&name) {
+            return false;
+        }
+        frame.insert(name, value);
+        true
+    }
+
+    pub fn get(&self, name: &str) -> Option<&T> {
+        let (outer, inner) = self.frames.split_at(self.base);
+        inner
+            .iter()
+            .rev()
+            .chain(outer.first())
+            .find_map(|frame| frame.get(name))
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut T> {
+        let (outer, inner) = self.frames.split_at_mut(self.base);
+        inner
+            .iter_mut()
+            .rev()
+            .chain(outer.first_mut())
+            .find_map(|frame| frame.get_mut(name))
+    }
+
+    /// Every visible name with its value, sorted by name, those shadowed being left out
+    pub fn visible(&self) -> Vec<(&str, &T)> {
+        let (outer, inner) = self.frames.split_at(self.base);
+        let mut visible: Vec<(&str, &T)> = vec![];
+        for frame in inner.iter().rev().chain(outer.first()) {
+            for (name, value) in frame.iter() {
+                if !visible.iter().any(|(visible, _)| visible == name) {
+                    visible.push((name, value));
+                }
+            }
+        }
+        visible.sort_by_key(|(name, _)| *name);
+        visible
+    }
+}
+
+impl<T> Default for Scopes<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Calls a program may nest unless told otherwise, the interpreter using the stack of the host for
+/// each of them
+pub const DEFAULT_CALL_DEPTH: usize = 1000;
+
+/// Bounds on the resources a program run by the interpreter may use, `None` meaning unbounded
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// Statements executed, along with the iterations of loops
+    pub steps: Option<usize>,
+    /// Calls in progress at once, the one of `main` included. Always bounded, since deeper
+    /// recursion would overflow the stack of the host
+    pub call_depth: usize,
+    /// Bytes taken by the strings held in variables
+    pub string_bytes: Option<usize>,
+    /// Forbids reading the input, so that what a run does only depends on the program
+    pub deterministic: bool,
+    /// Aborts the program when a signed operation overflows, instead of wrapping around
+    pub overflow_checks: bool,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            steps: None,
+            call_depth: DEFAULT_CALL_DEPTH,
+            string_bytes: None,
+            deterministic: false,
+            overflow_checks: false,
+        }
+    }
+}
+
+/// Bytes taken by the strings of a value, those in the fields of structs included
+pub fn string_bytes(data: Option<&VariableData>) -> usize {
+    match data {
+        Some(VariableData::String(s)) => s.len(),
+        Some(VariableData::Struct(fields)) => fields.iter().map(|f| string_bytes(Some(f))).sum(),
+        _ => 0,
+    }
+}
+
+/// Bytes taken by the strings of the variables of a scope
+fn scope_string_bytes(scope: &HashMap<String, Variable>) -> usize {
+    scope
+        .values()
+        .map(|variable| string_bytes(variable.data.as_ref()))
+        .sum()
+}
+
+/// State of the interpreter: the variables visible to it, the resources it used, the console of
+/// the program and the debugger stepping through it
+pub struct Environment<'a> {
+    scopes: Scopes<Variable>,
+    console: Box<dyn Console + 'a>,
+    debugger: Option<&'a mut dyn Debugger>,
+    limits: Limits,
+    steps: usize,
+    depth: usize,
+    strings: usize,
+    /// Arguments of the program, its own name first
+    args: Vec<String>,
+}
+
+impl<'a> Environment<'a> {
+    pub fn new() -> Self {
+        Self {
+            scopes: Scopes::new(),
+            console: Box::new(Stdio),
+            debugger: None,
+            limits: Limits::default(),
+            steps: 0,
+            depth: 0,
+            strings: 0,
+            args: vec![],
+        }
+    }
+
+    pub fn with_debugger(debugger: &'a mut dyn Debugger) -> Self {
+        Self {
+            debugger: Some(debugger),
+            ..Self::new()
+        }
+    }
+
+    pub fn console(&mut self) -> &mut (dyn Console + 'a) {
+        self.console.as_mut()
+    }
+
+    pub fn set_console(&mut self, console: Box<dyn Console + 'a>) {
+        self.console = console;
+    }
+
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.args = args;
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn scopes(&self) -> &Scopes<Variable> {
+        &self.scopes
+    }
+
+    /// Starts a function call, failing when too many are already in progress
+    pub fn enter_call(&mut self) -> Result<usize> {
+        self.depth += 1;
+        if self.depth > self.limits.call_depth {
+            return Err(RuntimeError::CallDepth(self.limits.call_depth).into());
+        }
+        Ok(self.scopes.enter_call())
+    }
+
+    pub fn leave_call(&mut self, base: usize) {
+        let freed: usize = self.scopes.frames[self.scopes.base..]
+            .iter()
+            .map(scope_string_bytes)
+            .sum();
+        self.strings -= freed;
+        self.depth -= 1;
+        self.scopes.leave_call(base)
+    }
+
+    pub fn push_scope(&mut self) {
+        self.scopes.push_scope()
+    }
+
+    pub fn pop_scope(&mut self) {
+        if let Some(scope) = self.scopes.frames.last() {
+            self.strings -= scope_string_bytes(scope);
+        }
+        self.scopes.pop_scope()
+    }
+
+    /// Declares `name` in the innermost scope, returning false if it was already declared there
+    pub fn declare(&mut self, name: String, value: Variable) -> Result<bool> {
+        let bytes = string_bytes(value.data.as_ref());
+        if !self.scopes.declare(name, value) {
+            return Ok(false);
+        }
+        self.resize_strings(0, bytes)?;
+        Ok(true)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Variable> {
+        self.scopes.get(name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Variable> {
+        self.scopes.get_mut(name)
+    }
+
+    /// Accounts for the strings of a variable going from `before` to `after` bytes
+    pub fn resize_strings(&mut self, before: usize, after: usize) -> Result<()> {
+        self.strings = self.strings - before + after;
+        match self.limits.string_bytes {
+            Some(limit) if self.strings > limit => Err(RuntimeError::StringBytes(limit).into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Counts a statement or an iteration of a loop, failing once too many were executed
+    pub fn step(&mut self) -> Result<()> {
+        self.steps += 1;
+        match self.limits.steps {
+            Some(limit) if self.steps > limit => Err(RuntimeError::Steps(limit).into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Counts the statement starting on `line` and lets the debugger stop before it. The debugger
+    /// sees the environment without itself, so that the expressions it evaluates are not debugged
+    pub fn statement(&mut self, line: usize) -> Result<()> {
+        self.step()?;
+        if let Some(debugger) = self.debugger.take() {
+            debugger.statement(line, self);
+            self.debugger = Some(debugger);
+        }
+        Ok(())
+    }
+
+    /// Tells the debugger the function `name` was called, its arguments being declared
+    pub fn call(&mut self, name: &str) {
+        if let Some(debugger) = self.debugger.as_mut() {
+            debugger.call(name);
+        }
+    }
+
+    /// Tells the debugger the function being executed returned `value` of the given kind
+    pub fn ret(&mut self, kind: &VariableKind, value: &VariableData) {
+        if let Some(debugger) = self.debugger.as_mut() {
+            debugger.ret(kind, value);
+        }
+    }
+}
+
+impl Default for Environment<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, PartialOrd)]
 pub struct Variable {
     pub kind: VariableKind,
     pub data: Option<VariableData>,
@@ -12,16 +318,16 @@ impl Variable {
         Self { kind, data }
     }
 
-    pub fn match_data_kind(data: VariableData, kind: VariableKind) -> VariableData {
+    pub fn match_data_kind(data: VariableData, kind: &VariableKind) -> VariableData {
         match data {
             VariableData::String(_) => {
-                assert_eq!(kind, VariableKind::String);
+                assert_eq!(*kind, VariableKind::String);
                 data
             }
             VariableData::Number(n) => {
-                if kind == VariableKind::Number {
+                if matches!(kind, VariableKind::Number | VariableKind::Enum(_)) {
                     data
-                } else if kind == VariableKind::Bool {
+                } else if *kind == VariableKind::Bool {
                     VariableData::Bool(n != 0)
                 } else {
                     panic!()
@@ -29,34 +335,163 @@ impl Variable {
             }
 
             VariableData::Bool(b) => {
-                if kind == VariableKind::Number {
+                if *kind == VariableKind::Number {
                     VariableData::Number(b as Number)
-                } else if kind == VariableKind::Bool {
+                } else if *kind == VariableKind::Bool {
                     data
                 } else {
                     panic!()
                 }
             }
+            VariableData::Array(_) => {
+                assert!(matches!(kind, VariableKind::Array(_)));
+                data
+            }
+            VariableData::Struct(_) => {
+                assert!(matches!(kind, VariableKind::Struct(_)));
+                data
+            }
             VariableData::None => {
-                assert_eq!(kind, VariableKind::None);
+                assert_eq!(*kind, VariableKind::None);
                 data
             }
         }
     }
 }
 
-#[derive(Copy, Clone, Debug, Hash, PartialEq, PartialOrd)]
+#[derive(Clone, Debug, Hash, PartialEq, PartialOrd)]
 pub enum VariableKind {
     String,
     Number,
     Bool,
+    /// Array of ints of the given length
+    Array(usize),
+    Struct(Rc<StructType>),
+    /// Stored as the int value of the variant
+    Enum(Rc<EnumType>),
     None,
 }
 
+impl VariableKind {
+    /// Number of 4 byte words taken by a variable of this kind in the compiled program
+    pub fn words(&self) -> usize {
+        match self {
+            VariableKind::Array(len) => *len,
+            VariableKind::Struct(t) => t.words(),
+            _ => 1,
+        }
+    }
+
+    /// Value of a variable declared without an initializer, None if reading it is an error
+    pub fn zeroed(&self) -> Option<VariableData> {
+        match self {
+            VariableKind::Array(len) => Some(VariableData::Array(vec![0; *len])),
+            VariableKind::Enum(t) => Some(VariableData::Number(t.first())),
+            VariableKind::Struct(t) => Some(VariableData::Struct(
+                t.fields
+                    .iter()
+                    .map(|(_, kind)| match kind {
+                        VariableKind::Number => VariableData::Number(0),
+                        VariableKind::Bool => VariableData::Bool(false),
+                        VariableKind::String => VariableData::String(String::new()),
+                        kind => kind.zeroed().unwrap(),
+                    })
+                    .collect(),
+            )),
+            _ => None,
+        }
+    }
+
+    /// Words of a variable declared without an initializer that are not zero, with their offsets:
+    /// those of enums, which start as their first variant in every target
+    pub fn initial_words(&self) -> Vec<(usize, Number)> {
+        match self {
+            VariableKind::Enum(t) if t.first() != 0 => vec![(0, t.first())],
+            VariableKind::Struct(t) => {
+                let mut words = vec![];
+                let mut offset = 0;
+                for (_, kind) in t.fields.iter() {
+                    let field = kind.initial_words().into_iter();
+                    words.extend(field.map(|(word, value)| (offset + word, value)));
+                    offset += kind.words();
+                }
+                words
+            }
+            _ => vec![],
+        }
+    }
+}
+
+/// Fields of a user defined struct, laid out one after the other in declaration order
+#[derive(Hash, PartialEq, PartialOrd)]
+pub struct StructType {
+    pub name: String,
+    pub fields: Vec<(String, VariableKind)>,
+}
+
+impl StructType {
+    pub fn words(&self) -> usize {
+        self.fields.iter().map(|(_, kind)| kind.words()).sum()
+    }
+
+    /// Index, offset in words and kind of a field
+    pub fn field(&self, name: &str) -> Option<(usize, usize, &VariableKind)> {
+        let mut offset = 0;
+        for (idx, (field, kind)) in self.fields.iter().enumerate() {
+            if field == name {
+                return Some((idx, offset, kind));
+            }
+            offset += kind.words();
+        }
+        None
+    }
+}
+
+impl fmt::Debug for StructType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+/// Named int values, at least one of them
+#[derive(Hash, PartialEq, PartialOrd)]
+pub struct EnumType {
+    pub name: String,
+    pub variants: Vec<(String, Number)>,
+}
+
+impl EnumType {
+    /// Value of the first variant, which variables declared without an initializer start as
+    pub fn first(&self) -> Number {
+        self.variants[0].1
+    }
+
+    pub fn value(&self, variant: &str) -> Option<Number> {
+        self.variants
+            .iter()
+            .find(|(name, _)| name == variant)
+            .map(|(_, value)| *value)
+    }
+
+    pub fn variant(&self, value: Number) -> Option<&str> {
+        self.variants
+            .iter()
+            .find(|(_, v)| *v == value)
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+impl fmt::Debug for EnumType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
 impl From<TokenKind> for VariableKind {
     fn from(tk: TokenKind) -> Self {
         match tk {
             TokenKind::TypeString => VariableKind::String,
+            TokenKind::TypeVoid => VariableKind::None,
             TokenKind::TypeNumber => VariableKind::Number,
             TokenKind::TypeBool => VariableKind::Bool,
             _ => panic!("Convert TokenKind to VariableKind"),
@@ -64,14 +499,30 @@ impl From<TokenKind> for VariableKind {
     }
 }
 
-#[derive(Clone, Debug, Hash)]
+#[derive(Clone, Debug)]
 pub enum VariableData {
     String(String),
     Number(Number),
     Bool(bool),
+    Array(Vec<Number>),
+    /// Values of the fields in declaration order
+    Struct(Vec<VariableData>),
     None,
 }
 
+impl VariableData {
+    pub fn to_number(&self) -> Number {
+        match self {
+            VariableData::Number(n) => *n,
+            VariableData::Bool(b) => *b as Number,
+            VariableData::String(_) => panic!("Cannot convert String to Number"),
+            VariableData::Array(_) => panic!("Cannot convert Array to Number"),
+            VariableData::Struct(_) => panic!("Cannot convert Struct to Number"),
+            VariableData::None => panic!("Cannot convert None to Number"),
+        }
+    }
+}
+
 impl PartialEq for VariableData {
     fn eq(&self, other: &Self) -> bool {
         let s_val: Number = match self {
@@ -83,6 +534,7 @@ impl PartialEq for VariableData {
                 }
                 panic!("Compare string with non string")
             }
+            VariableData::Array(_) | VariableData::Struct(_) => panic!("Compare aggregate"),
             VariableData::None => {
                 if let VariableData::None = other {
                     return true;
@@ -97,6 +549,7 @@ impl PartialEq for VariableData {
             VariableData::String(_s1) => {
                 panic!("Compare string with non string")
             }
+            VariableData::Array(_) | VariableData::Struct(_) => panic!("Compare aggregate"),
             VariableData::None => {
                 return false;
             }
@@ -113,6 +566,7 @@ impl PartialOrd for VariableData {
             VariableData::String(_) => {
                 panic!("Ord string")
             }
+            VariableData::Array(_) | VariableData::Struct(_) => panic!("Ord aggregate"),
             VariableData::None => {
                 panic!("Ord None")
             }
@@ -124,6 +578,7 @@ impl PartialOrd for VariableData {
             VariableData::String(_) => {
                 panic!("Ord string")
             }
+            VariableData::Array(_) | VariableData::Struct(_) => panic!("Ord aggregate"),
             VariableData::None => {
                 panic!("Ord None")
             }